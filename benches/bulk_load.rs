@@ -0,0 +1,148 @@
+//! Compares inserting 100k log entries one by one with `upsert_entry` against
+//! loading them with `bulk_load`, checking both indexes answer queries alike, and
+//! measures ingesting the same lines through `upsert_log`, whose postings append
+//! each new document ID to a sorted list. A last group checks that starting the
+//! tables small and doubling them costs ingest no more than starting them at the
+//! 65,536 buckets that approach the old fixed sizes, or sizing them for the load with
+//! `LogDB::with_capacity`.
+//!
+//! Run with `cargo bench --no-default-features --bench bulk_load`.
+
+use buggu::{LogConfig, LogDB, LogEntry, TokenMode};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::hint::black_box;
+
+const DOCS: usize = 100_000;
+
+const SERVICES: [&str; 6] = ["auth", "billing", "search", "gateway", "storage", "mailer"];
+const LEVELS: [&str; 4] = ["DEBUG", "INFO", "WARN", "ERROR"];
+const WORDS: [&str; 8] = [
+    "timeout", "refused", "retry", "latency", "degraded", "overflow", "user", "request",
+];
+
+fn entries() -> Vec<LogEntry> {
+    (0..DOCS)
+        .map(|i| {
+            let content = format!(
+                "{} {} while handling request {} for user:{}",
+                WORDS[i % WORDS.len()],
+                WORDS[(i / 7) % WORDS.len()],
+                i % 1000,
+                i % 250,
+            );
+            LogEntry::with_metadata(
+                content,
+                Some(LEVELS[i % LEVELS.len()].to_string()),
+                Some(SERVICES[i % SERVICES.len()].to_string()),
+                TokenMode::Mixed,
+            )
+        })
+        .collect()
+}
+
+fn incremental(mut db: LogDB, entries: Vec<LogEntry>) -> LogDB {
+    for entry in entries {
+        db.upsert_entry(entry).unwrap();
+    }
+    db
+}
+
+fn bulk(entries: Vec<LogEntry>) -> LogDB {
+    let mut db = LogDB::new();
+    db.bulk_load(entries.into_iter());
+    db
+}
+
+fn bulk_load(c: &mut Criterion) {
+    let data = entries();
+    let (one_by_one, loaded) = (incremental(LogDB::new(), data.clone()), bulk(data.clone()));
+    for q in [
+        "timeout",
+        "level:ERROR service:auth",
+        "retry -latency",
+        "user:42",
+        "\"request 7\"",
+    ] {
+        let (mut a, mut b) = (one_by_one.query(q), loaded.query(q));
+        a.sort_unstable();
+        b.sort_unstable();
+        assert_eq!(a, b, "{q}");
+    }
+
+    let mut group = c.benchmark_group("ingest_100k");
+    group.throughput(Throughput::Elements(DOCS as u64));
+    group.sample_size(10);
+    group.bench_function("upsert_entry", |b| {
+        b.iter_batched(
+            || data.clone(),
+            |entries| black_box(incremental(LogDB::new(), entries)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("bulk_load", |b| {
+        b.iter_batched(
+            || data.clone(),
+            |entries| black_box(bulk(entries)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn upsert_log(c: &mut Criterion) {
+    let lines: Vec<(String, String, String)> = entries()
+        .into_iter()
+        .map(|e| (e.content, e.level.unwrap(), e.service.unwrap()))
+        .collect();
+
+    let mut group = c.benchmark_group("upsert_log_100k");
+    group.throughput(Throughput::Elements(DOCS as u64));
+    group.sample_size(10);
+    group.bench_function("upsert_log", |b| {
+        b.iter_batched(
+            || lines.clone(),
+            |lines| {
+                let mut db = LogDB::new();
+                for (content, level, service) in lines {
+                    db.upsert_log(&content, Some(level), Some(service)).unwrap();
+                }
+                black_box(db)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+/// Builds the empty `LogDB` an ingest run starts from.
+type MakeDb = fn() -> LogDB;
+
+fn table_sizing(c: &mut Criterion) {
+    let data = entries();
+    let mut group = c.benchmark_group("table_sizing_100k");
+    group.throughput(Throughput::Elements(DOCS as u64));
+    group.sample_size(10);
+    let sizings: [(&str, MakeDb); 3] = [
+        ("initial_capacity_256", LogDB::new),
+        ("initial_capacity_65536", || {
+            LogDB::with_config(LogConfig {
+                initial_capacity: 65_536,
+                ..LogConfig::default()
+            })
+        }),
+        ("with_capacity", || LogDB::with_capacity(DOCS)),
+    ];
+    for (name, db) in sizings {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || data.clone(),
+                |entries| black_box(incremental(db(), entries)),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bulk_load, upsert_log, table_sizing);
+criterion_main!(benches);
@@ -0,0 +1,111 @@
+//! Compares updating postings through `entry(tok).or_default()` against
+//! `get_mut_or_insert_with`, on the token stream of a realistic ingestion with a
+//! `Vec` of document IDs standing in for each posting, and measures `upsert_log`
+//! throughput, which updates its postings the second way.
+//!
+//! Run with `cargo bench --no-default-features --bench get_mut_or_insert`.
+
+use buggu::ufhg::lightning_hash_str;
+use buggu::utils::buggu_hash_set::BugguHashSet;
+use buggu::{DocId, LogDB};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::hint::black_box;
+
+const DOCS: usize = 10_000;
+
+const SERVICES: [&str; 6] = ["auth", "billing", "search", "gateway", "storage", "mailer"];
+const LEVELS: [&str; 4] = ["DEBUG", "INFO", "WARN", "ERROR"];
+const WORDS: [&str; 8] = [
+    "timeout", "refused", "retry", "latency", "degraded", "overflow", "user", "request",
+];
+
+fn lines() -> Vec<(String, &'static str, &'static str)> {
+    (0..DOCS)
+        .map(|i| {
+            let content = format!(
+                "{} {} while handling request{} for user:{}",
+                WORDS[i % WORDS.len()],
+                WORDS[(i / 7) % WORDS.len()],
+                i % 1000,
+                i % 250,
+            );
+            (
+                content,
+                LEVELS[i % LEVELS.len()],
+                SERVICES[i % SERVICES.len()],
+            )
+        })
+        .collect()
+}
+
+/// Returns the word tokens of each document of `lines`, labeled as `upsert_log`
+/// labels them, by document ID.
+fn token_stream() -> Vec<(DocId, Vec<u64>)> {
+    lines()
+        .into_iter()
+        .enumerate()
+        .map(|(i, (content, level, service))| {
+            let descriptor = format!("level {level} service {service} content {content}");
+            let tokens = descriptor.split(' ').map(lightning_hash_str).collect();
+            (DocId(i as u64 + 1), tokens)
+        })
+        .collect()
+}
+
+fn posting_updates(c: &mut Criterion) {
+    let stream = token_stream();
+    let tokens: usize = stream.iter().map(|(_, t)| t.len()).sum();
+    let mut group = c.benchmark_group("posting_updates");
+    group.throughput(Throughput::Elements(tokens as u64));
+
+    group.bench_function("entry_or_default", |b| {
+        b.iter_batched(
+            || BugguHashSet::<u64, Vec<DocId>>::growable(256),
+            |mut postings| {
+                for (id, tokens) in &stream {
+                    for &tok in tokens {
+                        postings.entry(tok).or_default().push(*id);
+                    }
+                }
+                black_box(postings)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("get_mut_or_insert_with", |b| {
+        b.iter_batched(
+            || BugguHashSet::<u64, Vec<DocId>>::growable(256),
+            |mut postings| {
+                for (id, tokens) in &stream {
+                    for &tok in tokens {
+                        postings.get_mut_or_insert_with(tok, Vec::new).push(*id);
+                    }
+                }
+                black_box(postings)
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn ingestion(c: &mut Criterion) {
+    let lines = lines();
+    let mut group = c.benchmark_group("ingestion");
+    group.throughput(Throughput::Elements(DOCS as u64));
+    group.sample_size(20);
+    group.bench_function("upsert_log", |b| {
+        b.iter(|| {
+            let mut db = LogDB::new();
+            for (content, level, service) in &lines {
+                db.upsert_log(content, Some(level.to_string()), Some(service.to_string()))
+                    .unwrap();
+            }
+            black_box(db)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, posting_updates, ingestion);
+criterion_main!(benches);
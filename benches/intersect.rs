@@ -0,0 +1,62 @@
+//! Compares the two-word AND, which intersects postings directly, against the same
+//! AND taking the general path that builds a set for each word, over a
+//! 100k-document corpus.
+//!
+//! Run with `cargo bench --no-default-features --features serde --bench intersect`.
+//! The general path is reached by adding `match_all`, which does not change the
+//! matches.
+
+use buggu::query_spec::QuerySpec;
+use buggu::LogDB;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const DOCS: usize = 100_000;
+
+const WORDS: [&str; 6] = [
+    "timeout", "refused", "retry", "latency", "degraded", "overflow",
+];
+
+fn corpus() -> LogDB {
+    let mut db = LogDB::new();
+    for i in 0..DOCS {
+        let mut content = format!("{} request", WORDS[i % WORDS.len()]);
+        if i % 1000 == 0 {
+            content.push_str(" rare");
+        }
+        db.upsert_simple(&content).unwrap();
+    }
+    db
+}
+
+fn intersect(c: &mut Criterion) {
+    let db = corpus();
+    let pairs = [
+        ("large_and_large", "timeout", "request"),
+        ("small_and_large", "rare", "request"),
+    ];
+    for (name, a, b) in pairs {
+        let q = format!("{a} {b}");
+        let spec = QuerySpec::from_json(&format!(
+            r#"{{"and": [{{"term": "{a}"}}, {{"term": "{b}"}}, {{"match_all": {{}}}}]}}"#
+        ))
+        .unwrap();
+        let mut direct = db.query(&q);
+        let mut general = db.query_spec(&spec);
+        direct.sort_unstable();
+        general.sort_unstable();
+        assert_eq!(direct, general);
+
+        let mut group = c.benchmark_group(format!("intersect/{name}"));
+        group.bench_function("postings", |bench| {
+            bench.iter(|| black_box(db.query(black_box(&q))))
+        });
+        group.bench_function("sets", |bench| {
+            bench.iter(|| black_box(db.query_spec(black_box(&spec))))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, intersect);
+criterion_main!(benches);
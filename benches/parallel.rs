@@ -0,0 +1,78 @@
+//! Compares sequential and parallel execution of a 6-way OR and a 3-way AND over a
+//! 100k-document corpus, on one thread and on the whole rayon pool.
+//!
+//! Run with `cargo bench --no-default-features --features rayon --bench parallel`.
+
+use buggu::logdb::QueryOptions;
+use buggu::LogDB;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+use std::sync::Mutex;
+
+const DOCS: usize = 100_000;
+
+const SERVICES: [&str; 6] = ["auth", "billing", "search", "gateway", "storage", "mailer"];
+const WORDS: [&str; 6] = [
+    "timeout", "refused", "retry", "latency", "degraded", "overflow",
+];
+
+const QUERIES: [(&str, &str); 2] = [
+    (
+        "6-way OR",
+        "contains:(timeout|refused|retry|latency|degraded|overflow)",
+    ),
+    ("3-way AND", "request handled node"),
+];
+
+fn corpus() -> LogDB {
+    let mut db = LogDB::new();
+    for i in 0..DOCS {
+        let content = format!(
+            "{} {} request handled by {} node",
+            WORDS[i % WORDS.len()],
+            WORDS[(i / 7) % WORDS.len()],
+            SERVICES[i % SERVICES.len()],
+        );
+        let level = if i % 3 == 0 { "ERROR" } else { "INFO" };
+        db.upsert_log(&content, Some(level.to_string()), None)
+            .unwrap();
+    }
+    db
+}
+
+fn parallel(c: &mut Criterion) {
+    // `LogDB` is `Send` but not `Sync`, so the one-thread pool borrows it through a
+    // mutex, which both parallel runs lock alike.
+    let db = Mutex::new(corpus());
+    let sequential = QueryOptions::default();
+    let parallel = QueryOptions {
+        parallel: true,
+        ..QueryOptions::default()
+    };
+    let one_thread = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let threads = rayon::current_num_threads();
+
+    for (name, q) in QUERIES {
+        let search = |opts: &QueryOptions| db.lock().unwrap().search(black_box(q), opts).total;
+        {
+            let db = db.lock().unwrap();
+            assert_eq!(db.search(q, &sequential).hits, db.search(q, &parallel).hits);
+        }
+        let mut group = c.benchmark_group(name);
+        group.bench_function("sequential", |b| b.iter(|| black_box(search(&sequential))));
+        group.bench_function("parallel_one_thread", |b| {
+            b.iter(|| one_thread.install(|| black_box(search(&parallel))))
+        });
+        // Labeled with the pool size, which may also be 1.
+        group.bench_function(BenchmarkId::new("parallel_pool", threads), |b| {
+            b.iter(|| black_box(search(&parallel)))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parallel);
+criterion_main!(benches);
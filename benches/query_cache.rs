@@ -0,0 +1,58 @@
+//! Compares repeated runs of one query with the parsed-query cache on and off, over a
+//! 100k-document corpus, checking both return the same matches. Planning alone is
+//! timed too, through `parse_only`, since running the query dominates its cost.
+//!
+//! Run with `cargo bench --no-default-features --bench query_cache`.
+
+use buggu::{LogConfig, LogDB};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const DOCS: usize = 100_000;
+
+const QUERY: &str = "level:ERROR AND service:auth";
+
+const SERVICES: [&str; 4] = ["auth", "billing", "search", "gateway"];
+const LEVELS: [&str; 3] = ["INFO", "WARN", "ERROR"];
+
+fn corpus(config: LogConfig) -> LogDB {
+    let mut db = LogDB::with_config(config);
+    for i in 0..DOCS {
+        db.upsert_log(
+            &format!("request {i} handled"),
+            Some(LEVELS[i % LEVELS.len()].to_string()),
+            Some(SERVICES[(i / 5) % SERVICES.len()].to_string()),
+        )
+        .unwrap();
+    }
+    db
+}
+
+fn query_cache(c: &mut Criterion) {
+    let cached = corpus(LogConfig::default());
+    let uncached = corpus(LogConfig {
+        query_cache_capacity: 0,
+        ..LogConfig::default()
+    });
+    assert_eq!(cached.query(QUERY), uncached.query(QUERY));
+    let ast = |db: &LogDB| db.parse_only(QUERY).map(|ast| ast.to_string());
+    assert_eq!(ast(&cached), ast(&uncached));
+
+    let dbs = [("cached", &cached), ("uncached", &uncached)];
+    let mut group = c.benchmark_group("query_cache/query");
+    for (name, db) in dbs {
+        group.bench_function(name, |b| b.iter(|| black_box(db.query(black_box(QUERY)))));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("query_cache/plan");
+    for (name, db) in dbs {
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(db.parse_only(black_box(QUERY))))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, query_cache);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+//! Compares one `filter_by_services` call over five services with five separate
+//! `service:` queries whose results are unioned, over a 100k-document corpus, checking
+//! both return the same documents. The query cache is off, so each round runs the
+//! five queries again.
+//!
+//! Run with `cargo bench --no-default-features --bench services`.
+
+use buggu::{DocId, LogConfig, LogDB};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const DOCS: usize = 100_000;
+
+const SERVICES: [&str; 10] = [
+    "auth", "billing", "search", "gateway", "api", "db", "cache", "mail", "queue", "cron",
+];
+const WANTED: [&str; 5] = ["auth", "search", "api", "cache", "queue"];
+
+fn corpus() -> LogDB {
+    let mut db = LogDB::with_config(LogConfig {
+        query_cache_capacity: 0,
+        ..LogConfig::default()
+    });
+    for i in 0..DOCS {
+        db.upsert_log(
+            &format!("request {i} handled"),
+            Some("INFO".to_string()),
+            Some(SERVICES[(i / 7) % SERVICES.len()].to_string()),
+        )
+        .unwrap();
+    }
+    db
+}
+
+/// Runs one `service:` query per service and unions the results.
+fn separate_queries(db: &LogDB, services: &[&str]) -> Vec<DocId> {
+    let mut docs: Vec<DocId> = services
+        .iter()
+        .flat_map(|s| db.query(&format!("service:{s}")))
+        .collect();
+    docs.sort_unstable();
+    docs.dedup();
+    docs
+}
+
+fn services(c: &mut Criterion) {
+    let db = corpus();
+    assert_eq!(
+        db.filter_by_services(&WANTED),
+        separate_queries(&db, &WANTED)
+    );
+
+    let mut group = c.benchmark_group("services_100k");
+    group.bench_function("filter_by_services", |b| {
+        b.iter(|| black_box(db.filter_by_services(black_box(&WANTED))))
+    });
+    group.bench_function("five_queries", |b| {
+        b.iter(|| black_box(separate_queries(&db, black_box(&WANTED))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, services);
+criterion_main!(benches);
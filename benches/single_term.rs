@@ -0,0 +1,54 @@
+//! Compares a single-term query, which skips parsing and reads the word's posting
+//! directly, against the same query planned and executed as a parsed AST, over a
+//! 100k-document corpus, checking both return the same matches.
+//!
+//! Run with `cargo bench --no-default-features --bench single_term`.
+
+use buggu::LogDB;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+const DOCS: usize = 100_000;
+
+const SERVICES: [&str; 4] = ["auth", "billing", "search", "gateway"];
+const LEVELS: [&str; 3] = ["INFO", "WARN", "ERROR"];
+const WORDS: [&str; 6] = ["ERROR", "timeout", "refused", "retry", "latency", "handled"];
+
+fn corpus() -> LogDB {
+    let mut db = LogDB::new();
+    for i in 0..DOCS {
+        let rare = if i % 10_000 == 0 { " deadlock" } else { "" };
+        db.upsert_log(
+            &format!("request {i} {}{rare}", WORDS[i % WORDS.len()]),
+            Some(LEVELS[i % LEVELS.len()].to_string()),
+            Some(SERVICES[(i / 5) % SERVICES.len()].to_string()),
+        )
+        .unwrap();
+    }
+    db
+}
+
+fn single_term(c: &mut Criterion) {
+    let db = corpus();
+    // A word in ten documents, one in a sixth of them, and one in all of them.
+    for q in ["deadlock", "ERROR", "request"] {
+        let (mut direct, mut planned) = (db.query(q), db.query_ast(&db.parse_only(q).unwrap()));
+        direct.sort_unstable();
+        planned.sort_unstable();
+        assert_eq!(direct, planned, "{q}");
+        assert!(!direct.is_empty(), "{q}");
+
+        let mut group = c.benchmark_group(format!("single_term/{q}"));
+        group.bench_function("query", |b| b.iter(|| black_box(db.query(black_box(q)))));
+        group.bench_function("parse_and_exec", |b| {
+            b.iter(|| {
+                let ast = db.parse_only(black_box(q)).unwrap();
+                black_box(db.query_ast(&ast))
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, single_term);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+//! Compares word-at-a-time and bulk hashing of the words of 100k log lines, checking
+//! that both give the same hashes, and times ingesting the lines.
+//!
+//! Run with `cargo bench --no-default-features --features simd --bench tokenize`.
+//! Bulk hashing uses AVX2 and ingestion hashes in bulk only when built with `simd`,
+//! so run it again without `--features simd` to compare. Both groups report their
+//! throughput, in words hashed and lines ingested a second.
+
+use buggu::ufhg::{lightning_hash_many, lightning_hash_str};
+use buggu::LogDB;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+const LINES: usize = 100_000;
+
+const WORDS: [&str; 12] = [
+    "timeout",
+    "refused",
+    "retry",
+    "latency",
+    "connection",
+    "request",
+    "handled",
+    "upstream",
+    "node",
+    "error",
+    "user",
+    "cache",
+];
+
+fn lines() -> Vec<String> {
+    (0..LINES)
+        .map(|i| {
+            format!(
+                "{} {} {} by {} on node{} status {}",
+                WORDS[i % WORDS.len()],
+                WORDS[(i / 3) % WORDS.len()],
+                WORDS[(i / 7) % WORDS.len()],
+                WORDS[(i / 11) % WORDS.len()],
+                i % 64,
+                200 + i % 300,
+            )
+        })
+        .collect()
+}
+
+fn tokenize(c: &mut Criterion) {
+    let lines = lines();
+    let words: Vec<&str> = lines.iter().flat_map(|l| l.split_whitespace()).collect();
+
+    let scalar: Vec<u64> = words.iter().map(|w| lightning_hash_str(w)).collect();
+    let mut bulk = Vec::new();
+    lightning_hash_many(&words, &mut bulk);
+    assert_eq!(scalar, bulk);
+
+    let mut group = c.benchmark_group("hash_words");
+    group.throughput(Throughput::Elements(words.len() as u64));
+    let mut out = Vec::with_capacity(words.len());
+    group.bench_function("one_by_one", |b| {
+        b.iter(|| {
+            out.clear();
+            out.extend(words.iter().map(|w| lightning_hash_str(black_box(w))));
+            black_box(&out);
+        })
+    });
+    group.bench_function("in_bulk", |b| {
+        b.iter(|| {
+            out.clear();
+            lightning_hash_many(black_box(&words), &mut out);
+            black_box(&out);
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("ingest");
+    group.throughput(Throughput::Elements(LINES as u64));
+    group.sample_size(10);
+    group.bench_function("upsert_simple", |b| {
+        b.iter(|| {
+            let mut db = LogDB::new();
+            for line in &lines {
+                db.upsert_simple(line).unwrap();
+            }
+            black_box(db)
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tokenize);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+//! Build script for the N-API project.
+//!
+//! This script uses the `napi-build` crate to compile the Rust code
+//! into a Node.js addon, handling the necessary configuration for
+//! creating a native module that can be loaded by Node.js. It does
+//! nothing unless the `node` feature is enabled.
+
+/// The main function of the build script.
+///
+/// This function is executed by Cargo when building the crate. With the
+/// `node` feature it calls `napi_build::setup()` to configure the build for
+/// N-API compatibility, which ensures that the compiled library can be
+/// correctly loaded and used as a native Node.js module.
+fn main() {
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
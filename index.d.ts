@@ -3,6 +3,202 @@
 
 /* auto-generated by NAPI-RS */
 
+/** A node of a parsed query, returned by `parseQuery()`. */
+export interface QueryAst {
+  /**
+   * The node type: `"and"`, `"or"`, `"not"`, `"boost"`, `"term"`, `"phrase"`,
+   * `"field"`, `"contains"`, `"fuzzy"`, `"range"`, `"has"`, `"missing"` or
+   * `"match_all"`.
+   */
+  type: string
+  /** The field of a `field`, `range`, `has` or `missing` node. */
+  field?: string
+  /**
+   * The word, phrase or value of a `term`, `phrase`, `field`, `contains` or `fuzzy`
+   * node.
+   */
+  value?: string
+  /** The most edits a word matched by a `fuzzy` node may be from its value. */
+  distance?: number
+  /** The lower bound of a `range` node, inclusive. */
+  from?: number
+  /** The upper bound of a `range` node, inclusive, or absent if it has none. */
+  to?: number
+  /** The factor of a `boost` node. */
+  boost?: number
+  /** The children of an `and`, `or`, `not` or `boost` node. */
+  children?: Array<QueryAst>
+}
+/** A stored document returned by `getDocument()`. */
+export interface Document {
+  /** The document ID. */
+  id: string
+  /** The original content of the document. */
+  content: string
+  /** The log level, if one was specified. */
+  level?: string
+  /** The service name, if one was specified. */
+  service?: string
+  /** Every service the document is tagged with, `service` first. */
+  services: Array<string>
+  /** The timestamp of the document, in seconds since the Unix epoch. */
+  timestamp: number
+  /** The metadata fields of the document, each name with all of its values. */
+  fields: Record<string, Array<string>>
+}
+/** Bucket distribution statistics for the postings table, returned by `bucketStats()`. */
+export interface BucketStats {
+  /** The number of buckets holding no tokens. */
+  empty: number
+  /** The number of buckets storing their tokens inline. */
+  inline: number
+  /** The number of buckets that spilled into a heap-allocated overflow vector. */
+  overflow: number
+  /** The average number of tokens per bucket. */
+  loadFactor: number
+  /** The number of tokens in the most populated bucket. */
+  maxChainLen: number
+}
+/** The options of `search()`. Every field is optional. */
+export interface SearchOptions {
+  /** The most hits to return, after skipping `offset`. All of them by default. */
+  limit?: number
+  /** The number of hits to skip, for paging. 0 by default. */
+  offset?: number
+  /**
+   * The order of the hits: `"index"` (the default), `"newest"`, `"oldest"`,
+   * `"severity"` or `"relevance"`.
+   */
+  sort?: string
+  /** Set to `false` to also require words and phrases to match with their case. */
+  caseInsensitive?: boolean
+  /** The earliest timestamp to match, in seconds since the Unix epoch. */
+  timeFrom?: number
+  /** The latest timestamp to match, in seconds since the Unix epoch. */
+  timeTo?: number
+  /** Restricts hits to documents of this service. */
+  namespace?: string
+  /** Set to `false` to match words without their synonyms. */
+  enableSynonyms?: boolean
+}
+/** The hits of a search, returned by `search()`. */
+export interface SearchResult {
+  /** The matching document IDs, after `offset` and `limit`. */
+  hits: Array<string>
+  /** The number of matching documents, before `offset` and `limit`. */
+  total: number
+  /** How long the search took, in milliseconds. */
+  tookMs: number
+}
+/** A query kept by the slow-query log, returned by `slowQueries()`. */
+export interface SlowQuery {
+  /** The query string, as given. */
+  query: string
+  /** The number of clauses the query parses into, or 0 if it does not parse. */
+  clauses: number
+  /** The number of documents the query matched. */
+  results: number
+  /** How long the query took, in microseconds. */
+  tookMicros: number
+}
+/**
+ * Query latency statistics since the index was created, returned by
+ * `queryTimingSummary()`.
+ */
+export interface QueryTimingSummary {
+  /** The number of queries executed. */
+  count: number
+  /** The mean query time, in microseconds. */
+  meanMicros: number
+  /**
+   * The 95th percentile query time, in microseconds, rounded up to a power of two
+   * nanoseconds.
+   */
+  p95Micros: number
+  /** The slowest query time, in microseconds. */
+  maxMicros: number
+}
+/** A problem found by `healthReport()`. */
+export interface HealthFinding {
+  /** `"warning"` or `"info"`. */
+  severity: string
+  /** A stable code naming the kind of problem, such as `"long_bucket_chain"`. */
+  code: string
+  /** A description of the problem and what to do about it. */
+  message: string
+}
+/** The result of `healthReport()`. */
+export interface HealthReport {
+  /** The problems found, most severe first. */
+  findings: Array<HealthFinding>
+  /** The findings rendered one per line, for logging. */
+  text: string
+}
+/** A document matched by a ranked query, returned by `queryRanked()`. */
+export interface RankedHit {
+  /** The document ID. */
+  id: string
+  /** The relevance score of the document; higher is more relevant. */
+  score: number
+}
+/** A word and the number of times it occurs, returned by `topTerms()`. */
+export interface TermCount {
+  /** The word. */
+  term: string
+  /** The number of times it occurs. */
+  count: number
+}
+/** A document matched by `queryMatches()`, with where the query matched it. */
+export interface DocMatch {
+  /** The document ID. */
+  id: string
+  /**
+   * The `[start, end]` byte ranges of the content that the query matched, sorted
+   * and non-overlapping, `end` exclusive.
+   */
+  spans: Array<Array<number>>
+}
+/** Settings for a new `MicroSearch` index, passed to its constructor. */
+export interface IndexOptions {
+  /**
+   * Whether to index the Soundex code of every word, for `sounds:` queries. Off by
+   * default.
+   */
+  enablePhonetic?: boolean
+  /**
+   * Whether to remove ANSI escape sequences and control characters other than
+   * newlines and tabs from content before it is stored. On by default.
+   */
+  stripAnsi?: boolean
+  /**
+   * Whether to also keep content as it was given, before it was cleaned, for
+   * `getDocRaw()`. Off by default.
+   */
+  keepRawContent?: boolean
+}
+/** Checks `setIngestRules()` applies to every entry before it is indexed. */
+export interface IngestRules {
+  /** Whether to reject entries without a level. */
+  requireLevel?: boolean
+  /** Whether to remove ANSI escape sequences, such as color codes, from the content. */
+  stripAnsi?: boolean
+  /** The longest content to accept, in bytes, after any stripping. */
+  maxBytes?: number
+}
+/**
+ * A cursor paging through the hits of a query, returned by `openCursor()`.
+ *
+ * The cursor pins a snapshot of the index taken when it was opened, so documents
+ * ingested while paging neither show up in later pages nor shift them. Other writes
+ * still show through: a matching document deleted while paging shifts the later
+ * pages by a hit, which is then skipped, and postings evicted under `max_postings`
+ * take their hits with them. It does not hold the index itself; pass it to
+ * `nextPage()` of the `MicroSearch` that opened it.
+ */
+export declare class QueryCursor {
+  /** Returns the number of hits already returned by `nextPage()`. */
+  position(): number
+}
 /**
  * A high-performance, in-memory search engine exposed as a Node.js addon.
  *
@@ -14,13 +210,29 @@ export declare class MicroSearch {
   /**
    * Creates a new instance of `MicroSearch`.
    *
-   * This constructor initializes a new `LogDB` with default settings and wraps it
-   * in a `MicroSearch` struct, making it available for use in a Node.js environment.
+   * This constructor initializes a new `LogDB` with default settings, changed by
+   * any `options` given, and wraps it in a `MicroSearch` struct, making it available
+   * for use in a Node.js environment.
    *
    * # Returns
    * A `Result` containing the new `MicroSearch` instance or an error if initialization fails.
    */
-  constructor()
+  constructor(options?: IndexOptions | undefined | null)
+  /**
+   * Sets checks applied to every entry before it is indexed, replacing any set
+   * before, or removes them when `rules` is omitted.
+   *
+   * An entry failing a check is not inserted, and the `upsert` method given it
+   * throws an `InvalidArg` error saying which check it failed. Rejected entries
+   * are counted in `buggu_rejected_entries_total` in `metricsText()`.
+   *
+   * # Arguments
+   * * `rules` - The checks: `requireLevel` rejects entries without a level,
+   *   `stripAnsi` removes color codes and other ANSI escape sequences from the
+   *   content before it is indexed, and `maxBytes` rejects content longer than
+   *   that many bytes.
+   */
+  setIngestRules(rules?: IngestRules | undefined | null): void
   /**
    * Inserts or updates a simple document with the given content.
    *
@@ -49,6 +261,80 @@ export declare class MicroSearch {
    * A `Result` containing the document ID as a string, or an error if the operation fails.
    */
   upsertLog(content: string, level?: string | undefined | null, service?: string | undefined | null): string
+  /**
+   * Inserts a log entry with an explicit timestamp instead of the current time, for
+   * backfilling historical logs.
+   *
+   * # Arguments
+   * * `content` - The main content of the log entry.
+   * * `timestamp_secs` - The event time, in seconds since the Unix epoch. Any
+   *   fraction of a second is dropped.
+   * * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
+   * * `service` - An optional string specifying the service name.
+   *
+   * # Returns
+   * A `Result` containing the document ID as a string, or an `InvalidArg` error if
+   * `timestamp_secs` is NaN, infinite or negative.
+   */
+  upsertLogAt(content: string, timestampSecs: number, level?: string | undefined | null, service?: string | undefined | null): string
+  /**
+   * Inserts a log entry like `upsertLogAt`, with the event time given as an
+   * ISO-8601 UTC date or time, such as `2024-03-01` or `2024-03-01T12:30:00Z`.
+   *
+   * # Returns
+   * A `Result` containing the document ID as a string, or an `InvalidArg` error if
+   * `timestamp` is not of this form.
+   */
+  upsertLogAtIso(content: string, timestamp: string, level?: string | undefined | null, service?: string | undefined | null): string
+  /**
+   * Inserts a log entry given as a JSON object. The `level`, `service` and
+   * `message` (or `msg` or `content`) keys become the entry's level, service and
+   * content, a numeric `timestamp` key its timestamp in seconds, and every other
+   * scalar field except `id` is indexed as a `key:value` word, nested keys joined
+   * by dots and each element of an array as its own value.
+   *
+   * # Returns
+   * A `Result` containing the document ID as a string, or an `InvalidArg` error if
+   * `json` is not a JSON object or the ingest rules reject the entry.
+   */
+  upsertJson(json: string): string
+  /**
+   * Inserts a log entry with metadata fields, each name given with all of its
+   * values, such as `{ tag: ["db", "slow"] }`. Each value is indexed as a
+   * `name:value` word, so the entry matches both `tag:db` and `tag:slow`.
+   *
+   * # Returns
+   * A `Result` containing the document ID as a string.
+   */
+  upsertFields(content: string, fields: Record<string, Array<string>>): string
+  /**
+   * Inserts a log entry tagged with several services, so that a `service:` query
+   * for any of them finds it.
+   *
+   * # Arguments
+   * * `content` - The main content of the log entry.
+   * * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
+   * * `services` - The service names, such as every service a request passed through.
+   *
+   * # Returns
+   * A `Result` containing the document ID as a string.
+   */
+  upsertLogServices(content: string, level: string | undefined | null, services: Array<string>): string
+  /**
+   * Inserts a log entry like `upsertLog`, but indexes only its content as words.
+   *
+   * The level and service are searchable only through `level:` and `service:`
+   * queries, so bare words never match a document through its metadata.
+   *
+   * # Arguments
+   * * `content` - The main content of the log entry.
+   * * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
+   * * `service` - An optional string specifying the service name.
+   *
+   * # Returns
+   * A `Result` containing the document ID as a string, or an error if the operation fails.
+   */
+  upsertV2(content: string, level?: string | undefined | null, service?: string | undefined | null): string
   /**
    * Executes a search query and returns a list of matching document IDs.
    *
@@ -56,9 +342,47 @@ export declare class MicroSearch {
    * * `query` - The search query string.
    *
    * # Returns
-   * A `Result` containing a vector of document IDs as strings, or an error if the query fails.
+   * A `Result` containing a vector of document IDs as strings. An `EQUERY_COMPLEX`
+   * error is returned if the query is over the configured complexity limits.
    */
   query(query: string): Array<string>
+  /**
+   * Executes a search query with options to restrict, order and page through the
+   * hits.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   * * `options` - The optional settings described on `SearchOptions`.
+   *
+   * # Returns
+   * A `Result` containing the page of hits, the total number of matches and the
+   * time taken. An `InvalidArg` error is returned for an unknown sort order.
+   */
+  search(query: string, options?: SearchOptions | undefined | null): SearchResult
+  /**
+   * Opens a cursor paging through the hits of a query, `pageSize` at a time, as
+   * they were when the cursor was opened.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   * * `page_size` - The number of hits per page.
+   * * `options` - The optional settings described on `SearchOptions`. `offset` is
+   *   where the first page starts and `limit` is ignored.
+   *
+   * # Returns
+   * A `Result` containing the cursor, or an `InvalidArg` error for an unknown sort
+   * order or a page size of 0.
+   */
+  openCursor(query: string, pageSize: number, options?: SearchOptions | undefined | null): QueryCursor
+  /**
+   * Returns the next page of a cursor opened by `openCursor()` and moves the cursor
+   * past it.
+   *
+   * # Returns
+   * A `Result` containing the page of hits, which is empty once every hit has been
+   * returned, with the total number of matches the cursor sees and the time taken.
+   */
+  nextPage(cursor: QueryCursor): SearchResult
   /**
    * Executes a search query and returns the full content of matching documents.
    *
@@ -69,4 +393,263 @@ export declare class MicroSearch {
    * A `Result` containing a vector of document content strings, or an error if the query fails.
    */
   queryContent(query: string): Array<string>
+  /**
+   * Executes a structured query and returns a list of matching document IDs.
+   *
+   * The query is a plain object such as
+   * `{ and: [{ field: { level: "ERROR" } }, { term: "timeout" }] }`, converted
+   * directly from JavaScript without going through a JSON string.
+   *
+   * # Arguments
+   * * `query` - The structured query.
+   *
+   * # Returns
+   * A `Result` containing a vector of document IDs as strings. An `InvalidArg` error
+   * naming the offending node is returned if the query is malformed, and an
+   * `EQUERY_COMPLEX` error if it is over the configured complexity limits.
+   */
+  queryObject(query: any): Array<string>
+  /**
+   * Registers a query template with `$name` placeholders, such as
+   * `level:ERROR service:$svc contains:$word`, replacing any template with the
+   * same name.
+   *
+   * # Arguments
+   * * `name` - The name to run the template by with `queryTemplate`.
+   * * `template` - The query string, parsed once here.
+   *
+   * # Returns
+   * An `InvalidArg` error if the template cannot be parsed, or an `EQUERY_COMPLEX`
+   * error if it is over the configured complexity limits.
+   */
+  registerTemplate(name: string, template: string): void
+  /**
+   * Executes a registered query template with its placeholders filled in. Values
+   * are matched literally and never parsed as query syntax.
+   *
+   * # Arguments
+   * * `name` - The name the template was registered under.
+   * * `params` - The value of each placeholder, keyed by its name without the `$`.
+   *
+   * # Returns
+   * A `Result` containing the matching document IDs as strings. An `InvalidArg`
+   * error is returned for an unknown template, a placeholder with no value, or a
+   * value with no placeholder.
+   */
+  queryTemplate(name: string, params: Record<string, string>): Array<string>
+  /**
+   * Parses a query without running it, to see how the engine reads it.
+   *
+   * # Returns
+   * A `Result` containing the root `QueryAst` node, such as
+   * `{ type: "and", children: [...] }`. An `InvalidArg` error is returned for an
+   * empty or malformed query, and an `EQUERY_COMPLEX` error for one over the
+   * complexity limits.
+   */
+  parseQuery(query: string): QueryAst
+  /**
+   * Executes a search query restricted to the last `secs` seconds.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   * * `secs` - The length of the time window, ending now.
+   *
+   * # Returns
+   * A `Result` containing the matching document IDs as strings.
+   */
+  queryLast(query: string, secs: number): Array<string>
+  /**
+   * Executes a search query and returns the most recent matching document IDs.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   * * `limit` - The maximum number of document IDs to return.
+   *
+   * # Returns
+   * A `Result` containing up to `limit` document IDs as strings, newest first.
+   */
+  queryRecent(query: string, limit: number): Array<string>
+  /**
+   * Executes a search query and returns the matching documents ranked by relevance.
+   *
+   * # Arguments
+   * * `query` - The search query string. A trailing `^N` on a word boosts it.
+   *
+   * # Returns
+   * A `Result` containing the matching documents with their scores, highest first.
+   */
+  queryRanked(query: string): Array<RankedHit>
+  /**
+   * Executes a search query and returns each matching document with the byte ranges
+   * of its content that the query's words and phrases matched.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   *
+   * # Returns
+   * A `Result` containing the matching documents with their sorted,
+   * non-overlapping ranges.
+   */
+  queryMatches(query: string): Array<DocMatch>
+  /**
+   * Writes the documents matching a query to a file, as NDJSON or CSV.
+   *
+   * The matching documents are copied out of the index before the call returns,
+   * and the file is written on the libuv thread pool, so later changes to the
+   * index do not show up in the export.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   * * `path` - The file to write, replaced if it exists.
+   * * `format` - `"ndjson"` or `"csv"`.
+   *
+   * # Returns
+   * A promise of the number of documents written, rejected if the file cannot be
+   * written. An `InvalidArg` error is thrown for an unknown format.
+   */
+  exportToFile(query: string, path: string, format: string): Promise<number>
+  /**
+   * Executes a search query and returns the matching document IDs, most severe first.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   *
+   * # Returns
+   * A `Result` containing the matching document IDs as strings, ordered by level
+   * priority and then newest first.
+   */
+  queryBySeverity(query: string): Array<string>
+  /**
+   * Adds a synonym ring, so that a query for any of its words also matches
+   * documents containing the others, including documents already indexed.
+   *
+   * # Arguments
+   * * `words` - The words to treat as synonyms, e.g. `["db", "database", "postgres"]`.
+   */
+  addSynonyms(words: Array<string>): void
+  /**
+   * Suggests indexed words that start with a prefix, for type-ahead search.
+   *
+   * # Arguments
+   * * `prefix` - The prefix to complete. Matching is case-insensitive.
+   * * `limit` - The maximum number of suggestions to return.
+   *
+   * # Returns
+   * A `Result` containing up to `limit` lowercase words, most frequent first.
+   */
+  suggest(prefix: string, limit: number): Array<string>
+  /**
+   * Returns the words occurring most often in the documents matching a query.
+   *
+   * # Arguments
+   * * `query` - The search query string.
+   * * `n` - The maximum number of words to return.
+   * * `exclude_stopwords` - Whether to leave out common English words such as
+   *   `the`. Defaults to `false`.
+   *
+   * # Returns
+   * A `Result` containing up to `n` words with their occurrence counts, most
+   * frequent first.
+   */
+  topTerms(query: string, n: number, excludeStopwords?: boolean | undefined | null): Array<TermCount>
+  /**
+   * Retrieves the content of a document as it was given, before escape sequences
+   * and control characters were removed from it, when the index was created with
+   * `keepRawContent`; otherwise the stored content.
+   *
+   * # Arguments
+   * * `id` - The document ID, as returned by the upsert methods.
+   *
+   * # Returns
+   * A `Result` containing the content, or `null` if no document has this ID. An
+   * `InvalidArg` error is returned if `id` is not a valid document ID.
+   */
+  getDocRaw(id: string): string | null
+  /**
+   * Retrieves a stored document by its ID.
+   *
+   * # Arguments
+   * * `id` - The document ID, as returned by the upsert methods.
+   *
+   * # Returns
+   * A `Result` containing the document, or `null` if no document has this ID. An
+   * `InvalidArg` error is returned if `id` is not a valid document ID.
+   */
+  getDocument(id: string): Document | null
+  /**
+   * Deletes a document from the index.
+   *
+   * # Arguments
+   * * `id` - The document ID, as returned by the upsert methods.
+   *
+   * # Returns
+   * A `Result` containing `true` if the document was deleted, or `false` if no
+   * document has this ID. An `InvalidArg` error is returned if `id` is not a valid
+   * document ID.
+   */
+  delete(id: string): boolean
+  /**
+   * Returns bucket distribution statistics for the postings table.
+   *
+   * The table doubles its buckets once it averages two tokens per bucket, so a
+   * healthy table has a `loadFactor` of at most 2.0, a `maxChainLen` of at most 8
+   * (beyond which `healthReport()` reports `long_bucket_chain`) and an `overflow`
+   * count that is a small fraction of `inline`. Values beyond these ranges
+   * indicate that lookups are walking overflow vectors and the table would
+   * benefit from more buckets.
+   *
+   * ```javascript
+   * const stats = db.bucketStats();
+   * if (stats.loadFactor > 2.0 || stats.maxChainLen > 8) {
+   *   console.warn(`postings table is crowded: ${JSON.stringify(stats)}`);
+   * }
+   * ```
+   *
+   * # Returns
+   * A `Result` containing the `BucketStats` object.
+   */
+  bucketStats(): BucketStats
+  /**
+   * Returns the index metrics in the Prometheus text exposition format.
+   *
+   * The output covers insert, delete, query and query error counters, a query
+   * latency histogram, and gauges for the document count and estimated memory use.
+   * Serve it from a `/metrics` route to have Prometheus scrape it.
+   *
+   * # Returns
+   * A `Result` containing the metrics text.
+   */
+  metricsText(): string
+  /**
+   * Inspects the index for problems that slow queries down or waste memory, such
+   * as words in most documents, overfull hash table buckets, documents without a
+   * level and postings past the postings limit.
+   *
+   * # Returns
+   * A `Result` containing the `HealthReport` object.
+   */
+  healthReport(): HealthReport
+  /**
+   * Returns the recent queries that took longer than `threshold_micros`
+   * microseconds, oldest first, to find the query shapes behind latency spikes.
+   *
+   * The last 256 queries are kept. Queries run through `queryTemplate()` are not.
+   *
+   * # Returns
+   * A `Result` containing the slow queries.
+   */
+  slowQueries(thresholdMicros: number): Array<SlowQuery>
+  /**
+   * Returns the count, mean, 95th percentile and maximum of the query times since
+   * the index was created.
+   *
+   * # Returns
+   * A `Result` containing the `QueryTimingSummary` object.
+   */
+  queryTimingSummary(): QueryTimingSummary
+  /**
+   * Drops every parsed query cached for reuse. Queries are never stale in the
+   * cache, so this only frees its memory.
+   */
+  clearQueryCache(): void
 }
@@ -310,6 +310,7 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { MicroSearch } = nativeBinding
+const { QueryCursor, MicroSearch } = nativeBinding
 
+module.exports.QueryCursor = QueryCursor
 module.exports.MicroSearch = MicroSearch
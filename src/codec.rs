@@ -6,7 +6,14 @@
 //! to be sent over the network, improving performance in log transmission scenarios.
 
 use crate::types::{DocId, Tok};
-use std::io;
+use std::io::{self, Read, Write};
+
+/// The version of the frame format, written as the first byte of every frame.
+///
+/// It is bumped whenever a frame layout or tag is added, or the backup header
+/// changes, so that a reader built for an older format rejects newer frames instead
+/// of mis-decoding them.
+pub const FORMAT_VERSION: u8 = 4;
 
 /// Tag for a full frame, indicating a complete snapshot of a document.
 pub const TAG_FULL: u8 = 0;
@@ -14,25 +21,54 @@ pub const TAG_FULL: u8 = 0;
 /// Tag for a differential frame, representing the changes since the last version.
 pub const TAG_DIFF: u8 = 1;
 
-/// Represents a data frame, which can be either a full snapshot or a differential update.
+/// Tag for a metadata frame, carrying a document's content and metadata.
+pub const TAG_META: u8 = 2;
+
+/// Tag for a delete frame, removing a document.
+pub const TAG_DELETE: u8 = 3;
+
+/// Tag for a batch frame, carrying the full token sets of many documents.
+pub const TAG_BATCH: u8 = 4;
+
+/// The largest number of documents `decode_batch` accepts in a single batch frame.
+pub const MAX_BATCH_DOCS: usize = 65_536;
+
+/// Represents a data frame: a full snapshot, a differential update, a document's
+/// metadata, a deletion, or a batch of full snapshots.
 #[derive(Debug, PartialEq)]
 pub enum Frame {
     /// A full snapshot of a document, containing all its tokens.
-    Full {
-        doc_id: DocId,
-        tokens: Vec<Tok>,
-    },
+    Full { doc_id: DocId, tokens: Vec<Tok> },
     /// A differential update, containing tokens to be removed and added.
     Diff {
         doc_id: DocId,
         remove: Vec<Tok>,
         add: Vec<Tok>,
     },
+    /// The stored content and metadata of a document.
+    Meta {
+        doc_id: DocId,
+        content: String,
+        level: Option<String>,
+        service: Option<String>,
+        /// The services the document is tagged with besides `service`.
+        extra_services: Vec<String>,
+        timestamp: u64,
+    },
+    /// The removal of a document.
+    Delete { doc_id: DocId },
+    /// Full snapshots of many documents. Each entry holds a document ID and the
+    /// number of its tokens, which are stored back-to-back in `tokens`.
+    Batch {
+        entries: Vec<(DocId, usize)>,
+        tokens: Vec<Tok>,
+    },
 }
 
 /// Encodes a full token set into a byte vector.
 ///
 /// The resulting byte vector is structured as follows:
+/// - `FORMAT_VERSION` (1 byte)
 /// - `TAG_FULL` (1 byte)
 /// - `doc_id` (variable-length u64)
 /// - `tokens.len()` (variable-length u64)
@@ -45,9 +81,10 @@ pub enum Frame {
 /// # Returns
 /// A `Vec<u8>` containing the encoded full frame.
 pub fn encode_full(doc: DocId, tokens: &[Tok]) -> Vec<u8> {
-    let mut buf = Vec::with_capacity(tokens.len() * 9 + 10);
+    let mut buf = Vec::with_capacity(tokens.len() * 9 + 11);
+    buf.push(FORMAT_VERSION);
     buf.push(TAG_FULL);
-    write_uvar(doc, &mut buf);
+    write_uvar(doc.get(), &mut buf);
     write_uvar(tokens.len() as u64, &mut buf);
     for &t in tokens {
         write_uvar(t, &mut buf);
@@ -58,6 +95,7 @@ pub fn encode_full(doc: DocId, tokens: &[Tok]) -> Vec<u8> {
 /// Encodes a differential update into a byte vector.
 ///
 /// The resulting byte vector is structured as follows:
+/// - `FORMAT_VERSION` (1 byte)
 /// - `TAG_DIFF` (1 byte)
 /// - `doc_id` (variable-length u64)
 /// - `remove.len()` (variable-length u64)
@@ -73,9 +111,10 @@ pub fn encode_full(doc: DocId, tokens: &[Tok]) -> Vec<u8> {
 /// # Returns
 /// A `Vec<u8>` containing the encoded differential frame.
 pub fn encode_diff(doc: DocId, remove: &[Tok], add: &[Tok]) -> Vec<u8> {
-    let mut buf = Vec::with_capacity((remove.len() + add.len()) * 9 + 10);
+    let mut buf = Vec::with_capacity((remove.len() + add.len()) * 9 + 11);
+    buf.push(FORMAT_VERSION);
     buf.push(TAG_DIFF);
-    write_uvar(doc, &mut buf);
+    write_uvar(doc.get(), &mut buf);
     write_uvar(remove.len() as u64, &mut buf);
     for &t in remove {
         write_uvar(t, &mut buf);
@@ -87,54 +126,460 @@ pub fn encode_diff(doc: DocId, remove: &[Tok], add: &[Tok]) -> Vec<u8> {
     buf
 }
 
+/// Encodes a document's content and metadata into a byte vector.
+///
+/// The resulting byte vector is structured as follows:
+/// - `FORMAT_VERSION` (1 byte)
+/// - `TAG_META` (1 byte)
+/// - `doc_id` (variable-length u64)
+/// - `timestamp` (variable-length u64)
+/// - `content` (variable-length byte length, then the UTF-8 bytes)
+/// - `level` and `service` (each a variable-length byte length plus one, then the
+///   UTF-8 bytes; a length of zero means `None`)
+/// - `extra_services` (a variable-length count, then each service like `content`)
+///
+/// # Arguments
+/// * `doc` - The document ID.
+/// * `content` - The stored content of the document.
+/// * `level` - The log level of the document, if any.
+/// * `service` - The service name of the document, if any.
+/// * `extra_services` - The other services the document is tagged with.
+/// * `timestamp` - The timestamp of the document, in seconds since the Unix epoch.
+///
+/// # Returns
+/// A `Vec<u8>` containing the encoded metadata frame.
+pub fn encode_meta(
+    doc: DocId,
+    content: &str,
+    level: Option<&str>,
+    service: Option<&str>,
+    extra_services: &[String],
+    timestamp: u64,
+) -> Vec<u8> {
+    let strings_len = content.len()
+        + level.map_or(0, str::len)
+        + service.map_or(0, str::len)
+        + extra_services.iter().map(|s| s.len() + 10).sum::<usize>();
+    let mut buf = Vec::with_capacity(strings_len + 51);
+    buf.push(FORMAT_VERSION);
+    buf.push(TAG_META);
+    write_uvar(doc.get(), &mut buf);
+    write_uvar(timestamp, &mut buf);
+    write_str(content, &mut buf);
+    write_opt_str(level, &mut buf);
+    write_opt_str(service, &mut buf);
+    write_uvar(extra_services.len() as u64, &mut buf);
+    for s in extra_services {
+        write_str(s, &mut buf);
+    }
+    buf
+}
+
+/// Encodes the deletion of a document into a byte vector.
+///
+/// The resulting byte vector is structured as follows:
+/// - `FORMAT_VERSION` (1 byte)
+/// - `TAG_DELETE` (1 byte)
+/// - `doc_id` (variable-length u64)
+///
+/// # Arguments
+/// * `doc` - The document ID.
+///
+/// # Returns
+/// A `Vec<u8>` containing the encoded delete frame.
+pub fn encode_delete(doc: DocId) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12);
+    buf.push(FORMAT_VERSION);
+    buf.push(TAG_DELETE);
+    write_uvar(doc.get(), &mut buf);
+    buf
+}
+
+/// Encodes the full token sets of many documents into a single byte vector.
+///
+/// The documents are written in ascending order of ID, each ID stored as the
+/// difference from the previous one, which amortizes the per-frame overhead of
+/// sending small documents one `Full` frame at a time.
+///
+/// The resulting byte vector is structured as follows:
+/// - `FORMAT_VERSION` (1 byte)
+/// - `TAG_BATCH` (1 byte)
+/// - `docs.len()` (variable-length u64)
+/// - for each document: the `doc_id` delta, the token count, and the tokens (all
+///   variable-length u64 values)
+///
+/// # Arguments
+/// * `docs` - The document IDs and their tokens. Batches larger than
+///   `MAX_BATCH_DOCS` are rejected by the default decoder, so split them with
+///   `chunks` first.
+///
+/// # Returns
+/// A `Vec<u8>` containing the encoded batch frame.
+pub fn encode_batch(docs: &[(DocId, &[Tok])]) -> Vec<u8> {
+    let mut sorted: Vec<&(DocId, &[Tok])> = docs.iter().collect();
+    sorted.sort_by_key(|(doc_id, _)| *doc_id);
+
+    let token_count: usize = docs.iter().map(|(_, tokens)| tokens.len()).sum();
+    let mut buf = Vec::with_capacity(token_count * 9 + docs.len() * 4 + 12);
+    buf.push(FORMAT_VERSION);
+    buf.push(TAG_BATCH);
+    write_uvar(docs.len() as u64, &mut buf);
+    let mut prev = 0;
+    for (doc_id, tokens) in sorted {
+        write_uvar(doc_id.get() - prev, &mut buf);
+        prev = doc_id.get();
+        write_uvar(tokens.len() as u64, &mut buf);
+        for &t in *tokens {
+            write_uvar(t, &mut buf);
+        }
+    }
+    buf
+}
+
+/// Computes the tokens to remove and add to turn the token set `old` into `new`.
+///
+/// Both token lists are treated as sets: the returned lists are sorted and free of
+/// duplicates, and a token present in both inputs appears in neither output.
+///
+/// # Arguments
+/// * `old` - The current tokens of a document.
+/// * `new` - The tokens the document should have.
+///
+/// # Returns
+/// A `(remove, add)` tuple, ready to be passed to `encode_diff`.
+pub fn diff_tokens(old: &[Tok], new: &[Tok]) -> (Vec<Tok>, Vec<Tok>) {
+    let mut old = old.to_vec();
+    let mut new = new.to_vec();
+    old.sort_unstable();
+    old.dedup();
+    new.sort_unstable();
+    new.dedup();
+
+    let mut remove = Vec::new();
+    let mut add = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        match old[i].cmp(&new[j]) {
+            std::cmp::Ordering::Less => {
+                remove.push(old[i]);
+                i += 1;
+            }
+            std::cmp::Ordering::Greater => {
+                add.push(new[j]);
+                j += 1;
+            }
+            std::cmp::Ordering::Equal => {
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    remove.extend_from_slice(&old[i..]);
+    add.extend_from_slice(&new[j..]);
+    (remove, add)
+}
+
+/// Encodes a `Frame` into a byte vector.
+///
+/// This dispatches to the `encode_*` function matching the frame variant.
+pub fn encode(frame: &Frame) -> Vec<u8> {
+    match frame {
+        Frame::Full { doc_id, tokens } => encode_full(*doc_id, tokens),
+        Frame::Diff {
+            doc_id,
+            remove,
+            add,
+        } => encode_diff(*doc_id, remove, add),
+        Frame::Meta {
+            doc_id,
+            content,
+            level,
+            service,
+            extra_services,
+            timestamp,
+        } => encode_meta(
+            *doc_id,
+            content,
+            level.as_deref(),
+            service.as_deref(),
+            extra_services,
+            *timestamp,
+        ),
+        Frame::Delete { doc_id } => encode_delete(*doc_id),
+        Frame::Batch { entries, tokens } => {
+            let mut docs = Vec::with_capacity(entries.len());
+            let mut rest = tokens.as_slice();
+            for &(doc_id, len) in entries {
+                let (head, tail) = rest.split_at(len.min(rest.len()));
+                docs.push((doc_id, head));
+                rest = tail;
+            }
+            encode_batch(&docs)
+        }
+    }
+}
+
+/// The error returned when a frame cannot be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The frame ended before all of its fields were read.
+    Truncated,
+    /// A length or count exceeds a limit in `DecodeOptions`, or the bytes left in
+    /// the frame.
+    TooLarge,
+    /// The frame has a tag this version of the codec doesn't know.
+    BadTag(u8),
+    /// The frame was written with a different format version.
+    BadVersion(u8),
+    /// A variable-length integer is longer than ten bytes or overflows a `u64`.
+    BadVarint,
+    /// A string field is not valid UTF-8.
+    InvalidUtf8,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "frame is truncated"),
+            DecodeError::TooLarge => write!(f, "frame field exceeds its limit"),
+            DecodeError::BadTag(tag) => write!(f, "unknown frame tag {tag}"),
+            DecodeError::BadVersion(v) => write!(f, "unsupported frame version {v}"),
+            DecodeError::BadVarint => write!(f, "malformed varint"),
+            DecodeError::InvalidUtf8 => write!(f, "invalid utf-8"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for io::Error {
+    fn from(e: DecodeError) -> Self {
+        let kind = match e {
+            DecodeError::Truncated => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// Limits applied while decoding, guarding against corrupted or malicious frames
+/// causing huge allocations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// The largest number of tokens accepted in a single token list.
+    pub max_tokens: usize,
+    /// The largest encoded frame accepted, in bytes.
+    pub max_frame_len: usize,
+    /// The largest number of documents accepted in a batch frame.
+    pub max_batch_docs: usize,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            max_tokens: 1 << 20,
+            max_frame_len: MAX_FRAME_LEN,
+            max_batch_docs: MAX_BATCH_DOCS,
+        }
+    }
+}
+
+/// Decodes a byte slice into a `Frame` using the default `DecodeOptions`.
+///
+/// # Arguments
+/// * `bytes` - The byte slice to decode.
+///
+/// # Returns
+/// A `Result` containing the decoded `Frame` or a `DecodeError` if decoding fails.
+pub fn decode(bytes: &[u8]) -> Result<Frame, DecodeError> {
+    decode_with_options(bytes, &DecodeOptions::default())
+}
+
 /// Decodes a byte slice into a `Frame`.
 ///
-/// This function reads the tag from the first byte to determine whether the frame
-/// is a full snapshot or a differential update, then decodes the rest of the bytes
-/// accordingly.
+/// This function checks the format version in the first byte, then reads the tag
+/// from the second byte to determine the kind of frame and decodes the rest of the
+/// bytes accordingly. Every length read from the frame is checked against `opts`
+/// and against the bytes left in the frame before anything is allocated for it.
 ///
 /// # Arguments
 /// * `bytes` - The byte slice to decode.
+/// * `opts` - The limits to enforce.
 ///
 /// # Returns
-/// A `Result` containing the decoded `Frame` or an `io::Error` if decoding fails.
-pub fn decode(mut bytes: &[u8]) -> io::Result<Frame> {
-    if bytes.is_empty() {
-        return Err(io::ErrorKind::UnexpectedEof.into());
+/// A `Result` containing the decoded `Frame` or a `DecodeError` if decoding fails.
+pub fn decode_with_options(mut bytes: &[u8], opts: &DecodeOptions) -> Result<Frame, DecodeError> {
+    let tag = read_header(bytes, opts)?;
+    if tag == TAG_BATCH {
+        let mut entries = Vec::new();
+        let mut tokens = Vec::new();
+        for entry in decode_batch_with_options(bytes, opts)? {
+            let (doc_id, doc_tokens) = entry?;
+            entries.push((doc_id, doc_tokens.len()));
+            tokens.extend_from_slice(&doc_tokens);
+        }
+        return Ok(Frame::Batch { entries, tokens });
     }
 
-    let tag = bytes[0];
-    bytes = &bytes[1..];
-    let doc_id = read_uvar(&mut bytes)?;
+    bytes = &bytes[2..];
+    let doc_id = DocId::from(read_uvar(&mut bytes)?);
 
     match tag {
         TAG_FULL => {
-            let len = read_uvar(&mut bytes)? as usize;
-            let mut tokens = Vec::with_capacity(len);
-            for _ in 0..len {
-                tokens.push(read_uvar(&mut bytes)?);
-            }
+            let tokens = read_tokens(&mut bytes, opts)?;
             Ok(Frame::Full { doc_id, tokens })
         }
         TAG_DIFF => {
-            let rlen = read_uvar(&mut bytes)? as usize;
-            let mut remove = Vec::with_capacity(rlen);
-            for _ in 0..rlen {
-                remove.push(read_uvar(&mut bytes)?);
-            }
-            let alen = read_uvar(&mut bytes)? as usize;
-            let mut add = Vec::with_capacity(alen);
-            for _ in 0..alen {
-                add.push(read_uvar(&mut bytes)?);
-            }
+            let remove = read_tokens(&mut bytes, opts)?;
+            let add = read_tokens(&mut bytes, opts)?;
             Ok(Frame::Diff {
                 doc_id,
                 remove,
                 add,
             })
         }
-        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "bad tag")),
+        TAG_META => {
+            let timestamp = read_uvar(&mut bytes)?;
+            let content = read_str(&mut bytes)?;
+            let level = read_opt_str(&mut bytes)?;
+            let service = read_opt_str(&mut bytes)?;
+            // Each service takes at least its length byte, which `read_count` checks.
+            let count = read_count(&mut bytes, usize::MAX)?;
+            let extra_services = (0..count)
+                .map(|_| read_str(&mut bytes))
+                .collect::<Result<_, _>>()?;
+            Ok(Frame::Meta {
+                doc_id,
+                content,
+                level,
+                service,
+                extra_services,
+                timestamp,
+            })
+        }
+        TAG_DELETE => Ok(Frame::Delete { doc_id }),
+        _ => Err(DecodeError::BadTag(tag)),
+    }
+}
+
+/// Decodes a batch frame written by `encode_batch` using the default
+/// `DecodeOptions`.
+///
+/// The returned iterator decodes one document at a time, so a batch is never
+/// materialized as a whole.
+///
+/// # Arguments
+/// * `bytes` - The encoded batch frame.
+///
+/// # Returns
+/// A `BatchReader` over the documents, or a `DecodeError` if the header is invalid
+/// or the batch is too large.
+pub fn decode_batch(bytes: &[u8]) -> Result<BatchReader<'_>, DecodeError> {
+    decode_batch_with_options(bytes, &DecodeOptions::default())
+}
+
+/// Decodes a batch frame written by `encode_batch`, enforcing the limits in `opts`.
+///
+/// # Arguments
+/// * `bytes` - The encoded batch frame.
+/// * `opts` - The limits to enforce, including the largest number of documents.
+///
+/// # Returns
+/// A `BatchReader` over the documents, or a `DecodeError` if the header is invalid
+/// or the batch holds more than `opts.max_batch_docs` documents.
+pub fn decode_batch_with_options<'a>(
+    mut bytes: &'a [u8],
+    opts: &DecodeOptions,
+) -> Result<BatchReader<'a>, DecodeError> {
+    let tag = read_header(bytes, opts)?;
+    if tag != TAG_BATCH {
+        return Err(DecodeError::BadTag(tag));
+    }
+    bytes = &bytes[2..];
+    let remaining = read_count(&mut bytes, opts.max_batch_docs)?;
+    Ok(BatchReader {
+        bytes,
+        remaining,
+        prev: 0,
+        max_tokens: opts.max_tokens,
+    })
+}
+
+/// An iterator over the documents of a batch frame, returned by `decode_batch`.
+///
+/// Each item is a document ID with its tokens. Iteration stops after the first
+/// error.
+#[derive(Debug)]
+pub struct BatchReader<'a> {
+    /// The undecoded remainder of the batch.
+    bytes: &'a [u8],
+    /// The number of documents left to decode.
+    remaining: usize,
+    /// The ID of the previously decoded document, which the next delta applies to.
+    prev: u64,
+    /// The largest number of tokens accepted for a single document.
+    max_tokens: usize,
+}
+
+impl BatchReader<'_> {
+    /// Decodes the next document, advancing past it.
+    fn read_doc(&mut self) -> Result<(DocId, Vec<Tok>), DecodeError> {
+        let delta = read_uvar(&mut self.bytes)?;
+        let doc_id = self.prev.checked_add(delta).ok_or(DecodeError::TooLarge)?;
+        self.prev = doc_id;
+        let len = read_count(&mut self.bytes, self.max_tokens)?;
+        let mut tokens = Vec::with_capacity(len);
+        for _ in 0..len {
+            tokens.push(read_uvar(&mut self.bytes)?);
+        }
+        Ok((DocId::from(doc_id), tokens))
+    }
+}
+
+impl Iterator for BatchReader<'_> {
+    type Item = Result<(DocId, Vec<Tok>), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let doc = self.read_doc();
+        if doc.is_err() {
+            self.remaining = 0;
+        }
+        Some(doc)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+/// Checks the frame length and format version, returning the tag.
+#[inline]
+fn read_header(bytes: &[u8], opts: &DecodeOptions) -> Result<u8, DecodeError> {
+    if bytes.len() > opts.max_frame_len {
+        return Err(DecodeError::TooLarge);
+    }
+    if bytes.len() < 2 {
+        return Err(DecodeError::Truncated);
+    }
+    if bytes[0] != FORMAT_VERSION {
+        return Err(DecodeError::BadVersion(bytes[0]));
+    }
+    Ok(bytes[1])
+}
+
+/// Reads a token list written as a count followed by the tokens.
+#[inline]
+fn read_tokens(src: &mut &[u8], opts: &DecodeOptions) -> Result<Vec<Tok>, DecodeError> {
+    let len = read_count(src, opts.max_tokens)?;
+    let mut tokens = Vec::with_capacity(len);
+    for _ in 0..len {
+        tokens.push(read_uvar(src)?);
     }
+    Ok(tokens)
 }
 
 /// Writes a `u64` as a variable-length integer to a byte vector.
@@ -160,6 +605,67 @@ fn write_uvar(mut n: u64, out: &mut Vec<u8>) {
     }
 }
 
+/// Writes a string as its variable-length byte length followed by its UTF-8 bytes.
+#[inline]
+fn write_str(s: &str, out: &mut Vec<u8>) {
+    write_uvar(s.len() as u64, out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/// Writes an optional string, using a length of zero for `None` and the byte length
+/// plus one otherwise.
+#[inline]
+fn write_opt_str(s: Option<&str>, out: &mut Vec<u8>) {
+    match s {
+        Some(s) => {
+            write_uvar(s.len() as u64 + 1, out);
+            out.extend_from_slice(s.as_bytes());
+        }
+        None => write_uvar(0, out),
+    }
+}
+
+/// Reads `len` bytes from the slice as a UTF-8 string, advancing the slice.
+#[inline]
+fn read_str_bytes(src: &mut &[u8], len: u64) -> Result<String, DecodeError> {
+    if (src.len() as u64) < len {
+        return Err(DecodeError::Truncated);
+    }
+    let (head, tail) = src.split_at(len as usize);
+    *src = tail;
+    String::from_utf8(head.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+}
+
+/// Reads a string written by `write_str`.
+#[inline]
+fn read_str(src: &mut &[u8]) -> Result<String, DecodeError> {
+    let len = read_uvar(src)?;
+    read_str_bytes(src, len)
+}
+
+/// Reads an optional string written by `write_opt_str`.
+#[inline]
+fn read_opt_str(src: &mut &[u8]) -> Result<Option<String>, DecodeError> {
+    match read_uvar(src)? {
+        0 => Ok(None),
+        len => read_str_bytes(src, len - 1).map(Some),
+    }
+}
+
+/// Reads an element count, rejecting counts above `max` or larger than the bytes
+/// left in the slice.
+///
+/// Every counted element takes at least one byte, so a larger count can only come
+/// from a corrupted or malicious frame and must not be trusted for an allocation.
+#[inline]
+fn read_count(src: &mut &[u8], max: usize) -> Result<usize, DecodeError> {
+    let len = read_uvar(src)?;
+    if len > max as u64 || len > src.len() as u64 {
+        return Err(DecodeError::TooLarge);
+    }
+    Ok(len as usize)
+}
+
 /// Reads a variable-length integer from a byte slice.
 ///
 /// This function decodes a `u64` that was previously written with `write_uvar`.
@@ -167,28 +673,761 @@ fn write_uvar(mut n: u64, out: &mut Vec<u8>) {
 ///
 /// # Arguments
 /// * `src` - A mutable reference to the byte slice to read from. The slice is
-///           advanced past the bytes that are read.
+///   advanced past the bytes that are read.
 ///
 /// # Returns
-/// A `Result` containing the decoded `u64` or an `io::Error` if decoding fails.
+/// A `Result` containing the decoded `u64` or a `DecodeError` if decoding fails.
 #[inline]
-fn read_uvar(src: &mut &[u8]) -> io::Result<u64> {
+fn read_uvar(src: &mut &[u8]) -> Result<u64, DecodeError> {
     let mut shift = 0;
     let mut acc = 0u64;
     for _ in 0..10 {
         if src.is_empty() {
-            return Err(io::ErrorKind::UnexpectedEof.into());
+            return Err(DecodeError::Truncated);
         }
         let b = src[0];
         *src = &src[1..];
+        if shift == 63 && b & 0x7E != 0 {
+            return Err(DecodeError::BadVarint);
+        }
         acc |= ((b & 0x7F) as u64) << shift;
         if b & 0x80 == 0 {
             return Ok(acc);
         }
         shift += 7;
     }
-    Err(io::Error::new(
-        io::ErrorKind::InvalidData,
-        "varint too long",
-    ))
-}
\ No newline at end of file
+    Err(DecodeError::BadVarint)
+}
+
+/// The largest frame payload accepted by `FrameReader`, guarding against a corrupted
+/// length prefix causing a huge allocation.
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Writes a stream of length-prefixed frames to an underlying writer.
+///
+/// Each frame is written as a varint payload length followed by the encoded frame.
+/// When checksums are enabled, a little-endian CRC32 of the payload follows it, and
+/// the stream must be read by a `FrameReader` created with `with_checksum`.
+#[derive(Debug)]
+pub struct FrameWriter<W: Write> {
+    /// The destination of the encoded frames.
+    inner: W,
+    /// Whether a CRC32 is appended to every frame.
+    checksum: bool,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// Creates a `FrameWriter` that writes frames without checksums.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            checksum: false,
+        }
+    }
+
+    /// Creates a `FrameWriter` that appends a CRC32 to every frame.
+    pub fn with_checksum(inner: W) -> Self {
+        Self {
+            inner,
+            checksum: true,
+        }
+    }
+
+    /// Encodes and writes a single frame.
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write_encoded(&encode(frame))
+    }
+
+    /// Writes a frame that has already been encoded with one of the `encode_*` functions.
+    pub fn write_encoded(&mut self, payload: &[u8]) -> io::Result<()> {
+        let mut header = Vec::with_capacity(10);
+        write_uvar(payload.len() as u64, &mut header);
+        self.inner.write_all(&header)?;
+        self.inner.write_all(payload)?;
+        if self.checksum {
+            self.inner.write_all(&crc32(payload).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Consumes the `FrameWriter`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads a stream of length-prefixed frames written by a `FrameWriter`.
+///
+/// The reader is an iterator over `io::Result<Frame>`. The stream ending cleanly
+/// between two frames ends the iteration, while the stream ending inside a frame
+/// yields an `UnexpectedEof` error. Iteration stops after the first error.
+#[derive(Debug)]
+pub struct FrameReader<R: Read> {
+    /// The source of the encoded frames.
+    inner: R,
+    /// Whether every frame is followed by a CRC32 of its payload.
+    checksum: bool,
+    /// Set once the stream has ended or produced an error.
+    done: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Creates a `FrameReader` for a stream written without checksums.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            checksum: false,
+            done: false,
+        }
+    }
+
+    /// Creates a `FrameReader` for a stream written with `FrameWriter::with_checksum`.
+    pub fn with_checksum(inner: R) -> Self {
+        Self {
+            inner,
+            checksum: true,
+            done: false,
+        }
+    }
+
+    /// Consumes the `FrameReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the next frame, returning `Ok(None)` at a clean end of stream.
+    pub fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let Some(len) = self.read_len()? else {
+            return Ok(None);
+        };
+        if len > MAX_FRAME_LEN as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame too large",
+            ));
+        }
+
+        let mut payload = vec![0u8; len as usize];
+        self.inner.read_exact(&mut payload)?;
+        if self.checksum {
+            let mut crc = [0u8; 4];
+            self.inner.read_exact(&mut crc)?;
+            if u32::from_le_bytes(crc) != crc32(&payload) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "frame checksum mismatch",
+                ));
+            }
+        }
+        Ok(Some(decode(&payload)?))
+    }
+
+    /// Reads the varint length prefix of the next frame.
+    ///
+    /// Returns `Ok(None)` if the stream ends before the first byte of the prefix.
+    fn read_len(&mut self) -> io::Result<Option<u64>> {
+        let mut shift = 0;
+        let mut acc = 0u64;
+        for i in 0..10 {
+            let mut byte = [0u8; 1];
+            loop {
+                match self.inner.read(&mut byte) {
+                    Ok(0) if i == 0 => return Ok(None),
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(_) => break,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            acc |= ((byte[0] & 0x7F) as u64) << shift;
+            if byte[0] & 0x80 == 0 {
+                return Ok(Some(acc));
+            }
+            shift += 7;
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "varint too long",
+        ))
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_frame() {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// The magic bytes at the start of every backup file.
+pub const BACKUP_MAGIC: [u8; 4] = *b"BGBK";
+
+/// The kind of a backup file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupKind {
+    /// Every document in the database.
+    Snapshot,
+    /// The documents changed or deleted since an earlier generation.
+    Incremental,
+}
+
+/// The fixed-size header of a backup file, followed by a checksummed frame stream.
+///
+/// The header is structured as follows:
+/// - `BACKUP_MAGIC` (4 bytes)
+/// - `FORMAT_VERSION` (1 byte)
+/// - the kind (1 byte: 0 for a snapshot, 1 for an incremental backup)
+/// - `doc_count`, `since`, `generation` and `next_doc_id` (little-endian u64 each)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupHeader {
+    /// Whether the file is a snapshot or an incremental backup.
+    pub kind: BackupKind,
+    /// The number of documents described by the file, each as a `Full` frame
+    /// followed by a `Meta` frame, or as a single `Delete` frame.
+    pub doc_count: u64,
+    /// The generation the backup starts after. Always zero for a snapshot.
+    pub since: u64,
+    /// The generation of the database when the backup was written.
+    pub generation: u64,
+    /// The ID the database would have given its next document when the backup was
+    /// written, so a restore never hands out the ID of a document it has seen,
+    /// including deleted ones.
+    pub next_doc_id: DocId,
+}
+
+impl BackupHeader {
+    /// The encoded length of a header, in bytes.
+    pub const LEN: usize = 38;
+
+    /// Writes the header to `w`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut buf = [0u8; Self::LEN];
+        buf[..4].copy_from_slice(&BACKUP_MAGIC);
+        buf[4] = FORMAT_VERSION;
+        buf[5] = match self.kind {
+            BackupKind::Snapshot => 0,
+            BackupKind::Incremental => 1,
+        };
+        buf[6..14].copy_from_slice(&self.doc_count.to_le_bytes());
+        buf[14..22].copy_from_slice(&self.since.to_le_bytes());
+        buf[22..30].copy_from_slice(&self.generation.to_le_bytes());
+        buf[30..38].copy_from_slice(&self.next_doc_id.get().to_le_bytes());
+        w.write_all(&buf)
+    }
+
+    /// Reads a header from `r`, rejecting files that are not backups or were
+    /// written with a different format version.
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut buf = [0u8; Self::LEN];
+        r.read_exact(&mut buf)?;
+        if buf[..4] != BACKUP_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a backup file",
+            ));
+        }
+        if buf[4] != FORMAT_VERSION {
+            return Err(DecodeError::BadVersion(buf[4]).into());
+        }
+        let kind = match buf[5] {
+            0 => BackupKind::Snapshot,
+            1 => BackupKind::Incremental,
+            other => return Err(DecodeError::BadTag(other).into()),
+        };
+        let read_u64 = |at: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&buf[at..at + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        Ok(Self {
+            kind,
+            doc_count: read_u64(6),
+            since: read_u64(14),
+            generation: read_u64(22),
+            next_doc_id: DocId(read_u64(30)),
+        })
+    }
+}
+
+/// The lookup table for the reflected CRC-32 (IEEE 802.3) polynomial.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Computes the CRC-32 (IEEE 802.3) checksum of a byte slice.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc = CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns 10k documents with one to eight tokens each, not in ID order.
+    fn sample_docs() -> Vec<(DocId, Vec<Tok>)> {
+        (0..10_000u64)
+            .rev()
+            .map(|i| {
+                let tokens = (0..1 + i % 8).map(|t| i * 31 + t).collect();
+                (DocId(i * 3 + 1), tokens)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn batch_round_trips_10k_docs() {
+        let docs = sample_docs();
+        let refs: Vec<(DocId, &[Tok])> = docs.iter().map(|(id, t)| (*id, t.as_slice())).collect();
+        let bytes = encode_batch(&refs);
+
+        let mut expected = docs.clone();
+        expected.sort_by_key(|(id, _)| *id);
+        let decoded: Vec<(DocId, Vec<Tok>)> = decode_batch(&bytes)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(decoded, expected);
+
+        let Frame::Batch { entries, tokens } = decode(&bytes).unwrap() else {
+            panic!("not a batch frame");
+        };
+        assert_eq!(entries.len(), 10_000);
+        assert_eq!(
+            tokens.len(),
+            expected.iter().map(|(_, t)| t.len()).sum::<usize>()
+        );
+        assert_eq!(encode(&Frame::Batch { entries, tokens }), bytes);
+    }
+
+    #[test]
+    fn batch_is_smaller_than_full_frames() {
+        let docs = sample_docs();
+        let refs: Vec<(DocId, &[Tok])> = docs.iter().map(|(id, t)| (*id, t.as_slice())).collect();
+        let batch = encode_batch(&refs).len();
+        let full: usize = refs.iter().map(|&(id, t)| encode_full(id, t).len()).sum();
+        assert!(batch < full, "batch {batch} bytes, full frames {full}");
+    }
+
+    #[test]
+    fn oversized_lengths_are_rejected() {
+        let mut huge_batch = vec![FORMAT_VERSION, TAG_BATCH];
+        write_uvar(u64::MAX, &mut huge_batch);
+        assert_eq!(decode_batch(&huge_batch).err(), Some(DecodeError::TooLarge));
+
+        let mut huge_tokens = vec![FORMAT_VERSION, TAG_FULL];
+        write_uvar(1, &mut huge_tokens);
+        write_uvar(1 << 40, &mut huge_tokens);
+        assert_eq!(decode(&huge_tokens), Err(DecodeError::TooLarge));
+
+        let opts = DecodeOptions {
+            max_batch_docs: 2,
+            ..DecodeOptions::default()
+        };
+        let three = encode_batch(&[(DocId(1), &[][..]), (DocId(2), &[]), (DocId(3), &[])]);
+        assert_eq!(
+            decode_batch_with_options(&three, &opts).err(),
+            Some(DecodeError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn corrupted_frames_never_panic() {
+        let docs = sample_docs();
+        let refs: Vec<(DocId, &[Tok])> = docs[..64]
+            .iter()
+            .map(|(id, t)| (*id, t.as_slice()))
+            .collect();
+        let frames = [
+            encode_batch(&refs),
+            encode_full(DocId(7), &[1, 2, 3]),
+            encode_diff(DocId(7), &[1], &[4, 5]),
+        ];
+        // A fixed xorshift sequence keeps failures reproducible.
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2_000 {
+            let mut bytes = frames[next() as usize % frames.len()].clone();
+            for _ in 0..1 + next() % 4 {
+                let i = 1 + next() as usize % (bytes.len() - 1);
+                bytes[i] = next() as u8;
+            }
+            bytes.truncate(2 + next() as usize % bytes.len());
+            let _ = decode(&bytes);
+            if let Ok(reader) = decode_batch(&bytes) {
+                reader.for_each(drop);
+            }
+        }
+    }
+
+    /// Returns 5k alternating `Full` and `Diff` frames.
+    fn mixed_frames() -> Vec<Frame> {
+        (0..5_000u64)
+            .map(|i| {
+                let doc_id = DocId(i / 2 + 1);
+                if i % 2 == 0 {
+                    Frame::Full {
+                        doc_id,
+                        tokens: (0..i % 12).map(|t| t << (i % 50)).collect(),
+                    }
+                } else {
+                    Frame::Diff {
+                        doc_id,
+                        remove: (0..i % 3).collect(),
+                        add: vec![i, u64::MAX - i],
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Writes `frames` to a buffer, with checksums if `checksum` is set.
+    fn write_stream(frames: &[Frame], checksum: bool) -> Vec<u8> {
+        let mut writer = if checksum {
+            FrameWriter::with_checksum(Vec::new())
+        } else {
+            FrameWriter::new(Vec::new())
+        };
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.flush().unwrap();
+        writer.into_inner()
+    }
+
+    /// A reader that returns at most one byte per call.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn frame_streams_round_trip() {
+        let frames = mixed_frames();
+        for checksum in [false, true] {
+            let bytes = write_stream(&frames, checksum);
+            let cursor = io::Cursor::new(bytes.as_slice());
+            let reader = if checksum {
+                FrameReader::with_checksum(cursor)
+            } else {
+                FrameReader::new(cursor)
+            };
+            let decoded: Vec<Frame> = reader.collect::<io::Result<_>>().unwrap();
+            assert_eq!(decoded, frames);
+
+            let trickled = if checksum {
+                FrameReader::with_checksum(Trickle(&bytes))
+            } else {
+                FrameReader::new(Trickle(&bytes))
+            };
+            assert_eq!(trickled.count(), frames.len());
+        }
+    }
+
+    #[test]
+    fn frame_streams_tell_clean_and_truncated_ends_apart() {
+        let frames = mixed_frames();
+        let bytes = write_stream(&frames[..3], true);
+
+        let mut reader = FrameReader::with_checksum(io::Cursor::new(&bytes[..0]));
+        assert!(reader.read_frame().unwrap().is_none());
+
+        let first = write_stream(&frames[..1], true).len();
+        for cut in 1..bytes.len() {
+            let mut reader = FrameReader::with_checksum(io::Cursor::new(&bytes[..cut]));
+            let results: Vec<_> = reader.by_ref().collect();
+            let (last, ok) = results.split_last().unwrap();
+            assert!(ok.iter().all(Result::is_ok));
+            if cut == first || cut == write_stream(&frames[..2], true).len() {
+                assert!(last.is_ok(), "cut at a frame boundary, {cut}");
+            } else {
+                let err = last.as_ref().unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof, "cut at {cut}");
+            }
+            assert!(reader.next().is_none());
+        }
+    }
+
+    #[test]
+    fn flipped_bytes_fail_the_checksum() {
+        let frames = mixed_frames();
+        let bytes = write_stream(&frames[..64], true);
+        let first = write_stream(&frames[..1], true).len();
+        // Skip the length prefix, which would only misalign the stream.
+        for i in 1..first {
+            for bit in [0x01, 0x80] {
+                let mut corrupted = bytes.clone();
+                corrupted[i] ^= bit;
+                let mut reader = FrameReader::with_checksum(corrupted.as_slice());
+                let err = reader.next().unwrap().unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData, "byte {i}");
+                assert!(reader.next().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_frame_lengths_are_rejected() {
+        let mut bytes = Vec::new();
+        write_uvar(MAX_FRAME_LEN as u64 + 1, &mut bytes);
+        let err = FrameReader::new(bytes.as_slice())
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn meta_and_delete_frames_round_trip() {
+        let meta = Frame::Meta {
+            doc_id: DocId(300),
+            content: "déjà vu: ログ failed".to_string(),
+            level: Some("ERROR".to_string()),
+            service: None,
+            extra_services: vec!["api".to_string(), String::new()],
+            timestamp: 1_700_000_000,
+        };
+        let bytes = encode(&meta);
+        assert_eq!(
+            bytes,
+            encode_meta(
+                DocId(300),
+                "déjà vu: ログ failed",
+                Some("ERROR"),
+                None,
+                &["api".to_string(), String::new()],
+                1_700_000_000
+            )
+        );
+        assert_eq!(decode(&bytes), Ok(meta));
+
+        let empty = Frame::Meta {
+            doc_id: DocId(0),
+            content: String::new(),
+            level: Some(String::new()),
+            service: Some(String::new()),
+            extra_services: Vec::new(),
+            timestamp: 0,
+        };
+        assert_eq!(decode(&encode(&empty)), Ok(empty));
+
+        let delete = Frame::Delete {
+            doc_id: DocId(u64::MAX),
+        };
+        assert_eq!(encode(&delete), encode_delete(DocId(u64::MAX)));
+        assert_eq!(decode(&encode(&delete)), Ok(delete));
+    }
+
+    #[test]
+    fn meta_frames_with_invalid_utf8_are_rejected() {
+        let mut bytes = encode_meta(DocId(1), "abc", None, None, &[], 5);
+        let at = bytes.len() - 6;
+        assert_eq!(&bytes[at..at + 3], b"abc");
+        bytes[at + 1] = 0xFF;
+        assert_eq!(decode(&bytes), Err(DecodeError::InvalidUtf8));
+
+        let mut level = encode_meta(DocId(1), "", Some("é"), None, &[], 5);
+        let last = level.len() - 4;
+        level[last] = 0xC3;
+        level[last + 1] = 0x28;
+        assert_eq!(decode(&level), Err(DecodeError::InvalidUtf8));
+    }
+
+    #[test]
+    fn frames_from_other_versions_are_rejected() {
+        for mut bytes in [
+            encode_full(DocId(1), &[1]),
+            encode_meta(DocId(1), "x", None, None, &[], 0),
+            encode_delete(DocId(1)),
+        ] {
+            bytes[0] = FORMAT_VERSION - 1;
+            assert_eq!(
+                decode(&bytes),
+                Err(DecodeError::BadVersion(FORMAT_VERSION - 1))
+            );
+            bytes[0] = FORMAT_VERSION;
+            bytes[1] = 9;
+            assert_eq!(decode(&bytes), Err(DecodeError::BadTag(9)));
+        }
+        let truncated = encode_meta(DocId(1), "some content", None, None, &[], 0);
+        assert_eq!(
+            decode(&truncated[..truncated.len() - 4]),
+            Err(DecodeError::Truncated)
+        );
+    }
+
+    #[test]
+    fn random_buffers_never_allocate_past_their_length() {
+        let mut state = 0xD1B5_4A32_D192_ED03u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let opts = DecodeOptions {
+            max_tokens: 64,
+            ..DecodeOptions::default()
+        };
+        for _ in 0..20_000 {
+            let len = next() as usize % 48;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            if len >= 2 && next() % 2 == 0 {
+                bytes[0] = FORMAT_VERSION;
+                bytes[1] = (next() % 6) as u8;
+            }
+            // Every token and string takes at least one byte of the frame.
+            match decode_with_options(&bytes, &opts) {
+                Ok(Frame::Full { tokens, .. }) => assert!(tokens.capacity() <= len),
+                Ok(Frame::Diff { remove, add, .. }) => {
+                    assert!(remove.capacity() + add.capacity() <= len)
+                }
+                Ok(Frame::Meta {
+                    content,
+                    extra_services,
+                    ..
+                }) => assert!(content.capacity() + extra_services.capacity() <= len),
+                Ok(Frame::Batch { entries, tokens }) => {
+                    assert!(entries.len() + tokens.len() <= len)
+                }
+                Ok(Frame::Delete { .. }) | Err(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn crafted_lengths_get_the_right_errors() {
+        // Nine bytes claiming 2^60 tokens.
+        let mut bytes = vec![FORMAT_VERSION, TAG_FULL, 1];
+        write_uvar(1 << 60, &mut bytes);
+        assert!(bytes.len() <= 12);
+        assert_eq!(decode(&bytes), Err(DecodeError::TooLarge));
+
+        // More tokens than bytes left.
+        assert_eq!(
+            decode(&[FORMAT_VERSION, TAG_DIFF, 1, 5, 1, 2]),
+            Err(DecodeError::TooLarge)
+        );
+        // Over the configured limit, though the bytes are there.
+        let opts = DecodeOptions {
+            max_tokens: 2,
+            ..DecodeOptions::default()
+        };
+        let three = encode_full(DocId(1), &[1, 2, 3]);
+        assert_eq!(
+            decode_with_options(&three, &opts),
+            Err(DecodeError::TooLarge)
+        );
+        let small_frames = DecodeOptions {
+            max_frame_len: three.len() - 1,
+            ..DecodeOptions::default()
+        };
+        assert_eq!(
+            decode_with_options(&three, &small_frames),
+            Err(DecodeError::TooLarge)
+        );
+
+        // A string longer than the frame.
+        let mut meta = vec![FORMAT_VERSION, TAG_META, 1, 0];
+        write_uvar(1 << 40, &mut meta);
+        meta.extend_from_slice(b"abc");
+        assert_eq!(decode(&meta), Err(DecodeError::Truncated));
+
+        assert_eq!(decode(&[]), Err(DecodeError::Truncated));
+        assert_eq!(decode(&[FORMAT_VERSION]), Err(DecodeError::Truncated));
+        assert_eq!(
+            decode(&[FORMAT_VERSION, TAG_FULL]),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(
+            decode(&[FORMAT_VERSION, 200, 1]),
+            Err(DecodeError::BadTag(200))
+        );
+        assert_eq!(
+            decode(&[
+                FORMAT_VERSION,
+                TAG_DELETE,
+                0xFF,
+                0xFF,
+                0xFF,
+                0xFF,
+                0xFF,
+                0xFF,
+                0xFF,
+                0xFF,
+                0xFF,
+                0x7F
+            ]),
+            Err(DecodeError::BadVarint)
+        );
+    }
+
+    #[test]
+    fn diff_tokens_treats_lists_as_sets() {
+        assert_eq!(diff_tokens(&[3, 1, 2], &[2, 3, 1, 1]), (vec![], vec![]));
+        assert_eq!(
+            diff_tokens(&[5, 1, 1, 9], &[9, 7, 2, 7]),
+            (vec![1, 5], vec![2, 7])
+        );
+        assert_eq!(diff_tokens(&[], &[4, 4]), (vec![], vec![4]));
+        assert_eq!(
+            diff_tokens(&[u64::MAX, 0], &[]),
+            (vec![0, u64::MAX], vec![])
+        );
+    }
+}
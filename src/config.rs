@@ -10,6 +10,40 @@ use crate::utils::buggu_hash_set::BugguHashSet;
 use std::fs;
 use std::io;
 
+/// The order in which `LogDB` evicts postings once it holds more than
+/// `LogConfig::max_postings` of them.
+///
+/// Evicting a token's posting drops it from every document that contains it, so
+/// those documents no longer match queries for it. The documents themselves, and
+/// their other tokens, are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Never evict, leaving `max_postings` unenforced.
+    #[default]
+    Never,
+    /// Evict the tokens first indexed longest ago.
+    Fifo,
+    /// Evict the tokens a query last looked up longest ago, or that were indexed
+    /// longest ago if no query has looked them up since.
+    Lru,
+}
+
+/// How a query word such as `tag:db`, whose field is none of the ones the query
+/// syntax knows (`level`, `service`, `has`, `timestamp` and the like), is matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownFieldMode {
+    /// Reject the query with `QueryError::UnknownField`. The lenient query methods
+    /// match nothing. Escape the colon, as in `http\://`, to search the word as is.
+    Error,
+    /// Match the `field:value` word itself, as indexed from content, JSON keys and
+    /// `upsert_fields`, so `tag:db` finds documents tagged `db`.
+    #[default]
+    AsExtractedField,
+    /// Match documents containing both the field name and the value as words, so
+    /// `tag:db` is `tag AND db`.
+    AsText,
+}
+
 /// Defines the configuration for log parsing, indexing, and querying.
 ///
 /// This struct holds all the settings that control how LogDB operates. It includes
@@ -18,10 +52,15 @@ use std::io;
 /// extraction.
 #[derive(Debug, Clone)]
 pub struct LogConfig {
-    /// The maximum number of postings to keep in memory before flushing to disk.
-    /// This helps control memory usage for large-scale log indexing.
+    /// The maximum number of postings to keep in memory. Once there are more, the
+    /// oldest are evicted, as chosen by `eviction_policy`, until a tenth of the limit
+    /// is free again. This helps control memory usage for large-scale log indexing.
     pub max_postings: usize,
 
+    /// Which postings to evict once there are more than `max_postings`. The default,
+    /// `EvictionPolicy::Never`, keeps every posting.
+    pub eviction_policy: EvictionPolicy,
+
     /// The duration in seconds after which a document is considered stale and may
     /// be eligible for cleanup. This is useful for managing the lifecycle of log entries.
     pub stale_secs: u64,
@@ -42,9 +81,90 @@ pub struct LogConfig {
     /// `enable_ngrams` is true.
     pub max_ngram_size: usize,
 
+    /// A flag to enable or disable phonetic indexing. When enabled, the Soundex code
+    /// of every word is indexed, so a `sounds:` query such as `sounds:smith` also
+    /// matches `smyth`. Off by default, since it costs memory and ingestion time;
+    /// without it, `sounds:` queries match nothing.
+    pub enable_phonetic: bool,
+
     /// A flag to enable or disable the extraction of common patterns, such as IP
     /// addresses, error codes, and other structured data from log messages.
     pub enable_patterns: bool,
+
+    /// A flag to enable or disable indexing runs of whitespace as tokens. They are
+    /// never searched for, so leaving this off roughly halves the tokens stored per
+    /// document.
+    pub index_whitespace: bool,
+
+    /// The minimum length, in characters, of the words and fields indexed from
+    /// content. Shorter ones are dropped at ingestion, and querying for them matches
+    /// nothing. The default of 0 indexes every word.
+    pub min_token_len: usize,
+
+    /// The most clauses, such as terms and field filters, a query may have. A query
+    /// with more is rejected with `QueryError::TooComplex`.
+    pub max_query_clauses: usize,
+
+    /// The deepest a query may nest groups such as `And`, `Or` and `Not`. A query
+    /// nested deeper is rejected with `QueryError::TooComplex`.
+    pub max_query_depth: usize,
+
+    /// How a query word with a field the query syntax doesn't know, such as `tag:db`,
+    /// is matched. The default, `UnknownFieldMode::AsExtractedField`, matches the
+    /// `tag:db` word itself. Applies to query strings; structured `field` nodes always
+    /// match the word.
+    pub unknown_field_mode: UnknownFieldMode,
+
+    /// Synonym rings: a query for a bare word in a ring also matches documents
+    /// containing any other word in it. Expansion happens at query time only, so the
+    /// index is unaffected and rings can change without reindexing.
+    pub synonyms: Vec<Vec<String>>,
+
+    /// How many of the most recent queries the slow-query log keeps for
+    /// `LogDB::slow_queries`. Set it to 0 to stop recording them; the timing summary
+    /// is kept regardless.
+    pub query_log_capacity: usize,
+
+    /// How many parsed queries `LogDB` caches, so a query string run again skips
+    /// parsing. Set it to 0 to parse every query afresh.
+    pub query_cache_capacity: usize,
+
+    /// The number of buckets each of the index's hash tables starts with. The tables
+    /// double as they fill, so this only needs raising to skip the early doublings
+    /// when a large load is expected; `LogDB::with_capacity` sizes them for a known
+    /// document count.
+    pub initial_capacity: usize,
+
+    /// The most words `LogDB::term_dict` keeps, evicting the least recently indexed
+    /// ones beyond it, or no limit with `None`.
+    pub term_dict_capacity: Option<usize>,
+
+    /// The most bytes of content `LogDB` stores and tokenizes per document, or no limit
+    /// with 0. Longer content is cut at a character boundary and ends in the word
+    /// `truncated:true`, which the limit leaves room for, so a `truncated:true` query
+    /// finds the cut documents.
+    pub max_content_bytes: usize,
+
+    /// The most bytes of content `LogDB` stores per document, or no limit with 0.
+    /// Unlike `max_content_bytes`, this only limits what is stored: the whole content,
+    /// up to `max_content_bytes`, is still tokenized, so a word past the cap still
+    /// matches a query for it. The stored content is cut at a character boundary and
+    /// ends in `…`, which the cap leaves room for, and is what `get_content` and
+    /// `query_content` return. Prefix, fuzzy and phrase queries and the term
+    /// statistics cover the whole content too, while a case-sensitive search only
+    /// checks the case of words in the stored part, and `has:` queries, snippets
+    /// and highlighting only see the stored part.
+    pub max_content_len: usize,
+
+    /// Whether to remove ANSI escape sequences, such as terminal color codes, and
+    /// control characters other than newlines and tabs from content before it is
+    /// stored and tokenized. On by default.
+    pub strip_ansi: bool,
+
+    /// Whether to also keep the content of each document as it was given, before
+    /// `strip_ansi` cleaned it, for `LogDB::get_doc_raw`. Off by default, since it
+    /// stores the content twice for every document it changes.
+    pub keep_raw_content: bool,
 }
 
 impl Default for LogConfig {
@@ -54,7 +174,7 @@ impl Default for LogConfig {
     /// WARN, ERROR, FATAL) with corresponding priorities. It also sets reasonable
     /// limits for in-memory postings and stale document cleanup.
     fn default() -> Self {
-        let mut log_levels = BugguHashSet::new(4096);
+        let mut log_levels = BugguHashSet::growable(16);
 
         // Pre-populate with standard log levels using their hashes for fast lookups.
         // This offers a 40x speed improvement over string-based comparisons.
@@ -67,12 +187,28 @@ impl Default for LogConfig {
 
         Self {
             max_postings: 32_000,
+            eviction_policy: EvictionPolicy::Never,
             stale_secs: 3600, // 1 hour
             log_levels,
-            services: BugguHashSet::new(4096),
+            services: BugguHashSet::growable(16),
             enable_ngrams: true,
             max_ngram_size: 3,
+            enable_phonetic: false,
             enable_patterns: true,
+            index_whitespace: false,
+            min_token_len: 0,
+            max_query_clauses: 1024,
+            max_query_depth: 32,
+            unknown_field_mode: UnknownFieldMode::AsExtractedField,
+            synonyms: Vec::new(),
+            query_log_capacity: 256,
+            query_cache_capacity: 128,
+            initial_capacity: 256,
+            term_dict_capacity: None,
+            max_content_bytes: 0,
+            max_content_len: 0,
+            strip_ansi: true,
+            keep_raw_content: false,
         }
     }
 }
@@ -181,6 +317,28 @@ impl LogConfig {
         self.log_levels.insert(level_hash, priority);
     }
 
+    /// Adds a synonym ring, so that a query for any of its words matches all of them.
+    ///
+    /// # Arguments
+    /// * `ring` - The words to treat as synonyms of each other, e.g. `["db", "database"]`.
+    pub fn with_synonyms(mut self, ring: &[&str]) -> Self {
+        self.synonyms
+            .push(ring.iter().map(|w| w.to_string()).collect());
+        self
+    }
+
+    /// Compiles the synonym rings into a map from each word's hash to the index of
+    /// its ring in `synonyms`. A word in several rings maps to the last of them.
+    pub fn synonym_lookup(&self) -> BugguHashSet<u64, usize> {
+        let mut lookup = BugguHashSet::new(64);
+        for (i, ring) in self.synonyms.iter().enumerate() {
+            for word in ring {
+                lookup.insert(lightning_hash_str(word), i);
+            }
+        }
+        lookup
+    }
+
     /// Checks if a log level with the given priority exists.
     ///
     /// # Arguments
@@ -208,3 +366,60 @@ impl LogConfig {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synonym_lookup_maps_every_word_to_its_ring() {
+        let config = LogConfig::default()
+            .with_synonyms(&["db", "database"])
+            .with_synonyms(&["auth", "login", "signin"]);
+        let lookup = config.synonym_lookup();
+        assert_eq!(lookup.len(), 5);
+        for (word, ring) in [("db", 0), ("database", 0), ("login", 1), ("signin", 1)] {
+            assert_eq!(lookup.get(&lightning_hash_str(word)), Some(&ring), "{word}");
+        }
+        assert_eq!(lookup.get(&lightning_hash_str("cache")), None);
+
+        // A word in two rings belongs to the later one.
+        let config = config.with_synonyms(&["db", "store"]);
+        assert_eq!(
+            config.synonym_lookup().get(&lightning_hash_str("db")),
+            Some(&2)
+        );
+    }
+
+    #[test]
+    fn level_and_service_tables_grow_past_their_first_size() {
+        let mut config = LogConfig::default();
+        // Letters from `j` on hash to two digits each, so these names never collide.
+        let names: Vec<String> = (0..60u8)
+            .map(|i| {
+                [b'j' + i / 17, b'j' + i % 17]
+                    .iter()
+                    .map(|&b| b as char)
+                    .collect()
+            })
+            .collect();
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(config.register_service(name), i as u8);
+            config.add_log_level(&name.to_uppercase(), 10 + i as u8);
+        }
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(config.register_service(name), i as u8, "{name}");
+            assert_eq!(config.service_id(name), i as u8);
+            assert_eq!(
+                config.log_level_priority(&name.to_uppercase()),
+                10 + i as u8
+            );
+        }
+        assert_eq!(config.service_hashes().len(), 60);
+        assert_eq!(config.log_level_hashes().len(), 66);
+        assert!(config.has_log_level_priority(69));
+        assert!(!config.has_log_level_priority(70));
+        assert_eq!(config.service_id("unregistered"), 0);
+        assert_eq!(config.log_level_priority("UNCONFIGURED"), 2);
+    }
+}
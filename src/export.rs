@@ -0,0 +1,216 @@
+//! # Exports
+//!
+//! This module writes documents out in the formats accepted by `LogDB::export`:
+//! newline-delimited JSON, which `LogDB::upsert_json` reads back, and CSV with
+//! RFC 4180 quoting. Rows are written one at a time, so an export never holds more
+//! than a single document's text in memory.
+
+use crate::logdb::MetaEntry;
+use crate::types::DocId;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// The header row of a CSV export, naming its columns.
+pub const CSV_HEADER: &str = "id,timestamp,level,service,content";
+
+/// The format `LogDB::export` writes documents in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// One JSON object per line, with the keys `id`, `timestamp`, `level`, `service`
+    /// and `content`. A missing level or service is written as `null`.
+    Ndjson,
+    /// A header row followed by one row per document, with the columns of
+    /// `CSV_HEADER`. Fields holding a comma, a quote or a line break are quoted, and
+    /// rows end with CRLF, as in RFC 4180. A missing level or service is left empty.
+    Csv,
+}
+
+impl ExportFormat {
+    /// Returns the lowercase name of the format, as accepted by `from_str`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    /// Writes whatever precedes the first row: the header row for CSV, and nothing
+    /// for NDJSON.
+    pub(crate) fn write_header(self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            ExportFormat::Ndjson => Ok(()),
+            ExportFormat::Csv => write!(out, "{CSV_HEADER}\r\n"),
+        }
+    }
+
+    /// Writes a document as a single row.
+    pub(crate) fn write_row(
+        self,
+        out: &mut impl Write,
+        id: DocId,
+        entry: &MetaEntry,
+    ) -> io::Result<()> {
+        match self {
+            ExportFormat::Ndjson => {
+                write!(out, "{{\"id\":{},\"timestamp\":{}", id, entry.timestamp())?;
+                out.write_all(b",\"level\":")?;
+                write_json_opt(out, entry.level())?;
+                out.write_all(b",\"service\":")?;
+                write_json_opt(out, entry.service())?;
+                out.write_all(b",\"content\":")?;
+                write_json_str(out, entry.content())?;
+                out.write_all(b"}\n")
+            }
+            ExportFormat::Csv => {
+                write!(out, "{},{},", id, entry.timestamp())?;
+                write_csv_field(out, entry.level().unwrap_or(""))?;
+                out.write_all(b",")?;
+                write_csv_field(out, entry.service().unwrap_or(""))?;
+                out.write_all(b",")?;
+                write_csv_field(out, entry.content())?;
+                out.write_all(b"\r\n")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The error returned when parsing an unknown `ExportFormat` name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseExportFormatError {
+    input: String,
+}
+
+impl fmt::Display for ParseExportFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown export format {:?}, expected \"ndjson\" or \"csv\"",
+            self.input
+        )
+    }
+}
+
+impl std::error::Error for ParseExportFormatError {}
+
+impl FromStr for ExportFormat {
+    type Err = ParseExportFormatError;
+
+    /// Parses `ndjson` or `csv`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("ndjson") {
+            Ok(ExportFormat::Ndjson)
+        } else if s.eq_ignore_ascii_case("csv") {
+            Ok(ExportFormat::Csv)
+        } else {
+            Err(ParseExportFormatError {
+                input: s.to_string(),
+            })
+        }
+    }
+}
+
+/// Writes a string as a JSON string literal, or `null` for `None`.
+fn write_json_opt(out: &mut impl Write, s: Option<&str>) -> io::Result<()> {
+    match s {
+        Some(s) => write_json_str(out, s),
+        None => out.write_all(b"null"),
+    }
+}
+
+/// Writes a string as a JSON string literal, escaping quotes, backslashes and control
+/// characters.
+fn write_json_str(out: &mut impl Write, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escape: &[u8] = match c {
+            '"' => b"\\\"",
+            '\\' => b"\\\\",
+            '\n' => b"\\n",
+            '\r' => b"\\r",
+            '\t' => b"\\t",
+            c if c < ' ' => b"",
+            _ => continue,
+        };
+        out.write_all(&s.as_bytes()[start..i])?;
+        if escape.is_empty() {
+            write!(out, "\\u{:04x}", c as u32)?;
+        } else {
+            out.write_all(escape)?;
+        }
+        start = i + c.len_utf8();
+    }
+    out.write_all(&s.as_bytes()[start..])?;
+    out.write_all(b"\"")
+}
+
+/// Writes a CSV field, quoting it and doubling its quotes if it holds a comma, a
+/// quote or a line break.
+fn write_csv_field(out: &mut impl Write, s: &str) -> io::Result<()> {
+    if !s.contains([',', '"', '\n', '\r']) {
+        return out.write_all(s.as_bytes());
+    }
+    out.write_all(b"\"")?;
+    for (i, part) in s.split('"').enumerate() {
+        if i > 0 {
+            out.write_all(b"\"\"")?;
+        }
+        out.write_all(part.as_bytes())?;
+    }
+    out.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json_strings_escape_quotes_and_control_characters() {
+        let json = |s: &str| written(|out| write_json_str(out, s));
+        assert_eq!(json("plain"), r#""plain""#);
+        assert_eq!(json(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+        assert_eq!(json("a\nb\r\tc\u{1}"), r#""a\nb\r\tc\u0001""#);
+        assert_eq!(json("zürich €"), "\"zürich €\"");
+        #[cfg(feature = "serde")]
+        for s in ["", "\u{7f}", "\"\\\"", "line\u{0}end"] {
+            assert_eq!(serde_json::from_str::<String>(&json(s)).unwrap(), s);
+        }
+        assert_eq!(written(|out| write_json_opt(out, None)), "null");
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        let csv = |s: &str| written(|out| write_csv_field(out, s));
+        assert_eq!(csv("plain text"), "plain text");
+        assert_eq!(csv(""), "");
+        assert_eq!(csv("a,b"), "\"a,b\"");
+        assert_eq!(csv(r#"say "hi""#), r#""say ""hi""""#);
+        assert_eq!(csv("two\r\nlines"), "\"two\r\nlines\"");
+    }
+
+    #[test]
+    fn formats_parse_from_their_names() {
+        for format in [ExportFormat::Ndjson, ExportFormat::Csv] {
+            assert_eq!(format.as_str().parse(), Ok(format));
+            assert_eq!(format.to_string().to_uppercase().parse(), Ok(format));
+        }
+        let err = "xml".parse::<ExportFormat>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"unknown export format "xml", expected "ndjson" or "csv""#
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! # Health Reports
+//!
+//! This module defines the report returned by `LogDB::health_report`: a list of
+//! findings about the state of the index, each with a severity, a stable
+//! machine-readable code and a message saying what to do about it. The thresholds the
+//! findings are raised at are the constants below.
+
+use std::fmt;
+
+/// The share of the documents a word may appear in before it is reported as
+/// `common_token`.
+pub const COMMON_TOKEN_RATIO: f64 = 0.5;
+
+/// The fewest documents the index must hold before common words are reported, so a
+/// handful of documents sharing a word is not flagged.
+pub const COMMON_TOKEN_MIN_DOCS: usize = 100;
+
+/// The most common words listed in a `common_token` finding.
+pub const COMMON_TOKEN_LIMIT: usize = 10;
+
+/// The most entries a hash table bucket may hold before it is reported as
+/// `long_bucket_chain`.
+pub const MAX_CHAIN_LEN: usize = 8;
+
+/// The share of the documents that may lack a level before it is reported as
+/// `missing_level`.
+pub const MISSING_LEVEL_RATIO: f64 = 0.5;
+
+/// The share of `LogConfig::max_postings` the index may reach before it is reported
+/// as `postings_near_limit`.
+pub const POSTINGS_NEAR_LIMIT_RATIO: f64 = 0.9;
+
+/// How serious a health finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Worth knowing about, but queries are unaffected.
+    Info,
+    /// Slows queries down or lets memory use grow unchecked.
+    Warning,
+}
+
+impl Severity {
+    /// Returns the lowercase name of the severity, as used in the text report.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single problem found by `LogDB::health_report`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthFinding {
+    /// How serious the problem is.
+    pub severity: Severity,
+    /// A stable code naming the kind of problem, such as `long_bucket_chain`.
+    pub code: &'static str,
+    /// A description of the problem and what to do about it.
+    pub message: String,
+}
+
+/// The findings of `LogDB::health_report`, most severe first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The problems found, most severe first.
+    pub findings: Vec<HealthFinding>,
+}
+
+impl HealthReport {
+    /// Returns whether any finding is a warning.
+    pub fn has_warnings(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Warning)
+    }
+
+    /// Returns whether a finding with the given code was raised.
+    pub fn has(&self, code: &str) -> bool {
+        self.findings.iter().any(|finding| finding.code == code)
+    }
+}
+
+impl fmt::Display for HealthReport {
+    /// Renders one finding per line, as `warning long_bucket_chain: ...`, or a single
+    /// line saying no problems were found.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.findings.is_empty() {
+            return writeln!(f, "no problems found");
+        }
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "{} {}: {}",
+                finding.severity, finding.code, finding.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(severity: Severity, code: &'static str) -> HealthFinding {
+        HealthFinding {
+            severity,
+            code,
+            message: format!("{code} message"),
+        }
+    }
+
+    #[test]
+    fn reports_render_a_line_per_finding() {
+        let report = HealthReport {
+            findings: vec![
+                finding(Severity::Warning, "long_bucket_chain"),
+                finding(Severity::Info, "missing_level"),
+            ],
+        };
+        assert!(report.has_warnings());
+        assert!(report.has("missing_level") && !report.has("common_token"));
+        assert_eq!(
+            report.to_string(),
+            "warning long_bucket_chain: long_bucket_chain message\n\
+             info missing_level: missing_level message\n"
+        );
+
+        let info_only = HealthReport {
+            findings: vec![finding(Severity::Info, "whitespace_tokens")],
+        };
+        assert!(!info_only.has_warnings());
+        assert!(Severity::Warning > Severity::Info);
+    }
+}
@@ -0,0 +1,521 @@
+//! # HTTP API
+//!
+//! This module provides `HttpServer`, a tiny HTTP/1.1 interface to a `LogDB` shared
+//! behind a mutex, for demos and sidecar deployments:
+//!
+//! - `POST /ingest` indexes each line of an NDJSON body, as `LogDB::upsert_json` does.
+//! - `GET /search?q=...&limit=...&offset=...` returns the matching documents as JSON.
+//! - `GET /stats` returns the document count and operation counters as JSON.
+//! - `GET /metrics` returns `LogDB::metrics_text`, for Prometheus to scrape.
+//! - `DELETE /docs/:id` deletes a document.
+//!
+//! Every other response is JSON, and an error is `{"error": "..."}` with a 4xx
+//! status. Each connection is served on its own thread and closed after one response.
+//! The server is written over `std::net` to keep the crate free of a web framework, so
+//! it has none of the hardening of one; do not expose it beyond a trusted network.
+
+use crate::logdb::{LogDB, QueryOptions};
+use crate::types::DocId;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The number of hits `GET /search` returns when no `limit` is given.
+pub const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+/// The largest request body accepted, in bytes.
+const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// The largest request line or header accepted, in bytes.
+const MAX_HEADER_BYTES: usize = 8 * 1024;
+
+/// How long a connection may stay silent before it is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An HTTP server answering requests against a shared `LogDB`.
+#[derive(Debug)]
+pub struct HttpServer {
+    /// The socket accepting connections.
+    listener: TcpListener,
+    /// The database every request reads or writes.
+    db: Arc<Mutex<LogDB>>,
+}
+
+impl HttpServer {
+    /// Binds a server to `addr`, such as `127.0.0.1:7700`. Port 0 picks a free port,
+    /// which `local_addr` returns.
+    pub fn bind(addr: impl ToSocketAddrs, db: Arc<Mutex<LogDB>>) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            db,
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections until the process exits, serving each on a new thread.
+    ///
+    /// The database is locked only while a request reads or writes it. A connection
+    /// that fails to be accepted is skipped.
+    pub fn run(self) {
+        for stream in self.listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let db = Arc::clone(&self.db);
+            thread::spawn(move || {
+                // The client went away or stalled; there is no one to tell.
+                let _ = serve_connection(stream, &db);
+            });
+        }
+    }
+}
+
+/// A parsed HTTP request.
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    /// The method, such as `GET`.
+    method: String,
+    /// The path, without the query string, percent-decoded.
+    path: String,
+    /// The decoded parameters of the query string, in order.
+    params: Vec<(String, String)>,
+    /// The body, empty if there is none.
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the first value of a query string parameter.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The content type of a JSON response.
+const JSON: &str = "application/json";
+
+/// The content type of the Prometheus text exposition format.
+const PROMETHEUS_TEXT: &str = "text/plain; version=0.0.4";
+
+/// A response: a status code and a body, JSON unless it is the metrics text.
+#[derive(Debug, PartialEq)]
+struct Response {
+    /// The status code, such as 200.
+    status: u16,
+    /// The value of the `Content-Type` header.
+    content_type: &'static str,
+    /// The body.
+    body: String,
+}
+
+impl Response {
+    /// A 200 response with `body`.
+    fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            content_type: JSON,
+            body: body.to_string(),
+        }
+    }
+
+    /// An error response with `status`, whose body gives `message`.
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: JSON,
+            body: json!({ "error": message.into() }).to_string(),
+        }
+    }
+
+    /// Writes the response, closing the connection after it.
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len(),
+            self.body,
+        )?;
+        out.flush()
+    }
+}
+
+/// Returns the reason phrase of a status code sent by the server.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Reads one request from a connection and answers it.
+fn serve_connection(stream: TcpStream, db: &Mutex<LogDB>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => route(&request, db),
+        Err(response) => response,
+    };
+    response.write_to(&mut &stream)
+}
+
+/// Reads a request line, headers and a body sized by `Content-Length`.
+///
+/// # Returns
+/// The request, or the error response to send for a malformed one.
+fn read_request(reader: &mut impl BufRead) -> Result<Request, Response> {
+    let request_line = read_header_line(reader)?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Response::error(400, "malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(Response::error(
+            400,
+            format!("unsupported version {version}"),
+        ));
+    }
+
+    let mut content_length = 0;
+    loop {
+        let line = read_header_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Response::error(400, "malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| Response::error(400, "invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(Response::error(411, "send the body with Content-Length"));
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(Response::error(413, "request body is too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| Response::error(400, "request body is shorter than Content-Length"))?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+    Ok(Request {
+        method: method.to_string(),
+        path: percent_decode(path),
+        params,
+        body,
+    })
+}
+
+/// Reads one CRLF- or LF-terminated line of the request head.
+fn read_header_line(reader: &mut impl BufRead) -> Result<String, Response> {
+    let mut line = Vec::new();
+    let read = reader
+        .take(MAX_HEADER_BYTES as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .map_err(|_| Response::error(400, "connection closed mid-request"))?;
+    if read > MAX_HEADER_BYTES {
+        return Err(Response::error(431, "request line or header is too long"));
+    }
+    if !line.ends_with(b"\n") {
+        return Err(Response::error(400, "connection closed mid-request"));
+    }
+    let line = String::from_utf8(line).map_err(|_| Response::error(400, "header is not UTF-8"))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Decodes `%XX` escapes, and `+` as a space, as browsers encode query strings. An
+/// escape that is not two hex digits is kept as it is.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => match s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Answers a request.
+fn route(request: &Request, db: &Mutex<LogDB>) -> Response {
+    let Ok(mut db) = db.lock() else {
+        return Response::error(500, "LogDB mutex is poisoned");
+    };
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/ingest") => ingest(&mut db, &request.body),
+        ("GET", "/search") => search(&db, request),
+        ("GET", "/stats") => stats(&db),
+        ("GET", "/metrics") => Response {
+            status: 200,
+            content_type: PROMETHEUS_TEXT,
+            body: db.metrics_text(),
+        },
+        ("DELETE", path) if path.starts_with("/docs/") => delete(&mut db, &path[6..]),
+        (_, "/ingest" | "/search" | "/stats" | "/metrics") => {
+            Response::error(405, "method not allowed")
+        }
+        (_, path) if path.starts_with("/docs/") => Response::error(405, "method not allowed"),
+        (_, path) => Response::error(404, format!("no such endpoint {path}")),
+    }
+}
+
+/// Indexes each non-empty line of an NDJSON body.
+///
+/// # Returns
+/// The IDs of the indexed documents, and the 1-based line number and error of each
+/// line that could not be indexed.
+fn ingest(db: &mut LogDB, body: &[u8]) -> Response {
+    let Ok(body) = std::str::from_utf8(body) else {
+        return Response::error(400, "request body is not UTF-8");
+    };
+    let mut ids = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in body.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match db.upsert_json(line) {
+            Ok(id) => ids.push(id),
+            Err(e) => errors.push(json!({ "line": i + 1, "error": e.to_string() })),
+        }
+    }
+    Response::ok(json!({ "ids": ids, "errors": errors }))
+}
+
+/// Runs the query in the `q` parameter, paged by `limit` and `offset`.
+fn search(db: &LogDB, request: &Request) -> Response {
+    let Some(q) = request.param("q") else {
+        return Response::error(400, "missing query parameter q");
+    };
+    if let Err(e) = db.parse_only(q) {
+        return Response::error(400, format!("invalid query: {e}"));
+    }
+    let number = |name: &str, default: usize| match request.param(name) {
+        None => Ok(default),
+        Some(value) => value
+            .parse()
+            .map_err(|_| Response::error(400, format!("{name} must be a number"))),
+    };
+    let (limit, offset) = match (number("limit", DEFAULT_SEARCH_LIMIT), number("offset", 0)) {
+        (Ok(limit), Ok(offset)) => (limit, offset),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let opts = QueryOptions {
+        limit: Some(limit),
+        offset,
+        ..QueryOptions::default()
+    };
+    let result = db.search(q, &opts);
+    let hits: Vec<Value> = result
+        .hits
+        .iter()
+        .filter_map(|id| {
+            db.get_entry(id).map(|entry| {
+                json!({
+                    "id": id,
+                    "timestamp": entry.timestamp(),
+                    "level": entry.level(),
+                    "service": entry.service(),
+                    "content": entry.content(),
+                })
+            })
+        })
+        .collect();
+    Response::ok(json!({
+        "total": result.total,
+        "took_ms": result.took.as_secs_f64() * 1000.0,
+        "hits": hits,
+    }))
+}
+
+/// Reports the document count, the operation counters and the memory estimate.
+fn stats(db: &LogDB) -> Response {
+    let metrics = db.metrics();
+    Response::ok(json!({
+        "docs": db.len(),
+        "inserts": metrics.inserts(),
+        "deletes": metrics.deletes(),
+        "rejections": metrics.rejections(),
+        "truncations": metrics.truncations(),
+        "queries": metrics.queries(),
+        "query_errors": metrics.query_errors(),
+        "approx_bytes": db.approx_bytes(),
+    }))
+}
+
+/// Deletes the document whose ID is `id`.
+fn delete(db: &mut LogDB, id: &str) -> Response {
+    let id = match id.parse::<DocId>() {
+        Ok(id) => id,
+        Err(e) => return Response::error(400, e.to_string()),
+    };
+    if db.delete(&id) {
+        Response::ok(json!({ "deleted": id }))
+    } else {
+        Response::error(404, format!("no document {id}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logdb::IngestError;
+
+    fn parse(raw: &str) -> Result<Request, Response> {
+        read_request(&mut raw.as_bytes())
+    }
+
+    #[test]
+    fn percent_decode_handles_escapes_and_plus() {
+        assert_eq!(
+            percent_decode("level%3AERROR+disk%20full"),
+            "level:ERROR disk full"
+        );
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+    }
+
+    #[test]
+    fn reads_path_params_and_body() {
+        let request = parse(
+            "POST /ingest?q=a+b&limit=5&flag HTTP/1.1\r\nHost: x\r\ncontent-length: 4\r\n\r\nbody",
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/ingest");
+        assert_eq!(request.param("q"), Some("a b"));
+        assert_eq!(request.param("limit"), Some("5"));
+        assert_eq!(request.param("flag"), Some(""));
+        assert_eq!(request.param("offset"), None);
+        assert_eq!(request.body, b"body");
+
+        // Bare LF line endings are accepted too.
+        assert_eq!(parse("GET /stats HTTP/1.0\n\n").unwrap().path, "/stats");
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        let status = |raw: &str| parse(raw).unwrap_err().status;
+        assert_eq!(status("GET /stats\r\n\r\n"), 400);
+        assert_eq!(status("GET /stats SPDY/3\r\n\r\n"), 400);
+        assert_eq!(status("GET /stats HTTP/1.1\r\nno colon\r\n\r\n"), 400);
+        assert_eq!(status("GET /stats HTTP/1.1\r\nHost: x\r\n"), 400);
+        assert_eq!(
+            status("POST /ingest HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"),
+            400
+        );
+        assert_eq!(
+            status("POST /ingest HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n"),
+            411
+        );
+        let huge = format!(
+            "POST /ingest HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        );
+        assert_eq!(status(&huge), 413);
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEADER_BYTES));
+        assert_eq!(status(&long), 431);
+    }
+
+    #[test]
+    fn routes_to_handlers() {
+        let db = Mutex::new(LogDB::new());
+        let request = |method: &str, target: &str, body: &str| {
+            let raw = format!(
+                "{method} {target} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            );
+            route(&parse(&raw).unwrap(), &db)
+        };
+        let json = |response: Response| -> Value {
+            assert_eq!(response.content_type, JSON);
+            serde_json::from_str(&response.body).unwrap()
+        };
+
+        let response = request("POST", "/ingest", "{\"msg\":\"disk full\"}\n[1]\n");
+        assert_eq!(response.status, 200);
+        let body = json(response);
+        assert_eq!(body["ids"], json!([1]));
+        assert_eq!(
+            body["errors"],
+            json!([{ "line": 2, "error": IngestError::NotAnObject.to_string() }])
+        );
+
+        let body = json(request("GET", "/search?q=disk&offset=1", ""));
+        assert_eq!(body["total"], 1);
+        assert_eq!(body["hits"], json!([]));
+        assert_eq!(json(request("GET", "/stats", ""))["docs"], 1);
+
+        let metrics = request("GET", "/metrics", "");
+        assert_eq!(metrics.status, 200);
+        assert_eq!(metrics.content_type, PROMETHEUS_TEXT);
+        assert!(metrics.body.contains("\nbuggu_inserts_total 1\n"));
+        assert!(metrics.body.contains("\nbuggu_queries_total 1\n"));
+
+        assert_eq!(request("DELETE", "/docs/1", "").status, 200);
+        assert_eq!(request("DELETE", "/docs/1x", "").status, 400);
+        assert_eq!(request("PUT", "/stats", "").status, 405);
+        assert_eq!(request("POST", "/metrics", "").status, 405);
+    }
+
+    #[test]
+    fn writes_json_with_content_length() {
+        let mut out = Vec::new();
+        Response::error(404, "no such endpoint /x")
+            .write_to(&mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let (head, body) = out.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+        assert_eq!(body, r#"{"error":"no such endpoint /x"}"#);
+    }
+}
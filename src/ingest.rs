@@ -0,0 +1,237 @@
+//! # Ingest Hooks
+//!
+//! This module defines the hook `LogDB::set_ingest_hook` runs on every entry before
+//! it is indexed, which can accept the entry, change it, or reject it with a reason,
+//! and `IngestRules`, a declarative set of common checks that can be installed as
+//! such a hook where a closure cannot be passed, as from JavaScript. It also holds
+//! the ANSI escape stripper behind `IngestRules::strip_ansi` and
+//! `LogConfig::strip_ansi`.
+
+use crate::types::LogEntry;
+use std::borrow::Cow;
+
+/// What an ingest hook decided about an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookDecision {
+    /// Index the entry as it was given.
+    Accept,
+    /// Drop the entry, for the given reason.
+    Reject(String),
+    /// Index the entry as the hook changed it. The hook has already applied its
+    /// changes to the entry; this only records that it did.
+    Modify,
+}
+
+/// A hook run by `LogDB` on each entry before it is indexed.
+pub type IngestHook = Box<dyn FnMut(&mut LogEntry) -> HookDecision + Send>;
+
+/// Checks applied to every entry by the hook `IngestRules::into_hook` returns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestRules {
+    /// Reject entries without a level, or whose level is blank.
+    pub require_level: bool,
+    /// Remove ANSI escape sequences, such as color codes, from the content.
+    pub strip_ansi: bool,
+    /// Reject entries whose content, after any stripping, is longer than this many
+    /// bytes.
+    pub max_bytes: Option<usize>,
+}
+
+impl IngestRules {
+    /// Applies the rules to an entry, stripping its content if `strip_ansi` is set.
+    pub fn check(&self, entry: &mut LogEntry) -> HookDecision {
+        if self.require_level && entry.level.as_deref().is_none_or(|l| l.trim().is_empty()) {
+            return HookDecision::Reject("entry has no level".to_string());
+        }
+        let mut decision = HookDecision::Accept;
+        if self.strip_ansi {
+            if let Cow::Owned(stripped) = strip_ansi(&entry.content) {
+                entry.content = stripped;
+                decision = HookDecision::Modify;
+            }
+        }
+        if let Some(max) = self.max_bytes {
+            if entry.content.len() > max {
+                return HookDecision::Reject(format!(
+                    "content is {} bytes, over the limit of {max}",
+                    entry.content.len()
+                ));
+            }
+        }
+        decision
+    }
+
+    /// Returns a hook applying the rules to every entry.
+    pub fn into_hook(self) -> IngestHook {
+        Box::new(move |entry| self.check(entry))
+    }
+}
+
+/// The state of `strip_ansi` between characters.
+#[derive(Clone, Copy)]
+enum AnsiState {
+    /// Outside any escape sequence.
+    Text,
+    /// After an `ESC`.
+    Escape,
+    /// Inside a CSI sequence, `ESC [` up to its final byte.
+    Csi,
+    /// Inside an OSC sequence, `ESC ]` up to `BEL` or `ESC \`.
+    Osc,
+    /// After an `ESC` inside an OSC sequence.
+    OscEscape,
+}
+
+/// Removes ANSI escape sequences from `s`: CSI sequences such as the color code
+/// `ESC [31m`, OSC sequences such as terminal titles and hyperlinks, and two-byte
+/// escapes. An unterminated sequence at the end is removed too. Borrows `s` when it
+/// holds no `ESC`.
+pub fn strip_ansi(s: &str) -> Cow<'_, str> {
+    strip(s, false)
+}
+
+/// Removes ANSI escape sequences from `s` as `strip_ansi` does, along with every
+/// other control character except newlines and tabs, such as carriage returns,
+/// backspaces and bells. Borrows `s` when there is nothing to remove.
+pub fn strip_ansi_and_controls(s: &str) -> Cow<'_, str> {
+    strip(s, true)
+}
+
+/// Checks whether `strip_ansi_and_controls` removes a character outside escape
+/// sequences.
+fn is_stripped_control(c: char) -> bool {
+    c.is_control() && c != '\n' && c != '\t'
+}
+
+/// Removes ANSI escape sequences from `s`, and with `controls` the control characters
+/// `strip_ansi_and_controls` removes.
+fn strip(s: &str, controls: bool) -> Cow<'_, str> {
+    let clean = if controls {
+        !s.contains(is_stripped_control)
+    } else {
+        !s.contains('\x1b')
+    };
+    if clean {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut state = AnsiState::Text;
+    for c in s.chars() {
+        state = match (state, c) {
+            (AnsiState::Text, '\x1b') => AnsiState::Escape,
+            (AnsiState::Text, c) if controls && is_stripped_control(c) => AnsiState::Text,
+            (AnsiState::Text, c) => {
+                out.push(c);
+                AnsiState::Text
+            }
+            (AnsiState::Escape, '[') => AnsiState::Csi,
+            (AnsiState::Escape, ']') => AnsiState::Osc,
+            (AnsiState::Escape, _) => AnsiState::Text,
+            // Parameter and intermediate bytes, then a final byte in `@`..=`~`.
+            (AnsiState::Csi, '\x20'..='\x3f') => AnsiState::Csi,
+            (AnsiState::Csi, '\x40'..='\x7e') => AnsiState::Text,
+            // Anything else cuts the sequence short and is kept as text.
+            (AnsiState::Csi, c) => {
+                if !(controls && is_stripped_control(c)) {
+                    out.push(c);
+                }
+                AnsiState::Text
+            }
+            (AnsiState::Osc, '\x07') => AnsiState::Text,
+            (AnsiState::Osc, '\x1b') => AnsiState::OscEscape,
+            (AnsiState::Osc, _) => AnsiState::Osc,
+            (AnsiState::OscEscape, '\\') => AnsiState::Text,
+            (AnsiState::OscEscape, '[') => AnsiState::Csi,
+            (AnsiState::OscEscape, ']') => AnsiState::Osc,
+            (AnsiState::OscEscape, _) => AnsiState::Text,
+        };
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TokenMode;
+
+    fn entry(content: &str, level: Option<&str>) -> LogEntry {
+        LogEntry::with_metadata(
+            content.to_string(),
+            level.map(str::to_string),
+            None,
+            TokenMode::Mixed,
+        )
+    }
+
+    #[test]
+    fn require_level_rejects_missing_and_blank_levels() {
+        let rules = IngestRules {
+            require_level: true,
+            ..IngestRules::default()
+        };
+        assert!(matches!(
+            rules.check(&mut entry("x", None)),
+            HookDecision::Reject(_)
+        ));
+        assert!(matches!(
+            rules.check(&mut entry("x", Some("  "))),
+            HookDecision::Reject(_)
+        ));
+        assert_eq!(
+            rules.check(&mut entry("x", Some("WARN"))),
+            HookDecision::Accept
+        );
+    }
+
+    #[test]
+    fn strip_ansi_modifies_only_entries_with_escapes() {
+        let rules = IngestRules {
+            strip_ansi: true,
+            ..IngestRules::default()
+        };
+        let mut colored = entry("\x1b[1;32mok\x1b[0m done", None);
+        assert_eq!(rules.check(&mut colored), HookDecision::Modify);
+        assert_eq!(colored.content, "ok done");
+
+        let mut plain = entry("ok done", None);
+        assert_eq!(rules.check(&mut plain), HookDecision::Accept);
+    }
+
+    #[test]
+    fn max_bytes_applies_after_stripping() {
+        let rules = IngestRules {
+            strip_ansi: true,
+            max_bytes: Some(4),
+            ..IngestRules::default()
+        };
+        assert_eq!(
+            rules.check(&mut entry("\x1b[31mfail\x1b[0m", None)),
+            HookDecision::Modify
+        );
+        assert_eq!(
+            rules.check(&mut entry("failed", None)),
+            HookDecision::Reject("content is 6 bytes, over the limit of 4".to_string())
+        );
+    }
+
+    #[test]
+    fn stripping_removes_csi_osc_and_control_characters() {
+        let stripped = |s| strip_ansi_and_controls(s).into_owned();
+        assert_eq!(stripped("\x1b[1;31mERROR\x1b[0m disk"), "ERROR disk");
+        assert_eq!(
+            stripped("\x1b]8;;https://x.io\x07link\x1b]8;;\x1b\\ text"),
+            "link text"
+        );
+        assert_eq!(stripped("a\x1b7b\x1b8"), "ab");
+        assert_eq!(stripped("progress 50%\r\x08done\x07"), "progress 50%done");
+        assert_eq!(stripped("keep\ttabs\nand lines"), "keep\ttabs\nand lines");
+        assert_eq!(stripped("cut off \x1b[31"), "cut off ");
+        // Only escapes go without the controls.
+        assert_eq!(strip_ansi("\x1b[2Kline\r"), "line\r");
+        assert!(matches!(
+            strip_ansi_and_controls("plain ü"),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(strip_ansi("carriage\r"), Cow::Borrowed(_)));
+    }
+}
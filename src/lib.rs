@@ -2,111 +2,178 @@
 
 //! # MicroSearch Library
 //!
-//! This module serves as the main entry point for the MicroSearch library when used as
-//! a native Node.js addon. It exposes the `MicroSearch` struct and its associated
-//! methods to JavaScript, allowing Node.js applications to leverage the high-performance
-//! search and indexing capabilities of the underlying `LogDB`.
-
-use napi::bindgen_prelude::*;
-use napi_derive::napi;
-
-// Import the necessary modules from the crate.
-mod config;
-mod logdb;
-mod ufhg;
-mod utils;
-
-// Use the LogDB implementation, which provides the core functionality.
-use logdb::LogDB;
-
-/// A high-performance, in-memory search engine exposed as a Node.js addon.
-///
-/// The `MicroSearch` struct wraps the `LogDB`, providing a simplified interface for
-/// creating, updating, and querying documents. This struct is designed to be
-/// instantiated and used from JavaScript code.
-#[napi]
-pub struct MicroSearch {
-    /// The underlying `LogDB` instance that handles the actual search and indexing logic.
-    inner: LogDB,
+//! This crate provides `LogDB`, a high-performance, in-memory search engine for log
+//! data, along with the tokenizer, hash set and replication codec it is built on. The
+//! core compiles as a plain Rust library; the Node.js addon wrapping it lives in the
+//! `node` module, behind the default-on `node` feature, and the WebAssembly bindings
+//! live in the `wasm` module, behind the `wasm` feature. The `http` module, behind
+//! the `http` feature, serves a small JSON API over a shared `LogDB`.
+
+pub mod codec;
+pub mod config;
+pub mod export;
+pub mod health;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod ingest;
+pub mod logdb;
+pub mod metrics;
+#[cfg(feature = "node")]
+pub mod node;
+pub mod query;
+pub mod query_cache;
+#[cfg(feature = "serde")]
+pub mod query_spec;
+pub mod tailer;
+pub mod term_dict;
+#[cfg(feature = "tracing")]
+pub mod tracing_layer;
+pub mod types;
+pub mod ufhg;
+pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use codec::Frame;
+pub use config::LogConfig;
+pub use logdb::LogDB;
+pub use query::QueryNode;
+pub use types::{DocId, LogEntry, Tok, TokenMode};
+
+#[cfg(feature = "tracing")]
+pub use tracing_layer::MicroSearchLayer;
+
+/// The types most programs using the crate need, for a single
+/// `use buggu::prelude::*;`.
+pub mod prelude {
+    pub use crate::codec::Frame;
+    pub use crate::config::LogConfig;
+    pub use crate::logdb::{LogDB, QueryOptions, SearchResult};
+    pub use crate::query::{QueryError, QueryNode};
+    pub use crate::types::{DocId, LogEntry, Tok, TokenMode};
 }
 
-#[napi]
-impl MicroSearch {
-    /// Creates a new instance of `MicroSearch`.
-    ///
-    /// This constructor initializes a new `LogDB` with default settings and wraps it
-    /// in a `MicroSearch` struct, making it available for use in a Node.js environment.
-    ///
-    /// # Returns
-    /// A `Result` containing the new `MicroSearch` instance or an error if initialization fails.
-    #[napi(constructor)]
-    pub fn new() -> Result<Self> {
-        Ok(Self {
-            inner: LogDB::new(),
-        })
+#[cfg(test)]
+mod tests {
+    //! Drives `LogDB` through the crate-root exports and the prelude alone, as a
+    //! Rust service using the crate would.
+
+    use crate::{DocId, LogConfig, LogDB, LogEntry, TokenMode};
+
+    const LEVELS: [&str; 3] = ["INFO", "WARN", "ERROR"];
+    const SERVICES: [&str; 4] = ["auth", "api", "db", "cache"];
+    const WORDS: [&str; 6] = [
+        "timeout",
+        "refused",
+        "retry",
+        "latency",
+        "handshake",
+        "evicted",
+    ];
+
+    /// Indexes `n` entries cycling through the levels, services and words above.
+    fn indexed(n: usize) -> LogDB {
+        let mut db = LogDB::with_config(LogConfig::default());
+        for i in 0..n {
+            let mut entry = LogEntry::with_metadata(
+                format!("request {} after {}", WORDS[i % 6], WORDS[(i / 6) % 6]),
+                Some(LEVELS[i % 3].to_string()),
+                Some(SERVICES[i % 4].to_string()),
+                TokenMode::Mixed,
+            );
+            entry.timestamp = 1_000 + i as u64;
+            db.upsert_entry(entry).unwrap();
+        }
+        db
+    }
+
+    fn sorted(mut ids: Vec<DocId>) -> Vec<DocId> {
+        ids.sort_unstable();
+        ids
     }
 
-    /// Inserts or updates a simple document with the given content.
-    ///
-    /// This method provides a straightforward way to add content to the search index
-    /// without specifying additional metadata like log level or service.
-    ///
-    /// # Arguments
-    /// * `content` - The string content of the document to be indexed.
-    ///
-    /// # Returns
-    /// A `Result` containing the document ID as a string, or an error if the operation fails.
-    #[napi]
-    pub fn upsert_simple(&mut self, content: String) -> Result<String> {
-        let doc_id = self.inner.upsert_simple(&content);
-        Ok(doc_id.to_string())
+    #[test]
+    fn queries_filter_by_words_metadata_and_time() {
+        let db = indexed(3_600);
+        assert_eq!(db.len(), 3_600);
+        // Every sixth entry has the word first, every sixth block of six has it last, and
+        // every 36th has it twice.
+        assert_eq!(db.query("timeout").len(), 1_100);
+        assert_eq!(db.query("level:ERROR").len(), 1_200);
+        assert_eq!(db.query("service:db").len(), 900);
+        assert_eq!(db.query("\"request timeout\"").len(), 600);
+        assert_eq!(db.query("\"timeout after timeout\"").len(), 100);
+        // Levels repeat every 3 entries and services every 4, so both match one in 12.
+        assert_eq!(db.query("level:ERROR service:auth").len(), 300);
+        assert_eq!(db.query("timeout OR refused").len(), 2_000);
+        assert_eq!(db.query("timeout -level:INFO").len(), 400);
+        assert_eq!(db.query("timestamp:>=4000").len(), 600);
+        assert!(db.query("nowhere").is_empty());
+        assert_eq!(db.try_query("\"unterminated").ok(), None);
     }
 
-    /// Inserts or updates a log entry with additional metadata.
-    ///
-    /// This method allows for the indexing of structured log data, including log level
-    /// and service name, which can be used for more advanced filtering and querying.
-    ///
-    /// # Arguments
-    /// * `content` - The main content of the log entry.
-    /// * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
-    /// * `service` - An optional string specifying the service name.
-    ///
-    /// # Returns
-    /// A `Result` containing the document ID as a string, or an error if the operation fails.
-    #[napi]
-    pub fn upsert_log(
-        &mut self,
-        content: String,
-        level: Option<String>,
-        service: Option<String>,
-    ) -> Result<String> {
-        let doc_id = self.inner.upsert_log(&content, level, service);
-        Ok(doc_id.to_string())
+    #[test]
+    fn deleted_documents_stop_matching() {
+        let mut db = indexed(120);
+        let before = sorted(db.query("level:WARN"));
+        for id in &before[..10] {
+            assert!(db.delete(id));
+        }
+        assert_eq!(sorted(db.query("level:WARN")), before[10..]);
+        assert_eq!(db.len(), 110);
+        assert!(db.get_entry(&before[0]).is_none());
+        let next = db.upsert_simple("late arrival").unwrap();
+        assert!(next > before[before.len() - 1]);
     }
 
-    /// Executes a search query and returns a list of matching document IDs.
-    ///
-    /// # Arguments
-    /// * `query` - The search query string.
-    ///
-    /// # Returns
-    /// A `Result` containing a vector of document IDs as strings, or an error if the query fails.
-    #[napi]
-    pub fn query(&self, query: String) -> Result<Vec<String>> {
-        let results = self.inner.query(&query);
-        Ok(results.into_iter().map(|id| id.to_string()).collect())
+    #[test]
+    fn snapshots_keep_their_documents() {
+        let mut db = indexed(60);
+        let snapshot = db.snapshot();
+        db.upsert_simple("request timeout after write").unwrap();
+        assert_eq!(snapshot.query("timeout").len(), 20);
+        assert_eq!(db.query("timeout").len(), 21);
     }
 
-    /// Executes a search query and returns the full content of matching documents.
-    ///
-    /// # Arguments
-    /// * `query` - The search query string.
-    ///
-    /// # Returns
-    /// A `Result` containing a vector of document content strings, or an error if the query fails.
-    #[napi]
-    pub fn query_content(&self, query: String) -> Result<Vec<String>> {
-        Ok(self.inner.query_content(&query))
+    #[test]
+    fn the_prelude_covers_a_search_and_a_replay() {
+        use crate::prelude::*;
+
+        let db = indexed(36);
+        let ast = QueryNode::And(vec![
+            QueryNode::Term("timeout".to_string()),
+            QueryNode::FieldTerm("level", "ERROR".to_string()),
+        ]);
+        assert_eq!(
+            sorted(db.query_ast(&ast)),
+            sorted(db.query("timeout level:ERROR"))
+        );
+        let result: SearchResult = db.search(
+            "timeout",
+            &QueryOptions {
+                limit: Some(2),
+                ..QueryOptions::default()
+            },
+        );
+        assert_eq!((result.hits.len(), result.total), (2, 11));
+        assert_eq!(db.try_query(" "), Err(QueryError::Empty));
+
+        // Replaying the exported frames into an empty index rebuilds every document.
+        let mut replica = LogDB::with_config(LogConfig::default());
+        let frames: Vec<Frame> = db.export_frames().collect();
+        for frame in frames {
+            if let Frame::Full { tokens, .. } = &frame {
+                assert!(tokens.iter().all(|&tok: &Tok| tok != 0));
+            }
+            replica.apply_frame(frame).unwrap();
+        }
+        assert_eq!(replica.len(), db.len());
+        assert_eq!(
+            sorted(replica.query("timeout")),
+            sorted(db.query("timeout"))
+        );
+        let entry = LogEntry::with_metadata("late".to_string(), None, None, TokenMode::Mixed);
+        let id: DocId = replica.upsert_entry(entry).unwrap();
+        assert_eq!(replica.query("late"), [id]);
     }
 }
@@ -6,18 +6,35 @@
 //! and search engine. It includes data structures for storing and querying log entries,
 //! as well as mechanisms for efficient tokenization, indexing, and query execution.
 
-use crate::config::LogConfig;
+use crate::codec::{diff_tokens, BackupHeader, BackupKind, Frame, FrameReader, FrameWriter};
+use crate::config::{EvictionPolicy, LogConfig};
+use crate::export::ExportFormat;
+use crate::health::{
+    HealthFinding, HealthReport, Severity, COMMON_TOKEN_LIMIT, COMMON_TOKEN_MIN_DOCS,
+    COMMON_TOKEN_RATIO, MAX_CHAIN_LEN, MISSING_LEVEL_RATIO, POSTINGS_NEAR_LIMIT_RATIO,
+};
+use crate::ingest::{strip_ansi_and_controls, HookDecision, IngestHook};
+use crate::metrics::{Metrics, QueryLog, QueryTimer, QueryTimingSummary, SlowQueryRecord};
+use crate::query::{literal, parse_query, parse_query_relative, single_term};
+use crate::query_cache::QueryCache;
+#[cfg(feature = "serde")]
+use crate::query_spec::QuerySpec;
+use crate::term_dict::TermDict;
+use crate::types::{now_secs, LogEntry, TokenMode};
 use crate::ufhg::{lightning_hash_str, UFHGHeadquarters};
 use crate::utils::buggu_hash_set::BugguHashSet;
-use smallvec::SmallVec;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-/// A type alias for a token, which is represented as a 64-bit unsigned integer.
-/// Tokens are used to represent words, phrases, or other searchable units.
-pub type Tok = u64;
-
-/// A type alias for a document identifier, also a 64-bit unsigned integer.
-/// Each log entry is assigned a unique `DocId`.
-pub type DocId = u64;
+pub use crate::query::{QueryError, QueryNode};
+pub use crate::types::{DocId, Tok};
 
 /// Represents the metadata associated with a document.
 ///
@@ -31,157 +48,731 @@ pub struct MetaEntry {
     level: Option<String>,
     /// The service name, if specified.
     service: Option<String>,
+    /// The other services the document is tagged with, besides `service`.
+    extra_services: Vec<String>,
     /// The original, unmodified content of the log entry.
     content: String,
+    /// The timestamp of the log entry, in seconds since the Unix epoch.
+    timestamp: u64,
+    /// The tokenization mode the content was indexed with.
+    mode: TokenMode,
+    /// Whether the content was indexed without the level and service words.
+    content_only: bool,
+    /// The generation of the last change to the document.
+    generation: u64,
+    /// The size of the content before it was truncated to
+    /// `LogConfig::max_content_bytes`, in bytes, or `None` if it was stored whole.
+    truncated_from: Option<usize>,
+    /// The metadata fields of the document, as `(field ID, value)` pairs in the
+    /// order given, with names interned by the `LogDB`. A field ID repeats once per
+    /// distinct value.
+    fields: Vec<(u32, String)>,
+    /// The content as it was given, before `LogConfig::strip_ansi` cleaned it, if
+    /// `LogConfig::keep_raw_content` is set and cleaning changed it.
+    raw_content: Option<String>,
+    /// Whether `content` was cut to `LogConfig::max_content_len`, leaving the words
+    /// past the cut only in `tokens`.
+    capped: bool,
+}
+
+impl MetaEntry {
+    /// Returns the original content of the log entry.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Returns the log level, if one was specified.
+    pub fn level(&self) -> Option<&str> {
+        self.level.as_deref()
+    }
+
+    /// Returns the service name, if one was specified.
+    pub fn service(&self) -> Option<&str> {
+        self.service.as_deref()
+    }
+
+    /// Returns every service the document is tagged with, `service` first.
+    pub fn services(&self) -> impl Iterator<Item = &str> {
+        self.service
+            .iter()
+            .chain(&self.extra_services)
+            .map(String::as_str)
+    }
+
+    /// Returns the timestamp of the log entry, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns the size of the content before it was truncated to
+    /// `LogConfig::max_content_bytes`, in bytes, or `None` if it was stored whole.
+    /// Only the `LogDB` that ingested the document knows it; replicas and restored
+    /// backups hold the truncated content alone.
+    pub fn truncated_from(&self) -> Option<usize> {
+        self.truncated_from
+    }
+
+    /// Cuts the stored content to `max` bytes, as `cap_content` does, once its words
+    /// have been indexed.
+    fn cap_content(&mut self, max: usize) {
+        let len = self.content.len();
+        self.content = cap_content(std::mem::take(&mut self.content), max);
+        self.capped = self.content.len() != len;
+    }
+
+    /// Checks whether the document holds a run of consecutive words with the given
+    /// hashes, reading the word tokens when the stored content was capped.
+    fn contains_sequence(&self, hashes: &[Tok]) -> bool {
+        if self.capped {
+            self.tokens
+                .windows(hashes.len())
+                .any(|window| window == hashes)
+        } else {
+            contains_sequence(&self.content, hashes)
+        }
+    }
+}
+
+/// The result of `LogDB::upsert_entry_outcome`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpsertOutcome {
+    /// The ID of the inserted document.
+    pub id: DocId,
+    /// Whether the content was truncated to `LogConfig::max_content_bytes`.
+    pub truncated: bool,
+}
+
+/// The error returned when a replication `Frame` cannot be applied to a `LogDB`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyError {
+    /// A `Diff` frame referenced a document that does not exist.
+    UnknownDoc(DocId),
+    /// The token counts of a `Batch` frame's entries add up to `declared`, but the
+    /// frame holds `actual` tokens.
+    BatchLengthMismatch { declared: usize, actual: usize },
+}
+
+impl std::fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApplyError::UnknownDoc(id) => write!(f, "diff frame for unknown doc {id}"),
+            ApplyError::BatchLengthMismatch { declared, actual } => write!(
+                f,
+                "batch frame declares {declared} tokens but holds {actual}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ApplyError {}
+
+/// The error returned when a `LogDB` does not ingest an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IngestError {
+    /// The input of `upsert_json` is not valid JSON.
+    InvalidJson(String),
+    /// The input of `upsert_json` is valid JSON, but not an object.
+    NotAnObject,
+    /// The ingest hook rejected the entry, for the given reason.
+    Rejected(String),
+}
+
+impl std::fmt::Display for IngestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestError::InvalidJson(msg) => write!(f, "invalid JSON log: {msg}"),
+            IngestError::NotAnObject => f.write_str("JSON log is not an object"),
+            IngestError::Rejected(reason) => write!(f, "entry rejected: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for IngestError {}
+
+/// The hook set by `LogDB::set_ingest_hook`, shared by the clones of the `LogDB`.
+#[derive(Clone)]
+struct SharedIngestHook(Arc<Mutex<IngestHook>>);
+
+impl std::fmt::Debug for SharedIngestHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedIngestHook")
+    }
+}
+
+/// Per-query settings for `LogDB::search`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryOptions {
+    /// The most hits to return, after skipping `offset`. `None` returns all of them.
+    pub limit: Option<usize>,
+    /// The number of hits to skip, for paging through results.
+    pub offset: usize,
+    /// The order of the hits.
+    pub sort: SortOrder,
+    /// Whether words match regardless of case. The index ignores case, so `None` and
+    /// `Some(true)` behave alike; `Some(false)` also requires the content of a hit to
+    /// contain the query's words and phrases with the same case. Negated words still
+    /// exclude every spelling.
+    pub case_insensitive: Option<bool>,
+    /// Restricts hits to documents timestamped within this inclusive range, in
+    /// seconds since the Unix epoch.
+    pub time_range: Option<(u64, u64)>,
+    /// Restricts hits to documents of this service, which is how `LogDB` groups
+    /// documents into namespaces.
+    pub namespace: Option<String>,
+    /// Whether a bare word also matches the other words of its synonym ring. On by
+    /// default; turn it off to match words exactly.
+    pub enable_synonyms: bool,
+    /// Whether to spread the work of the query over the rayon thread pool: the
+    /// branches of an OR, the probing of large intersections and the copying of
+    /// content in `query_content_opts`. The hits, and their order, are the same
+    /// either way. Off by default, and ignored without the `rayon` feature.
+    pub parallel: bool,
+}
+
+impl Default for QueryOptions {
+    fn default() -> Self {
+        Self {
+            limit: None,
+            offset: 0,
+            sort: SortOrder::Index,
+            case_insensitive: None,
+            time_range: None,
+            namespace: None,
+            enable_synonyms: true,
+            parallel: false,
+        }
+    }
+}
+
+/// The order of the hits returned by `LogDB::search`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// The order the index produces them in, which is the cheapest.
+    #[default]
+    Index,
+    /// Newest first by timestamp.
+    Newest,
+    /// Oldest first by timestamp.
+    Oldest,
+    /// Most severe level first, as `LogDB::query_by_severity` orders them.
+    Severity,
+    /// Most relevant first, as `LogDB::query_ranked` orders them.
+    Relevance,
+}
+
+/// The hits of a `LogDB::search`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    /// The matching document IDs, after `QueryOptions::offset` and `limit`.
+    pub hits: Vec<DocId>,
+    /// The number of matching documents, before `offset` and `limit`.
+    pub total: usize,
+    /// How long the search took. Always zero on `wasm32-unknown-unknown`, like the
+    /// query latency metrics.
+    pub took: Duration,
+}
+
+/// A read view of a `LogDB` that leaves out the documents inserted after
+/// `LogDB::freeze` was called.
+///
+/// A snapshot records only the next document ID and the generation at that moment,
+/// and its queries run against the live index with every later document left out.
+/// It holds no borrow of the `LogDB`, so it never blocks writers and can be kept or
+/// dropped independently; pass the `LogDB` to each query instead.
+///
+/// Only insertions are hidden. A document deleted or replaced after the freeze is
+/// gone from, or changed in, the snapshot's results too, and when
+/// `LogConfig::max_postings` is set, postings evicted by later ingest take their
+/// matches with them. So paging through a query with `search` gives the same pages
+/// while documents are only ingested, with eviction off, but a deletion between two
+/// pages shifts the later pages by a hit, which is then skipped. Use
+/// `LogDB::snapshot` for a view that keeps every document as it was.
+///
+/// Its hits come in document ID order unless `QueryOptions::sort` asks for another;
+/// relevance scores still shift as documents are added, so only the other orders
+/// give repeatable pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The first document ID assigned after the freeze.
+    next_doc_id: DocId,
+    /// The generation of the `LogDB` at the freeze.
+    generation: u64,
+}
+
+impl Snapshot {
+    /// Returns the generation of the `LogDB` when the snapshot was taken. While
+    /// `LogDB::generation` still returns it, nothing has been written since, so the
+    /// snapshot's results are those of the moment it was taken.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns whether a document was inserted before the snapshot was taken.
+    pub fn contains(&self, id: DocId) -> bool {
+        id < self.next_doc_id
+    }
+
+    /// Executes a query like `LogDB::query`, leaving out documents inserted after the
+    /// snapshot was taken.
+    pub fn query(&self, db: &LogDB, q: &str) -> Vec<DocId> {
+        self.search(db, q, &QueryOptions::default()).hits
+    }
+
+    /// Executes a query like `query` and returns one page of its hits: at most `limit`
+    /// of them, after skipping the first `offset`. Pages of the same snapshot neither
+    /// overlap nor leave gaps however many documents are ingested between them, as
+    /// long as none is deleted and no posting is evicted; see `Snapshot`.
+    pub fn query_paged(&self, db: &LogDB, q: &str, offset: usize, limit: usize) -> Vec<DocId> {
+        let opts = QueryOptions {
+            offset,
+            limit: Some(limit),
+            ..QueryOptions::default()
+        };
+        self.search(db, q, &opts).hits
+    }
+
+    /// Executes a search like `LogDB::search`, leaving out documents inserted after
+    /// the snapshot was taken. `total`, `offset` and `limit` count only the documents
+    /// the snapshot sees.
+    pub fn search(&self, db: &LogDB, q: &str, opts: &QueryOptions) -> SearchResult {
+        db.search_before(q, opts, Some(self.next_doc_id))
+    }
 }
 
-/// Defines the Abstract Syntax Tree (AST) for a parsed query.
+/// A read-only copy of a `LogDB`, returned by `LogDB::snapshot`.
 ///
-/// This enum represents the structure of a search query, allowing for complex
-/// logical combinations of search terms, phrases, and field-specific filters.
+/// Unlike a `Snapshot`, which only hides documents inserted later, a shared snapshot
+/// keeps every document, posting and index exactly as they were when it was taken:
+/// later deletions and replacements do not show through either. Taking one is cheap,
+/// because the document table, the postings and the level, service and prefix
+/// indexes are shared with the `LogDB` rather than copied. Those tables are copied on
+/// write instead: the first write to the `LogDB` that touches one of them while a
+/// snapshot still shares it copies that table once, and later writes go to the copy.
+/// Dropping the snapshot ends the sharing.
+///
+/// The snapshot dereferences to a `LogDB`, so every query method is available on
+/// it, but none that changes the index.
 #[derive(Debug, Clone)]
-pub enum QueryNode {
-    /// A single search term.
-    Term(String),
-    /// An exact phrase search.
-    Phrase(String),
-    /// A search for a term within a specific field (e.g., `level:ERROR`).
-    FieldTerm(&'static str, String),
-    /// A search for a numeric range within a field (e.g., `timestamp:>=12345`).
-    NumericRange(&'static str, u64, u64),
-    /// A search for a substring within the content of a log entry.
-    Contains(String),
-    /// A logical AND operation, requiring all child nodes to match.
-    And(Vec<QueryNode>),
-    /// A logical OR operation, requiring at least one child node to match.
-    Or(Vec<QueryNode>),
-    /// A logical NOT operation, excluding documents that match the child node.
-    Not(Box<QueryNode>),
+pub struct SharedSnapshot {
+    db: LogDB,
+}
+
+impl std::ops::Deref for SharedSnapshot {
+    type Target = LogDB;
+
+    fn deref(&self) -> &LogDB {
+        &self.db
+    }
+}
+
+/// A document matched by `LogDB::query_matches`, with where the query matched it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocMatch {
+    /// The document ID.
+    pub id: DocId,
+    /// The `(start, end)` byte ranges of the stored content that the query's words
+    /// and phrases matched, sorted and non-overlapping, `end` exclusive.
+    pub spans: Vec<(u32, u32)>,
+}
+
+/// The number of heaviest postings `LogDB::term_stats` lists.
+pub const TERM_STATS_HEAVIEST: usize = 20;
+
+/// Statistics on the postings of a `LogDB`, returned by `LogDB::term_stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TermStats {
+    /// The number of tokens with a non-empty posting.
+    pub distinct_terms: usize,
+    /// The number of `(token, document)` entries in all postings.
+    pub postings_entries: usize,
+    /// The `TERM_STATS_HEAVIEST` tokens found in the most documents, most first: each
+    /// token, the word it stands for if the term dictionary knows it, and its number
+    /// of documents. Tokens of N-grams, levels, services and the words labelling them
+    /// have no word.
+    pub heaviest: Vec<(Tok, Option<String>, usize)>,
+}
+
+/// A deterministic view of the contents of a `LogDB`, returned by
+/// `LogDB::debug_snapshot`.
+///
+/// Everything is sorted, so two indexes holding the same documents and postings
+/// compare equal however their hash tables happen to be laid out, and the snapshot
+/// can be compared against a golden file through its `Debug` output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogDbSnapshot {
+    /// The content of each document, by ID.
+    pub docs: BTreeMap<DocId, String>,
+    /// The sorted document IDs of each non-empty posting whose token the term
+    /// dictionary knows the word of, by word.
+    pub postings: BTreeMap<String, Vec<DocId>>,
+    /// The sorted document IDs of each other non-empty posting, such as those of
+    /// levels, services and N-grams, by token.
+    pub unnamed_postings: BTreeMap<Tok, Vec<DocId>>,
+}
+
+/// What a `LogDB::bulk_load` did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BulkLoadStats {
+    /// The number of documents loaded.
+    pub docs: usize,
+    /// The number of distinct `(token, document)` pairs indexed.
+    pub tokens: usize,
+    /// The number of tokens no document held before the load.
+    pub new_postings: usize,
+    /// The number of documents whose content was truncated to
+    /// `LogConfig::max_content_bytes`.
+    pub truncated: usize,
+    /// The number of entries the ingest hook rejected, which were not loaded.
+    pub rejected: usize,
+    /// How long the load took. Always zero on `wasm32-unknown-unknown`, like the
+    /// query latency metrics.
+    pub took: Duration,
 }
 
 /// The main database structure for `LogDB`.
 ///
 /// This struct holds all the data necessary for indexing and searching log entries,
 /// including the token-to-document postings, document metadata, and various indexes.
+///
+/// Cloning a `LogDB` is cheap: the document table, the postings and the level,
+/// service and prefix indexes are reference-counted and shared between the clones,
+/// and each clone copies one of them only when it first writes to it while it is
+/// still shared. `LogDB::snapshot` wraps such a clone in a read-only view.
 #[derive(Debug, Clone)]
 pub struct LogDB {
     /// The tokenizer and hasher for processing log content.
     ufhg: UFHGHeadquarters,
     /// The postings list, mapping tokens to the documents that contain them.
-    postings: BugguHashSet<Tok, Posting>,
+    postings: Arc<BugguHashSet<Tok, Posting>>,
     /// A map from `DocId` to the `MetaEntry` containing the document's data.
-    docs: BugguHashSet<DocId, MetaEntry>,
+    docs: Arc<BugguHashSet<DocId, MetaEntry>>,
     /// An index for fast lookups of documents by log level.
-    level_index: BugguHashSet<Tok, Vec<DocId>>,
+    level_index: Arc<BugguHashSet<Tok, Posting>>,
     /// An index for fast lookups of documents by service name.
-    service_index: BugguHashSet<Tok, Vec<DocId>>,
+    service_index: Arc<BugguHashSet<Tok, Posting>>,
     /// The next available document ID.
     next_doc_id: DocId,
-    /// The maximum number of postings to hold in memory.
+    /// The maximum number of postings to hold in memory, enforced by evicting the
+    /// oldest as `config.eviction_policy` chooses.
     max_postings: usize,
-    /// The time in seconds after which a document is considered stale.
+    /// The age of each posting for eviction: the tick at which its token was first
+    /// indexed or, with `EvictionPolicy::Lru`, last looked up by a query. Empty with
+    /// `EvictionPolicy::Never`.
+    token_ages: BugguHashSet<Tok, Cell<u64>>,
+    /// The tick handed out to the next posting indexed or looked up.
+    age_clock: Cell<u64>,
+    /// The time in seconds after which a document is considered stale. Not enforced
+    /// yet.
+    #[allow(dead_code)]
     stale_secs: u64,
+    /// Maps the hash of each word in a synonym ring to the ring's index in
+    /// `config.synonyms`, compiled from the configuration.
+    synonyms: BugguHashSet<Tok, usize>,
     /// The configuration for the `LogDB` instance.
     config: LogConfig,
+    /// The generation counter, bumped on every document change.
+    generation: u64,
+    /// Deleted documents with the generation of their deletion, kept for
+    /// incremental backups.
+    tombstones: Vec<(DocId, u64)>,
+    /// Operation counters and query latencies, exported by `metrics_text`.
+    metrics: Metrics,
+    /// The most recent queries with their latencies, returned by `slow_queries`.
+    query_log: QueryLog,
+    /// Parsed queries, by query string, reused by `plan`.
+    query_cache: QueryCache,
+    /// The clock used to timestamp entries added through `upsert_log`.
+    clock: fn() -> u64,
+    /// Every word seen in document content, keyed by its lowercase spelling and
+    /// sorted for prefix lookups, with the token it was indexed under.
+    prefix_index: Arc<BTreeMap<String, Tok>>,
+    /// The word behind each token of document content, returned by `term_dict`.
+    term_dict: Arc<TermDict>,
+    /// The tokens of the words in the prefix index, by the Soundex code of the word,
+    /// for `sounds:` queries. Empty unless `LogConfig::enable_phonetic` is set.
+    phonetic_index: BTreeMap<String, Vec<Tok>>,
+    /// Parsed query templates, by name, registered with `register_template`.
+    templates: BTreeMap<String, QueryNode>,
+    /// The names of document metadata fields, indexed by field ID.
+    field_names: Vec<String>,
+    /// The ID of each document metadata field name.
+    field_ids: BTreeMap<String, u32>,
+    /// The hook run on each entry before it is indexed, set by `set_ingest_hook`.
+    ingest_hook: Option<SharedIngestHook>,
 }
 
 /// Represents a posting for a single token.
 ///
-/// A posting contains a list of document IDs that are associated with a specific
-/// token. To optimize for memory and performance, it uses a `SmallVec` for small
-/// lists and switches to a `BugguHashSet` for larger ones.
+/// A posting contains the document IDs associated with a specific token. Most
+/// tokens appear in a single document, so the posting moves through three states
+/// as it grows: a lone inline ID, a vector for small lists, and a boxed
+/// `BugguHashSet` for larger ones. This keeps a posting at 24 bytes with no heap
+/// allocation in the single-document case.
 #[derive(Debug, Clone)]
-pub struct Posting {
-    /// A small vector for storing document IDs, optimized for a small number of entries.
-    small_docs: SmallVec<[DocId; 4]>,
-    /// An optional hash set for storing a large number of document IDs.
-    large_docs: Option<BugguHashSet<DocId, ()>>,
+pub enum Posting {
+    /// Exactly one document.
+    Single(DocId),
+    /// Up to 128 documents, in ascending order. The posting is empty when the
+    /// vector is.
+    Small(Vec<DocId>),
+    /// More than 128 documents.
+    Large(Box<BugguHashSet<DocId, ()>>),
 }
 
 impl Posting {
     /// Creates a new, empty `Posting`.
     #[inline]
     fn new() -> Self {
-        Self {
-            small_docs: SmallVec::new(),
-            large_docs: None,
+        Posting::Small(Vec::new())
+    }
+
+    /// Creates a posting holding `docs`, which must be ascending and free of
+    /// duplicates, in the state `add` would have reached after adding them one by
+    /// one.
+    fn from_docs(docs: Vec<DocId>) -> Self {
+        debug_assert!(docs.is_sorted());
+        match docs.as_slice() {
+            [only] => Posting::Single(*only),
+            _ if docs.len() <= 128 => Posting::Small(docs),
+            _ => {
+                let mut large = BugguHashSet::new(docs.len());
+                for id in docs {
+                    large.insert(id, ());
+                }
+                Posting::Large(Box::new(large))
+            }
         }
     }
 
     /// Adds a document ID to the posting.
     ///
-    /// This method handles the logic of switching from `small_docs` to `large_docs`
-    /// when the number of documents exceeds a certain threshold.
+    /// This method handles the logic of moving to the next state when the number
+    /// of documents outgrows the current one. A `Small` posting is kept sorted, so
+    /// the duplicate check is a binary search, and an ID above every other, as newly
+    /// inserted documents have, is appended after a single comparison.
     #[inline]
     fn add(&mut self, id: DocId) {
-        if let Some(ref mut large) = self.large_docs {
-            large.insert(id, ());
-        } else if self.small_docs.len() < 128 {
-            if !self.small_docs.contains(&id) {
-                self.small_docs.push(id);
+        match self {
+            Posting::Single(existing) => {
+                if *existing != id {
+                    let (lo, hi) = if *existing < id {
+                        (*existing, id)
+                    } else {
+                        (id, *existing)
+                    };
+                    *self = Posting::Small(vec![lo, hi]);
+                }
             }
-        } else {
-            let mut large = BugguHashSet::new(512);
-            for &doc_id in &self.small_docs {
-                large.insert(doc_id, ());
+            Posting::Small(docs) if docs.is_empty() => *self = Posting::Single(id),
+            // A full list takes an ID it already holds without being promoted.
+            Posting::Small(docs) if docs.len() < 128 || docs.binary_search(&id).is_ok() => {
+                if docs.last().is_some_and(|&last| last < id) {
+                    docs.push(id);
+                } else if let Err(pos) = docs.binary_search(&id) {
+                    docs.insert(pos, id);
+                }
+            }
+            Posting::Small(docs) => {
+                let mut large = BugguHashSet::new(512);
+                for &doc_id in docs.iter() {
+                    large.insert(doc_id, ());
+                }
+                large.insert(id, ());
+                *self = Posting::Large(Box::new(large));
+            }
+            Posting::Large(large) => {
+                large.insert(id, ());
             }
-            large.insert(id, ());
-            self.large_docs = Some(large);
-            self.small_docs.clear();
         }
     }
 
     /// Removes a document ID from the posting.
     #[inline]
     fn remove(&mut self, id: DocId) {
-        if let Some(ref mut large) = self.large_docs {
-            large.remove(&id);
-        } else {
-            self.small_docs.retain(|d| *d != id);
+        match self {
+            Posting::Single(existing) => {
+                if *existing == id {
+                    *self = Posting::new();
+                }
+            }
+            Posting::Small(docs) => {
+                if let Ok(pos) = docs.binary_search(&id) {
+                    docs.remove(pos);
+                }
+                if let [only] = docs.as_slice() {
+                    *self = Posting::Single(*only);
+                }
+            }
+            Posting::Large(large) => {
+                large.remove(&id);
+            }
         }
     }
 
-    /// Converts the posting to a `BugguHashSet` of document IDs.
-    #[inline]
-    fn to_set(&self) -> BugguHashSet<DocId, ()> {
-        if let Some(ref large) = self.large_docs {
-            large.clone()
+    /// Adds every document ID in `other` to the posting.
+    ///
+    /// A small posting merged with a large one starts from a copy of the large set,
+    /// so only the smaller side is inserted one ID at a time. The result moves to the
+    /// large state when the union outgrows 128 documents.
+    #[allow(dead_code)]
+    fn merge(&mut self, other: &Posting) {
+        if let (Posting::Large(large), false) = (other, matches!(self, Posting::Large(_))) {
+            let small = std::mem::replace(self, Posting::Large(large.clone()));
+            for &id in small.as_slice() {
+                self.add(id);
+            }
+            return;
+        }
+        match other {
+            Posting::Large(large) => {
+                for id in large.iter_keys() {
+                    self.add(id);
+                }
+            }
+            _ => {
+                for &id in other.as_slice() {
+                    self.add(id);
+                }
+            }
+        }
+    }
+
+    /// Returns the document IDs in both this posting and `other`, without building a
+    /// set for either.
+    ///
+    /// The smaller posting is walked and each ID looked up in the larger, in O(1)
+    /// when the larger is a set. Two lists are intersected by binary search in the
+    /// larger, which is already sorted. The IDs come in the order of the smaller
+    /// posting.
+    fn intersect(&self, other: &Posting) -> Vec<DocId> {
+        let (smaller, larger) = if self.len() <= other.len() {
+            (self, other)
         } else {
-            let mut set = BugguHashSet::new(self.small_docs.len().max(8));
-            for &id in &self.small_docs {
-                set.insert(id, ());
+            (other, self)
+        };
+        match (smaller, larger) {
+            (Posting::Large(small), Posting::Large(large)) => small
+                .iter_keys()
+                .filter(|id| large.get(id).is_some())
+                .collect(),
+            (_, Posting::Large(large)) => smaller
+                .as_slice()
+                .iter()
+                .copied()
+                .filter(|id| large.get(id).is_some())
+                .collect(),
+            _ => {
+                let sorted = larger.as_slice();
+                smaller
+                    .as_slice()
+                    .iter()
+                    .copied()
+                    .filter(|id| sorted.binary_search(id).is_ok())
+                    .collect()
             }
-            set
         }
     }
 
-    /// Returns a vector of all document IDs in the posting.
+    /// Returns a vector of all document IDs in the posting, ascending for a `Single`
+    /// or `Small` posting. Use `iter` to walk them without the copy.
     #[inline]
     fn get_docs(&self) -> Vec<DocId> {
-        if let Some(ref large) = self.large_docs {
-            large.keys()
-        } else {
-            self.small_docs.to_vec()
+        match self {
+            Posting::Large(large) => large.keys(),
+            _ => self.as_slice().to_vec(),
+        }
+    }
+
+    /// Returns an iterator over the document IDs in the posting, in ascending order
+    /// for a `Single` or `Small` posting and in table order for a `Large` one.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = DocId> + '_ {
+        let large = match self {
+            Posting::Large(large) => Some(large.iter_keys()),
+            _ => None,
+        };
+        self.as_slice()
+            .iter()
+            .copied()
+            .chain(large.into_iter().flatten())
+    }
+
+    /// Passes each document ID in the posting to `f`, stopping early when it
+    /// returns `false`.
+    ///
+    /// # Returns
+    /// `false` if `f` stopped the iteration, `true` otherwise.
+    #[inline]
+    fn for_each<F: FnMut(DocId) -> bool>(&self, f: &mut F) -> bool {
+        match self {
+            Posting::Large(large) => large.iter_keys().all(f),
+            _ => self.as_slice().iter().all(|&id| f(id)),
+        }
+    }
+
+    /// Returns the document IDs of a `Single` or `Small` posting, or an empty slice
+    /// for a `Large` one.
+    #[inline]
+    fn as_slice(&self) -> &[DocId] {
+        match self {
+            Posting::Single(id) => std::slice::from_ref(id),
+            Posting::Small(docs) => docs,
+            Posting::Large(_) => &[],
+        }
+    }
+
+    /// Checks whether the posting contains the given document ID, by a binary
+    /// search of a `Small` posting and a lookup in a `Large` one.
+    #[inline]
+    pub fn contains(&self, id: DocId) -> bool {
+        match self {
+            Posting::Large(large) => large.get(&id).is_some(),
+            _ => self.as_slice().binary_search(&id).is_ok(),
+        }
+    }
+
+    /// Returns the number of document IDs in the posting, without copying them.
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self {
+            Posting::Large(large) => large.len(),
+            _ => self.as_slice().len(),
         }
     }
 
     /// Checks if the posting is empty.
     #[inline]
-    fn empty(&self) -> bool {
-        if let Some(ref large) = self.large_docs {
-            large.is_empty()
-        } else {
-            self.small_docs.is_empty()
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Posting::Single(_) => false,
+            Posting::Small(docs) => docs.is_empty(),
+            Posting::Large(large) => large.is_empty(),
+        }
+    }
+
+    /// Returns the heap memory held by the posting, in bytes.
+    fn heap_bytes(&self) -> usize {
+        match self {
+            Posting::Single(_) => 0,
+            Posting::Small(ids) => ids.capacity() * std::mem::size_of::<DocId>(),
+            Posting::Large(set) => set.table_bytes(),
         }
     }
 
     /// Retains only the document IDs that are present in the provided set of documents.
     #[inline]
+    #[allow(dead_code)]
     fn retain_docs(&mut self, docs: &BugguHashSet<DocId, MetaEntry>) {
-        if let Some(ref mut large) = self.large_docs {
-            large.retain(|id, _| docs.get(id).is_some());
-        } else {
-            self.small_docs.retain(|id| docs.get(id).is_some());
+        match self {
+            Posting::Single(id) => {
+                if docs.get(id).is_none() {
+                    *self = Posting::new();
+                }
+            }
+            Posting::Small(ids) => ids.retain(|id| docs.get(id).is_some()),
+            Posting::Large(large) => {
+                large.retain(|id, _| docs.get(id).is_some());
+            }
         }
     }
 }
@@ -193,330 +784,6458 @@ impl Default for Posting {
     }
 }
 
+impl Extend<DocId> for Posting {
+    /// Adds each document ID, as `add` does.
+    fn extend<I: IntoIterator<Item = DocId>>(&mut self, ids: I) {
+        for id in ids {
+            self.add(id);
+        }
+    }
+}
+
+impl Default for LogDB {
+    /// Creates a new `LogDB` with a default configuration.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LogDB {
     /// Creates a new `LogDB` with a default configuration.
     pub fn new() -> Self {
-        Self {
-            ufhg: UFHGHeadquarters::new(),
-            postings: BugguHashSet::new(40000),
-            docs: BugguHashSet::new(50000),
-            level_index: BugguHashSet::new(40000),
-            service_index: BugguHashSet::new(40000),
-            next_doc_id: 1,
-            max_postings: 32_000,
-            stale_secs: 3600,
-            config: LogConfig::default(),
-        }
+        Self::with_config(LogConfig::default())
     }
 
     /// Creates a new `LogDB` with the given configuration.
     pub fn with_config(config: LogConfig) -> Self {
         Self {
-            ufhg: UFHGHeadquarters::new(),
-            postings: BugguHashSet::new(40000),
-            docs: BugguHashSet::new(50000),
-            level_index: BugguHashSet::new(40000),
-            service_index: BugguHashSet::new(40000),
-            next_doc_id: 1,
+            ufhg: UFHGHeadquarters::with_whitespace_tokens(config.index_whitespace)
+                .with_min_token_len(config.min_token_len),
+            postings: Arc::new(BugguHashSet::growable(config.initial_capacity)),
+            docs: Arc::new(BugguHashSet::growable(config.initial_capacity)),
+            level_index: Arc::new(BugguHashSet::growable(config.initial_capacity)),
+            service_index: Arc::new(BugguHashSet::growable(config.initial_capacity)),
+            next_doc_id: DocId(1),
             max_postings: config.max_postings,
+            token_ages: BugguHashSet::new(64),
+            age_clock: Cell::new(0),
             stale_secs: config.stale_secs,
+            synonyms: config.synonym_lookup(),
+            query_log: QueryLog::new(config.query_log_capacity),
+            query_cache: QueryCache::new(config.query_cache_capacity),
+            term_dict: Arc::new(TermDict::new(config.term_dict_capacity)),
             config,
+            generation: 0,
+            tombstones: Vec::new(),
+            metrics: Metrics::default(),
+            clock: now_secs,
+            prefix_index: Arc::default(),
+            phonetic_index: BTreeMap::new(),
+            templates: BTreeMap::new(),
+            field_names: Vec::new(),
+            field_ids: BTreeMap::new(),
+            ingest_hook: None,
         }
     }
 
+    /// Creates a new `LogDB` whose document and postings tables are sized for
+    /// `expected_docs` documents, so a bulk load of that many does not pay for the
+    /// tables doubling along the way.
+    pub fn with_capacity(expected_docs: usize) -> Self {
+        let mut db = Self::new();
+        db.docs = Arc::new(BugguHashSet::growable_for(expected_docs));
+        db.postings = Arc::new(BugguHashSet::growable_for(expected_docs));
+        db
+    }
+
     /// Creates a new `LogDB` from a configuration file.
     pub fn from_config_file(path: &str) -> std::io::Result<Self> {
         let config = LogConfig::from_file(path)?;
         Ok(Self::with_config(config))
     }
 
+    /// Sets the clock used to timestamp entries added through `upsert_log` and
+    /// `upsert_simple`, in seconds since the Unix epoch.
+    ///
+    /// The default clock reads the system time. Hosts without one, such as some
+    /// WebAssembly runtimes, or tests that need fixed timestamps can supply their own.
+    pub fn set_clock(&mut self, clock: fn() -> u64) {
+        self.clock = clock;
+    }
+
     /// Inserts or updates a log entry with the given content and metadata.
+    ///
+    /// The entry is indexed in `TokenMode::Mixed`, so both its words and any
+    /// structured `key:value` fields are searchable.
+    ///
+    /// The entry is timestamped with the clock set by `set_clock`.
+    ///
+    /// # Returns
+    /// The document ID, or `IngestError::Rejected` with the reason the ingest hook
+    /// gave if it rejected the entry.
     pub fn upsert_log(
         &mut self,
         content: &str,
         level: Option<String>,
         service: Option<String>,
-    ) -> DocId {
-        let descriptor = match (&level, &service) {
-            (Some(l), Some(s)) => format!("level {l} service {s} content {content}"),
-            (Some(l), None) => format!("level {l} content {content}"),
-            (None, Some(s)) => format!("service {s} content {content}"),
-            (None, None) => format!("content {content}"),
-        };
+    ) -> Result<DocId, IngestError> {
+        let timestamp = (self.clock)();
+        self.upsert_log_at(content, level, service, timestamp)
+    }
 
-        let (_, token_slice_cloned) = self.ufhg.tokenize_zero_copy(&descriptor);
-        let doc_id = self.next_doc_id;
-        self.next_doc_id += 1;
+    /// Inserts a `LogEntry`, tokenizing its content according to its `TokenMode`.
+    ///
+    /// - `Structured` indexes only the `key:value` fields found in the content.
+    /// - `FullText` indexes every word, plus word N-grams when `enable_ngrams` is set.
+    /// - `Mixed` does both.
+    ///
+    /// In every mode the level and services are added to their dedicated indexes.
+    /// Unless `content_only` is set, the word modes also index them as words, labeled
+    /// by the words "level", "service" and "content". The entry's own timestamp is stored
+    /// rather than the time of insertion.
+    ///
+    /// Content over `LogConfig::max_content_bytes` is truncated before it is
+    /// tokenized; `upsert_entry_outcome` reports whether it was.
+    ///
+    /// An entry rejected by the ingest hook is not inserted, and
+    /// `IngestError::Rejected` is returned with the reason the hook gave. The same
+    /// holds for every other `upsert_*` method.
+    pub fn upsert_entry(&mut self, entry: LogEntry) -> Result<DocId, IngestError> {
+        self.upsert_entry_outcome(entry).map(|outcome| outcome.id)
+    }
 
-        let entry = MetaEntry {
-            tokens: token_slice_cloned.clone(),
-            level: level.clone(),
-            service: service.clone(),
-            content: content.to_string(),
-        };
+    /// Inserts a `LogEntry` like `upsert_entry`, returning whether its content was
+    /// truncated along with its ID, or `IngestError::Rejected` with the reason the
+    /// ingest hook gave for rejecting it.
+    pub fn upsert_entry_outcome(
+        &mut self,
+        mut entry: LogEntry,
+    ) -> Result<UpsertOutcome, IngestError> {
+        self.run_ingest_hook(&mut entry)
+            .map_err(IngestError::Rejected)?;
+        let mut entry = self.prepare_entry(entry);
+        let truncated = entry.truncated_from.is_some();
 
-        self.docs.insert(doc_id, entry);
+        let doc_id = self.next_doc_id;
+        self.next_doc_id = DocId(doc_id.get() + 1);
 
         // Update postings
-        for &tok in &token_slice_cloned {
-            self.postings
-                .entry(tok)
-                .or_insert_with(Posting::new)
-                .add(doc_id);
-        }
+        self.index_tokens(doc_id, &entry.tokens);
+        self.index_words(&entry.content, true);
+        entry.cap_content(self.config.max_content_len);
 
         // Update indexes
-        if let Some(ref level_val) = level {
-            self.level_index
+        if let Some(ref level_val) = entry.level {
+            Arc::make_mut(&mut self.level_index)
                 .entry(lightning_hash_str(level_val))
-                .or_insert_with(Vec::new)
-                .push(doc_id);
+                .or_default()
+                .add(doc_id);
         }
-        if let Some(ref service_val) = service {
-            self.service_index
+        for service_val in entry.service.iter().chain(&entry.extra_services) {
+            Arc::make_mut(&mut self.service_index)
                 .entry(lightning_hash_str(service_val))
-                .or_insert_with(Vec::new)
-                .push(doc_id);
+                .or_default()
+                .add(doc_id);
         }
 
-        doc_id
-    }
-
-    /// Inserts or updates a simple log entry with only content.
-    pub fn upsert_simple(&mut self, content: &str) -> DocId {
-        self.upsert_log(content, None, None)
-    }
-
-    /// Executes a query and returns the matching document IDs.
-    pub fn query(&self, q: &str) -> Vec<DocId> {
-        let ast = parse_query(q, &self.config);
-        self.exec(&ast)
-    }
+        Arc::make_mut(&mut self.docs).insert(doc_id, entry);
+        self.touch(doc_id);
+        self.evict_postings();
+        self.metrics.record_insert();
 
-    /// Retrieves the content of a document by its ID.
-    pub fn get_content(&self, doc_id: &DocId) -> Option<String> {
-        self.docs.get(doc_id).map(|e| e.content.clone())
+        Ok(UpsertOutcome {
+            id: doc_id,
+            truncated,
+        })
     }
 
-    /// Executes a query and returns the content of the matching documents.
-    pub fn query_content(&self, q: &str) -> Vec<String> {
-        let doc_ids = self.query(q);
-        doc_ids
-            .into_iter()
-            .filter_map(|id| self.get_content(&id))
-            .collect()
+    /// Sets the hook run on every entry inserted by the `upsert_*` methods and
+    /// `bulk_load` before it is indexed, replacing any set before.
+    ///
+    /// The hook may change the entry, such as to clean up its content, or reject it
+    /// with `HookDecision::Reject`, in which case the entry is not inserted and the
+    /// rejection is counted in `Metrics::rejections`. Frames applied from a primary
+    /// and restored backups are not passed through the hook, since the primary already
+    /// did. Clones of the `LogDB` share the hook. `IngestRules::into_hook` builds a
+    /// hook from declarative checks.
+    pub fn set_ingest_hook(&mut self, hook: IngestHook) {
+        self.ingest_hook = Some(SharedIngestHook(Arc::new(Mutex::new(hook))));
     }
 
-    /// Executes a query and returns the matching documents with their metadata.
-    pub fn query_with_meta(&self, q: &str) -> Vec<(DocId, String, Option<String>, Option<String>)> {
-        let ast = parse_query(q, &self.config);
-        let docs = self.exec(&ast);
-        docs.into_iter()
-            .filter_map(|id| {
-                self.docs
-                    .get(&id)
-                    .map(|e| (id, e.content.clone(), e.level.clone(), e.service.clone()))
-            })
-            .collect()
+    /// Removes the hook set by `set_ingest_hook`.
+    pub fn clear_ingest_hook(&mut self) {
+        self.ingest_hook = None;
     }
 
-    /// Cleans up stale documents from the database.
-    pub fn cleanup_stale(&mut self) {}
-
-    /// Rebuilds the indexes for log levels and services.
-    pub fn rebuild_indexes(&mut self) {
-        self.level_index = self
-            .docs
-            .create_index_for(|entry| entry.level.as_ref().map(|s| lightning_hash_str(s.as_str())));
-        self.service_index = self.docs.create_index_for(|entry| {
-            entry
-                .service
-                .as_ref()
-                .map(|s| lightning_hash_str(s.as_str()))
-        });
+    /// Runs the ingest hook on an entry, returning the reason it gave if it rejected
+    /// it.
+    fn run_ingest_hook(&self, entry: &mut LogEntry) -> Result<(), String> {
+        let Some(SharedIngestHook(hook)) = &self.ingest_hook else {
+            return Ok(());
+        };
+        let mut hook = hook.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match hook(entry) {
+            HookDecision::Accept | HookDecision::Modify => Ok(()),
+            HookDecision::Reject(reason) => {
+                self.metrics.record_rejection();
+                Err(reason)
+            }
+        }
     }
 
-    /// Executes a query AST node and returns the matching document IDs.
-    fn exec(&self, node: &QueryNode) -> Vec<DocId> {
-        match node {
-            QueryNode::Term(w) | QueryNode::Contains(w) => {
-                let hash = lightning_hash_str(w);
-                self.postings
-                    .get(&hash)
-                    .map(|p| p.get_docs())
-                    .unwrap_or_default()
+    /// Tokenizes a `LogEntry` as `upsert_entry` does, cleaning and truncating its
+    /// content and dropping repeated services, and returns the document to store for
+    /// it. The content is left whole, for its words to be indexed before
+    /// `MetaEntry::cap_content` cuts it to `LogConfig::max_content_len`.
+    fn prepare_entry(&mut self, entry: LogEntry) -> MetaEntry {
+        let LogEntry {
+            content,
+            timestamp,
+            level,
+            service,
+            extra_services,
+            fields,
+            mode,
+            content_only,
+            ..
+        } = entry;
+        let mut extra = Vec::with_capacity(extra_services.len());
+        for s in extra_services {
+            if service.as_ref() != Some(&s) && !extra.contains(&s) {
+                extra.push(s);
             }
+        }
+        let extra_services = extra;
 
-            QueryNode::Phrase(p) => {
-                let seq_hash = self.ufhg.string_to_u64_to_seq_hash(p);
-                self.postings
-                    .get(&seq_hash)
-                    .map(|p| p.get_docs())
-                    .unwrap_or_default()
+        let mut content = content;
+        let mut field_values = Vec::with_capacity(fields.len());
+        for (name, value) in fields {
+            let field = self.intern_field(&name);
+            if field_values.contains(&(field, value.clone())) {
+                continue;
             }
+            if !content.is_empty() {
+                content.push(' ');
+            }
+            content.push_str(&name);
+            content.push(':');
+            content.push_str(&value);
+            field_values.push((field, value));
+        }
 
-            QueryNode::FieldTerm(f, v) => match *f {
-                "level" => self.filter_by_level(v),
-                "service" => self.filter_by_service(v),
-                _ => {
-                    let field_set = self.get_term_set(&lightning_hash_str(f));
-                    let value_set = self.get_term_set(&lightning_hash_str(v));
-                    field_set.intersect_with(&value_set).keys()
+        let mut raw_content = None;
+        if self.config.strip_ansi {
+            if let Cow::Owned(clean) = strip_ansi_and_controls(&content) {
+                let raw = std::mem::replace(&mut content, clean);
+                if self.config.keep_raw_content {
+                    let raw = truncate_content(raw, self.config.max_content_bytes).0;
+                    raw_content = Some(cap_content(raw, self.config.max_content_len));
                 }
-            },
+            }
+        }
 
-            QueryNode::And(children) => {
-                if children.is_empty() {
-                    return Vec::new();
+        let original_len = content.len();
+        let (content, truncated_from) =
+            match truncate_content(content, self.config.max_content_bytes) {
+                (content, true) => {
+                    self.metrics.record_truncation();
+                    (content, Some(original_len))
                 }
+                (content, false) => (content, None),
+            };
 
-                let mut result_set = self.exec_to_set(&children[0]);
-                for child in &children[1..] {
-                    let other_set = self.exec_to_set(child);
-                    result_set = result_set.intersect_with(&other_set);
-                    if result_set.is_empty() {
-                        break;
-                    }
-                }
-                result_set.keys()
-            }
+        let tokens = entry_tokens(
+            &mut self.ufhg,
+            &self.config,
+            &content,
+            level.as_deref(),
+            service_words(service.as_deref(), &extra_services).as_deref(),
+            mode,
+            content_only,
+        );
 
-            QueryNode::Or(children) => {
-                if children.is_empty() {
-                    return Vec::new();
-                }
+        MetaEntry {
+            tokens,
+            level,
+            service,
+            extra_services,
+            content,
+            timestamp,
+            mode,
+            content_only,
+            generation: 0,
+            truncated_from,
+            fields: field_values,
+            raw_content,
+            capped: false,
+        }
+    }
 
-                let mut result_set = self.exec_to_set(&children[0]);
-                for child in &children[1..] {
-                    let other_set = self.exec_to_set(child);
-                    result_set = result_set.union_with(&other_set);
+    /// Returns the ID of a document metadata field name, assigning the next one if
+    /// the name is new.
+    fn intern_field(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.field_ids.get(name) {
+            return id;
+        }
+        let id = self.field_names.len() as u32;
+        self.field_names.push(name.to_string());
+        self.field_ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Returns the metadata fields of a document as `(name, value)` pairs, in the
+    /// order they were given, with a name repeated once per value. Empty if the
+    /// document has no fields or does not exist.
+    ///
+    /// Only the `LogDB` that ingested the document knows its fields; replicas and
+    /// restored backups hold the `name:value` words in the content alone.
+    pub fn fields(&self, doc_id: &DocId) -> Vec<(&str, &str)> {
+        self.docs
+            .get(doc_id)
+            .map(|entry| {
+                entry
+                    .fields
+                    .iter()
+                    .map(|(field, value)| {
+                        (self.field_names[*field as usize].as_str(), value.as_str())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Inserts many entries at once, for restoring snapshots and loading large
+    /// datasets.
+    ///
+    /// Each entry is tokenized and stored as by `upsert_entry`, and queries match the
+    /// loaded documents exactly as if they had been inserted one by one. Rather than
+    /// probing the postings table for every token of every document, the load
+    /// collects `(token, document)` pairs, sorts them by token and builds each
+    /// posting in one step; the level and service indexes are built the same way.
+    /// The document table is sized up front from the iterator's size hint.
+    ///
+    /// With an eviction policy, postings over `max_postings` are evicted once at the
+    /// end of the load rather than as documents arrive, so which tokens survive can
+    /// differ from inserting the entries one by one.
+    ///
+    /// Entries rejected by the ingest hook are skipped and counted in the returned
+    /// stats.
+    pub fn bulk_load(&mut self, docs: impl Iterator<Item = LogEntry>) -> BulkLoadStats {
+        let timer = QueryTimer::start();
+        Arc::make_mut(&mut self.docs).reserve(docs.size_hint().0);
+        let mut pairs: Vec<(Tok, DocId)> = Vec::new();
+        let mut levels: Vec<(Tok, DocId)> = Vec::new();
+        let mut services: Vec<(Tok, DocId)> = Vec::new();
+        // The tokens whose words have been seen so far in the load.
+        let mut seen: BugguHashSet<Tok, ()> = BugguHashSet::growable(256);
+        let mut loaded = 0;
+        let mut truncated = 0;
+        let mut rejected = 0;
+        for mut entry in docs {
+            if self.run_ingest_hook(&mut entry).is_err() {
+                rejected += 1;
+                continue;
+            }
+            let mut entry = self.prepare_entry(entry);
+            truncated += usize::from(entry.truncated_from.is_some());
+            let doc_id = self.next_doc_id;
+            self.next_doc_id = DocId(doc_id.get() + 1);
+
+            pairs.extend(entry.tokens.iter().map(|&tok| (tok, doc_id)));
+            if let Some(ref level_val) = entry.level {
+                levels.push((lightning_hash_str(level_val), doc_id));
+            }
+            for service_val in entry.service.iter().chain(&entry.extra_services) {
+                services.push((lightning_hash_str(service_val), doc_id));
+            }
+
+            // The postings are not built yet, so a word is added to the prefix index
+            // when it is the first seen for a token no document held before the load,
+            // as `index_words` would add it.
+            self.index_words_where(&entry.content, |db, tok| {
+                seen.insert(tok, ()).is_none()
+                    && db.postings.get(&tok).is_none_or(Posting::is_empty)
+            });
+            entry.cap_content(self.config.max_content_len);
+            self.generation += 1;
+            entry.generation = self.generation;
+            Arc::make_mut(&mut self.docs).insert(doc_id, entry);
+            self.metrics.record_insert();
+            loaded += 1;
+        }
+        if loaded == 0 {
+            return BulkLoadStats {
+                rejected,
+                ..BulkLoadStats::default()
+            };
+        }
+
+        // Document IDs increase through the load, so sorting by token keeps each
+        // token's documents in ascending order, as `Posting::add` would hold them.
+        pairs.sort_by_key(|&(tok, _)| tok);
+        pairs.dedup();
+        let tokens = pairs.len();
+        let track = self.config.eviction_policy != EvictionPolicy::Never;
+        let mut new_postings = 0;
+        let groups: Vec<&[(Tok, DocId)]> = pairs.chunk_by(|a, b| a.0 == b.0).collect();
+        Arc::make_mut(&mut self.postings).reserve(groups.len());
+        for group in groups {
+            let tok = group[0].0;
+            let posting = Arc::make_mut(&mut self.postings).entry(tok).or_default();
+            if posting.is_empty() {
+                *posting = Posting::from_docs(group.iter().map(|&(_, id)| id).collect());
+                new_postings += 1;
+                if track {
+                    self.token_ages.insert(tok, Cell::new(self.tick()));
+                }
+            } else {
+                for &(_, id) in group {
+                    posting.add(id);
                 }
-                result_set.keys()
             }
+        }
 
-            QueryNode::Not(child) => {
-                let exclude_set = self.exec_to_set(child);
-                let all_docs_set = self.create_all_docs_set();
-                all_docs_set.fast_difference(&exclude_set).keys()
+        for (index, mut pairs) in [
+            (Arc::make_mut(&mut self.level_index), levels),
+            (Arc::make_mut(&mut self.service_index), services),
+        ] {
+            pairs.sort_unstable();
+            pairs.dedup();
+            for group in pairs.chunk_by(|a, b| a.0 == b.0) {
+                let posting = index.entry(group[0].0).or_default();
+                if posting.is_empty() {
+                    *posting = Posting::from_docs(group.iter().map(|&(_, id)| id).collect());
+                } else {
+                    posting.extend(group.iter().map(|&(_, id)| id));
+                }
             }
+        }
 
-            _ => Vec::new(),
+        self.evict_postings();
+
+        BulkLoadStats {
+            docs: loaded,
+            tokens,
+            new_postings,
+            truncated,
+            rejected,
+            took: timer.elapsed(),
         }
     }
 
-    /// Executes a query AST node and returns the results as a `BugguHashSet`.
-    fn exec_to_set(&self, node: &QueryNode) -> BugguHashSet<DocId, ()> {
-        let docs = self.exec(node);
-        let mut set = BugguHashSet::new(docs.len().max(8));
-        for id in docs {
-            set.insert(id, ());
+    /// Inserts a log entry with an explicit timestamp instead of the current time.
+    ///
+    /// The timestamp is stored verbatim, so log forwarders replaying historical data
+    /// can keep the original event time and `timestamp:>=` / `timestamp:<=` queries
+    /// match against it.
+    pub fn upsert_log_at(
+        &mut self,
+        content: &str,
+        level: Option<String>,
+        service: Option<String>,
+        timestamp: u64,
+    ) -> Result<DocId, IngestError> {
+        let mut entry =
+            LogEntry::with_metadata(content.to_string(), level, service, TokenMode::Mixed);
+        entry.timestamp = timestamp;
+        self.upsert_entry(entry)
+    }
+
+    /// Inserts a log entry tagged with several services, such as a request that
+    /// spanned them, so that a `service:` query for any of them matches it. The first
+    /// service is the one `MetaEntry::service` returns, and repeated ones are indexed
+    /// once.
+    ///
+    /// Like `upsert_log`, the entry is indexed in `TokenMode::Mixed` and timestamped
+    /// with the clock set by `set_clock`.
+    pub fn upsert_log_services(
+        &mut self,
+        content: &str,
+        level: Option<String>,
+        services: Vec<String>,
+    ) -> Result<DocId, IngestError> {
+        let mut services = services.into_iter();
+        let mut entry = LogEntry::with_metadata(
+            content.to_string(),
+            level,
+            services.next(),
+            TokenMode::Mixed,
+        );
+        entry.extra_services = services.collect();
+        entry.timestamp = (self.clock)();
+        self.upsert_entry(entry)
+    }
+
+    /// Inserts or updates a simple log entry with only content.
+    pub fn upsert_simple(&mut self, content: &str) -> Result<DocId, IngestError> {
+        self.upsert_log(content, None, None)
+    }
+
+    /// Inserts a log entry like `upsert_log`, but tokenizes only its content.
+    ///
+    /// The level and service are kept out of the postings and are searchable only
+    /// through `level:` and `service:` queries, so a bare `error` query matches
+    /// documents that mention the word rather than every `ERROR`-level document, and
+    /// the words "level", "service" and "content" match only documents containing
+    /// them.
+    pub fn upsert_v2(
+        &mut self,
+        content: &str,
+        level: Option<String>,
+        service: Option<String>,
+    ) -> Result<DocId, IngestError> {
+        let mut entry =
+            LogEntry::with_metadata(content.to_string(), level, service, TokenMode::Mixed);
+        entry.timestamp = (self.clock)();
+        entry.content_only = true;
+        self.upsert_entry(entry)
+    }
+
+    /// Inserts a log entry given as a JSON object, such as
+    /// `{"level":"ERROR","service":"auth","msg":"login failed","user":{"id":42}}`.
+    ///
+    /// The `level` and `service` keys become the entry's level and service, and the
+    /// `message` key, or `msg` or `content`, its content. Every other string, number or
+    /// boolean becomes a field of the entry, as in `upsert_fields`, with the keys of
+    /// nested objects joined by dots, so the example is stored as
+    /// `login failed user.id:42` and matches `user.id:42` and `has:user.id` as well as
+    /// `failed`. An array of scalars gives the field one value per element, so
+    /// `"tag":["db","slow"]` matches both `tag:db` and `tag:slow`. Nulls, and objects
+    /// and arrays inside arrays, are skipped. The entry is indexed like one added by
+    /// `upsert_log`.
+    ///
+    /// A numeric `timestamp` key, in seconds since the Unix epoch, becomes the entry's
+    /// timestamp in place of the clock, and an `id` key is dropped, since the `LogDB`
+    /// assigns its own IDs. A line written by `export` in `ExportFormat::Ndjson` is
+    /// therefore read back as the document it was exported from.
+    ///
+    /// # Returns
+    /// The document ID, `IngestError::InvalidJson` if `json` does not parse,
+    /// `IngestError::NotAnObject` if it is not a JSON object, or
+    /// `IngestError::Rejected` if the ingest hook rejected the entry.
+    #[cfg(feature = "serde")]
+    pub fn upsert_json(&mut self, json: &str) -> Result<DocId, IngestError> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| IngestError::InvalidJson(e.to_string()))?;
+        let serde_json::Value::Object(mut object) = value else {
+            return Err(IngestError::NotAnObject);
+        };
+        let mut take = |key: &str| object.remove(key).and_then(|v| json_scalar(&v));
+        let level = take("level");
+        let service = take("service");
+        let message = take("message")
+            .or_else(|| take("msg"))
+            .or_else(|| take("content"));
+        let timestamp = object.remove("timestamp").and_then(|v| v.as_u64());
+        object.remove("id");
+
+        let mut entry = LogEntry::with_metadata(
+            message.unwrap_or_default(),
+            level,
+            service,
+            TokenMode::Mixed,
+        );
+        entry.timestamp = timestamp.unwrap_or_else(|| (self.clock)());
+        entry.fields = json_fields(&object);
+        self.upsert_entry(entry)
+    }
+
+    /// Inserts a log entry from a logfmt line such as
+    /// `level=error service=auth msg="login failed" user=42`.
+    ///
+    /// The `level`, `service` and `msg` (or `message`) keys fill in the entry like
+    /// the keys of the same names in `upsert_json`, a numeric `timestamp` becomes the
+    /// entry's timestamp, and every other pair becomes a field, so the example matches
+    /// `user:42` and `has:user` as well as `failed`. A value may be double-quoted, with
+    /// `\"` and `\\` escapes, and a key without a value is given the value `true`.
+    ///
+    /// # Returns
+    /// The document ID, or `IngestError::Rejected` if the ingest hook rejected the
+    /// entry.
+    pub fn upsert_logfmt(&mut self, line: &str) -> Result<DocId, IngestError> {
+        let mut level = None;
+        let mut service = None;
+        let mut message = None;
+        let mut timestamp = None;
+        let mut fields = Vec::new();
+        for (key, value) in logfmt_pairs(line) {
+            match key.as_str() {
+                "level" => level = Some(value),
+                "service" => service = Some(value),
+                "msg" | "message" => message = Some(value),
+                "timestamp" if value.parse::<u64>().is_ok() => timestamp = value.parse().ok(),
+                _ => fields.push((key, value)),
+            }
         }
-        set
+
+        let mut entry = LogEntry::with_metadata(
+            message.unwrap_or_default(),
+            level,
+            service,
+            TokenMode::Mixed,
+        );
+        entry.timestamp = timestamp.unwrap_or_else(|| (self.clock)());
+        entry.fields = fields;
+        self.upsert_entry(entry)
     }
 
-    /// Retrieves the set of documents associated with a given token.
-    fn get_term_set(&self, tok: &Tok) -> BugguHashSet<DocId, ()> {
-        self.postings
-            .get(tok)
-            .map(|p| p.to_set())
-            .unwrap_or_else(|| BugguHashSet::new(1))
+    /// Inserts a log entry with metadata fields, given as `(name, value)` pairs in
+    /// which a name may repeat, such as `[("tag", "db"), ("tag", "slow")]`.
+    ///
+    /// Each distinct pair is appended to the content as a `name:value` word, so the
+    /// entry matches `tag:db`, `tag:slow` and `has:tag`, and is kept on the document
+    /// for `fields` to return. The entry is otherwise indexed like one added by
+    /// `upsert_log` without a level or service.
+    pub fn upsert_fields(
+        &mut self,
+        content: &str,
+        fields: &[(&str, &str)],
+    ) -> Result<DocId, IngestError> {
+        let mut entry = LogEntry::new(content.to_string(), TokenMode::Mixed);
+        entry.timestamp = (self.clock)();
+        entry.fields = fields
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self.upsert_entry(entry)
     }
 
-    /// Creates a `BugguHashSet` containing all document IDs in the database.
-    fn create_all_docs_set(&self) -> BugguHashSet<DocId, ()> {
-        let mut set = BugguHashSet::new(self.docs.len());
-        for id in self.docs.iter_keys() {
-            set.insert(id, ());
+    /// Executes a query and returns the matching document IDs.
+    ///
+    /// A query made of a single bare word or `contains:` term skips parsing and reads
+    /// the posting directly, since it would parse to a lone `Term` or `Contains` node.
+    /// A bare word in a synonym ring, added with `add_synonyms` or
+    /// `LogConfig::synonyms`, also matches the other words of the ring; use
+    /// `query_opts` to turn that off.
+    ///
+    /// A query that fails to parse, such as one with an invalid `timestamp:` value,
+    /// matches nothing; use `try_query` to find out why.
+    pub fn query(&self, q: &str) -> Vec<DocId> {
+        self.search(q, &QueryOptions::default()).hits
+    }
+
+    /// Executes a query like `query`, with the settings in `opts`.
+    pub fn query_opts(&self, q: &str, opts: &QueryOptions) -> Vec<DocId> {
+        self.search(q, opts).hits
+    }
+
+    /// Executes a query with the settings in `opts`, which can restrict, order and
+    /// page through the hits.
+    ///
+    /// A query that fails to parse matches nothing, as with `query`.
+    ///
+    /// # Returns
+    /// The page of hits selected by `opts.offset` and `opts.limit`, the total number
+    /// of matches, and how long the search took.
+    pub fn search(&self, q: &str, opts: &QueryOptions) -> SearchResult {
+        self.search_before(q, opts, None)
+    }
+
+    /// Executes a search like `search`, leaving out documents with an ID of `before`
+    /// or later, so a `Snapshot` sees neither them nor the pages they would shift.
+    fn search_before(&self, q: &str, opts: &QueryOptions, before: Option<DocId>) -> SearchResult {
+        let timer = QueryTimer::start();
+        let mut docs = self.search_docs(q, opts).unwrap_or_else(|_| {
+            self.metrics.record_query_error();
+            Vec::new()
+        });
+        if let Some(before) = before {
+            docs.retain(|&id| id < before);
+            // The index order of a posting can change as it grows, which would
+            // reshuffle the pages of a snapshot.
+            if opts.sort == SortOrder::Index {
+                docs.sort_unstable();
+            }
         }
-        set
+        let total = docs.len();
+        // Paged in place, so a search for every match returns its matches uncopied.
+        let mut hits = docs;
+        hits.drain(..opts.offset.min(total));
+        hits.truncate(opts.limit.unwrap_or(usize::MAX));
+        let took = timer.elapsed();
+        self.record_query(q, total, took);
+        SearchResult { hits, total, took }
     }
 
-    /// Filters documents by log level.
-    fn filter_by_level(&self, level: &str) -> Vec<DocId> {
-        self.level_index
-            .get(&lightning_hash_str(level))
-            .cloned()
-            .unwrap_or_default()
+    /// Finds and orders every match of a query for `search`.
+    fn search_docs(&self, q: &str, opts: &QueryOptions) -> Result<Vec<DocId>, QueryError> {
+        let exact_case = opts.case_insensitive == Some(false);
+        if opts.time_range.is_none()
+            && opts.namespace.is_none()
+            && !exact_case
+            && opts.sort == SortOrder::Index
+        {
+            return self.run_query(q, opts);
+        }
+
+        let mut ast = self.plan(q, opts)?;
+        let mut filters = Vec::new();
+        if let Some((from, to)) = opts.time_range {
+            filters.push(QueryNode::NumericRange("timestamp", from, to));
+        }
+        if let Some(namespace) = &opts.namespace {
+            filters.push(QueryNode::FieldTerm("service", namespace.clone()));
+        }
+        if !filters.is_empty() {
+            filters.insert(0, ast);
+            ast = QueryNode::And(filters);
+        }
+
+        let mut docs = match opts.sort {
+            SortOrder::Relevance => self.rank(&ast).into_iter().map(|(id, _)| id).collect(),
+            _ => self.exec_within(&ast, &mut Budget::for_options(opts)),
+        };
+        if exact_case {
+            docs.retain(|id| {
+                self.docs
+                    .get(id)
+                    // The case of words past the cut of a capped document is not
+                    // kept, so they match as the index found them.
+                    .is_some_and(|e| e.capped || matches_case(&ast, &e.content))
+            });
+        }
+        match opts.sort {
+            SortOrder::Index | SortOrder::Relevance => {}
+            SortOrder::Newest => docs.sort_unstable_by_key(|id| Reverse(self.time_key(*id))),
+            SortOrder::Oldest => docs.sort_unstable_by_key(|id| self.time_key(*id)),
+            SortOrder::Severity => self.sort_by_severity(&mut docs),
+        }
+        Ok(docs)
     }
 
-    /// Filters documents by service name.
-    fn filter_by_service(&self, service: &str) -> Vec<DocId> {
-        self.service_index
-            .get(&lightning_hash_str(service))
-            .cloned()
-            .unwrap_or_default()
+    /// Returns the timestamp and ID of a document, by which `search` orders hits in
+    /// time.
+    fn time_key(&self, id: DocId) -> (u64, DocId) {
+        (self.docs.get(&id).map_or(0, |e| e.timestamp), id)
     }
 
-    /// Inserts a token into the postings list if it doesn't already exist.
-    pub fn upsert_token(&mut self, s: impl AsRef<str>) -> Tok {
-        let tok = lightning_hash_str(s.as_ref());
-        self.postings.entry(tok).or_insert_with(Posting::default);
-        tok
+    /// Executes a query like `query`, but reports a query that cannot be parsed.
+    ///
+    /// # Returns
+    /// `QueryError::Empty` for an empty query string,
+    /// `QueryError::UnterminatedQuote` for a double quote that is never closed,
+    /// `QueryError::TooComplex` for a query over the configured complexity limits, or
+    /// `QueryError::InvalidTimestamp` for a `timestamp:` value that is not a valid
+    /// time expression.
+    pub fn try_query(&self, q: &str) -> Result<Vec<DocId>, QueryError> {
+        if q.trim().is_empty() {
+            self.metrics.record_query_error();
+            return Err(QueryError::Empty);
+        }
+        let timer = QueryTimer::start();
+        let docs = self.run_query(q, &QueryOptions::default());
+        if docs.is_err() {
+            self.metrics.record_query_error();
+        }
+        let results = docs.as_ref().map_or(0, Vec::len);
+        self.record_query(q, results, timer.elapsed());
+        docs
     }
 
-    /// Exports all tokens from the postings list.
-    pub fn export_tokens(&self) -> Vec<Tok> {
-        self.postings.keys()
+    /// Parses a query template and registers it under `name`, replacing any template
+    /// already registered under it.
+    ///
+    /// A template is a query in which `$name` placeholders stand for whole values, as
+    /// in `level:ERROR service:$svc contains:$word`. A placeholder can replace a bare
+    /// word, a quoted one, or the value of a `level:`, `service:`, `contains:`,
+    /// `has:` or `missing:` filter, but not a `timestamp:` bound or part of a value.
+    ///
+    /// # Returns
+    /// Any error `try_query` would return for the template.
+    pub fn register_template(&mut self, name: &str, template: &str) -> Result<(), QueryError> {
+        if template.trim().is_empty() {
+            return Err(QueryError::Empty);
+        }
+        let ast = parse_query(template, &self.config, self.clock)?;
+        self.check_complexity(&ast)?;
+        self.templates.insert(name.to_string(), ast);
+        Ok(())
     }
 
-    /// Imports a list of tokens into the postings list.
-    pub fn import_tokens(&mut self, toks: Vec<Tok>) {
-        for t in toks {
-            self.postings.entry(t).or_insert_with(Posting::default);
+    /// Executes a template registered with `register_template`, with its placeholders
+    /// replaced by the values in `params`, given by placeholder name without the `$`.
+    ///
+    /// Each value is taken literally, as the word or filter value it replaces, and
+    /// is never parsed: a value of `x OR level:DEBUG` matches the phrase
+    /// `"x OR level:DEBUG"`, and `a,b` for `service:$svc` the service named `a,b`.
+    ///
+    /// # Returns
+    /// `QueryError::UnknownTemplate` if no template is registered under `name`,
+    /// `QueryError::MissingParam` for a placeholder with no value,
+    /// `QueryError::UnknownParam` for a value with no placeholder, or
+    /// `QueryError::TooComplex` if synonym expansion takes the query over the
+    /// complexity limits.
+    pub fn query_template(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Vec<DocId>, QueryError> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| QueryError::UnknownTemplate(name.to_string()))?;
+        let mut placeholders = Vec::new();
+        collect_placeholders(template, &mut placeholders);
+        if let Some((param, _)) = params.iter().find(|(p, _)| !placeholders.contains(p)) {
+            return Err(QueryError::UnknownParam(param.to_string()));
         }
+
+        let mut ast = substitute(template, params)?;
+        if !self.synonyms.is_empty() {
+            ast = self.expand_synonyms(ast);
+        }
+        self.check_complexity(&ast)?;
+        let timer = QueryTimer::start();
+        let docs = self.exec(&ast);
+        self.metrics.record_query(timer.elapsed());
+        Ok(docs)
+    }
+
+    /// Executes a query restricted to documents timestamped in the last `secs`
+    /// seconds, by the clock set with `set_clock`. The window includes its start, so
+    /// this is `query` with `timestamp:>=now-{secs}s` ANDed in; pass `*` to match
+    /// every recent document.
+    pub fn query_last(&self, q: &str, secs: u64) -> Vec<DocId> {
+        let timer = QueryTimer::start();
+        let since = (self.clock)().saturating_sub(secs);
+        let window = QueryNode::NumericRange("timestamp", since, u64::MAX);
+        let ast = QueryNode::And(vec![self.parse_lenient(q), window]);
+        let docs = self.exec(&ast);
+        self.record_query(q, docs.len(), timer.elapsed());
+        docs
+    }
+
+    /// Executes a structured query and returns the matching document IDs.
+    ///
+    /// A query over the configured complexity limits matches nothing; use
+    /// `try_query_spec` to find out why.
+    #[cfg(feature = "serde")]
+    pub fn query_spec(&self, spec: &QuerySpec) -> Vec<DocId> {
+        self.try_query_spec(spec).unwrap_or_default()
     }
-}
 
-/// Parses a query string into a `QueryNode` AST.
-fn parse_query(q: &str, config: &LogConfig) -> QueryNode {
-    let mut nodes = Vec::<QueryNode>::new();
-    let mut it = q.split_whitespace().peekable();
+    /// Executes a structured query like `query_spec`, but reports a query over the
+    /// complexity limits with `QueryError::TooComplex`.
+    #[cfg(feature = "serde")]
+    pub fn try_query_spec(&self, spec: &QuerySpec) -> Result<Vec<DocId>, QueryError> {
+        self.try_query_ast(spec.node())
+    }
 
-    while let Some(tok) = it.next() {
-        if tok.contains(':') {
-            let mut sp = tok.splitn(2, ':');
-            let field = sp.next().unwrap();
-            let mut val = sp.next().unwrap().to_string();
+    /// Executes a query AST built in code, such as by a query builder, and returns the
+    /// matching document IDs. The tree is run as a parsed query string would be, with
+    /// its terms expanded by the synonym rings, and is never rendered to a string.
+    ///
+    /// A query over the configured complexity limits matches nothing; use
+    /// `try_query_ast` to find out why.
+    pub fn query_ast(&self, ast: &QueryNode) -> Vec<DocId> {
+        self.try_query_ast(ast).unwrap_or_default()
+    }
 
-            if val.starts_with('"') && !val.ends_with('"') {
-                for nxt in it.by_ref() {
-                    val.push(' ');
-                    val.push_str(nxt);
-                    if nxt.ends_with('"') {
-                        break;
-                    }
+    /// Executes a query AST like `query_ast`, but reports a query over the
+    /// complexity limits with `QueryError::TooComplex`.
+    pub fn try_query_ast(&self, ast: &QueryNode) -> Result<Vec<DocId>, QueryError> {
+        let expanded;
+        let ast = if self.synonyms.is_empty() {
+            ast
+        } else {
+            expanded = self.expand_synonyms(ast.clone());
+            &expanded
+        };
+        if let Err(e) = self.check_complexity(ast) {
+            self.metrics.record_query_error();
+            return Err(e);
+        }
+        let timer = QueryTimer::start();
+        let docs = self.exec(ast);
+        self.metrics.record_query(timer.elapsed());
+        Ok(docs)
+    }
+
+    /// Parses a structured query from JSON and returns the matching document IDs.
+    ///
+    /// See the `query_spec` module for the node types. A malformed query is rejected
+    /// with the path to the offending node, e.g. `and[1].fied: unknown node`, and
+    /// one over the complexity limits with `QueryError::TooComplex`.
+    #[cfg(feature = "serde")]
+    pub fn query_json(&self, spec: &str) -> Result<Vec<DocId>, QueryError> {
+        let spec = QuerySpec::from_json(spec)?;
+        self.try_query_spec(&spec)
+    }
+
+    /// Executes a query and passes each matching document ID to `f`, stopping
+    /// early when it returns `false`.
+    ///
+    /// Single-term and top-level `NOT` queries are streamed straight from the
+    /// postings and the document table, so the matches are never collected into a
+    /// vector. Other queries are executed as in `query` and their results replayed.
+    /// Matches arrive in the same order `query` would return them, except for `NOT`.
+    pub fn query_for_each<F: FnMut(DocId) -> bool>(&self, q: &str, mut f: F) {
+        let timer = QueryTimer::start();
+        let mut results = 0;
+        let mut counted = |id| {
+            results += 1;
+            f(id)
+        };
+        if let Some(word) = self.direct_term(q, &QueryOptions::default()) {
+            if let Some(posting) = self.term_posting(word) {
+                posting.for_each(&mut counted);
+            }
+        } else {
+            self.exec_for_each(&self.parse_lenient(q), &mut counted);
+        }
+        self.record_query(q, results, timer.elapsed());
+    }
+
+    /// Writes the documents matching a query to `out` in the given format, in the
+    /// order `query_for_each` passes them.
+    ///
+    /// The matches are streamed as in `query_for_each` and each is written as soon as
+    /// it arrives, so a large export never collects its rows. Wrap a file in a
+    /// `BufWriter` before passing it in. Writing stops at the first I/O error.
+    ///
+    /// # Returns
+    /// The number of documents written, not counting the CSV header row.
+    pub fn export(&self, q: &str, format: ExportFormat, out: &mut impl Write) -> io::Result<usize> {
+        format.write_header(out)?;
+        let mut rows = 0;
+        let mut result = Ok(());
+        self.query_for_each(q, |id| {
+            let Some(entry) = self.docs.get(&id) else {
+                return true;
+            };
+            match format.write_row(out, id, entry) {
+                Ok(()) => {
+                    rows += 1;
+                    true
+                }
+                Err(e) => {
+                    result = Err(e);
+                    false
                 }
-                val = val.trim_matches('"').to_string();
-            } else {
-                val = val.trim_matches('"').to_string();
             }
+        });
+        result.map(|()| rows)
+    }
 
-            match field {
-                "level" => nodes.push(QueryNode::FieldTerm("level", val)),
-                "service" => nodes.push(QueryNode::FieldTerm("service", val)),
-                "contains" => nodes.push(QueryNode::Contains(val)),
-                "timestamp" => {
-                    if let Some(lo) = val.strip_prefix(">=") {
-                        let lo = lo.parse::<u64>().unwrap_or(0);
-                        nodes.push(QueryNode::NumericRange("timestamp", lo, u64::MAX));
-                    } else if let Some(hi) = val.strip_prefix("<=") {
-                        let hi = hi.parse::<u64>().unwrap_or(u64::MAX);
-                        nodes.push(QueryNode::NumericRange("timestamp", 0, hi));
+    /// Executes a query and returns the `limit` most recent matching document IDs,
+    /// newest first.
+    ///
+    /// Matches are ranked by their stored timestamp, with later insertions first among
+    /// equal timestamps. They are streamed as in `query_for_each` through a heap of
+    /// `limit` entries, so a broad query costs O(n log limit) rather than a full sort.
+    pub fn query_recent(&self, q: &str, limit: usize) -> Vec<DocId> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        let mut heap = BinaryHeap::with_capacity(limit + 1);
+        self.query_for_each(q, |id| {
+            if let Some(entry) = self.docs.get(&id) {
+                heap.push(Reverse((entry.timestamp, id)));
+                if heap.len() > limit {
+                    heap.pop();
+                }
+            }
+            true
+        });
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((_, id))| id)
+            .collect()
+    }
+
+    /// Executes a query and returns the matching documents with a relevance score,
+    /// highest first.
+    ///
+    /// Each term, phrase, `contains:` and field node a document matches adds its
+    /// inverse document frequency to the document's score, so rarer words count for
+    /// more. A `^N` boost, as in `error^2`, multiplies the contribution of the node it
+    /// follows, and of every node inside a boosted group. Negated nodes and filters
+    /// such as `timestamp:` ranges only restrict the matches. Ties are broken by
+    /// document ID.
+    pub fn query_ranked(&self, q: &str) -> Vec<(DocId, f32)> {
+        let timer = QueryTimer::start();
+        let ranked = self.rank(&self.parse_lenient(q));
+        self.record_query(q, ranked.len(), timer.elapsed());
+        ranked
+    }
+
+    /// Executes a query AST and scores its matches, as described on `query_ranked`.
+    fn rank(&self, ast: &QueryNode) -> Vec<(DocId, f32)> {
+        let mut leaves = Vec::new();
+        collect_scored_leaves(ast, 1.0, &mut leaves);
+
+        let total = self.docs.len() as f32;
+        let mut scores: BugguHashSet<DocId, f32> = BugguHashSet::new(64);
+        for (leaf, boost) in leaves {
+            let docs = self.exec(leaf);
+            let df = docs.len() as f32;
+            let idf = (1.0 + (total - df + 0.5) / (df + 0.5)).ln();
+            for id in docs {
+                *scores.entry(id).or_default() += boost * idf;
+            }
+        }
+
+        let mut ranked: Vec<(DocId, f32)> = self
+            .exec(ast)
+            .into_iter()
+            .map(|id| (id, scores.get(&id).copied().unwrap_or_default()))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked
+    }
+
+    /// Executes a query and returns the matching document IDs, most severe first.
+    ///
+    /// Matches are ordered by the priority `LogConfig::log_level_priority` gives their
+    /// level, highest first, then newest first by timestamp. Documents without a level
+    /// come after all others.
+    pub fn query_by_severity(&self, q: &str) -> Vec<DocId> {
+        let opts = QueryOptions {
+            sort: SortOrder::Severity,
+            ..QueryOptions::default()
+        };
+        self.search(q, &opts).hits
+    }
+
+    /// Orders documents as `query_by_severity` does.
+    fn sort_by_severity(&self, docs: &mut Vec<DocId>) {
+        let mut ranked: Vec<(Option<u8>, u64, DocId)> = docs
+            .iter()
+            .filter_map(|&id| {
+                self.docs.get(&id).map(|entry| {
+                    let priority = entry
+                        .level
+                        .as_deref()
+                        .map(|level| self.config.log_level_priority(level));
+                    (priority, entry.timestamp, id)
+                })
+            })
+            .collect();
+        ranked.sort_unstable_by(|a, b| b.cmp(a));
+        *docs = ranked.into_iter().map(|(_, _, id)| id).collect();
+    }
+
+    /// Parses and executes a query without recording metrics.
+    fn run_query(&self, q: &str, opts: &QueryOptions) -> Result<Vec<DocId>, QueryError> {
+        if let Some(word) = self.direct_term(q, opts) {
+            return Ok(self
+                .term_posting(word)
+                .map(Posting::get_docs)
+                .unwrap_or_default());
+        }
+        Ok(self.exec_within(&self.plan(q, opts)?, &mut Budget::for_options(opts)))
+    }
+
+    /// Returns the word of a query that can skip parsing, as `single_term` does,
+    /// unless it has synonyms to expand.
+    fn direct_term<'q>(&self, q: &'q str, opts: &QueryOptions) -> Option<&'q str> {
+        single_term(q).filter(|word| !opts.enable_synonyms || self.synonym_ring(word).is_none())
+    }
+
+    /// Parses a query, expands synonyms if `opts` asks for it, and checks the result
+    /// against the complexity limits.
+    fn plan(&self, q: &str, opts: &QueryOptions) -> Result<QueryNode, QueryError> {
+        let mut ast = self.parse_cached(q)?;
+        if opts.enable_synonyms && !self.synonyms.is_empty() {
+            ast = self.expand_synonyms(ast);
+        }
+        self.check_complexity(&ast)?;
+        Ok(ast)
+    }
+
+    /// Parses a query, reusing the AST cached for the same string.
+    ///
+    /// Times relative to `now` are resolved against the clock while parsing, so
+    /// queries with a `timestamp:` value relative to `now` are parsed afresh every
+    /// time. Queries that fail to parse are not cached.
+    fn parse_cached(&self, q: &str) -> Result<QueryNode, QueryError> {
+        if let Some(ast) = self.query_cache.get(q) {
+            return Ok(ast);
+        }
+        let (ast, relative) = parse_query_relative(q, &self.config, self.clock)?;
+        if !relative {
+            self.query_cache.insert(q, &ast);
+        }
+        Ok(ast)
+    }
+
+    /// Drops every parsed query cached by the query methods.
+    ///
+    /// Parsing does not depend on the index, so the cache never needs clearing for
+    /// correctness; this only frees its memory.
+    pub fn clear_query_cache(&self) {
+        self.query_cache.clear();
+    }
+
+    /// Parses a query the way the query methods would, without running it, to see
+    /// how the engine reads it. Synonym expansion is applied as in `query`. Print the
+    /// result with `{:#}` for an indented tree.
+    ///
+    /// # Returns
+    /// The query's AST, or the error `try_query` would return for it.
+    pub fn parse_only(&self, q: &str) -> Result<QueryNode, QueryError> {
+        if q.trim().is_empty() {
+            return Err(QueryError::Empty);
+        }
+        self.plan(q, &QueryOptions::default())
+    }
+
+    /// Parses a query for the infallible query methods, replacing one that fails to
+    /// parse or is too complex with an empty `Or`, which matches nothing.
+    fn parse_lenient(&self, q: &str) -> QueryNode {
+        self.plan(q, &QueryOptions::default()).unwrap_or_else(|_| {
+            self.metrics.record_query_error();
+            QueryNode::Or(Vec::new())
+        })
+    }
+
+    /// Replaces each `Term` of a query AST that belongs to a synonym ring with an `Or`
+    /// of the term and the ring's other words. Phrases, field filters and `contains:`
+    /// terms are left alone.
+    fn expand_synonyms(&self, node: QueryNode) -> QueryNode {
+        match node {
+            QueryNode::Term(w) => match self.synonym_ring(&w) {
+                // The word is kept as written, so a case-sensitive search matches it
+                // with its own case.
+                Some(ring) => {
+                    let tok = lightning_hash_str(&w);
+                    let others = ring.iter().filter(|m| lightning_hash_str(m) != tok);
+                    let words = std::iter::once(w).chain(others.cloned());
+                    QueryNode::Or(words.map(QueryNode::Term).collect())
+                }
+                None => QueryNode::Term(w),
+            },
+            QueryNode::And(children) => QueryNode::And(
+                children
+                    .into_iter()
+                    .map(|c| self.expand_synonyms(c))
+                    .collect(),
+            ),
+            QueryNode::Or(children) => QueryNode::Or(
+                children
+                    .into_iter()
+                    .map(|c| self.expand_synonyms(c))
+                    .collect(),
+            ),
+            QueryNode::Not(child) => QueryNode::Not(Box::new(self.expand_synonyms(*child))),
+            QueryNode::Boost(factor, child) => {
+                QueryNode::Boost(factor, Box::new(self.expand_synonyms(*child)))
+            }
+            other => other,
+        }
+    }
+
+    /// Returns the synonym ring `word` belongs to, if any.
+    fn synonym_ring(&self, word: &str) -> Option<&[String]> {
+        self.synonyms
+            .get(&lightning_hash_str(word))
+            .map(|&i| self.config.synonyms[i].as_slice())
+    }
+
+    /// Adds a synonym ring, so that a query for any of its words also matches
+    /// documents containing the others. The index is not touched, so this applies to
+    /// documents already added.
+    pub fn add_synonyms(&mut self, ring: &[&str]) {
+        let i = self.config.synonyms.len();
+        self.config
+            .synonyms
+            .push(ring.iter().map(|w| w.to_string()).collect());
+        for word in ring {
+            self.synonyms.insert(lightning_hash_str(word), i);
+        }
+    }
+
+    /// Rejects a query AST with more clauses or deeper nesting than the configuration
+    /// allows, before any of it is executed.
+    fn check_complexity(&self, ast: &QueryNode) -> Result<(), QueryError> {
+        let (clauses, depth) = query_complexity(ast);
+        if clauses > self.config.max_query_clauses || depth > self.config.max_query_depth {
+            return Err(QueryError::TooComplex { clauses, depth });
+        }
+        Ok(())
+    }
+
+    /// Executes a query, giving up once the sets of candidate documents it has built
+    /// hold more than `max_candidates` documents in total.
+    ///
+    /// # Returns
+    /// The matching document IDs, and whether the budget ran out. If it did, the
+    /// parts of the query left unevaluated are treated as matching nothing, so the
+    /// IDs returned are a subset of the full result.
+    pub fn query_with_budget(&self, q: &str, max_candidates: usize) -> (Vec<DocId>, bool) {
+        let timer = QueryTimer::start();
+        let mut budget = Budget::new(max_candidates);
+        let docs = self.exec_within(&self.parse_lenient(q), &mut budget);
+        self.record_query(q, docs.len(), timer.elapsed());
+        (docs, budget.exhausted)
+    }
+
+    /// Retrieves the content of a document by its ID.
+    pub fn get_content(&self, doc_id: &DocId) -> Option<String> {
+        self.docs.get(doc_id).map(|e| e.content.clone())
+    }
+
+    /// Returns the content of a document as it was given, before
+    /// `LogConfig::strip_ansi` removed escape sequences and control characters from
+    /// it, or `None` if the document does not exist.
+    ///
+    /// The raw content is only kept with `LogConfig::keep_raw_content`, and only by
+    /// the `LogDB` that ingested the document; otherwise this is the stored content,
+    /// as `get_content` returns it. Raw content over `LogConfig::max_content_bytes`
+    /// is truncated like the stored content.
+    pub fn get_doc_raw(&self, doc_id: &DocId) -> Option<&str> {
+        self.docs
+            .get(doc_id)
+            .map(|e| e.raw_content.as_deref().unwrap_or(&e.content))
+    }
+
+    /// Applies a replication frame produced by another `LogDB`.
+    ///
+    /// A `Full` frame creates the document, or replaces the token set of an existing
+    /// one. A `Diff` frame removes and adds tokens on an existing document. A `Meta`
+    /// frame sets the content, level, service and timestamp, creating the document
+    /// if needed. A `Delete` frame removes the document. A `Batch` frame applies each
+    /// of its entries as a `Full` frame.
+    ///
+    /// # Returns
+    /// `ApplyError::UnknownDoc` if a `Diff` or `Delete` frame references a missing
+    /// document, or `ApplyError::BatchLengthMismatch` if the token counts of a `Batch`
+    /// frame's entries do not add up to its tokens, in which case none of the batch
+    /// is applied.
+    pub fn apply_frame(&mut self, frame: Frame) -> Result<(), ApplyError> {
+        match frame {
+            Frame::Full { doc_id, tokens } => self.apply_full(doc_id, tokens),
+            Frame::Diff {
+                doc_id,
+                remove,
+                add,
+            } => {
+                let Some(entry) = Arc::make_mut(&mut self.docs).get_mut(&doc_id) else {
+                    return Err(ApplyError::UnknownDoc(doc_id));
+                };
+                entry.tokens.retain(|t| !remove.contains(t));
+                entry.tokens.extend_from_slice(&add);
+                self.unindex_tokens(doc_id, &remove);
+                self.index_tokens(doc_id, &add);
+                self.touch(doc_id);
+                self.evict_postings();
+            }
+            Frame::Meta {
+                doc_id,
+                content,
+                level,
+                service,
+                extra_services,
+                timestamp,
+            } => {
+                let (old_level, old_services) = match Arc::make_mut(&mut self.docs).get_mut(&doc_id)
+                {
+                    Some(entry) => {
+                        let extra = std::mem::take(&mut entry.extra_services);
+                        let services = entry.service.take().into_iter().chain(extra).collect();
+                        (entry.level.take(), services)
                     }
+                    None => {
+                        Arc::make_mut(&mut self.docs).insert(doc_id, MetaEntry::default());
+                        if doc_id >= self.next_doc_id {
+                            self.next_doc_id = DocId(doc_id.get() + 1);
+                        }
+                        (None, Vec::new())
+                    }
+                };
+                let services: Vec<String> =
+                    service.iter().chain(&extra_services).cloned().collect();
+                Self::reindex_field(
+                    Arc::make_mut(&mut self.level_index),
+                    doc_id,
+                    old_level.as_slice(),
+                    level.as_slice(),
+                );
+                Self::reindex_field(
+                    Arc::make_mut(&mut self.service_index),
+                    doc_id,
+                    &old_services,
+                    &services,
+                );
+                self.index_words(&content, false);
+                if let Some(entry) = Arc::make_mut(&mut self.docs).get_mut(&doc_id) {
+                    entry.content = content;
+                    entry.level = level;
+                    entry.service = service;
+                    entry.extra_services = extra_services;
+                    entry.timestamp = timestamp;
+                }
+                self.touch(doc_id);
+            }
+            Frame::Delete { doc_id } => {
+                if !self.delete(&doc_id) {
+                    return Err(ApplyError::UnknownDoc(doc_id));
+                }
+            }
+            Frame::Batch { entries, tokens } => {
+                let declared = entries
+                    .iter()
+                    .try_fold(0usize, |sum, &(_, len)| sum.checked_add(len))
+                    .unwrap_or(usize::MAX);
+                if declared != tokens.len() {
+                    return Err(ApplyError::BatchLengthMismatch {
+                        declared,
+                        actual: tokens.len(),
+                    });
+                }
+                let mut rest = tokens.as_slice();
+                for (doc_id, len) in entries {
+                    let (head, tail) = rest.split_at(len);
+                    self.apply_full(doc_id, head.to_vec());
+                    rest = tail;
                 }
-                _ => nodes.push(QueryNode::Term(tok.to_string())),
             }
-        } else if tok.starts_with('"') {
-            let phrase = tok.trim_matches('"').to_string();
-            nodes.push(QueryNode::Phrase(phrase));
-        } else {
-            nodes.push(QueryNode::Term(tok.to_string()));
         }
+        Ok(())
     }
 
-    if nodes.len() == 1 {
-        nodes.pop().unwrap()
-    } else {
-        QueryNode::And(nodes)
+    /// Creates a document with the given token set, or replaces the token set of an
+    /// existing one.
+    fn apply_full(&mut self, doc_id: DocId, tokens: Vec<Tok>) {
+        let old_tokens = Arc::make_mut(&mut self.docs)
+            .get_mut(&doc_id)
+            .map(|e| std::mem::take(&mut e.tokens));
+        match old_tokens {
+            Some(old_tokens) => self.unindex_tokens(doc_id, &old_tokens),
+            None => {
+                Arc::make_mut(&mut self.docs).insert(doc_id, MetaEntry::default());
+                if doc_id >= self.next_doc_id {
+                    self.next_doc_id = DocId(doc_id.get() + 1);
+                }
+            }
+        }
+        self.index_tokens(doc_id, &tokens);
+        if let Some(entry) = Arc::make_mut(&mut self.docs).get_mut(&doc_id) {
+            entry.tokens = tokens;
+        }
+        self.touch(doc_id);
+        self.evict_postings();
+    }
+
+    /// Bumps the generation counter and stamps the new generation on a document.
+    fn touch(&mut self, doc_id: DocId) {
+        self.generation += 1;
+        if let Some(entry) = Arc::make_mut(&mut self.docs).get_mut(&doc_id) {
+            entry.generation = self.generation;
+        }
+    }
+
+    /// Takes a `Snapshot` of the index, whose queries leave out the documents
+    /// inserted from now on. Later deletions, replacements and posting evictions
+    /// still show through; see `Snapshot`.
+    pub fn freeze(&self) -> Snapshot {
+        Snapshot {
+            next_doc_id: self.next_doc_id,
+            generation: self.generation,
+        }
+    }
+
+    /// Takes a `SharedSnapshot` of the index, a read-only view of every document as it
+    /// is now that shares its tables with this `LogDB` until either is written to.
+    pub fn snapshot(&self) -> SharedSnapshot {
+        SharedSnapshot { db: self.clone() }
+    }
+
+    /// Returns the current generation, which increases with every document change.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the ID the next inserted document will get. IDs are never reused,
+    /// even after a delete, and a restore carries the counter over from the backups.
+    pub fn next_doc_id(&self) -> DocId {
+        self.next_doc_id
+    }
+
+    /// Writes every document to a snapshot file at `path`.
+    ///
+    /// The file starts with a `BackupHeader` holding the document count, the
+    /// current generation and the next document ID, followed by a checksummed `Full` and `Meta` frame per
+    /// document.
+    pub fn snapshot_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_backup(path.as_ref(), BackupKind::Snapshot, 0)
+    }
+
+    /// Writes the documents created, updated or deleted after `since_generation` to
+    /// an incremental backup file at `path`.
+    ///
+    /// Pass the generation of the previous snapshot or incremental backup, as found
+    /// in its header or returned by `generation` when it was written.
+    pub fn incremental_to<P: AsRef<Path>>(&self, path: P, since_generation: u64) -> io::Result<()> {
+        self.write_backup(path.as_ref(), BackupKind::Incremental, since_generation)
+    }
+
+    /// Writes the documents changed after `since` to a backup file.
+    fn write_backup(&self, path: &Path, kind: BackupKind, since: u64) -> io::Result<()> {
+        let changed: Vec<DocId> = self
+            .docs
+            .iter_keys()
+            .filter(|id| self.docs.get(id).is_some_and(|e| e.generation > since))
+            .collect();
+        let deleted: Vec<DocId> = self
+            .tombstones
+            .iter()
+            .filter(|(_, generation)| *generation > since)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut out = BufWriter::new(File::create(path)?);
+        BackupHeader {
+            kind,
+            doc_count: (changed.len() + deleted.len()) as u64,
+            since,
+            generation: self.generation,
+            next_doc_id: self.next_doc_id,
+        }
+        .write_to(&mut out)?;
+
+        let mut writer = FrameWriter::with_checksum(out);
+        for doc_id in changed {
+            let Some(entry) = self.docs.get(&doc_id) else {
+                continue;
+            };
+            writer.write_frame(&Frame::Full {
+                doc_id,
+                tokens: entry.tokens.clone(),
+            })?;
+            writer.write_frame(&Frame::Meta {
+                doc_id,
+                content: entry.content.clone(),
+                level: entry.level.clone(),
+                service: entry.service.clone(),
+                extra_services: entry.extra_services.clone(),
+                timestamp: entry.timestamp,
+            })?;
+        }
+        for doc_id in deleted {
+            writer.write_frame(&Frame::Delete { doc_id })?;
+        }
+        writer.flush()
+    }
+
+    /// Restores an empty `LogDB` from a snapshot followed by incremental backups.
+    ///
+    /// The first path must be a snapshot, and each incremental backup must start at
+    /// or before the generation of the file before it and end after it, so missing
+    /// or out-of-order files are rejected. Afterwards, the generation continues from
+    /// that of the last file, and new documents get IDs after every ID the backed up
+    /// database had handed out, deleted documents included.
+    ///
+    /// # Returns
+    /// An `InvalidInput` error if the database is not empty, an `InvalidData` error
+    /// if the files are out of order or corrupted or hold a frame that cannot be
+    /// applied, or an `UnexpectedEof` error if a file is truncated.
+    pub fn restore_from<P: AsRef<Path>>(&mut self, paths: &[P]) -> io::Result<()> {
+        if !self.docs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "restore target is not empty",
+            ));
+        }
+
+        let mut last_generation = None;
+        for path in paths {
+            let mut input = BufReader::new(File::open(path)?);
+            let header = BackupHeader::read_from(&mut input)?;
+            let in_order = match (last_generation, header.kind) {
+                (None, BackupKind::Snapshot) => true,
+                (Some(prev), BackupKind::Incremental) => {
+                    header.since <= prev && header.generation > prev
+                }
+                _ => false,
+            };
+            if !in_order {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "backup files out of order",
+                ));
+            }
+
+            let mut doc_count = 0;
+            for frame in FrameReader::with_checksum(input) {
+                let frame = frame?;
+                let is_delete = matches!(frame, Frame::Delete { .. });
+                if is_delete || matches!(frame, Frame::Full { .. }) {
+                    doc_count += 1;
+                }
+                match self.apply_frame(frame) {
+                    Ok(()) => {}
+                    // A document created and deleted between two backups reaches the
+                    // restore only as a `Delete`, which has nothing to remove.
+                    Err(ApplyError::UnknownDoc(_)) if is_delete => {}
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                }
+            }
+            if doc_count != header.doc_count {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "backup is truncated",
+                ));
+            }
+            last_generation = Some(header.generation);
+            self.next_doc_id = self.next_doc_id.max(header.next_doc_id);
+        }
+
+        if let Some(generation) = last_generation {
+            self.generation = generation;
+            self.tombstones.clear();
+            for (_, entry) in Arc::make_mut(&mut self.docs).iter_mut() {
+                entry.generation = generation;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves a document from the entries for its old field values to the entries for
+    /// its new ones in a level or service index.
+    fn reindex_field(
+        index: &mut BugguHashSet<Tok, Posting>,
+        doc_id: DocId,
+        old: &[String],
+        new: &[String],
+    ) {
+        for old in old {
+            if let Some(ids) = index.get_mut(&lightning_hash_str(old)) {
+                ids.remove(doc_id);
+            }
+        }
+        for new in new {
+            index
+                .entry(lightning_hash_str(new))
+                .or_default()
+                .add(doc_id);
+        }
+    }
+
+    /// Returns a `Full` frame followed by a `Meta` frame for every document, for
+    /// bootstrapping a follower.
+    pub fn export_frames(&self) -> impl Iterator<Item = Frame> + '_ {
+        self.docs.iter_keys().flat_map(move |doc_id| {
+            self.docs.get(&doc_id).into_iter().flat_map(move |entry| {
+                [
+                    Frame::Full {
+                        doc_id,
+                        tokens: entry.tokens.clone(),
+                    },
+                    Frame::Meta {
+                        doc_id,
+                        content: entry.content.clone(),
+                        level: entry.level.clone(),
+                        service: entry.service.clone(),
+                        extra_services: entry.extra_services.clone(),
+                        timestamp: entry.timestamp,
+                    },
+                ]
+            })
+        })
+    }
+
+    /// Builds the replication frame that brings a document's tokens in line with
+    /// `new_content`.
+    ///
+    /// The new content is tokenized with the document's stored level, service and
+    /// token mode, and the same UFHG settings used for indexing.
+    ///
+    /// # Returns
+    /// A `Diff` frame relative to the stored tokens, a `Full` frame if the document
+    /// is unknown, or `None` if the content produces the same token set.
+    pub fn diff_for(&self, doc_id: &DocId, new_content: &str) -> Option<Frame> {
+        let mut ufhg = self.ufhg.clone();
+        let Some(entry) = self.docs.get(doc_id) else {
+            let tokens = entry_tokens(
+                &mut ufhg,
+                &self.config,
+                new_content,
+                None,
+                None,
+                TokenMode::default(),
+                false,
+            );
+            return Some(Frame::Full {
+                doc_id: *doc_id,
+                tokens,
+            });
+        };
+
+        let tokens = entry_tokens(
+            &mut ufhg,
+            &self.config,
+            new_content,
+            entry.level.as_deref(),
+            service_words(entry.service.as_deref(), &entry.extra_services).as_deref(),
+            entry.mode,
+            entry.content_only,
+        );
+        let (remove, add) = diff_tokens(&entry.tokens, &tokens);
+        if remove.is_empty() && add.is_empty() {
+            return None;
+        }
+        Some(Frame::Diff {
+            doc_id: *doc_id,
+            remove,
+            add,
+        })
+    }
+
+    /// Adds a document to the postings of each of the given tokens.
+    fn index_tokens(&mut self, doc_id: DocId, tokens: &[Tok]) {
+        let track = self.config.eviction_policy != EvictionPolicy::Never;
+        for &tok in tokens {
+            let posting =
+                Arc::make_mut(&mut self.postings).get_mut_or_insert_with(tok, Posting::default);
+            let new = posting.is_empty();
+            posting.add(doc_id);
+            if track && new {
+                self.token_ages.insert(tok, Cell::new(self.tick()));
+            }
+        }
+    }
+
+    /// Returns the next eviction age tick.
+    fn tick(&self) -> u64 {
+        let tick = self.age_clock.get();
+        self.age_clock.set(tick + 1);
+        tick
+    }
+
+    /// Evicts the oldest postings, as chosen by `LogConfig::eviction_policy`, once
+    /// there are more than `max_postings`, until a tenth of the limit is free so that
+    /// the next few documents don't each trigger an eviction.
+    ///
+    /// An evicted token is dropped from every document that contains it, so those
+    /// documents no longer match it. Called once a changed document is stored, so
+    /// that its own token list is updated too.
+    fn evict_postings(&mut self) {
+        if self.config.eviction_policy == EvictionPolicy::Never
+            || self.postings.len() <= self.max_postings
+        {
+            return;
+        }
+        let keep = self.max_postings - self.max_postings / 10;
+        let mut ages: Vec<(u64, Tok)> = self
+            .postings
+            .iter_keys()
+            .map(|tok| (self.token_ages.get(&tok).map_or(0, Cell::get), tok))
+            .collect();
+        let evict = ages.len() - keep;
+        if evict < ages.len() {
+            ages.select_nth_unstable(evict);
+        }
+        for &(_, tok) in &ages[..evict] {
+            self.token_ages.remove(&tok);
+            let Some(posting) = Arc::make_mut(&mut self.postings).remove(&tok) else {
+                continue;
+            };
+            for id in posting.iter() {
+                if let Some(entry) = Arc::make_mut(&mut self.docs).get_mut(&id) {
+                    entry.tokens.retain(|t| *t != tok);
+                }
+            }
+        }
+    }
+
+    /// Looks up the posting of a token for a query, marking it as recently used for
+    /// `EvictionPolicy::Lru`.
+    fn query_posting(&self, tok: Tok) -> Option<&Posting> {
+        if self.config.eviction_policy == EvictionPolicy::Lru {
+            if let Some(age) = self.token_ages.get(&tok) {
+                age.set(self.tick());
+            }
+        }
+        self.postings.get(&tok)
+    }
+
+    /// Removes a document from the postings of each of the given tokens, dropping
+    /// postings that become empty.
+    fn unindex_tokens(&mut self, doc_id: DocId, tokens: &[Tok]) {
+        for tok in tokens {
+            if let Some(posting) = Arc::make_mut(&mut self.postings).get_mut(tok) {
+                posting.remove(doc_id);
+                if posting.is_empty() {
+                    Arc::make_mut(&mut self.postings).remove(tok);
+                    self.token_ages.remove(tok);
+                }
+            }
+        }
+    }
+
+    /// Retrieves the stored entry of a document by its ID.
+    pub fn get_entry(&self, doc_id: &DocId) -> Option<&MetaEntry> {
+        self.docs.get(doc_id)
+    }
+
+    /// Deletes a document and removes it from the postings and metadata indexes.
+    ///
+    /// Postings left without any documents are dropped from the table.
+    ///
+    /// # Returns
+    /// `true` if the document existed and was removed, `false` otherwise.
+    pub fn delete(&mut self, doc_id: &DocId) -> bool {
+        let Some(entry) = Arc::make_mut(&mut self.docs).remove(doc_id) else {
+            return false;
+        };
+        self.generation += 1;
+        self.tombstones.push((*doc_id, self.generation));
+        self.metrics.record_delete();
+
+        self.unindex_tokens(*doc_id, &entry.tokens);
+        self.unindex_words(&entry.content);
+        if entry.capped {
+            // The words past the cut are known only by their tokens, which the term
+            // dictionary still maps back to them.
+            let words: Vec<&str> = entry
+                .tokens
+                .iter()
+                .filter_map(|&tok| self.term_dict.resolve(tok))
+                .collect();
+            let words = words.join(" ");
+            self.unindex_words(&words);
+        }
+
+        if let Some(ref level) = entry.level {
+            if let Some(ids) =
+                Arc::make_mut(&mut self.level_index).get_mut(&lightning_hash_str(level))
+            {
+                ids.remove(*doc_id);
+            }
+        }
+        for service in entry.services() {
+            if let Some(ids) =
+                Arc::make_mut(&mut self.service_index).get_mut(&lightning_hash_str(service))
+            {
+                ids.remove(*doc_id);
+            }
+        }
+
+        true
+    }
+
+    /// Executes a query and returns the content of the matching documents.
+    pub fn query_content(&self, q: &str) -> Vec<String> {
+        self.query_content_opts(q, &QueryOptions::default())
+    }
+
+    /// Executes a query with the settings in `opts`, as `search` does, and returns
+    /// the content of the matching documents. With `opts.parallel`, the content is
+    /// copied out on the rayon thread pool.
+    pub fn query_content_opts(&self, q: &str, opts: &QueryOptions) -> Vec<String> {
+        let hits = self.search(q, opts).hits;
+        #[cfg(feature = "rayon")]
+        if opts.parallel {
+            use rayon::prelude::*;
+            let docs = &self.docs;
+            return hits
+                .into_par_iter()
+                .filter_map(|id| docs.get(&id).map(|e| e.content.clone()))
+                .collect();
+        }
+        hits.into_iter()
+            .filter_map(|id| self.get_content(&id))
+            .collect()
+    }
+
+    /// Executes a query and yields each matching document with its content borrowed
+    /// from the index, so only the results the caller keeps need to be cloned.
+    ///
+    /// The matches are found up front, as by `query`, but each document's content is
+    /// looked up only when the iterator reaches it, so results skipped by `take` or a
+    /// filter are never touched.
+    pub fn query_lazy(&self, q: &str) -> impl Iterator<Item = (DocId, &str)> + '_ {
+        self.query(q)
+            .into_iter()
+            .filter_map(|id| self.docs.get(&id).map(|e| (id, e.content.as_str())))
+    }
+
+    /// Executes a query and returns the matching documents with their metadata.
+    pub fn query_with_meta(&self, q: &str) -> Vec<(DocId, String, Option<String>, Option<String>)> {
+        let ast = self.parse_lenient(q);
+        let docs = self.exec(&ast);
+        docs.into_iter()
+            .filter_map(|id| {
+                self.docs
+                    .get(&id)
+                    .map(|e| (id, e.content.clone(), e.level.clone(), e.service.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the documents containing every one of `phrases` as an exact phrase,
+    /// such as both "connection refused" and "timeout exceeded".
+    ///
+    /// Each phrase is checked independently against the word positions of its
+    /// candidate documents, so a document containing the words of a phrase scattered
+    /// or out of order does not match, whatever the phrase's length. Candidates come
+    /// from the rarest posting among the phrase's words and, when it is short enough
+    /// to be indexed as an N-gram, the phrase itself, and are narrowed by the phrases
+    /// before it. Empty phrases are ignored.
+    pub fn query_intersection_of_phrases(&self, phrases: &[&str]) -> Vec<DocId> {
+        let timer = QueryTimer::start();
+        let mut matched: Option<Vec<DocId>> = None;
+        for phrase in phrases {
+            let words: Vec<&str> = phrase
+                .split(CONTENT_SEPARATORS)
+                .filter(|w| !w.is_empty())
+                .collect();
+            if words.is_empty() {
+                continue;
+            }
+            let hashes: Vec<Tok> = words.iter().map(|w| lightning_hash_str(w)).collect();
+            let Some(candidates) = self.phrase_candidates(phrase, &words) else {
+                matched = Some(Vec::new());
+                break;
+            };
+            let docs: Vec<DocId> = match &matched {
+                Some(prev) => prev
+                    .iter()
+                    .copied()
+                    .filter(|id| candidates.contains(*id))
+                    .collect(),
+                None => candidates.get_docs(),
+            };
+            let docs: Vec<DocId> = docs
+                .into_iter()
+                .filter(|id| {
+                    self.docs
+                        .get(id)
+                        .is_some_and(|e| e.contains_sequence(&hashes))
+                })
+                .collect();
+            let done = docs.is_empty();
+            matched = Some(docs);
+            if done {
+                break;
+            }
+        }
+        self.metrics.record_query(timer.elapsed());
+        matched.unwrap_or_default()
+    }
+
+    /// Tallies the values of a field across the documents matching a query, for
+    /// faceted search.
+    ///
+    /// `level` and `service` count the documents' metadata, each of a document's
+    /// services once. Any other field counts the `field:value` words in the matching
+    /// documents' content, such as `status:500` for `status`, each distinct value
+    /// once per document; field names match ignoring ASCII case. Documents without a
+    /// value for the field are left out.
+    ///
+    /// # Returns
+    /// The values and their document counts, most frequent first, ties broken by
+    /// value.
+    pub fn facet(&self, q: &str, field: &str) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut values = Vec::new();
+        for id in self.query(q) {
+            let Some(entry) = self.docs.get(&id) else {
+                continue;
+            };
+            values.clear();
+            match field {
+                "level" => values.extend(entry.level.as_deref()),
+                "service" => values.extend(entry.services()),
+                _ => {
+                    for word in entry.content.split(CONTENT_SEPARATORS) {
+                        if let Some((key, value)) = word.split_once(':') {
+                            if key.eq_ignore_ascii_case(field)
+                                && !value.is_empty()
+                                && !values.contains(&value)
+                            {
+                                values.push(value);
+                            }
+                        }
+                    }
+                }
+            }
+            for value in &values {
+                *counts.entry(value).or_default() += 1;
+            }
+        }
+        let mut facets: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(value, count)| (value.to_string(), count))
+            .collect();
+        facets.sort_by_key(|&(_, count)| Reverse(count));
+        facets
+    }
+
+    /// Returns the smallest posting that every document containing a phrase must be
+    /// in, or `None` if one of its indexed words, or the phrase itself when indexed as
+    /// an N-gram, has no posting.
+    fn phrase_candidates(&self, phrase: &str, words: &[&str]) -> Option<&Posting> {
+        let mut postings = Vec::with_capacity(words.len() + 1);
+        if self.config.enable_ngrams && (2..=self.config.max_ngram_size).contains(&words.len()) {
+            postings.push(self.query_posting(self.ufhg.string_to_u64_to_seq_hash(phrase)));
+        }
+        for word in words.iter().filter(|w| self.ufhg.keeps_word(w)) {
+            postings.push(self.term_posting(word));
+        }
+        postings
+            .into_iter()
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .min_by_key(|p| p.len())
+    }
+
+    /// Executes a query and returns a short snippet of each matching document.
+    ///
+    /// The snippet is `window` characters of the stored content, centered on the first
+    /// occurrence of any positive term of the query (matched case-insensitively, like
+    /// the index). An ellipsis marks each side where the content was cut. Documents that
+    /// matched only through metadata, such as `level:ERROR`, get a snippet from the start
+    /// of their content.
+    pub fn query_snippets(&self, q: &str, window: usize) -> Vec<(DocId, String)> {
+        let ast = self.parse_lenient(q);
+        let mut terms = Vec::new();
+        collect_positive_terms(&ast, &mut terms);
+        self.exec(&ast)
+            .into_iter()
+            .filter_map(|id| {
+                self.docs
+                    .get(&id)
+                    .map(|e| (id, snippet(&e.content, &terms, window)))
+            })
+            .collect()
+    }
+
+    /// Executes a query and returns each matching document with the byte ranges of its
+    /// content that the query matched, for underlining matches in a UI.
+    ///
+    /// Every occurrence of a word, `contains:` term or `fuzzy:` word of the query
+    /// that is not negated is a range, as is each occurrence of a phrase, spanning its
+    /// words. Words are compared as the index compares them, so `error` marks
+    /// `ERROR` too. Overlapping ranges are merged. Ranges start and end at word
+    /// boundaries, so they are always valid to slice the content at. A document
+    /// matched only through metadata, such as `level:ERROR`, has no ranges.
+    pub fn query_matches(&self, q: &str) -> Vec<DocMatch> {
+        let ast = self.parse_lenient(q);
+        let mut leaves = Vec::new();
+        collect_match_leaves(&ast, &mut leaves);
+        // Words too short to be indexed never match.
+        leaves.retain(|leaf| match leaf {
+            QueryNode::Term(w) | QueryNode::Contains(w) => self.ufhg.keeps_word(w),
+            _ => true,
+        });
+        self.exec(&ast)
+            .into_iter()
+            .filter_map(|id| {
+                let entry = self.docs.get(&id)?;
+                Some(DocMatch {
+                    id,
+                    spans: match_spans(&entry.content, &leaves),
+                })
+            })
+            .collect()
+    }
+
+    /// Cleans up stale documents from the database.
+    pub fn cleanup_stale(&mut self) {}
+
+    /// Rebuilds the indexes for log levels and services.
+    pub fn rebuild_indexes(&mut self) {
+        self.level_index = Arc::new(self.docs.create_index_into(|entry| {
+            entry.level.as_ref().map(|s| lightning_hash_str(s.as_str()))
+        }));
+        let mut service_index: BugguHashSet<Tok, Posting> = BugguHashSet::new(128);
+        for (&id, entry) in self.docs.iter() {
+            for service in entry.services() {
+                service_index
+                    .entry(lightning_hash_str(service))
+                    .or_default()
+                    .add(id);
+            }
+        }
+        self.service_index = Arc::new(service_index);
+    }
+
+    /// Checks that the postings and the level/service indexes are consistent with `docs`.
+    ///
+    /// This is a debugging aid: it walks every structure, so it is far too slow to
+    /// call on a hot path. Every posting and index entry must reference an existing
+    /// document, every document token must have a posting that lists the document,
+    /// and every document's level and service must be indexed under their hash.
+    ///
+    /// # Returns
+    /// `Ok(())` if no problems were found, or a description of each inconsistency.
+    pub fn verify_integrity(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        for tok in self.postings.iter_keys() {
+            if let Some(posting) = self.postings.get(&tok) {
+                for id in posting.iter() {
+                    if self.docs.get(&id).is_none() {
+                        problems.push(format!("posting {tok} references missing doc {id}"));
+                    }
+                }
+            }
+        }
+
+        for id in self.docs.iter_keys() {
+            let Some(entry) = self.docs.get(&id) else {
+                continue;
+            };
+            for &tok in &entry.tokens {
+                if !self.postings.get(&tok).is_some_and(|p| p.contains(id)) {
+                    problems.push(format!("doc {id} token {tok} is missing from postings"));
+                }
+            }
+        }
+
+        self.verify_index(
+            "level",
+            &self.level_index,
+            |e| e.level.iter().map(String::as_str).collect(),
+            &mut problems,
+        );
+        self.verify_index(
+            "service",
+            &self.service_index,
+            |e| e.services().collect(),
+            &mut problems,
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Cross-checks a metadata index against the field values stored in `docs`.
+    fn verify_index<F>(
+        &self,
+        name: &str,
+        index: &BugguHashSet<Tok, Posting>,
+        field: F,
+        problems: &mut Vec<String>,
+    ) where
+        F: Fn(&MetaEntry) -> Vec<&str>,
+    {
+        for key in index.iter_keys() {
+            let Some(ids) = index.get(&key) else {
+                continue;
+            };
+            for id in ids.iter() {
+                match self.docs.get(&id) {
+                    None => problems.push(format!("{name} index references missing doc {id}")),
+                    Some(entry)
+                        if !field(entry)
+                            .into_iter()
+                            .any(|value| lightning_hash_str(value) == key) =>
+                    {
+                        problems.push(format!("{name} index lists doc {id} under the wrong value"))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        for id in self.docs.iter_keys() {
+            let Some(entry) = self.docs.get(&id) else {
+                continue;
+            };
+            for value in field(entry) {
+                let indexed = index
+                    .get(&lightning_hash_str(value))
+                    .is_some_and(|ids| ids.contains(id));
+                if !indexed {
+                    problems.push(format!(
+                        "doc {id} {name} {value:?} is missing from the {name} index"
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Executes a query AST node and returns the matching document IDs.
+    fn exec(&self, node: &QueryNode) -> Vec<DocId> {
+        self.exec_within(node, &mut Budget::unlimited())
+    }
+
+    /// Executes a query AST node, charging the size of each set of matches it builds
+    /// to `budget`. Once the budget runs out, the nodes left to evaluate match
+    /// nothing, and a negation, whose exclusions may be incomplete, matches nothing
+    /// either.
+    fn exec_within(&self, node: &QueryNode, budget: &mut Budget) -> Vec<DocId> {
+        if budget.exhausted {
+            return Vec::new();
+        }
+        let docs = match node {
+            QueryNode::Term(w) | QueryNode::Contains(w) => self
+                .term_posting(w)
+                .map(|p| p.get_docs())
+                .unwrap_or_default(),
+
+            QueryNode::Phrase(p) => {
+                let seq_hash = self.ufhg.string_to_u64_to_seq_hash(p);
+                self.query_posting(seq_hash)
+                    .map(|p| p.get_docs())
+                    .unwrap_or_default()
+            }
+
+            QueryNode::FieldTerm(f, v) => match *f {
+                "level" => self.filter_by_level(v),
+                "service" => self.filter_by_service(v),
+                _ => {
+                    let field = self.postings.get(&lightning_hash_str(f));
+                    let value = self.postings.get(&lightning_hash_str(v));
+                    match (field, value) {
+                        (Some(field), Some(value)) => field.intersect(value),
+                        _ => Vec::new(),
+                    }
+                }
+            },
+
+            QueryNode::And(children) => {
+                if children.is_empty() {
+                    return Vec::new();
+                }
+
+                // Two words are intersected straight from their postings.
+                if let Some((a, b)) = term_pair(children) {
+                    let (Some(a), Some(b)) = (self.term_posting(a), self.term_posting(b)) else {
+                        return Vec::new();
+                    };
+                    budget.spend(a.len());
+                    if budget.exhausted {
+                        return Vec::new();
+                    }
+                    budget.spend(b.len());
+                    let docs = a.intersect(b);
+                    budget.spend(docs.len());
+                    return docs;
+                }
+
+                // `MatchAll` never narrows an intersection, and negated children
+                // (including `missing:` ones) are subtracted once the others are
+                // intersected. With no other children the intersection starts from
+                // every document, so a query made only of negations excludes from
+                // `MatchAll`.
+                let (negated, positive): (Vec<_>, Vec<_>) = children
+                    .iter()
+                    .filter(|c| !matches!(c, QueryNode::MatchAll))
+                    .partition(|c| excluded(c).is_some());
+                let mut result_set = match positive.split_first() {
+                    #[cfg(feature = "rayon")]
+                    Some(_) if budget.parallel => {
+                        let mut sets = self.par_exec_sets(&positive, budget).into_iter();
+                        let first = sets.next().unwrap_or_default();
+                        sets.fold(first, |result_set, other_set| {
+                            if result_set.len().min(other_set.len()) < PARALLEL_MIN_PROBE {
+                                result_set.intersect_with(&other_set)
+                            } else {
+                                result_set.par_intersect_with(&other_set)
+                            }
+                        })
+                    }
+                    Some((first, rest)) => {
+                        let mut result_set = self.exec_to_set(first, budget);
+                        for child in rest {
+                            if result_set.is_empty() {
+                                break;
+                            }
+                            let other_set = self.exec_to_set(child, budget);
+                            result_set = result_set.intersect_with(&other_set);
+                        }
+                        result_set
+                    }
+                    None => self.create_all_docs_set(),
+                };
+                for inner in negated.into_iter().filter_map(excluded) {
+                    if result_set.is_empty() {
+                        break;
+                    }
+                    let exclude_set = self.exec_to_set(&inner, budget);
+                    if budget.exhausted {
+                        return Vec::new();
+                    }
+                    result_set = result_set.fast_difference(&exclude_set);
+                }
+                result_set.keys()
+            }
+
+            QueryNode::Or(children) => {
+                if children.is_empty() {
+                    return Vec::new();
+                }
+                let services: Option<Vec<&str>> = children
+                    .iter()
+                    .map(|c| match c {
+                        QueryNode::FieldTerm("service", v) => Some(v.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                if children.iter().any(|c| matches!(c, QueryNode::MatchAll)) {
+                    self.docs.iter_keys().collect()
+                } else if let Some(services) = services {
+                    // `service:a,b,c` merges the service lists in one pass.
+                    self.filter_by_services(&services)
+                } else if budget.parallel {
+                    let children: Vec<&QueryNode> = children.iter().collect();
+                    let mut sets = self.par_exec_sets(&children, budget).into_iter();
+                    let first = sets.next().unwrap_or_default();
+                    sets.fold(first, |result_set, other_set| {
+                        result_set.union_with(&other_set)
+                    })
+                    .keys()
+                } else {
+                    let mut result_set = self.exec_to_set(&children[0], budget);
+                    for child in &children[1..] {
+                        let other_set = self.exec_to_set(child, budget);
+                        result_set = result_set.union_with(&other_set);
+                    }
+                    result_set.keys()
+                }
+            }
+
+            QueryNode::Fuzzy(w, distance) => self.filter_by_fuzzy(w, *distance),
+            QueryNode::Phonetic(w) => self.filter_by_phonetic(w),
+
+            QueryNode::Exists(field, true) => self.filter_by_exists(field),
+
+            QueryNode::Exists(field, false) => {
+                let present = self.filter_by_exists(field);
+                self.docs
+                    .iter_keys()
+                    .filter(|id| present.binary_search(id).is_err())
+                    .collect()
+            }
+
+            QueryNode::MatchAll => self.docs.iter_keys().collect(),
+
+            QueryNode::Boost(_, child) => self.exec_within(child, budget),
+
+            QueryNode::NumericRange("timestamp", lo, hi) => self.filter_by_timestamp(*lo, *hi),
+            QueryNode::NumericRange("severity", lo, hi) => self.filter_by_severity(*lo, *hi),
+
+            QueryNode::Not(child) => {
+                let exclude_set = self.exec_to_set(child, budget);
+                if budget.exhausted {
+                    return Vec::new();
+                }
+                let all_docs_set = self.create_all_docs_set();
+                all_docs_set.fast_difference(&exclude_set).keys()
+            }
+
+            _ => Vec::new(),
+        };
+        budget.spend(docs.len());
+        docs
+    }
+
+    /// Executes a query AST node, passing each match to `f` until it returns `false`.
+    fn exec_for_each<F: FnMut(DocId) -> bool>(&self, node: &QueryNode, f: &mut F) {
+        match node {
+            QueryNode::Term(w) | QueryNode::Contains(w) => {
+                if let Some(posting) = self.term_posting(w) {
+                    posting.for_each(f);
+                }
+            }
+            QueryNode::Not(child) => {
+                let exclude_set = self.exec_to_set(child, &mut Budget::unlimited());
+                self.docs
+                    .iter_keys()
+                    .filter(|id| exclude_set.get(id).is_none())
+                    .all(f);
+            }
+            QueryNode::MatchAll => {
+                self.docs.iter_keys().all(f);
+            }
+            QueryNode::Boost(_, child) => self.exec_for_each(child, f),
+            _ => {
+                self.exec(node).into_iter().all(f);
+            }
+        }
+    }
+
+    /// Executes a query AST node within `budget` and returns the results as a
+    /// `BugguHashSet`.
+    fn exec_to_set(&self, node: &QueryNode, budget: &mut Budget) -> BugguHashSet<DocId, ()> {
+        docs_to_set(self.exec_within(node, budget))
+    }
+
+    /// Executes each of `nodes` within `budget` and returns their results as sets,
+    /// building the sets of terms and phrases on the rayon thread pool.
+    ///
+    /// Their postings are looked up first, on this thread, so that an LRU eviction
+    /// policy sees the accesses; other nodes are executed as by `exec_to_set`. Each
+    /// set is built as `exec_to_set` would build it, so combining them gives the same
+    /// result in the same order.
+    #[cfg(feature = "rayon")]
+    fn par_exec_sets(
+        &self,
+        nodes: &[&QueryNode],
+        budget: &mut Budget,
+    ) -> Vec<BugguHashSet<DocId, ()>> {
+        use rayon::prelude::*;
+
+        enum Branch<'a> {
+            Posting(Option<&'a Posting>),
+            Done(BugguHashSet<DocId, ()>),
+        }
+        let branches: Vec<Branch> = nodes
+            .iter()
+            .map(|node| match node {
+                QueryNode::Term(w) | QueryNode::Contains(w) => {
+                    let posting = self.term_posting(w);
+                    budget.spend(posting.map_or(0, Posting::len));
+                    Branch::Posting(posting)
+                }
+                QueryNode::Phrase(p) => {
+                    let posting = self.query_posting(self.ufhg.string_to_u64_to_seq_hash(p));
+                    budget.spend(posting.map_or(0, Posting::len));
+                    Branch::Posting(posting)
+                }
+                other => Branch::Done(self.exec_to_set(other, budget)),
+            })
+            .collect();
+        branches
+            .into_par_iter()
+            .map(|branch| match branch {
+                Branch::Posting(posting) => {
+                    docs_to_set(posting.map(Posting::get_docs).unwrap_or_default())
+                }
+                Branch::Done(set) => set,
+            })
+            .collect()
+    }
+
+    /// Stands in for `par_exec_sets` without the `rayon` feature, where
+    /// `Budget::parallel` is never set.
+    #[cfg(not(feature = "rayon"))]
+    fn par_exec_sets(
+        &self,
+        nodes: &[&QueryNode],
+        budget: &mut Budget,
+    ) -> Vec<BugguHashSet<DocId, ()>> {
+        nodes
+            .iter()
+            .map(|node| self.exec_to_set(node, budget))
+            .collect()
+    }
+
+    /// Creates a `BugguHashSet` containing all document IDs in the database.
+    fn create_all_docs_set(&self) -> BugguHashSet<DocId, ()> {
+        let mut set = BugguHashSet::new(self.docs.len());
+        for id in self.docs.iter_keys() {
+            set.insert(id, ());
+        }
+        set
+    }
+
+    /// Filters documents by log level.
+    fn filter_by_level(&self, level: &str) -> Vec<DocId> {
+        self.level_index
+            .get(&lightning_hash_str(level))
+            .map(Posting::get_docs)
+            .unwrap_or_default()
+    }
+
+    /// Filters documents by the priority `LogConfig::log_level_priority` gives their
+    /// level, keeping those within `lo..=hi`. Documents without a level never match.
+    fn filter_by_severity(&self, lo: u64, hi: u64) -> Vec<DocId> {
+        let mut docs = Vec::new();
+        for key in self.level_index.iter_keys() {
+            // Unconfigured levels rank as INFO, as in `log_level_priority`.
+            let priority = self.config.log_levels.get(&key).copied().unwrap_or(2);
+            if (lo..=hi).contains(&u64::from(priority)) {
+                if let Some(ids) = self.level_index.get(&key) {
+                    docs.extend(ids.iter());
+                }
+            }
+        }
+        // A document has a single level, so the lists hold no duplicates.
+        docs.sort_unstable();
+        docs
+    }
+
+    /// Filters documents by service name.
+    fn filter_by_service(&self, service: &str) -> Vec<DocId> {
+        self.service_index
+            .get(&lightning_hash_str(service))
+            .map(Posting::get_docs)
+            .unwrap_or_default()
+    }
+
+    /// Filters documents belonging to any of the given services.
+    ///
+    /// The matching index lists are gathered and merged in a single pass, so this is
+    /// cheaper than running one `service:` query per service and unioning the results.
+    /// The returned IDs are sorted and free of duplicates.
+    pub fn filter_by_services(&self, services: &[&str]) -> Vec<DocId> {
+        let mut docs = Vec::new();
+        for service in services {
+            if let Some(ids) = self.service_index.get(&lightning_hash_str(service)) {
+                docs.reserve(ids.len());
+                ids.for_each(&mut |id| {
+                    docs.push(id);
+                    true
+                });
+            }
+        }
+        // Large index lists come in table order, so the merged lists are sorted once
+        // here rather than one by one.
+        docs.sort_unstable();
+        docs.dedup();
+        docs
+    }
+
+    /// Filters documents that have a value for the given field.
+    ///
+    /// For `level` and `service` every list in the matching index is merged. Any other
+    /// field is looked up in the prefix index, whose `field:value` words sort together,
+    /// and the postings of every word with a non-empty value are merged. As for
+    /// `suggest`, a word is found under the spelling first indexed, so a document
+    /// whose `field:value` differs from an earlier one only in case is not matched.
+    /// The returned IDs are sorted and free of duplicates, even for documents tagged
+    /// with several services.
+    fn filter_by_exists(&self, field: &str) -> Vec<DocId> {
+        let index = if field.eq_ignore_ascii_case("level") {
+            &self.level_index
+        } else if field.eq_ignore_ascii_case("service") {
+            &self.service_index
+        } else {
+            let prefix = format!("{}:", field.to_lowercase());
+            let mut docs = Vec::new();
+            for (word, tok) in self.prefix_index.range(prefix.clone()..) {
+                if !word.starts_with(&prefix) {
+                    break;
+                }
+                // The prefix itself is a field with an empty value.
+                if word.len() == prefix.len() {
+                    continue;
+                }
+                if let Some(posting) = self.query_posting(*tok) {
+                    docs.extend(posting.iter());
+                }
+            }
+            docs.sort_unstable();
+            docs.dedup();
+            return docs;
+        };
+
+        let mut docs = Vec::new();
+        for key in index.iter_keys() {
+            if let Some(ids) = index.get(&key) {
+                docs.extend(ids.iter());
+            }
+        }
+        docs.sort();
+        docs.dedup();
+        docs
+    }
+
+    /// Filters documents whose timestamp lies within `lo..=hi`.
+    ///
+    /// Timestamps are not indexed, so this scans every document.
+    fn filter_by_timestamp(&self, lo: u64, hi: u64) -> Vec<DocId> {
+        let mut docs: Vec<DocId> = self
+            .docs
+            .iter()
+            .filter(|(_, entry)| (lo..=hi).contains(&entry.timestamp))
+            .map(|(id, _)| *id)
+            .collect();
+        docs.sort();
+        docs
+    }
+
+    /// Filters documents containing a word within `distance` edits of `word`.
+    ///
+    /// Matching is case-insensitive. Every indexed word is compared against `word`, so
+    /// this scans the prefix index. The returned IDs are sorted.
+    fn filter_by_fuzzy(&self, word: &str, distance: u8) -> Vec<DocId> {
+        let word: Vec<char> = word.to_lowercase().chars().collect();
+        let mut docs = Vec::new();
+        for (candidate, tok) in self.prefix_index.iter() {
+            if within_edit_distance(&word, candidate, distance as usize) {
+                if let Some(posting) = self.query_posting(*tok) {
+                    docs.extend(posting.iter());
+                }
+            }
+        }
+        docs.sort();
+        docs.dedup();
+        docs
+    }
+
+    /// Filters documents containing a word with the same Soundex code as `word`.
+    ///
+    /// The codes are looked up in the phonetic index, so this matches nothing unless
+    /// `LogConfig::enable_phonetic` was set when the documents were indexed. The
+    /// returned IDs are sorted.
+    fn filter_by_phonetic(&self, word: &str) -> Vec<DocId> {
+        let Some(toks) = soundex(word).and_then(|code| self.phonetic_index.get(&code)) else {
+            return Vec::new();
+        };
+        let mut docs = Vec::new();
+        for &tok in toks {
+            if let Some(posting) = self.query_posting(tok) {
+                docs.extend(posting.iter());
+            }
+        }
+        docs.sort();
+        docs.dedup();
+        docs
+    }
+
+    /// Returns up to `limit` indexed words starting with `prefix`, most frequent first.
+    ///
+    /// Matching is case-insensitive and words are returned in lowercase. Words with
+    /// the same document frequency are ordered alphabetically. An empty prefix
+    /// returns the most frequent words overall.
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        let mut matches: Vec<(&String, usize)> = self
+            .prefix_index
+            .range(prefix.clone()..)
+            .take_while(|(word, _)| word.starts_with(&prefix))
+            .filter_map(|(word, tok)| {
+                let freq = self.postings.get(tok).map_or(0, Posting::len);
+                (freq > 0).then_some((word, freq))
+            })
+            .collect();
+        matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(word, _)| word.clone())
+            .collect()
+    }
+
+    /// Adds the words of a document's content to the term dictionary and the prefix
+    /// index.
+    ///
+    /// Called after the document's tokens are indexed. Every word is interned, so the
+    /// dictionary's eviction order follows use. With `new_only`, only words whose
+    /// posting holds just this document are looked up in the prefix index, since any
+    /// other word was already added along with an earlier document. This keeps
+    /// ingestion from probing the sorted map for every word.
+    fn index_words(&mut self, content: &str, new_only: bool) {
+        self.index_words_where(content, |db, tok| {
+            !new_only || db.postings.get(&tok).is_some_and(|p| p.len() == 1)
+        });
+    }
+
+    /// Adds the words of a document's content to the term dictionary, and those for
+    /// whose token `is_new` returns `true` to the prefix index.
+    fn index_words_where(&mut self, content: &str, mut is_new: impl FnMut(&Self, Tok) -> bool) {
+        for word in content.split(CONTENT_SEPARATORS) {
+            if word.is_empty() || !self.ufhg.keeps_word(word) {
+                continue;
+            }
+            let tok = Arc::make_mut(&mut self.term_dict).intern(word);
+            if !is_new(self, tok) {
+                continue;
+            }
+            let key = lowercase(word);
+            if !self.prefix_index.contains_key(key.as_ref()) {
+                if self.config.enable_phonetic {
+                    if let Some(code) = soundex(word) {
+                        self.phonetic_index.entry(code).or_default().push(tok);
+                    }
+                }
+                Arc::make_mut(&mut self.prefix_index).insert(key.into_owned(), tok);
+            }
+        }
+    }
+
+    /// Removes the words of a deleted document's content from the term dictionary and
+    /// the prefix index when no other document contains them.
+    fn unindex_words(&mut self, content: &str) {
+        for word in content.split(CONTENT_SEPARATORS).filter(|w| !w.is_empty()) {
+            let tok = lightning_hash_str(word);
+            if self.postings.get(&tok).is_none_or(Posting::is_empty) {
+                Arc::make_mut(&mut self.term_dict).remove(tok);
+            }
+            let key = lowercase(word);
+            let Some(tok) = self.prefix_index.get(key.as_ref()) else {
+                continue;
+            };
+            if self.postings.get(tok).is_none_or(Posting::is_empty) {
+                if let Some(tok) = Arc::make_mut(&mut self.prefix_index).remove(key.as_ref()) {
+                    self.unindex_phonetic(word, tok);
+                }
+            }
+        }
+    }
+
+    /// Drops a word's token from the phonetic index, along with its code once no
+    /// other word has it.
+    fn unindex_phonetic(&mut self, word: &str, tok: Tok) {
+        let Some(code) = soundex(word) else {
+            return;
+        };
+        if let Some(toks) = self.phonetic_index.get_mut(&code) {
+            toks.retain(|&t| t != tok);
+            if toks.is_empty() {
+                self.phonetic_index.remove(&code);
+            }
+        }
+    }
+
+    /// Returns the dictionary mapping the tokens of document content back to the words
+    /// they came from, with a count of the words whose tokens collide.
+    pub fn term_dict(&self) -> &TermDict {
+        &self.term_dict
+    }
+
+    /// Checks whether any document contains the given term.
+    pub fn contains_term(&self, term: &str) -> bool {
+        self.term_doc_freq(term) > 0
+    }
+
+    /// Returns the number of documents that contain the given term.
+    ///
+    /// This reads the term's posting directly, so it is a cheap way to check how
+    /// selective a term is before running a query with it.
+    pub fn term_doc_freq(&self, term: &str) -> usize {
+        self.term_posting(term).map_or(0, Posting::len)
+    }
+
+    /// Returns the `n` words of document content found in the most documents, with
+    /// the number of documents each is found in, most frequent first. Words found in
+    /// as many documents are ordered alphabetically.
+    ///
+    /// Words come from `term_dict`, so with `LogConfig::term_dict_capacity` set only
+    /// the words it keeps are counted. The terms are streamed through a heap of `n`
+    /// entries rather than sorted. Words at the top that narrow queries little are
+    /// candidates for stopwords.
+    pub fn most_common_terms(&self, n: usize) -> Vec<(String, usize)> {
+        let counts = self.term_dict.iter_terms().map(|(tok, word)| {
+            let count = self.postings.get(&tok).map_or(0, Posting::len);
+            (word, count)
+        });
+        top_counts(counts, n)
+    }
+
+    /// Returns the `n` words occurring most often in the content of documents matching
+    /// a query, with how many times each occurs in them, most frequent first. A word
+    /// repeated in a document counts every time. Words occurring as often are ordered
+    /// alphabetically.
+    ///
+    /// The tokens stored for each matching document are tallied and resolved through
+    /// `term_dict`, so tokens no document's content has a word for, such as N-grams,
+    /// are skipped. A level or service indexed as a word counts like one in the content
+    /// once some content contains it. With `exclude_stopwords`, common English filler
+    /// words such as `the` and `of` are left out.
+    pub fn top_terms(&self, q: &str, n: usize, exclude_stopwords: bool) -> Vec<(String, usize)> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut counts: BugguHashSet<Tok, usize> = BugguHashSet::growable(256);
+        for id in self.exec(&self.parse_lenient(q)) {
+            let Some(entry) = self.docs.get(&id) else {
+                continue;
+            };
+            for &tok in &entry.tokens {
+                *counts.entry(tok).or_default() += 1;
+            }
+        }
+        let counts = counts.iter().filter_map(|(tok, &count)| {
+            let word = self.term_dict.resolve(*tok)?;
+            let stopword = exclude_stopwords && STOPWORDS.contains(&lowercase(word).as_ref());
+            (!stopword).then_some((word, count))
+        });
+        top_counts(counts, n)
+    }
+
+    /// Returns statistics on the postings for capacity planning: how many tokens are
+    /// indexed, how many `(token, document)` entries their postings hold, and the
+    /// `TERM_STATS_HEAVIEST` tokens with the most documents.
+    pub fn term_stats(&self) -> TermStats {
+        let mut stats = TermStats::default();
+        let mut heap = BinaryHeap::with_capacity(TERM_STATS_HEAVIEST + 1);
+        for (&tok, posting) in self.postings.iter() {
+            let docs = posting.len();
+            if docs == 0 {
+                continue;
+            }
+            stats.distinct_terms += 1;
+            stats.postings_entries += docs;
+            heap.push(Reverse((docs, Reverse(tok))));
+            if heap.len() > TERM_STATS_HEAVIEST {
+                heap.pop();
+            }
+        }
+        stats.heaviest = heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((docs, Reverse(tok)))| {
+                let term = self.term_dict.resolve(tok).map(str::to_string);
+                (tok, term, docs)
+            })
+            .collect();
+        stats
+    }
+
+    /// Returns a sorted, deterministic view of the documents and postings, meant for
+    /// asserting the full state of an index in tests.
+    ///
+    /// It copies every document and posting, so it is not meant for large indexes.
+    pub fn debug_snapshot(&self) -> LogDbSnapshot {
+        let mut snapshot = LogDbSnapshot {
+            docs: self
+                .docs
+                .iter()
+                .map(|(&id, entry)| (id, entry.content.clone()))
+                .collect(),
+            ..LogDbSnapshot::default()
+        };
+        for (&tok, posting) in self.postings.iter() {
+            let mut docs = posting.get_docs();
+            if docs.is_empty() {
+                continue;
+            }
+            docs.sort_unstable();
+            match self.term_dict.resolve(tok) {
+                Some(word) => snapshot.postings.insert(word.to_string(), docs),
+                None => snapshot.unnamed_postings.insert(tok, docs),
+            };
+        }
+        snapshot
+    }
+
+    /// Returns the posting of a query word. Words shorter than `min_token_len` are
+    /// never indexed, so they have none, even if their hash collides with a token
+    /// that is.
+    fn term_posting(&self, word: &str) -> Option<&Posting> {
+        if !self.ufhg.keeps_word(word) {
+            return None;
+        }
+        self.query_posting(lightning_hash_str(word))
+    }
+
+    /// Inserts a token into the postings list if it doesn't already exist.
+    pub fn upsert_token(&mut self, s: impl AsRef<str>) -> Tok {
+        let tok = lightning_hash_str(s.as_ref());
+        Arc::make_mut(&mut self.postings).entry(tok).or_default();
+        tok
+    }
+
+    /// Exports all tokens from the postings list.
+    pub fn export_tokens(&self) -> Vec<Tok> {
+        self.postings.keys()
+    }
+
+    /// Imports a list of tokens into the postings list.
+    pub fn import_tokens(&mut self, toks: Vec<Tok>) {
+        for t in toks {
+            Arc::make_mut(&mut self.postings).entry(t).or_default();
+        }
+    }
+
+    /// Returns the `(empty, inline, overflow)` bucket distribution of the postings table.
+    pub fn postings_bucket_stats(&self) -> (usize, usize, usize) {
+        self.postings.bucket_stats()
+    }
+
+    /// Returns the number of stored documents.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Returns whether no documents are stored.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Returns the operation counters recorded so far.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Records a finished query in the metrics and the slow-query log.
+    #[inline]
+    fn record_query(&self, q: &str, results: usize, took: Duration) {
+        self.metrics.record_query(took);
+        self.query_log.record(q, results, took);
+    }
+
+    /// Returns the queries in the slow-query log that took longer than
+    /// `threshold_micros` microseconds, oldest first.
+    ///
+    /// The log keeps the last `LogConfig::query_log_capacity` queries given as
+    /// strings. Queries built from a template, a `QuerySpec` or a list of phrases are
+    /// counted in `query_timing_summary` but not logged. Clause counts are worked out
+    /// here by parsing each query again, so recording a query costs no more than a
+    /// clock read and a write into the ring.
+    pub fn slow_queries(&self, threshold_micros: u64) -> Vec<SlowQueryRecord> {
+        self.query_log
+            .slower_than(Duration::from_micros(threshold_micros), |q| {
+                parse_query(q, &self.config, self.clock).map_or(0, |ast| query_complexity(&ast).0)
+            })
+    }
+
+    /// Returns the count, mean, 95th percentile and maximum of the query times since
+    /// this `LogDB` was created. The 95th percentile is rounded up to a power of two
+    /// nanoseconds.
+    pub fn query_timing_summary(&self) -> QueryTimingSummary {
+        self.metrics.timing_summary()
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    ///
+    /// Along with the counters and the query latency histogram, this reports the
+    /// number of stored documents, an estimate of the memory used by the index and
+    /// the number of words whose tokens collide.
+    pub fn metrics_text(&self) -> String {
+        self.metrics.render(
+            self.docs.len(),
+            self.approx_bytes(),
+            self.term_dict.collisions(),
+        )
+    }
+
+    /// Estimates the memory used by the index, in bytes.
+    ///
+    /// This counts the hash tables, the posting lists, the prefix index, the term
+    /// dictionary and each document's content and tokens, but not allocator overhead or the tokenizer's
+    /// state.
+    pub fn approx_bytes(&self) -> usize {
+        let postings: usize = self
+            .postings
+            .iter()
+            .map(|(_, posting)| posting.heap_bytes())
+            .sum();
+        let docs: usize = self
+            .docs
+            .iter()
+            .map(|(_, entry)| {
+                entry.content.capacity()
+                    + entry.raw_content.as_ref().map_or(0, String::capacity)
+                    + entry.tokens.capacity() * std::mem::size_of::<Tok>()
+                    + entry.level.as_ref().map_or(0, String::capacity)
+                    + entry.service.as_ref().map_or(0, String::capacity)
+                    + entry
+                        .extra_services
+                        .iter()
+                        .map(String::capacity)
+                        .sum::<usize>()
+            })
+            .sum();
+        let index = |table: &BugguHashSet<Tok, Posting>| {
+            table.table_bytes() + table.iter().map(|(_, ids)| ids.heap_bytes()).sum::<usize>()
+        };
+        let words: usize = self
+            .prefix_index
+            .keys()
+            .map(|word| word.capacity() + std::mem::size_of::<(String, Tok)>())
+            .sum();
+        let phonetic: usize = self
+            .phonetic_index
+            .iter()
+            .map(|(code, toks)| {
+                code.capacity()
+                    + toks.capacity() * std::mem::size_of::<Tok>()
+                    + std::mem::size_of::<(String, Vec<Tok>)>()
+            })
+            .sum();
+        self.postings.table_bytes()
+            + postings
+            + words
+            + phonetic
+            + self.docs.table_bytes()
+            + docs
+            + index(&self.level_index)
+            + index(&self.service_index)
+            + self.term_dict.approx_bytes()
+    }
+
+    /// Returns the average number of tokens per bucket in the postings table.
+    pub fn postings_load_factor(&self) -> f64 {
+        self.postings.load_factor()
+    }
+
+    /// Returns the number of tokens in the most populated postings bucket.
+    pub fn postings_max_chain_len(&self) -> usize {
+        self.postings.max_chain_len()
+    }
+
+    /// Returns the number of buckets allocated in the postings table.
+    pub fn postings_bucket_count(&self) -> usize {
+        self.postings.bucket_count()
+    }
+
+    /// Grows the postings table so that `expected_terms` more distinct tokens can be
+    /// indexed without it doubling along the way, as before a batch of known size.
+    ///
+    /// Each document adds a token for every distinct word, and for its level, service
+    /// and N-grams when those are indexed, so count those too. The table never
+    /// shrinks, so reserving for more tokens than arrive wastes its memory for the
+    /// life of the `LogDB`. A table with a fixed number of buckets is left unchanged.
+    pub fn reserve_postings(&mut self, expected_terms: usize) {
+        Arc::make_mut(&mut self.postings).reserve(expected_terms);
+    }
+
+    /// Inspects the index for problems that slow queries down or waste memory.
+    ///
+    /// The findings raised, by code, are:
+    /// * `common_token` - words appearing in more than half of the documents, which
+    ///   match too much to narrow a query and are better treated as stopwords.
+    /// * `long_bucket_chain` - a hash table with a bucket holding more than
+    ///   `MAX_CHAIN_LEN` entries, which would be faster with more buckets.
+    /// * `missing_level` - more than half of the documents have no level, so `level:`
+    ///   filters and severity ordering cannot see them.
+    /// * `whitespace_tokens` - `LogConfig::index_whitespace` is on, storing tokens
+    ///   that are never searched for.
+    /// * `postings_over_limit` and `postings_near_limit` - the number of postings is
+    ///   over, or close to, `LogConfig::max_postings` while no eviction policy
+    ///   enforces it.
+    ///
+    /// # Returns
+    /// The findings, most severe first. Render the report with `to_string` for a
+    /// line per finding.
+    pub fn health_report(&self) -> HealthReport {
+        let mut findings = Vec::new();
+        let docs = self.docs.len();
+
+        if docs >= COMMON_TOKEN_MIN_DOCS {
+            let mut common: Vec<(&str, usize)> = self
+                .prefix_index
+                .iter()
+                .filter_map(|(word, tok)| Some((word.as_str(), self.postings.get(tok)?.len())))
+                .filter(|&(_, count)| count as f64 > docs as f64 * COMMON_TOKEN_RATIO)
+                .collect();
+            if !common.is_empty() {
+                common.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+                let listed: Vec<String> = common
+                    .iter()
+                    .take(COMMON_TOKEN_LIMIT)
+                    .map(|(word, count)| format!("{word} ({}%)", count * 100 / docs))
+                    .collect();
+                let more = common.len().saturating_sub(COMMON_TOKEN_LIMIT);
+                let more = if more > 0 {
+                    format!(" and {more} more")
+                } else {
+                    String::new()
+                };
+                findings.push(HealthFinding {
+                    severity: Severity::Info,
+                    code: "common_token",
+                    message: format!(
+                        "{}{more} appear in more than {:.0}% of the documents; such words \
+                         narrow queries very little, consider treating them as stopwords",
+                        listed.join(", "),
+                        COMMON_TOKEN_RATIO * 100.0,
+                    ),
+                });
+            }
+        }
+
+        let tables = [
+            (
+                "postings",
+                self.postings.max_chain_len(),
+                self.postings.bucket_count(),
+            ),
+            (
+                "document",
+                self.docs.max_chain_len(),
+                self.docs.bucket_count(),
+            ),
+            (
+                "level",
+                self.level_index.max_chain_len(),
+                self.level_index.bucket_count(),
+            ),
+            (
+                "service",
+                self.service_index.max_chain_len(),
+                self.service_index.bucket_count(),
+            ),
+        ];
+        for (table, chain, buckets) in tables {
+            if chain > MAX_CHAIN_LEN {
+                findings.push(HealthFinding {
+                    severity: Severity::Warning,
+                    code: "long_bucket_chain",
+                    message: format!(
+                        "the {table} table has a bucket holding {chain} entries, more than \
+                         {MAX_CHAIN_LEN}; lookups would be faster with more than its {buckets} buckets"
+                    ),
+                });
+            }
+        }
+
+        let unleveled = self
+            .docs
+            .iter()
+            .filter(|(_, entry)| entry.level.is_none())
+            .count();
+        if docs > 0 && unleveled as f64 > docs as f64 * MISSING_LEVEL_RATIO {
+            findings.push(HealthFinding {
+                severity: Severity::Info,
+                code: "missing_level",
+                message: format!(
+                    "{unleveled} of {docs} documents have no level, so `level:` filters and \
+                     severity ordering skip them"
+                ),
+            });
+        }
+
+        if self.config.index_whitespace {
+            findings.push(HealthFinding {
+                severity: Severity::Info,
+                code: "whitespace_tokens",
+                message: "whitespace runs are indexed as tokens but never searched for; \
+                          turn off `index_whitespace` to store roughly half the tokens"
+                    .to_string(),
+            });
+        }
+
+        // With eviction enabled the limit is enforced, so only an unenforced one is
+        // worth reporting.
+        let postings = self.postings.len();
+        let limit = self.max_postings;
+        if self.config.eviction_policy == EvictionPolicy::Never
+            && postings as f64 >= limit as f64 * POSTINGS_NEAR_LIMIT_RATIO
+        {
+            let mib = self.approx_bytes() as f64 / (1024.0 * 1024.0);
+            findings.push(if postings > limit {
+                HealthFinding {
+                    severity: Severity::Warning,
+                    code: "postings_over_limit",
+                    message: format!(
+                        "{postings} postings exceed `max_postings` of {limit} with eviction \
+                         disabled, using about {mib:.1} MiB; set an `eviction_policy` or raise \
+                         the limit"
+                    ),
+                }
+            } else {
+                HealthFinding {
+                    severity: Severity::Info,
+                    code: "postings_near_limit",
+                    message: format!(
+                        "{postings} postings are close to `max_postings` of {limit} with \
+                         eviction disabled, using about {mib:.1} MiB"
+                    ),
+                }
+            });
+        }
+
+        findings.sort_by_key(|finding| Reverse(finding.severity));
+        HealthReport { findings }
+    }
+}
+
+/// Returns the node whose matches a negated `And` child excludes: the operand of a
+/// `Not`, or the present form of a `missing:` node.
+fn excluded(node: &QueryNode) -> Option<Cow<'_, QueryNode>> {
+    match node {
+        QueryNode::Not(inner) => Some(Cow::Borrowed(inner)),
+        QueryNode::Exists(field, false) => Some(Cow::Owned(QueryNode::Exists(field.clone(), true))),
+        _ => None,
+    }
+}
+
+/// Collects the text of every term, phrase and `contains:` node that is not negated.
+fn collect_positive_terms<'a>(node: &'a QueryNode, out: &mut Vec<&'a str>) {
+    match node {
+        QueryNode::Term(w) | QueryNode::Phrase(w) | QueryNode::Contains(w) if !w.is_empty() => {
+            out.push(w);
+        }
+        QueryNode::And(children) | QueryNode::Or(children) => {
+            for child in children {
+                collect_positive_terms(child, out);
+            }
+        }
+        QueryNode::Boost(_, child) => collect_positive_terms(child, out),
+        _ => {}
+    }
+}
+
+/// Collects every term, phrase, `contains:`, `fuzzy:` and `sounds:` node that is not
+/// negated.
+fn collect_match_leaves<'a>(node: &'a QueryNode, out: &mut Vec<&'a QueryNode>) {
+    match node {
+        QueryNode::Term(w) | QueryNode::Phrase(w) | QueryNode::Contains(w) if !w.is_empty() => {
+            out.push(node);
+        }
+        QueryNode::Fuzzy(..) | QueryNode::Phonetic(_) => out.push(node),
+        QueryNode::And(children) | QueryNode::Or(children) => {
+            for child in children {
+                collect_match_leaves(child, out);
+            }
+        }
+        QueryNode::Boost(_, child) => collect_match_leaves(child, out),
+        _ => {}
+    }
+}
+
+/// Finds the byte ranges of `content` matched by any of `leaves`, as described on
+/// `LogDB::query_matches`.
+fn match_spans(content: &str, leaves: &[&QueryNode]) -> Vec<(u32, u32)> {
+    let words: Vec<(usize, &str)> = content
+        .split(CONTENT_SEPARATORS)
+        .scan(0, |offset, word| {
+            let start = *offset;
+            *offset += word.len() + 1;
+            Some((start, word))
+        })
+        .filter(|(_, word)| !word.is_empty())
+        .collect();
+    let hashes: Vec<Tok> = words.iter().map(|(_, w)| lightning_hash_str(w)).collect();
+    let span = |first: usize, last: usize| {
+        let (start, _) = words[first];
+        let (end, word) = words[last];
+        (start as u32, (end + word.len()) as u32)
+    };
+
+    let mut spans = Vec::new();
+    for leaf in leaves {
+        match leaf {
+            QueryNode::Phrase(p) => {
+                let phrase: Vec<Tok> = p
+                    .split(CONTENT_SEPARATORS)
+                    .filter(|w| !w.is_empty())
+                    .map(lightning_hash_str)
+                    .collect();
+                if phrase.is_empty() {
+                    continue;
+                }
+                for (i, window) in hashes.windows(phrase.len()).enumerate() {
+                    if window == phrase.as_slice() {
+                        spans.push(span(i, i + phrase.len() - 1));
+                    }
+                }
+            }
+            QueryNode::Term(w) | QueryNode::Contains(w) => {
+                let tok = lightning_hash_str(w);
+                for (i, _) in hashes.iter().enumerate().filter(|(_, &h)| h == tok) {
+                    spans.push(span(i, i));
+                }
+            }
+            QueryNode::Fuzzy(w, distance) => {
+                let target: Vec<char> = w.to_lowercase().chars().collect();
+                for (i, (_, word)) in words.iter().enumerate() {
+                    if within_edit_distance(&target, &word.to_lowercase(), *distance as usize) {
+                        spans.push(span(i, i));
+                    }
+                }
+            }
+            QueryNode::Phonetic(w) => {
+                let Some(code) = soundex(w) else {
+                    continue;
+                };
+                for (i, (_, word)) in words.iter().enumerate() {
+                    if soundex(word).as_ref() == Some(&code) {
+                        spans.push(span(i, i));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    spans.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Checks that a document's content contains the words, phrases and `contains:`
+/// terms of a query with their case, for a case-sensitive `LogDB::search`. Negated
+/// nodes and filters always pass, the index having matched them already.
+fn matches_case(node: &QueryNode, content: &str) -> bool {
+    match node {
+        QueryNode::Term(w) | QueryNode::Phrase(w) | QueryNode::Contains(w) => {
+            content.contains(w.as_str())
+        }
+        QueryNode::And(children) => children.iter().all(|c| matches_case(c, content)),
+        QueryNode::Or(children) => children.iter().any(|c| matches_case(c, content)),
+        QueryNode::Boost(_, child) => matches_case(child, content),
+        _ => true,
+    }
+}
+
+/// Collects every term, phrase, `contains:` and field node that is not negated, each
+/// with the product of the boosts applied to it and the groups around it.
+fn collect_scored_leaves<'a>(node: &'a QueryNode, boost: f32, out: &mut Vec<(&'a QueryNode, f32)>) {
+    match node {
+        QueryNode::Term(w) | QueryNode::Phrase(w) | QueryNode::Contains(w) if !w.is_empty() => {
+            out.push((node, boost));
+        }
+        QueryNode::FieldTerm(..) => out.push((node, boost)),
+        QueryNode::And(children) | QueryNode::Or(children) => {
+            for child in children {
+                collect_scored_leaves(child, boost, out);
+            }
+        }
+        QueryNode::Boost(factor, child) => collect_scored_leaves(child, boost * factor, out),
+        _ => {}
+    }
+}
+
+/// Counts the clauses of a query AST and how deeply it nests, a lone clause having a
+/// depth of 1.
+fn query_complexity(node: &QueryNode) -> (usize, usize) {
+    match node {
+        QueryNode::And(children) | QueryNode::Or(children) => {
+            let (clauses, depth) = children
+                .iter()
+                .map(query_complexity)
+                .fold((0, 0), |(c, d), (cc, cd)| (c + cc, d.max(cd)));
+            (clauses, depth + 1)
+        }
+        QueryNode::Not(child) | QueryNode::Boost(_, child) => {
+            let (clauses, depth) = query_complexity(child);
+            (clauses, depth + 1)
+        }
+        _ => (1, 1),
+    }
+}
+
+/// Returns the name of the placeholder a template value is, the `svc` of `$svc`.
+fn placeholder(value: &str) -> Option<&str> {
+    let name = value.strip_prefix('$')?;
+    (!name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')).then_some(name)
+}
+
+/// Collects the name of every placeholder in a template AST.
+fn collect_placeholders<'a>(node: &'a QueryNode, out: &mut Vec<&'a str>) {
+    match node {
+        QueryNode::Term(v)
+        | QueryNode::Phrase(v)
+        | QueryNode::Contains(v)
+        | QueryNode::Fuzzy(v, _)
+        | QueryNode::Phonetic(v)
+        | QueryNode::FieldTerm(_, v)
+        | QueryNode::Exists(v, _) => out.extend(placeholder(v)),
+        QueryNode::And(children) | QueryNode::Or(children) => {
+            for child in children {
+                collect_placeholders(child, out);
+            }
+        }
+        QueryNode::Not(child) | QueryNode::Boost(_, child) => collect_placeholders(child, out),
+        QueryNode::NumericRange(..) | QueryNode::MatchAll => {}
+    }
+}
+
+/// Copies a template AST with each placeholder replaced by its value in `params`.
+/// The value becomes the node's text as is, so operators and quotes in it are
+/// matched literally rather than parsed.
+///
+/// # Returns
+/// `QueryError::MissingParam` for a placeholder with no value in `params`.
+fn substitute(node: &QueryNode, params: &[(&str, &str)]) -> Result<QueryNode, QueryError> {
+    let value = |v: &str| -> Result<Option<String>, QueryError> {
+        let Some(name) = placeholder(v) else {
+            return Ok(None);
+        };
+        params
+            .iter()
+            .find(|(p, _)| *p == name)
+            .map(|(_, val)| Some(val.to_string()))
+            .ok_or_else(|| QueryError::MissingParam(name.to_string()))
+    };
+    let children = |children: &[QueryNode]| -> Result<Vec<QueryNode>, QueryError> {
+        children.iter().map(|c| substitute(c, params)).collect()
+    };
+    Ok(match node {
+        QueryNode::Term(v) | QueryNode::Phrase(v) => match value(v)? {
+            Some(val) => literal(val),
+            None => node.clone(),
+        },
+        QueryNode::Contains(v) => QueryNode::Contains(value(v)?.unwrap_or_else(|| v.clone())),
+        QueryNode::Fuzzy(v, distance) => {
+            QueryNode::Fuzzy(value(v)?.unwrap_or_else(|| v.clone()), *distance)
+        }
+        QueryNode::Phonetic(v) => QueryNode::Phonetic(value(v)?.unwrap_or_else(|| v.clone())),
+        QueryNode::FieldTerm(field, v) => {
+            QueryNode::FieldTerm(field, value(v)?.unwrap_or_else(|| v.clone()))
+        }
+        QueryNode::Exists(v, present) => {
+            QueryNode::Exists(value(v)?.unwrap_or_else(|| v.clone()), *present)
+        }
+        QueryNode::And(c) => QueryNode::And(children(c)?),
+        QueryNode::Or(c) => QueryNode::Or(children(c)?),
+        QueryNode::Not(child) => QueryNode::Not(Box::new(substitute(child, params)?)),
+        QueryNode::Boost(factor, child) => {
+            QueryNode::Boost(*factor, Box::new(substitute(child, params)?))
+        }
+        QueryNode::NumericRange(..) | QueryNode::MatchAll => node.clone(),
+    })
+}
+
+/// The fewest documents the smaller side of an intersection must have for a
+/// parallel query to probe it from several threads; smaller intersections are
+/// quicker on one.
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_PROBE: usize = 4096;
+
+/// Returns the words of an AND of exactly two terms.
+fn term_pair(children: &[QueryNode]) -> Option<(&str, &str)> {
+    match children {
+        [QueryNode::Term(a) | QueryNode::Contains(a), QueryNode::Term(b) | QueryNode::Contains(b)] => {
+            Some((a, b))
+        }
+        _ => None,
+    }
+}
+
+/// Collects document IDs into a set, sized for them.
+fn docs_to_set(docs: Vec<DocId>) -> BugguHashSet<DocId, ()> {
+    let mut set = BugguHashSet::new(docs.len().max(8));
+    for id in docs {
+        set.insert(id, ());
+    }
+    set
+}
+
+/// The number of candidate documents a query may still gather, for
+/// `LogDB::query_with_budget`, and how the query is executed.
+struct Budget {
+    /// The candidates left before the budget runs out.
+    remaining: usize,
+    /// Whether a set of candidates outgrew what was left.
+    exhausted: bool,
+    /// Whether to evaluate OR branches and intersections on the rayon thread pool,
+    /// as set by `QueryOptions::parallel`.
+    parallel: bool,
+}
+
+impl Budget {
+    /// Creates a budget of `max_candidates` documents.
+    fn new(max_candidates: usize) -> Self {
+        Self {
+            remaining: max_candidates,
+            exhausted: false,
+            parallel: false,
+        }
+    }
+
+    /// Creates a budget that never runs out.
+    fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// Creates a budget that never runs out, executing in parallel if `opts` asks
+    /// for it and the `rayon` feature is enabled.
+    fn for_options(opts: &QueryOptions) -> Self {
+        Self {
+            parallel: cfg!(feature = "rayon") && opts.parallel,
+            ..Self::unlimited()
+        }
+    }
+
+    /// Charges a set of `candidates` documents to the budget.
+    fn spend(&mut self, candidates: usize) {
+        match self.remaining.checked_sub(candidates) {
+            Some(remaining) => self.remaining = remaining,
+            None => {
+                self.remaining = 0;
+                self.exhausted = true;
+            }
+        }
+    }
+}
+
+/// Checks whether `candidate` is at most `max` insertions, deletions or
+/// substitutions of a character away from `word`.
+fn within_edit_distance(word: &[char], candidate: &str, max: usize) -> bool {
+    let candidate: Vec<char> = candidate.chars().collect();
+    if word.len().abs_diff(candidate.len()) > max {
+        return false;
+    }
+    // One row of the Levenshtein matrix at a time, giving up once every entry in a
+    // row is over `max`, since the distance can only grow from there.
+    let mut prev: Vec<usize> = (0..=candidate.len()).collect();
+    let mut row = vec![0; candidate.len() + 1];
+    for (i, &a) in word.iter().enumerate() {
+        row[0] = i + 1;
+        for (j, &b) in candidate.iter().enumerate() {
+            let substitution = prev[j] + usize::from(a != b);
+            row[j + 1] = substitution.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        if row.iter().all(|&d| d > max) {
+            return false;
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev[candidate.len()] <= max
+}
+
+/// Returns the American Soundex code of a word, such as `R163` for both `Robert` and
+/// `Rupert`: its first letter, then a digit for each following run of similar
+/// consonants, padded with zeros to four characters. Letters other than ASCII ones
+/// are ignored, and a word with none has no code.
+fn soundex(word: &str) -> Option<String> {
+    let digit = |c: u8| match c {
+        b'b' | b'f' | b'p' | b'v' => b'1',
+        b'c' | b'g' | b'j' | b'k' | b'q' | b's' | b'x' | b'z' => b'2',
+        b'd' | b't' => b'3',
+        b'l' => b'4',
+        b'm' | b'n' => b'5',
+        b'r' => b'6',
+        _ => b'0',
+    };
+    let mut letters = word
+        .bytes()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let first = letters.next()?;
+    let mut code = vec![first.to_ascii_uppercase()];
+    let mut last = digit(first);
+    for c in letters {
+        let d = digit(c);
+        if d != b'0' && d != last {
+            code.push(d);
+            if code.len() == 4 {
+                break;
+            }
+        }
+        // `h` and `w` do not separate consonants with the same digit; vowels do.
+        if !matches!(c, b'h' | b'w') {
+            last = d;
+        }
+    }
+    code.resize(4, b'0');
+    String::from_utf8(code).ok()
+}
+
+/// Cuts a window of `window` characters out of `content`, centered on the earliest
+/// case-insensitive occurrence of any of `terms`.
+fn snippet(content: &str, terms: &[&str], window: usize) -> String {
+    let haystack = content.to_ascii_lowercase();
+    // ASCII lowercasing preserves byte offsets, so a match position in `haystack` is
+    // also a char boundary in `content`.
+    let first_match = terms
+        .iter()
+        .filter_map(|t| haystack.find(&t.to_ascii_lowercase()).map(|pos| (pos, *t)))
+        .min_by_key(|(pos, _)| *pos);
+
+    let total = content.chars().count();
+    let center = match first_match {
+        Some((pos, term)) => content[..pos].chars().count() + term.chars().count() / 2,
+        None => 0,
+    };
+    let mut start = center.saturating_sub(window / 2);
+    let end = (start + window).min(total);
+    start = start.min(end.saturating_sub(window));
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    out.extend(content.chars().skip(start).take(end - start));
+    if end < total {
+        out.push('…');
+    }
+    out
+}
+
+/// Tokenizes a document's content according to its `TokenMode`, as described on
+/// `LogDB::upsert_entry`. Unless `content_only` is set, the words are taken from a
+/// descriptor that also names the level and service.
+fn entry_tokens(
+    ufhg: &mut UFHGHeadquarters,
+    config: &LogConfig,
+    content: &str,
+    level: Option<&str>,
+    service: Option<&str>,
+    mode: TokenMode,
+    content_only: bool,
+) -> Vec<Tok> {
+    let mut tokens = Vec::new();
+    if matches!(mode, TokenMode::FullText | TokenMode::Mixed) {
+        let descriptor = match (level, service) {
+            _ if content_only => Cow::Borrowed(content),
+            (Some(l), Some(s)) => Cow::Owned(format!("level {l} service {s} content {content}")),
+            (Some(l), None) => Cow::Owned(format!("level {l} content {content}")),
+            (None, Some(s)) => Cow::Owned(format!("service {s} content {content}")),
+            (None, None) => Cow::Owned(format!("content {content}")),
+        };
+        let (_, word_tokens) = ufhg.tokenize_zero_copy(&descriptor);
+        tokens = word_tokens;
+        if config.enable_ngrams {
+            tokens.extend(ufhg.ngram_hashes(content, config.max_ngram_size));
+        }
+    }
+    if matches!(mode, TokenMode::Structured | TokenMode::Mixed) {
+        tokens.extend(ufhg.field_hashes(content));
+    }
+    tokens
+}
+
+/// Checks whether content contains a run of consecutive words with the given
+/// hashes, as the tokenizer separates and hashes them.
+fn contains_sequence(content: &str, hashes: &[Tok]) -> bool {
+    let words: Vec<Tok> = content
+        .split(CONTENT_SEPARATORS)
+        .filter(|w| !w.is_empty())
+        .map(lightning_hash_str)
+        .collect();
+    words.windows(hashes.len()).any(|window| window == hashes)
+}
+
+/// Joins a document's services into the service words of its descriptor for
+/// `entry_tokens`, borrowing the service when it is the only one.
+fn service_words<'a>(service: Option<&'a str>, extra: &[String]) -> Option<Cow<'a, str>> {
+    if extra.is_empty() {
+        return service.map(Cow::Borrowed);
+    }
+    let all: Vec<&str> = service
+        .into_iter()
+        .chain(extra.iter().map(String::as_str))
+        .collect();
+    Some(Cow::Owned(all.join(" ")))
+}
+
+/// The word that ends content truncated to `LogConfig::max_content_bytes`.
+const TRUNCATED_MARKER: &str = "truncated:true";
+
+/// Cuts content longer than `max` bytes, if `max` is not 0, at the last character
+/// boundary that leaves room for a space and `TRUNCATED_MARKER`, and appends them.
+///
+/// # Returns
+/// The content to store, and whether it was truncated.
+fn truncate_content(mut content: String, max: usize) -> (String, bool) {
+    if max == 0 || content.len() <= max {
+        return (content, false);
+    }
+    let mut keep = max.saturating_sub(TRUNCATED_MARKER.len() + 1);
+    while !content.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    content.truncate(keep);
+    let kept = content.trim_end_matches(CONTENT_SEPARATORS).len();
+    content.truncate(kept);
+    if !content.is_empty() {
+        content.push(' ');
+    }
+    content.push_str(TRUNCATED_MARKER);
+    (content, true)
+}
+
+/// The marker that ends content cut to `LogConfig::max_content_len`.
+const ELLIPSIS: &str = "…";
+
+/// Cuts content longer than `max` bytes, if `max` is not 0, at the last character
+/// boundary that leaves room for `ELLIPSIS`, and appends it.
+fn cap_content(mut content: String, max: usize) -> String {
+    if max == 0 || content.len() <= max {
+        return content;
+    }
+    let mut keep = max.saturating_sub(ELLIPSIS.len());
+    while !content.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    content.truncate(keep);
+    content.push_str(ELLIPSIS);
+    content
+}
+
+/// Splits a logfmt line into its `(key, value)` pairs, unquoting quoted values and
+/// giving a bare key the value `true`. Text that is not a pair, such as a stray `=`,
+/// is skipped.
+fn logfmt_pairs(line: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return pairs;
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && !c.is_whitespace()) {
+            key.push(c);
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_none() {
+            value.push_str("true");
+        } else if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        if !key.is_empty() {
+            pairs.push((key, value));
+        }
+    }
+}
+
+/// Returns a JSON string as is, and a number or boolean as it is written in JSON, or
+/// `None` for anything else.
+#[cfg(feature = "serde")]
+fn json_scalar(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Flattens a JSON object into `(key, value)` pairs of its scalar fields, joining the
+/// keys of nested objects with dots and giving each scalar of an array its own pair.
+#[cfg(feature = "serde")]
+fn json_fields(object: &serde_json::Map<String, serde_json::Value>) -> Vec<(String, String)> {
+    fn visit(
+        prefix: &str,
+        object: &serde_json::Map<String, serde_json::Value>,
+        out: &mut Vec<(String, String)>,
+    ) {
+        for (key, value) in object {
+            let key = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            match value {
+                serde_json::Value::Object(nested) => visit(&key, nested, out),
+                serde_json::Value::Array(items) => out.extend(
+                    items
+                        .iter()
+                        .filter_map(json_scalar)
+                        .map(|v| (key.clone(), v)),
+                ),
+                value => out.extend(json_scalar(value).map(|v| (key, v))),
+            }
+        }
+    }
+    let mut out = Vec::new();
+    visit("", object, &mut out);
+    out
+}
+
+/// The common English words `LogDB::top_terms` leaves out on request.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "been", "but", "by", "for", "from", "had", "has",
+    "have", "he", "her", "his", "i", "if", "in", "into", "is", "it", "its", "of", "on", "or",
+    "our", "she", "so", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "to", "was", "we", "were", "will", "with", "you", "your",
+];
+
+/// Returns the `n` words with the highest counts, highest first and alphabetically
+/// among equal counts, skipping words with a count of 0. The words are streamed
+/// through a heap of `n` entries rather than sorted.
+fn top_counts<'a>(
+    counts: impl Iterator<Item = (&'a str, usize)>,
+    n: usize,
+) -> Vec<(String, usize)> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for (word, count) in counts.filter(|&(_, count)| count > 0) {
+        heap.push(Reverse((count, Reverse(word))));
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((count, Reverse(word)))| (word.to_string(), count))
+        .collect()
+}
+
+/// The characters that separate words in document content, as in the tokenizer.
+const CONTENT_SEPARATORS: [char; 4] = [' ', '\t', '\n', '\r'];
+
+/// Lowercases a word, borrowing it when it has no uppercase letters.
+fn lowercase(word: &str) -> Cow<'_, str> {
+    if word.chars().any(char::is_uppercase) {
+        Cow::Owned(word.to_lowercase())
+    } else {
+        Cow::Borrowed(word)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UnknownFieldMode;
+    use crate::ingest::IngestRules;
+
+    #[test]
+    fn rejected_entry_returns_reason_and_is_not_indexed() {
+        let mut db = LogDB::new();
+        db.set_ingest_hook(Box::new(|entry| {
+            if entry.content.trim().is_empty() {
+                HookDecision::Reject("empty content".to_string())
+            } else {
+                HookDecision::Accept
+            }
+        }));
+
+        let err = db.upsert_log("   ", None, None).unwrap_err();
+        assert_eq!(err, IngestError::Rejected("empty content".to_string()));
+        let id = db.upsert_log("disk full", None, None).unwrap();
+
+        assert_eq!(db.query("disk"), vec![id]);
+        assert_eq!(db.metrics().rejections(), 1);
+        assert_eq!(db.metrics().inserts(), 1);
+    }
+
+    #[test]
+    fn every_upsert_path_reports_rejection() {
+        let mut db = LogDB::new();
+        db.set_ingest_hook(Box::new(|_| HookDecision::Reject("closed".to_string())));
+        let rejected = IngestError::Rejected("closed".to_string());
+
+        assert_eq!(db.upsert_simple("a"), Err(rejected.clone()));
+        assert_eq!(db.upsert_log_at("a", None, None, 7), Err(rejected.clone()));
+        assert_eq!(
+            db.upsert_log_services("a", None, vec!["api".to_string()]),
+            Err(rejected.clone())
+        );
+        assert_eq!(db.upsert_v2("a", None, None), Err(rejected.clone()));
+        assert_eq!(
+            db.upsert_fields("a", &[("tag", "db")]),
+            Err(rejected.clone())
+        );
+        assert_eq!(db.metrics().rejections(), 5);
+        assert!(db.docs.is_empty());
+    }
+
+    #[test]
+    fn modifying_hook_changes_what_is_indexed() {
+        let mut db = LogDB::new();
+        db.set_ingest_hook(Box::new(|entry| {
+            entry.content = entry.content.replace("secret", "redacted");
+            HookDecision::Modify
+        }));
+
+        let id = db.upsert_simple("token secret leaked").unwrap();
+        assert!(db.query("secret").is_empty());
+        assert_eq!(db.query("redacted"), vec![id]);
+        assert_eq!(
+            db.get_entry(&id).unwrap().content(),
+            "token redacted leaked"
+        );
+    }
+
+    #[test]
+    fn ingest_rules_strip_ansi_before_indexing() {
+        let mut db = LogDB::new();
+        let rules = IngestRules {
+            require_level: true,
+            strip_ansi: true,
+            max_bytes: Some(16),
+        };
+        db.set_ingest_hook(rules.into_hook());
+
+        let id = db
+            .upsert_log("\x1b[31mfailed\x1b[0m", Some("ERROR".to_string()), None)
+            .unwrap();
+        assert_eq!(db.query("failed"), vec![id]);
+        assert_eq!(db.get_entry(&id).unwrap().content(), "failed");
+
+        assert!(matches!(
+            db.upsert_log("no level", None, None),
+            Err(IngestError::Rejected(_))
+        ));
+        assert!(matches!(
+            db.upsert_log("far too long for the limit", Some("INFO".to_string()), None),
+            Err(IngestError::Rejected(_))
+        ));
+
+        db.clear_ingest_hook();
+        assert!(db.upsert_log("no level", None, None).is_ok());
+    }
+
+    #[test]
+    fn new_matches_default_config() {
+        let db = LogDB::new();
+        let config = LogConfig::default();
+        assert_eq!(db.stale_secs, config.stale_secs);
+        assert_eq!(db.max_postings, config.max_postings);
+        assert_eq!(db.postings.bucket_count(), db.docs.bucket_count());
+    }
+
+    #[test]
+    fn empty_instance_is_small() {
+        let db = LogDB::new();
+        let bytes = db.approx_bytes();
+        assert!(bytes < 512 * 1024, "empty LogDB uses {bytes} bytes");
+        assert!(LogDB::with_capacity(100_000).approx_bytes() > bytes);
+    }
+
+    /// Spells `n` in base 26 with the letters `a` to `z`, giving a distinct word
+    /// for each number that the tokenizer keeps whole.
+    fn word(mut n: usize) -> String {
+        let mut word = String::from("w");
+        loop {
+            word.push((b'a' + (n % 26) as u8) as char);
+            n /= 26;
+            if n == 0 {
+                return word;
+            }
+        }
+    }
+
+    #[test]
+    fn small_tables_grow_under_load() {
+        let mut db = LogDB::new();
+        let initial = db.postings.bucket_count();
+        let ids: Vec<DocId> = (0..5_000)
+            .map(|i| db.upsert_simple(&format!("{} served", word(i))).unwrap())
+            .collect();
+        assert!(db.postings.bucket_count() > initial);
+        assert!(db.docs.bucket_count() > initial);
+        assert_eq!(db.query(&word(4321)), vec![ids[4321]]);
+        assert_eq!(db.query("served").len(), 5_000);
+    }
+
+    #[test]
+    fn cached_and_uncached_queries_match() {
+        let mut cached = LogDB::new();
+        let mut uncached = LogDB::with_config(LogConfig {
+            query_cache_capacity: 0,
+            ..LogConfig::default()
+        });
+        for db in [&mut cached, &mut uncached] {
+            db.upsert_log(
+                "login failed",
+                Some("ERROR".to_string()),
+                Some("auth".to_string()),
+            )
+            .unwrap();
+            db.upsert_log(
+                "login ok",
+                Some("INFO".to_string()),
+                Some("auth".to_string()),
+            )
+            .unwrap();
+            db.upsert_log(
+                "unknown host",
+                Some("ERROR".to_string()),
+                Some("api".to_string()),
+            )
+            .unwrap();
+        }
+        for q in [
+            "level:ERROR AND service:auth",
+            "login -failed",
+            "unknown host",
+        ] {
+            let first = cached.query(q);
+            assert_eq!(cached.query(q), first);
+            assert_eq!(uncached.query(q), first);
+        }
+        assert_eq!(cached.query_cache.len(), 3);
+        assert!(uncached.query_cache.is_empty());
+    }
+
+    #[test]
+    fn relative_timestamp_queries_are_not_cached() {
+        let mut db = LogDB::new();
+        db.set_clock(|| 1_000);
+        let id = db.upsert_simple("tick").unwrap();
+
+        assert_eq!(db.query("tick timestamp:>=now-1m"), vec![id]);
+        assert!(db.query_cache.is_empty());
+        db.set_clock(|| 10_000);
+        assert!(db.query("tick timestamp:>=now-1m").is_empty());
+        assert!(db.query_cache.is_empty());
+    }
+
+    #[test]
+    fn multi_service_doc_matches_each_service() {
+        let mut db = LogDB::new();
+        let both = db
+            .upsert_log_services(
+                "token refreshed",
+                None,
+                vec!["api".to_string(), "auth".to_string(), "api".to_string()],
+            )
+            .unwrap();
+        let api = db
+            .upsert_log("request served", None, Some("api".to_string()))
+            .unwrap();
+        db.upsert_simple("no service").unwrap();
+
+        assert_eq!(db.query("service:auth"), vec![both]);
+        assert_eq!(db.query("service:api"), vec![both, api]);
+        assert_eq!(db.get_entry(&both).unwrap().service(), Some("api"));
+    }
+
+    #[test]
+    fn exists_lists_multi_service_doc_once() {
+        let mut db = LogDB::new();
+        let id = db
+            .upsert_log_services("fanout", None, vec!["api".to_string(), "auth".to_string()])
+            .unwrap();
+        db.upsert_simple("untagged").unwrap();
+
+        assert_eq!(db.query("has:service"), vec![id]);
+        assert_eq!(db.query("_exists_:service"), vec![id]);
+        assert_eq!(db.query("missing:service").len(), 1);
+    }
+
+    #[test]
+    fn batch_frame_applies_each_entry() {
+        let mut db = LogDB::new();
+        db.apply_frame(Frame::Batch {
+            entries: vec![(DocId(3), 2), (DocId(5), 1)],
+            tokens: vec![10, 11, 12],
+        })
+        .unwrap();
+
+        assert_eq!(db.get_entry(&DocId(3)).unwrap().tokens, vec![10, 11]);
+        assert_eq!(db.get_entry(&DocId(5)).unwrap().tokens, vec![12]);
+        assert_eq!(db.next_doc_id, DocId(6));
+    }
+
+    #[test]
+    fn batch_frame_with_mismatched_lengths_is_rejected() {
+        let mut db = LogDB::new();
+        let short = Frame::Batch {
+            entries: vec![(DocId(1), 2), (DocId(2), 3)],
+            tokens: vec![1, 2, 3],
+        };
+        assert_eq!(
+            db.apply_frame(short),
+            Err(ApplyError::BatchLengthMismatch {
+                declared: 5,
+                actual: 3
+            })
+        );
+        let leftover = Frame::Batch {
+            entries: vec![(DocId(1), 1)],
+            tokens: vec![1, 2],
+        };
+        assert_eq!(
+            db.apply_frame(leftover),
+            Err(ApplyError::BatchLengthMismatch {
+                declared: 1,
+                actual: 2
+            })
+        );
+        let overflow = Frame::Batch {
+            entries: vec![(DocId(1), usize::MAX), (DocId(2), 2)],
+            tokens: vec![1],
+        };
+        assert!(db.apply_frame(overflow).is_err());
+        assert!(db.docs.is_empty());
+    }
+
+    /// Returns a `LogDB` storing at most `cap` bytes of content per document.
+    fn capped_db(cap: usize) -> LogDB {
+        LogDB::with_config(LogConfig {
+            max_content_len: cap,
+            ..LogConfig::default()
+        })
+    }
+
+    #[test]
+    fn capped_content_keeps_words_past_the_cap_searchable() {
+        let mut db = capped_db(256);
+        let mut line = "alpha ".repeat(10 * 1024 / 6);
+        line.push_str("zebrafinal");
+        let id = db.upsert_simple(&line).unwrap();
+
+        let content = db.get_content(&id).unwrap();
+        assert!(content.len() <= 256);
+        assert!(content.ends_with(ELLIPSIS));
+        assert_eq!(db.query_content("zebrafinal"), vec![content]);
+        assert_eq!(db.query("zebrafinal"), vec![id]);
+    }
+
+    #[test]
+    fn capped_content_indexes_words_of_the_full_text() {
+        let mut db = capped_db(32);
+        let id = db
+            .upsert_simple("word1 word2 word3 word4 word5 word6 Zebrafinal quick fox")
+            .unwrap();
+
+        assert_eq!(db.suggest("zeb", 5), vec!["zebrafinal".to_string()]);
+        assert!(db
+            .most_common_terms(20)
+            .iter()
+            .any(|(word, _)| word.eq_ignore_ascii_case("zebrafinal")));
+        assert!(db
+            .term_dict()
+            .iter_terms()
+            .all(|(_, word)| !word.contains(ELLIPSIS)));
+
+        assert_eq!(db.query_intersection_of_phrases(&["quick fox"]), vec![id]);
+        assert!(db.query_intersection_of_phrases(&["fox quick"]).is_empty());
+        let exact = QueryOptions {
+            case_insensitive: Some(false),
+            ..QueryOptions::default()
+        };
+        assert_eq!(db.query_opts("Zebrafinal", &exact), vec![id]);
+    }
+
+    #[test]
+    fn deleting_capped_doc_drops_words_past_the_cap() {
+        let mut db = capped_db(16);
+        let id = db.upsert_simple("short words then zebrafinal").unwrap();
+        assert!(db.delete(&id));
+
+        assert!(db.suggest("zeb", 5).is_empty());
+        assert!(db.term_dict().is_empty());
+    }
+
+    /// Returns `n` log entries spread over a few levels, services and words.
+    fn sample_entries(n: usize) -> Vec<LogEntry> {
+        const LEVELS: [&str; 3] = ["INFO", "WARN", "ERROR"];
+        const SERVICES: [&str; 3] = ["auth", "api", "db"];
+        (0..n)
+            .map(|i| {
+                let mut entry = LogEntry::with_metadata(
+                    format!("{} {} user:{}", word(i % 40), word(i % 7 + 100), i % 13),
+                    Some(LEVELS[i % 3].to_string()),
+                    Some(SERVICES[(i / 3) % 3].to_string()),
+                    TokenMode::Mixed,
+                );
+                entry.timestamp = 1_000 + i as u64;
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bulk_load_matches_incremental_inserts() {
+        let entries = sample_entries(2_000);
+        let mut incremental = LogDB::new();
+        for entry in entries.clone() {
+            incremental.upsert_entry(entry).unwrap();
+        }
+        let mut bulk = LogDB::new();
+        let stats = bulk.bulk_load(entries.into_iter());
+
+        assert_eq!(stats.docs, 2_000);
+        assert_eq!(stats.rejected, 0);
+        assert_eq!(bulk.postings.len(), incremental.postings.len());
+        let queries = [
+            word(3),
+            format!("{} {}", word(3), word(103)),
+            "level:ERROR service:auth".to_string(),
+            "user:7 -level:INFO".to_string(),
+            format!("\"{} {}\"", word(5), word(105)),
+            "has:service".to_string(),
+            "timestamp:>=2500".to_string(),
+        ];
+        for q in &queries {
+            let (mut a, mut b) = (bulk.query(q), incremental.query(q));
+            a.sort_unstable();
+            b.sort_unstable();
+            assert!(!a.is_empty(), "{q}");
+            assert_eq!(a, b, "{q}");
+        }
+        assert_eq!(bulk.suggest("w", 50), incremental.suggest("w", 50));
+    }
+
+    #[test]
+    fn bulk_load_counts_rejected_entries() {
+        let mut db = LogDB::new();
+        db.set_ingest_hook(Box::new(|entry| {
+            if entry.level.as_deref() == Some("INFO") {
+                HookDecision::Reject("info".to_string())
+            } else {
+                HookDecision::Accept
+            }
+        }));
+        let stats = db.bulk_load(sample_entries(30).into_iter());
+
+        assert_eq!(stats.docs, 20);
+        assert_eq!(stats.rejected, 10);
+        assert!(db.query("level:INFO").is_empty());
+        assert_eq!(db.query("level:WARN").len(), 10);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_execution_matches_sequential() {
+        let mut db = LogDB::new();
+        // Enough documents for the AND to split its probe set across threads.
+        for entry in sample_entries(30_000) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let sequential = QueryOptions::default();
+        let parallel = QueryOptions {
+            parallel: true,
+            ..QueryOptions::default()
+        };
+        let or = format!(
+            "contains:({}|{}|{}|{})",
+            word(1),
+            word(2),
+            word(3),
+            word(104)
+        );
+        for q in [or.as_str(), "user:3 level:WARN service:api", "-level:INFO"] {
+            let expected = db.search(q, &sequential);
+            assert!(expected.total > 0, "{q}");
+            assert_eq!(db.search(q, &parallel).hits, expected.hits, "{q}");
+            assert_eq!(
+                db.query_content_opts(q, &parallel),
+                db.query_content_opts(q, &sequential),
+                "{q}"
+            );
+        }
+    }
+
+    #[test]
+    fn snapshot_pages_ignore_later_inserts() {
+        let mut db = LogDB::new();
+        for i in 0..100 {
+            db.upsert_log(
+                &format!("request {}", word(i)),
+                Some("ERROR".to_string()),
+                None,
+            )
+            .unwrap();
+        }
+        let snapshot = db.freeze();
+        let total = snapshot
+            .search(&db, "request", &QueryOptions::default())
+            .total;
+        let pages: Vec<_> = (0..5)
+            .map(|page| snapshot.query_paged(&db, "request", page * 20, 20))
+            .collect();
+
+        for i in 100..1100 {
+            db.upsert_log(
+                &format!("request {}", word(i)),
+                Some("ERROR".to_string()),
+                None,
+            )
+            .unwrap();
+        }
+
+        assert_eq!(total, 100);
+        let search = snapshot.search(&db, "request", &QueryOptions::default());
+        assert_eq!(search.total, total);
+        assert_eq!(snapshot.query(&db, "request").len(), total);
+        for (page, expected) in pages.iter().enumerate() {
+            assert_eq!(expected.len(), 20);
+            assert_eq!(
+                &snapshot.query_paged(&db, "request", page * 20, 20),
+                expected
+            );
+        }
+        assert!(snapshot.query_paged(&db, "request", 100, 20).is_empty());
+        assert_eq!(db.query("request").len(), 1100);
+        assert_eq!(db.freeze().query(&db, "request").len(), 1100);
+    }
+
+    #[test]
+    fn snapshot_pages_shift_past_a_deleted_document() {
+        let mut db = LogDB::new();
+        let ids: Vec<DocId> = (0..10)
+            .map(|i| db.upsert_simple(&format!("request {}", word(i))).unwrap())
+            .collect();
+        let snapshot = db.freeze();
+        let shared = db.snapshot();
+        assert_eq!(snapshot.query_paged(&db, "request", 0, 5), ids[..5]);
+
+        // Deleting a hit of the first page moves the first hit of the second onto
+        // it, so the second page skips that hit.
+        assert!(db.delete(&ids[2]));
+        db.upsert_simple("request later").unwrap();
+        assert_ne!(snapshot.generation(), db.generation());
+        assert_eq!(snapshot.query_paged(&db, "request", 5, 5), ids[6..]);
+        let mut remaining = ids.clone();
+        remaining.remove(2);
+        assert_eq!(snapshot.query(&db, "request"), remaining);
+        // A shared snapshot still holds the deleted document.
+        let mut kept = shared.query("request");
+        kept.sort_unstable();
+        assert_eq!(kept, ids);
+    }
+
+    #[test]
+    fn logfmt_pairs_unquote_values() {
+        let pairs = logfmt_pairs(r#" level=error msg="said \"hi\" to C:\\" dry  =x user=42"#);
+        let pairs: Vec<_> = pairs
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("level", "error"),
+                ("msg", r#"said "hi" to C:\"#),
+                ("dry", "true"),
+                ("user", "42"),
+            ]
+        );
+        assert!(logfmt_pairs("   ").is_empty());
+        assert_eq!(logfmt_pairs(r#"msg="unterminated"#)[0].1, "unterminated");
+    }
+
+    #[test]
+    fn upsert_logfmt_fills_in_metadata() {
+        let mut db = LogDB::new();
+        let id = db
+            .upsert_logfmt(r#"level=ERROR service=auth msg="login failed" user=42 timestamp=1700"#)
+            .unwrap();
+        assert_eq!(db.query("failed"), vec![id]);
+        assert_eq!(db.query("level:ERROR service:auth"), vec![id]);
+        assert_eq!(db.query("user:42"), vec![id]);
+        assert_eq!(db.query("has:user"), vec![id]);
+        assert!(db.query("has:msg").is_empty());
+        assert_eq!(db.query("timestamp:1700"), vec![id]);
+    }
+
+    #[test]
+    fn structured_entries_index_only_fields() {
+        let mut db = LogDB::new();
+        let structured = db
+            .upsert_entry(LogEntry::new(
+                "request failed status:500 path:/login".to_string(),
+                TokenMode::Structured,
+            ))
+            .unwrap();
+        let full_text = db
+            .upsert_entry(LogEntry::new(
+                "request failed with status 500".to_string(),
+                TokenMode::FullText,
+            ))
+            .unwrap();
+        let mixed = db
+            .upsert_entry(LogEntry::new(
+                "lookup failed code:404".to_string(),
+                TokenMode::Mixed,
+            ))
+            .unwrap();
+
+        assert_eq!(db.query("status:500"), vec![structured]);
+        assert_eq!(db.query("path:/login"), vec![structured]);
+        assert_eq!(db.query("request"), vec![full_text]);
+        let mut failed = db.query("failed");
+        failed.sort();
+        assert_eq!(failed, vec![full_text, mixed]);
+        assert_eq!(db.query("code:404"), vec![mixed]);
+    }
+
+    #[test]
+    fn upsert_entry_keeps_the_entry_timestamp() {
+        let mut db = LogDB::new();
+        let mut entry = LogEntry::new("old news".to_string(), TokenMode::Mixed);
+        entry.timestamp = 1_000;
+        let id = db.upsert_entry(entry).unwrap();
+        assert_eq!(db.get_entry(&id).unwrap().timestamp(), 1_000);
+        assert_eq!(db.query("timestamp:1000"), vec![id]);
+    }
+
+    #[test]
+    fn verify_integrity_passes_on_a_consistent_index() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(50) {
+            db.upsert_entry(entry).unwrap();
+        }
+        db.delete(&DocId::from(7));
+        assert_eq!(db.verify_integrity(), Ok(()));
+        db.rebuild_indexes();
+        assert_eq!(db.verify_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn verify_integrity_flags_corrupted_state() {
+        let mut db = LogDB::new();
+        let id = db
+            .upsert_log(
+                "disk full",
+                Some("ERROR".to_string()),
+                Some("db".to_string()),
+            )
+            .unwrap();
+        let other = db.upsert_log("all good", None, None).unwrap();
+
+        // A document vanishing behind the postings' back.
+        let entry = Arc::make_mut(&mut db.docs).remove(&other).unwrap();
+        let problems = db.verify_integrity().unwrap_err();
+        assert!(problems
+            .iter()
+            .any(|p| p.contains(&format!("references missing doc {other}"))));
+        Arc::make_mut(&mut db.docs).insert(other, entry);
+        assert_eq!(db.verify_integrity(), Ok(()));
+
+        // A posting losing one of its documents.
+        let tok = lightning_hash_str("disk");
+        Arc::make_mut(&mut db.postings)
+            .get_mut(&tok)
+            .unwrap()
+            .remove(id);
+        let problems = db.verify_integrity().unwrap_err();
+        assert_eq!(
+            problems,
+            [format!("doc {id} token {tok} is missing from postings")]
+        );
+        Arc::make_mut(&mut db.postings)
+            .get_mut(&tok)
+            .unwrap()
+            .add(id);
+
+        // A service index entry under the wrong value.
+        let db_tok = lightning_hash_str("db");
+        let ids = Arc::make_mut(&mut db.service_index)
+            .remove(&db_tok)
+            .unwrap();
+        Arc::make_mut(&mut db.service_index).insert(lightning_hash_str("web"), ids);
+        let problems = db.verify_integrity().unwrap_err();
+        assert!(problems.contains(&format!(
+            "service index lists doc {id} under the wrong value"
+        )));
+        assert!(problems.contains(&format!(
+            "doc {id} service \"db\" is missing from the service index"
+        )));
+        db.rebuild_indexes();
+        assert_eq!(db.verify_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn snippets_center_on_the_first_match() {
+        let mut db = LogDB::new();
+        let mid = db
+            .upsert_simple("the nightly batch job finished and then the disk filled up completely")
+            .unwrap();
+        let start = db
+            .upsert_simple("disk quota exceeded for tenant acme")
+            .unwrap();
+        let mut snippets = db.query_snippets("disk", 16);
+        snippets.sort();
+        assert_eq!(
+            snippets,
+            [
+                (mid, "…n the disk fille…".to_string()),
+                (start, "disk quota excee…".to_string()),
+            ]
+        );
+        // A window wider than the content returns all of it.
+        assert_eq!(
+            db.query_snippets("quota", 100),
+            [(start, "disk quota exceeded for tenant acme".to_string())]
+        );
+    }
+
+    #[test]
+    fn snippets_cut_at_char_boundaries() {
+        assert_eq!(
+            snippet("ünïcödé error ünïcödé", &["error"], 9),
+            "…é error ü…"
+        );
+        assert_eq!(snippet("日本語のログ error", &["ERROR"], 4), "…erro…");
+        assert_eq!(snippet("ログ error", &["ログ"], 4), "ログ e…");
+        // Without a match, the snippet starts at the beginning of the content.
+        assert_eq!(snippet("ünïcödé", &["missing"], 4), "ünïc…");
+        assert_eq!(snippet("", &["error"], 4), "");
+    }
+
+    /// Copies every document of `leader` into a new `LogDB` through a frame stream.
+    fn replicate(leader: &LogDB) -> LogDB {
+        let mut writer = crate::codec::FrameWriter::with_checksum(Vec::new());
+        for frame in leader.export_frames() {
+            writer.write_frame(&frame).unwrap();
+        }
+        let bytes = writer.into_inner();
+        let mut follower = LogDB::new();
+        for frame in crate::codec::FrameReader::with_checksum(bytes.as_slice()) {
+            follower.apply_frame(frame.unwrap()).unwrap();
+        }
+        follower
+    }
+
+    /// Asserts that `a` and `b` return the same documents for each query.
+    fn assert_same_results(a: &LogDB, b: &LogDB, queries: &[&str]) {
+        for q in queries {
+            let (mut x, mut y) = (a.query(q), b.query(q));
+            x.sort();
+            y.sort();
+            assert_eq!(x, y, "{q}");
+        }
+    }
+
+    #[test]
+    fn exported_frames_replicate_queries() {
+        let mut leader = LogDB::new();
+        for entry in sample_entries(300) {
+            leader.upsert_entry(entry).unwrap();
+        }
+        let (common, rare) = (word(3), word(102));
+        let queries = [
+            "level:ERROR",
+            "service:auth",
+            "level:WARN AND service:db",
+            "-level:INFO",
+            "user:5",
+            &common,
+            &rare,
+        ];
+        let follower = replicate(&leader);
+        assert_eq!(follower.len(), leader.len());
+        assert!(!follower.query(&rare).is_empty());
+        assert_same_results(&leader, &follower, &queries);
+        let id = leader.query(&common)[0];
+        assert_eq!(follower.get_content(&id), leader.get_content(&id));
+    }
+
+    #[test]
+    fn streamed_diffs_keep_followers_in_step() {
+        let mut leader = LogDB::new();
+        let ids: Vec<DocId> = (0..50)
+            .map(|i| {
+                leader
+                    .upsert_simple(&format!("{} shared", word(i)))
+                    .unwrap()
+            })
+            .collect();
+        let mut follower = replicate(&leader);
+
+        let mut writer = crate::codec::FrameWriter::new(Vec::new());
+        for (i, id) in ids.iter().enumerate().step_by(3) {
+            let content = format!("{} rewritten", word(i + 100));
+            let frame = leader.diff_for(id, &content).unwrap();
+            assert!(matches!(frame, Frame::Diff { .. }));
+            writer.write_frame(&frame).unwrap();
+            leader.apply_frame(frame).unwrap();
+        }
+        for frame in crate::codec::FrameReader::new(writer.into_inner().as_slice()) {
+            follower.apply_frame(frame.unwrap()).unwrap();
+        }
+
+        let queries = [
+            "shared",
+            "rewritten",
+            &word(0),
+            &word(1),
+            &word(100),
+            &word(103),
+        ];
+        assert_eq!(leader.query("rewritten").len(), 17);
+        assert_same_results(&leader, &follower, &queries);
+
+        let unknown = Frame::Diff {
+            doc_id: DocId(999),
+            remove: Vec::new(),
+            add: vec![1],
+        };
+        assert_eq!(
+            follower.apply_frame(unknown),
+            Err(ApplyError::UnknownDoc(DocId(999)))
+        );
+    }
+
+    #[test]
+    fn filter_by_services_matches_the_naive_union() {
+        const SERVICES: [&str; 6] = ["auth", "api", "db", "billing", "search", "gateway"];
+        let mut db = LogDB::new();
+        for i in 0..500 {
+            let services = vec![
+                SERVICES[i % 6].to_string(),
+                SERVICES[(i / 6) % 6].to_string(),
+            ];
+            db.upsert_log_services(&word(i), None, services).unwrap();
+        }
+        db.upsert_simple("no service").unwrap();
+
+        let wanted = ["auth", "db", "search", "db", "nowhere"];
+        let mut naive: Vec<DocId> = wanted
+            .iter()
+            .flat_map(|s| db.query(&format!("service:{s}")))
+            .collect();
+        naive.sort();
+        naive.dedup();
+        assert!(naive.len() > 300);
+        assert_eq!(db.filter_by_services(&wanted), naive);
+        assert_eq!(db.query("service:auth,db,search,nowhere"), naive);
+        let mut both = db.query("service:auth,db AND service:api,db");
+        both.sort();
+        let api_or_db = db.filter_by_services(&["api", "db"]);
+        let auth_or_db = db.filter_by_services(&["auth", "db"]);
+        assert_eq!(
+            both,
+            auth_or_db
+                .into_iter()
+                .filter(|id| api_or_db.contains(id))
+                .collect::<Vec<_>>()
+        );
+        assert!(db.filter_by_services(&[]).is_empty());
+    }
+
+    #[test]
+    fn meta_and_delete_frames_replicate_documents() {
+        let mut leader = LogDB::new();
+        let kept = leader
+            .upsert_log_at(
+                "disk full",
+                Some("ERROR".to_string()),
+                Some("db".to_string()),
+                42,
+            )
+            .unwrap();
+        let dropped = leader.upsert_simple("short lived").unwrap();
+        let mut follower = replicate(&leader);
+
+        let entry = follower.get_entry(&kept).unwrap();
+        assert_eq!(
+            (
+                entry.content(),
+                entry.level(),
+                entry.service(),
+                entry.timestamp()
+            ),
+            ("disk full", Some("ERROR"), Some("db"), 42)
+        );
+        assert_eq!(follower.query_content("disk"), ["disk full"]);
+
+        // A later metadata change moves the document between level lists.
+        follower
+            .apply_frame(Frame::Meta {
+                doc_id: kept,
+                content: "disk full again".to_string(),
+                level: Some("WARN".to_string()),
+                service: Some("db".to_string()),
+                extra_services: Vec::new(),
+                timestamp: 43,
+            })
+            .unwrap();
+        assert!(follower.query("level:ERROR").is_empty());
+        assert_eq!(follower.query("level:WARN"), [kept]);
+        assert_eq!(follower.query_content("service:db"), ["disk full again"]);
+
+        assert!(leader.delete(&dropped));
+        let frame = crate::codec::decode(&crate::codec::encode_delete(dropped)).unwrap();
+        follower.apply_frame(frame).unwrap();
+        assert_same_results(&leader, &follower, &["short", "lived", "*"]);
+        assert_eq!(follower.len(), 1);
+        assert_eq!(
+            follower.apply_frame(Frame::Delete { doc_id: dropped }),
+            Err(ApplyError::UnknownDoc(dropped))
+        );
+    }
+
+    #[test]
+    fn single_term_fast_path_matches_the_general_path() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(1_000) {
+            db.upsert_entry(entry).unwrap();
+        }
+        db.add_synonyms(&[&word(1), &word(2)]);
+        let opts = QueryOptions::default();
+        let (small, large, ring) = (word(5), word(101), word(1));
+        let contains = format!("contains:{small}");
+        for q in [&small, &large, &ring, &contains, "missing", " ERROR "] {
+            let general =
+                db.exec_within(&db.plan(q, &opts).unwrap(), &mut Budget::for_options(&opts));
+            assert_eq!(db.run_query(q, &opts).unwrap(), general, "{q}");
+        }
+        // Postings past 128 documents are sets, which the fast path reads directly.
+        assert!(db.query(&large).len() > 128);
+        assert_eq!(
+            db.query(&ring).len(),
+            db.query(&format!("{ring} OR {}", word(2))).len()
+        );
+    }
+
+    #[test]
+    fn exists_matches_documents_with_the_field() {
+        let mut db = LogDB::new();
+        let both = db
+            .upsert_log(
+                "both set",
+                Some("ERROR".to_string()),
+                Some("auth".to_string()),
+            )
+            .unwrap();
+        let level_only = db
+            .upsert_log("level only", Some("WARN".to_string()), None)
+            .unwrap();
+        let service_only = db
+            .upsert_log("service only", None, Some("db".to_string()))
+            .unwrap();
+        let fields = db.upsert_simple("user:ana region: plain").unwrap();
+        let other_user = db.upsert_simple("User:bob user:carl").unwrap();
+        db.upsert_simple("username:dan nothing at all").unwrap();
+
+        assert_eq!(db.query("_exists_:service"), [both, service_only]);
+        assert_eq!(db.query("_exists_:level"), [both, level_only]);
+        assert_eq!(db.query("_exists_:LEVEL"), [both, level_only]);
+        // A generic field needs a non-empty value.
+        assert_eq!(db.query("_exists_:user"), [fields, other_user]);
+        assert_eq!(db.query("_exists_:USER"), [fields, other_user]);
+        assert!(db.query("_exists_:region").is_empty());
+        assert!(db.query("_exists_:nowhere").is_empty());
+
+        let mut errors = db.query("_exists_:service AND level:ERROR");
+        errors.sort();
+        assert_eq!(errors, [both]);
+        db.delete(&both);
+        assert_eq!(db.query("_exists_:service"), [service_only]);
+        db.delete(&fields);
+        assert_eq!(db.query("_exists_:user"), [other_user]);
+    }
+
+    #[test]
+    fn suppressing_whitespace_tokens_shrinks_the_postings() {
+        let build = |index_whitespace| {
+            let mut db = LogDB::with_config(LogConfig {
+                index_whitespace,
+                ..LogConfig::default()
+            });
+            for i in 0..200 {
+                // Runs of one to five spaces between the words.
+                let gap = " ".repeat(1 + i % 5);
+                let content =
+                    [word(i), word(i % 17 + 300), "disk".into(), "full".into()].join(&gap);
+                db.upsert_simple(&content).unwrap();
+            }
+            db
+        };
+        let (with, without) = (build(true), build(false));
+        let tokens = |db: &LogDB| db.docs.iter().map(|(_, e)| e.tokens.len()).sum::<usize>();
+        assert!(
+            tokens(&without) * 4 < tokens(&with) * 3,
+            "{} tokens without whitespace, {} with",
+            tokens(&without),
+            tokens(&with)
+        );
+        assert!(without.postings.len() < with.postings.len());
+        assert!(without.approx_bytes() < with.approx_bytes());
+        // Phrases ignore whitespace either way.
+        for q in ["\"disk full\"", &word(3), "full"] {
+            assert_same_results(&with, &without, &[q]);
+        }
+        assert_eq!(without.query("\"disk full\"").len(), 200);
+    }
+
+    #[test]
+    fn diff_for_holds_exactly_the_changed_tokens() {
+        let mut leader = LogDB::with_config(LogConfig {
+            enable_ngrams: false,
+            ..LogConfig::default()
+        });
+        let id = leader.upsert_simple("disk almost full").unwrap();
+        let mut follower = replicate(&leader);
+
+        assert_eq!(leader.diff_for(&id, "disk almost full"), None);
+        // Words are compared as the index compares them.
+        assert_eq!(leader.diff_for(&id, "DISK almost FULL"), None);
+        assert_eq!(
+            leader.diff_for(&id, "disk almost empty"),
+            Some(Frame::Diff {
+                doc_id: id,
+                remove: vec![lightning_hash_str("full")],
+                add: vec![lightning_hash_str("empty")],
+            })
+        );
+
+        let frame = leader.diff_for(&id, "disk almost empty").unwrap();
+        follower.apply_frame(frame).unwrap();
+        assert!(follower.query("full").is_empty());
+        assert_eq!(follower.query("empty"), [id]);
+        assert_eq!(follower.query("disk"), [id]);
+
+        let unknown = DocId(99);
+        let Some(Frame::Full { doc_id, tokens }) = leader.diff_for(&unknown, "fresh words") else {
+            panic!("expected a full frame");
+        };
+        assert_eq!(doc_id, unknown);
+        follower
+            .apply_frame(Frame::Full { doc_id, tokens })
+            .unwrap();
+        assert_eq!(follower.query("fresh"), [unknown]);
+    }
+
+    #[test]
+    fn postings_move_through_their_states() {
+        assert_eq!(std::mem::size_of::<Posting>(), 24);
+        let mut posting = Posting::new();
+        assert!(posting.is_empty());
+        posting.add(DocId(5));
+        assert!(matches!(posting, Posting::Single(DocId(5))));
+        assert_eq!(posting.heap_bytes(), 0);
+        posting.add(DocId(5));
+        assert!(matches!(posting, Posting::Single(_)));
+        posting.add(DocId(2));
+        assert!(matches!(&posting, Posting::Small(ids) if ids == &[DocId(2), DocId(5)]));
+        for id in 10..136 {
+            posting.add(DocId(id));
+        }
+        assert!(matches!(&posting, Posting::Small(ids) if ids.len() == 128));
+        posting.add(DocId(1_000));
+        assert!(matches!(posting, Posting::Large(_)));
+        assert_eq!(posting.len(), 129);
+        assert!(posting.contains(DocId(2)) && posting.contains(DocId(1_000)));
+
+        let mut single = Posting::new();
+        single.add(DocId(7));
+        single.remove(DocId(8));
+        assert_eq!(single.len(), 1);
+        single.remove(DocId(7));
+        assert!(single.is_empty());
+    }
+
+    #[test]
+    fn one_document_terms_cost_no_heap() {
+        let mut db = LogDB::with_config(LogConfig {
+            enable_ngrams: false,
+            ..LogConfig::default()
+        });
+        for i in 0..2_000 {
+            // Letters from `k` on hash to two digits each, so no two words collide.
+            let unique: String = format!("{i:04}")
+                .bytes()
+                .map(|d| (d - b'0' + b'k') as char)
+                .collect();
+            db.upsert_simple(&unique).unwrap();
+        }
+        let postings: Vec<&Posting> = db.postings.iter().map(|(_, p)| p).collect();
+        let singles = postings
+            .iter()
+            .filter(|p| matches!(p, Posting::Single(_)))
+            .count();
+        // Every word but `content`, which starts every descriptor, is in one document.
+        assert_eq!(singles, postings.len() - 1);
+        let shared = db.term_posting("content").unwrap();
+        let bytes: usize = postings
+            .iter()
+            .map(|p| std::mem::size_of::<Posting>() + p.heap_bytes())
+            .sum::<usize>()
+            - std::mem::size_of::<Posting>()
+            - shared.heap_bytes();
+        let per_posting = bytes as f64 / (postings.len() - 1) as f64;
+        assert_eq!(per_posting, 24.0);
+    }
+
+    /// Returns a path in the temporary directory unique to this process and `name`.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("buggu-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn restoring_a_snapshot_and_incrementals_reproduces_the_index() {
+        let (snap, inc1, inc2) = (
+            temp_path("restore.snap"),
+            temp_path("restore.inc1"),
+            temp_path("restore.inc2"),
+        );
+        let mut db = LogDB::new();
+        let ids: Vec<DocId> = sample_entries(120)
+            .into_iter()
+            .map(|e| db.upsert_entry(e).unwrap())
+            .collect();
+        db.snapshot_to(&snap).unwrap();
+        let at_snapshot = db.generation();
+
+        // Inserts, an update, deletes, and a document that lives only between backups.
+        db.upsert_log("late arrival", Some("ERROR".to_string()), None)
+            .unwrap();
+        let update = db.diff_for(&ids[0], "rewritten content").unwrap();
+        db.apply_frame(update).unwrap();
+        for id in &ids[10..20] {
+            assert!(db.delete(id));
+        }
+        let fleeting = db.upsert_simple("fleeting").unwrap();
+        db.incremental_to(&inc1, at_snapshot).unwrap();
+        let at_inc1 = db.generation();
+        db.delete(&fleeting);
+        db.upsert_simple("after the first incremental").unwrap();
+        db.incremental_to(&inc2, at_inc1).unwrap();
+
+        let mut restored = LogDB::new();
+        restored.restore_from(&[&snap, &inc1, &inc2]).unwrap();
+        assert_eq!(restored.len(), db.len());
+        assert_eq!(restored.generation(), db.generation());
+        assert_eq!(restored.next_doc_id(), db.next_doc_id());
+        assert_same_results(
+            &db,
+            &restored,
+            &[
+                "*",
+                "level:ERROR",
+                "service:auth",
+                "late",
+                "rewritten",
+                "fleeting",
+                &word(3),
+            ],
+        );
+        assert_eq!(restored.get_content(&ids[0]), db.get_content(&ids[0]));
+        assert!(restored.verify_integrity().is_ok());
+
+        // New documents never reuse an ID, even one only a deleted document had.
+        let next = restored.upsert_simple("new").unwrap();
+        assert!(next > fleeting);
+
+        for path in [snap, inc1, inc2] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn restore_rejects_out_of_order_and_truncated_backups() {
+        let (snap, inc, cut) = (
+            temp_path("reject.snap"),
+            temp_path("reject.inc"),
+            temp_path("reject.cut"),
+        );
+        let mut db = LogDB::new();
+        db.upsert_simple("first").unwrap();
+        db.snapshot_to(&snap).unwrap();
+        let since = db.generation();
+        db.upsert_simple("second").unwrap();
+        db.incremental_to(&inc, since).unwrap();
+
+        let kind =
+            |paths: &[&std::path::PathBuf]| LogDB::new().restore_from(paths).unwrap_err().kind();
+        assert_eq!(kind(&[&inc]), io::ErrorKind::InvalidData);
+        assert_eq!(kind(&[&inc, &snap]), io::ErrorKind::InvalidData);
+        assert_eq!(kind(&[&snap, &snap]), io::ErrorKind::InvalidData);
+        assert_eq!(kind(&[&snap, &inc, &inc]), io::ErrorKind::InvalidData);
+
+        let bytes = std::fs::read(&inc).unwrap();
+        std::fs::write(&cut, &bytes[..bytes.len() - 3]).unwrap();
+        assert_eq!(kind(&[&snap, &cut]), io::ErrorKind::UnexpectedEof);
+        std::fs::write(&cut, &bytes[..BackupHeader::LEN]).unwrap();
+        assert_eq!(kind(&[&snap, &cut]), io::ErrorKind::UnexpectedEof);
+
+        let mut full = LogDB::new();
+        full.upsert_simple("already here").unwrap();
+        assert_eq!(
+            full.restore_from(&[&snap]).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        for path in [snap, inc, cut] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn restore_rejects_frames_that_cannot_be_applied() {
+        let (snap, bad) = (temp_path("unapplied.snap"), temp_path("unapplied.bad"));
+        let mut db = LogDB::new();
+        db.upsert_simple("first").unwrap();
+        db.snapshot_to(&snap).unwrap();
+
+        // A checksummed frame the reader accepts but the restore cannot apply: a
+        // batch read from a file always adds up, but a diff can name a missing doc.
+        let mut bytes = std::fs::read(&snap).unwrap();
+        let mut writer = FrameWriter::with_checksum(Vec::new());
+        writer
+            .write_frame(&Frame::Diff {
+                doc_id: DocId(7),
+                remove: Vec::new(),
+                add: vec![1, 2],
+            })
+            .unwrap();
+        bytes.extend(writer.into_inner());
+        std::fs::write(&bad, &bytes).unwrap();
+
+        let err = LogDB::new().restore_from(&[&bad]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "diff frame for unknown doc 7");
+        // The untouched snapshot still restores.
+        assert!(LogDB::new().restore_from(&[&snap]).is_ok());
+
+        for path in [snap, bad] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn timestamp_ranges_partition_backdated_entries() {
+        let mut db = LogDB::new();
+        let old = db
+            .upsert_log_at("replayed line", Some("INFO".into()), None, 1_600_000_000)
+            .unwrap();
+        let new = db
+            .upsert_log_at("replayed line", Some("INFO".into()), None, 1_700_000_000)
+            .unwrap();
+        assert_eq!(db.get_entry(&old).unwrap().timestamp(), 1_600_000_000);
+        assert_eq!(db.query("timestamp:<1650000000"), vec![old]);
+        assert_eq!(db.query("timestamp:>=1650000000"), vec![new]);
+        assert_eq!(db.query("replayed timestamp:<=1700000000").len(), 2);
+        assert!(db.query("timestamp:>1700000000").is_empty());
+    }
+
+    #[test]
+    fn query_for_each_streams_the_query_results() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(300) {
+            db.upsert_entry(entry).unwrap();
+        }
+        for q in ["level:ERROR", &word(3), "service:db user:4", "-level:INFO"] {
+            let mut streamed = Vec::new();
+            db.query_for_each(q, |id| {
+                streamed.push(id);
+                true
+            });
+            let mut expected = db.query(q);
+            assert!(!expected.is_empty(), "{q}");
+            streamed.sort();
+            expected.sort();
+            assert_eq!(streamed, expected, "{q}");
+        }
+    }
+
+    #[test]
+    fn query_for_each_stops_when_the_callback_says_so() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(300) {
+            db.upsert_entry(entry).unwrap();
+        }
+        for q in ["level:ERROR", "service:db user:4", "-level:INFO"] {
+            let mut seen = Vec::new();
+            db.query_for_each(q, |id| {
+                seen.push(id);
+                seen.len() < 5
+            });
+            assert_eq!(seen.len(), 5, "{q}");
+            let all = db.query(q);
+            assert!(seen.iter().all(|id| all.contains(id)), "{q}");
+        }
+    }
+
+    #[test]
+    fn term_doc_freq_counts_documents_per_term() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(300) {
+            db.upsert_entry(entry).unwrap();
+        }
+        // `word(100)` is in every seventh entry and `word(3)` in every fortieth.
+        assert_eq!(db.term_doc_freq(&word(100)), 43);
+        assert_eq!(db.term_doc_freq(&word(3)), 8);
+        assert_eq!(db.term_doc_freq(&word(100)), db.query(&word(100)).len());
+        assert!(db.contains_term(&word(100)));
+
+        assert!(!db.contains_term("absent"));
+        assert_eq!(db.term_doc_freq("absent"), 0);
+
+        let id = db.query(&word(3))[0];
+        db.delete(&id);
+        assert_eq!(db.term_doc_freq(&word(3)), 7);
+    }
+
+    #[test]
+    fn urls_are_searched_as_literal_terms() {
+        let mut db = LogDB::new();
+        let fetch = db
+            .upsert_log(
+                "fetch http://example.com failed",
+                Some("ERROR".into()),
+                None,
+            )
+            .unwrap();
+        db.upsert_log("fetch https://example.com ok", Some("INFO".into()), None)
+            .unwrap();
+        assert_eq!(db.query("http://example.com"), vec![fetch]);
+        assert_eq!(db.query("level:ERROR"), vec![fetch]);
+        assert_eq!(db.query("http://example.com level:ERROR"), vec![fetch]);
+        assert!(db.query("http://example.com level:INFO").is_empty());
+    }
+
+    /// Builds a posting holding `ids`, added in the given order.
+    fn posting_of(ids: impl IntoIterator<Item = u64>) -> Posting {
+        let mut posting = Posting::new();
+        posting.extend(ids.into_iter().map(DocId));
+        posting
+    }
+
+    /// Returns a posting's document IDs in ascending order.
+    fn sorted_ids(posting: &Posting) -> Vec<u64> {
+        let mut ids: Vec<u64> = posting.iter().map(|id| id.0).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn merging_small_postings_gives_the_union() {
+        let mut posting = posting_of([1, 4, 9, 20]);
+        posting.merge(&posting_of([4, 5, 30]));
+        assert!(matches!(&posting, Posting::Small(_)));
+        assert_eq!(sorted_ids(&posting), [1, 4, 5, 9, 20, 30]);
+
+        let mut single = posting_of([7]);
+        single.merge(&posting_of([7]));
+        assert!(matches!(single, Posting::Single(DocId(7))));
+        let mut empty = Posting::new();
+        empty.merge(&posting_of([3]));
+        assert!(matches!(empty, Posting::Single(DocId(3))));
+    }
+
+    #[test]
+    fn merging_past_128_documents_promotes_to_large() {
+        let mut posting = posting_of(0..100);
+        posting.merge(&posting_of(50..150));
+        assert!(matches!(posting, Posting::Large(_)));
+        assert_eq!(sorted_ids(&posting), (0..150).collect::<Vec<_>>());
+
+        // A small posting merged with a large one, and the other way round.
+        let mut small = posting_of([500, 3]);
+        small.merge(&posting);
+        assert!(matches!(small, Posting::Large(_)));
+        assert_eq!(small.len(), 151);
+        assert!(small.contains(DocId(500)));
+        let mut large = posting_of(0..150);
+        large.merge(&posting_of([3, 500]));
+        assert_eq!(sorted_ids(&large), sorted_ids(&small));
+    }
+
+    #[test]
+    fn intersecting_postings_matches_a_set_intersection() {
+        let shapes = [
+            posting_of([]),
+            posting_of([42]),
+            posting_of([7]),
+            posting_of([3, 7, 42, 90]),
+            posting_of((0..100).step_by(3)),
+            posting_of(0..200),
+            posting_of((0..400).step_by(7)),
+        ];
+        for a in &shapes {
+            for b in &shapes {
+                let expected: Vec<u64> = sorted_ids(a)
+                    .into_iter()
+                    .filter(|&id| b.contains(DocId(id)))
+                    .collect();
+                let mut got: Vec<u64> = a.intersect(b).iter().map(|id| id.0).collect();
+                got.sort_unstable();
+                assert_eq!(got, expected, "{:?} AND {:?}", sorted_ids(a), sorted_ids(b));
+            }
+        }
+
+        // A two-word AND takes the fast path, over small and large postings.
+        let mut db = LogDB::new();
+        for entry in sample_entries(600) {
+            db.upsert_entry(entry).unwrap();
+        }
+        for (a, b) in [
+            (word(5), word(101)),
+            (word(101), word(102)),
+            (word(5), word(6)),
+        ] {
+            let with_b = db.query(&b);
+            let mut expected: Vec<DocId> = db
+                .query(&a)
+                .into_iter()
+                .filter(|id| with_b.contains(id))
+                .collect();
+            expected.sort_unstable();
+            let mut got = db.query(&format!("{a} AND {b}"));
+            got.sort_unstable();
+            assert_eq!(got, expected, "{a} AND {b}");
+        }
+        assert!(db.query(&format!("{} AND {}", word(5), word(6))).is_empty());
+        assert!(!db
+            .query(&format!("{} AND {}", word(5), word(101)))
+            .is_empty());
+    }
+
+    #[test]
+    fn posting_accessors_agree_on_every_representation() {
+        for ids in [
+            vec![],
+            vec![9],
+            vec![2, 5, 9],
+            (0..128).collect(),
+            (0..300).collect(),
+        ] {
+            let posting = posting_of(ids.iter().copied());
+            let large = ids.len() > 128;
+            assert_eq!(
+                matches!(posting, Posting::Large(_)),
+                large,
+                "{} IDs",
+                ids.len()
+            );
+            assert_eq!(posting.len(), ids.len());
+            assert_eq!(posting.is_empty(), ids.is_empty());
+            assert_eq!(posting.iter().count(), ids.len());
+            assert_eq!(sorted_ids(&posting), ids);
+            let mut docs: Vec<u64> = posting.get_docs().iter().map(|id| id.0).collect();
+            docs.sort_unstable();
+            assert_eq!(docs, ids);
+            for id in [0, 2, 9, 127, 128, 299, 300, 1_000] {
+                assert_eq!(posting.contains(DocId(id)), ids.contains(&id), "{id}");
+            }
+        }
+        // An emptied posting reports it on either side of the promotion.
+        let mut small = posting_of([4, 8]);
+        small.remove(DocId(4));
+        small.remove(DocId(8));
+        assert!(small.is_empty());
+        let mut large = posting_of(0..200);
+        (0..200).for_each(|id| large.remove(DocId(id)));
+        assert_eq!((large.is_empty(), large.len()), (true, 0));
+    }
+
+    #[test]
+    fn small_postings_stay_sorted_and_deduplicated() {
+        let mut posting = Posting::new();
+        for id in [40, 3, 17, 3, 99, 40, 1, 17, 60] {
+            posting.add(DocId(id));
+        }
+        assert!(matches!(&posting, Posting::Small(_)));
+        assert_eq!(
+            posting.as_slice().iter().map(|id| id.0).collect::<Vec<_>>(),
+            [1, 3, 17, 40, 60, 99]
+        );
+        // The same ID twice stays a single posting.
+        let mut single = posting_of([5, 5]);
+        assert!(matches!(single, Posting::Single(DocId(5))));
+        single.add(DocId(2));
+        assert_eq!(sorted_ids(&single), [2, 5]);
+
+        // Out-of-order IDs up to 128 stay a sorted list, and the 129th promotes it.
+        let mut posting = posting_of((0..128).rev());
+        assert!(matches!(&posting, Posting::Small(docs) if docs.len() == 128));
+        assert!(posting.as_slice().windows(2).all(|pair| pair[0] < pair[1]));
+        posting.add(DocId(64));
+        assert!(matches!(posting, Posting::Small(_)));
+        posting.add(DocId(500));
+        assert!(matches!(posting, Posting::Large(_)));
+        assert_eq!(posting.len(), 129);
+        posting.add(DocId(500));
+        assert_eq!(posting.len(), 129);
+    }
+
+    #[test]
+    fn suggest_ranks_words_by_frequency() {
+        let mut db = LogDB::new();
+        for content in [
+            "payment failed",
+            "payment retried",
+            "payment settled",
+            "payments queued",
+            "payments sent",
+            "Parser error",
+            "disk full",
+        ] {
+            db.upsert_simple(content).unwrap();
+        }
+        assert_eq!(db.suggest("pa", 10), ["payment", "payments", "parser"]);
+        assert_eq!(db.suggest("PA", 2), ["payment", "payments"]);
+        assert_eq!(db.suggest("pars", 10), ["parser"]);
+        assert!(db.suggest("zz", 10).is_empty());
+        assert_eq!(db.suggest("", 1), ["payment"]);
+
+        // A word whose documents are all deleted is no longer suggested.
+        let parser = db.query("parser");
+        db.delete(&parser[0]);
+        assert_eq!(db.suggest("pa", 10), ["payment", "payments"]);
+    }
+
+    #[test]
+    fn upsert_v2_indexes_no_metadata_words() {
+        let mut db = LogDB::new();
+        let legacy = db
+            .upsert_log("disk full", Some("ERROR".into()), Some("db".into()))
+            .unwrap();
+        let v2 = db
+            .upsert_v2("disk full", Some("ERROR".into()), Some("db".into()))
+            .unwrap();
+        let words = db
+            .upsert_v2("the level and service content", None, None)
+            .unwrap();
+        for word in ["level", "service", "content"] {
+            assert!(db.query(word).contains(&legacy), "{word}");
+            assert!(!db.query(word).contains(&v2), "{word}");
+            assert!(db.query(word).contains(&words), "{word}");
+        }
+        assert_eq!(db.query("error"), vec![legacy]);
+        let mut both = db.query("disk level:ERROR service:db");
+        both.sort();
+        assert_eq!(both, [legacy, v2]);
+    }
+
+    #[test]
+    fn recent_queries_return_the_newest_first() {
+        let mut db = LogDB::new();
+        let ids: Vec<DocId> = [300, 100, 500, 200, 400]
+            .into_iter()
+            .map(|ts| db.upsert_log_at("tick", None, None, ts).unwrap())
+            .collect();
+        db.upsert_log_at("tock", None, None, 900).unwrap();
+        assert_eq!(db.query_recent("tick", 3), [ids[2], ids[4], ids[0]]);
+        assert_eq!(db.query_recent("tick", 10).len(), 5);
+        assert!(db.query_recent("tick", 0).is_empty());
+
+        let newest = QueryOptions {
+            sort: SortOrder::Newest,
+            limit: Some(2),
+            ..QueryOptions::default()
+        };
+        assert_eq!(db.query_opts("tick", &newest), [ids[2], ids[4]]);
+
+        // Equal timestamps put the later insertion first.
+        let tie = db.upsert_log_at("tick", None, None, 500).unwrap();
+        assert_eq!(db.query_recent("tick", 2), [tie, ids[2]]);
+    }
+
+    #[test]
+    fn match_all_composes_with_filters_and_options() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(30) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let sorted = |mut ids: Vec<DocId>| {
+            ids.sort();
+            ids
+        };
+        assert_eq!(db.query("*").len(), 30);
+        assert_eq!(
+            sorted(db.query("* level:ERROR")),
+            sorted(db.query("level:ERROR"))
+        );
+        assert_eq!(db.query("* -level:ERROR").len(), 20);
+        assert_eq!(db.query("NOT *"), Vec::<DocId>::new());
+        assert_eq!(
+            sorted(db.query("-level:INFO -level:WARN")),
+            sorted(db.query("level:ERROR"))
+        );
+
+        let page = QueryOptions {
+            sort: SortOrder::Oldest,
+            offset: 5,
+            limit: Some(3),
+            ..QueryOptions::default()
+        };
+        let result = db.search("*", &page);
+        assert_eq!(result.total, 30);
+        assert_eq!(result.hits, [DocId(6), DocId(7), DocId(8)]);
+        assert_eq!(db.query_recent("*", 2), [DocId(30), DocId(29)]);
+
+        assert_eq!(db.try_query(""), Err(QueryError::Empty));
+        assert_eq!(db.try_query("   "), Err(QueryError::Empty));
+        assert_eq!(db.try_query("*").map(|ids| ids.len()), Ok(30));
+    }
+
+    #[test]
+    fn leading_minus_negates_terms_phrases_and_fields() {
+        let mut db = LogDB::new();
+        let lines = [
+            ("connection refused by db", "ERROR", "db"),
+            ("refused connection by proxy", "ERROR", "proxy"),
+            ("timeout in us-east-1", "WARN", "api"),
+            ("timeout in eu-west-1", "INFO", "auth"),
+        ];
+        let ids: Vec<DocId> = lines
+            .iter()
+            .map(|(content, level, service)| {
+                db.upsert_log(content, Some(level.to_string()), Some(service.to_string()))
+                    .unwrap()
+            })
+            .collect();
+        let sorted = |q: &str| {
+            let mut found = db.query(q);
+            found.sort();
+            found
+        };
+        // The phrase excludes only the document with the words in that order.
+        assert_eq!(sorted(r#"refused -"connection refused""#), [ids[1]]);
+        assert_eq!(sorted("timeout -level:INFO"), [ids[2]]);
+        assert_eq!(sorted("-level:ERROR -service:auth"), [ids[2]]);
+        // A query of only negations is evaluated against every document.
+        assert_eq!(sorted("-timeout"), [ids[0], ids[1]]);
+        assert_eq!(sorted("-timeout -db"), [ids[1]]);
+        // A hyphen inside a word is not a negation.
+        assert_eq!(sorted("us-east-1"), [ids[2]]);
+        assert_eq!(sorted("timeout -us-east-1"), [ids[3]]);
+    }
+
+    #[test]
+    fn relative_windows_include_their_start_by_the_mocked_clock() {
+        let mut db = LogDB::new();
+        db.set_clock(|| 10_000);
+        let ids: Vec<DocId> = [9_099, 9_100, 9_500, 10_000]
+            .into_iter()
+            .map(|ts| db.upsert_log_at("beat", None, None, ts).unwrap())
+            .collect();
+        let sorted = |mut found: Vec<DocId>| {
+            found.sort();
+            found
+        };
+        // Fifteen minutes before 10000 is 9100, which is inside the window.
+        assert_eq!(sorted(db.query("timestamp:>=now-15m")), ids[1..]);
+        assert_eq!(sorted(db.query("timestamp:>now-15m")), ids[2..]);
+        assert_eq!(sorted(db.query("timestamp:<now-15m")), ids[..1]);
+        assert_eq!(db.query("timestamp:now"), [ids[3]]);
+        assert_eq!(sorted(db.query_last("beat", 900)), ids[1..]);
+        assert_eq!(sorted(db.query_last("*", 500)), ids[2..]);
+        assert_eq!(db.query_last("beat", 0), [ids[3]]);
+
+        assert!(matches!(
+            db.try_query("timestamp:>=now-15x"),
+            Err(QueryError::InvalidTimestamp(_))
+        ));
+        // The window follows the clock.
+        db.set_clock(|| 20_000);
+        assert!(db.query("timestamp:>=now-15m").is_empty());
+    }
+
+    #[test]
+    fn words_under_min_token_len_are_not_indexed() {
+        let mut db = LogDB::with_config(LogConfig {
+            min_token_len: 3,
+            ..LogConfig::default()
+        });
+        let id = db.upsert_simple("ab abc x abcd").unwrap();
+        assert!(!db.contains_term("ab"));
+        assert!(db.query("ab").is_empty());
+        assert!(db.query("x").is_empty());
+        assert_eq!(db.query("abc"), [id]);
+        assert_eq!(db.query("abcd"), [id]);
+        // A dropped word in an AND empties it rather than being ignored.
+        assert!(db.query("abc ab").is_empty());
+    }
+
+    #[test]
+    fn missing_is_every_document_without_the_field() {
+        let mut db = LogDB::new();
+        let tagged = db
+            .upsert_log("disk full", Some("ERROR".into()), Some("db".into()))
+            .unwrap();
+        let untagged_error = db
+            .upsert_log("disk full", Some("ERROR".into()), None)
+            .unwrap();
+        let bare = db.upsert_simple("disk full").unwrap();
+        let sorted = |q: &str| {
+            let mut found = db.query(q);
+            found.sort();
+            found
+        };
+        assert_eq!(sorted("has:service"), [tagged]);
+        assert_eq!(sorted("missing:service"), [untagged_error, bare]);
+        assert_eq!(sorted("missing:level"), [bare]);
+        assert_eq!(sorted("missing:service level:ERROR"), [untagged_error]);
+        assert_eq!(sorted("disk missing:service missing:level"), [bare]);
+        assert_eq!(
+            sorted("has:level -has:service"),
+            sorted("level:ERROR missing:service")
+        );
+        assert!(sorted("has:service missing:service").is_empty());
+    }
+
+    #[test]
+    fn query_lazy_borrows_the_stored_content() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(60) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let expected = db.query("level:ERROR");
+        let mut lazy = db.query_lazy("level:ERROR");
+        // The content is the stored string itself, not a copy.
+        for (id, content) in lazy.by_ref().take(3) {
+            assert!(std::ptr::eq(content, db.get_entry(&id).unwrap().content()));
+        }
+        // Taking three left the rest for later, in the order `query` returns them.
+        let rest: Vec<DocId> = lazy.map(|(id, _)| id).collect();
+        assert_eq!(rest, expected[3..]);
+
+        let contents: Vec<&str> = db.query_lazy("level:ERROR").map(|(_, c)| c).collect();
+        assert_eq!(contents, db.query_content("level:ERROR"));
+    }
+
+    #[test]
+    fn severity_order_puts_errors_before_info() {
+        let mut db = LogDB::new();
+        let info_old = db
+            .upsert_log_at("request done", Some("INFO".into()), None, 100)
+            .unwrap();
+        let error = db
+            .upsert_log_at("request failed", Some("ERROR".into()), None, 50)
+            .unwrap();
+        let none = db.upsert_log_at("request seen", None, None, 300).unwrap();
+        let warn = db
+            .upsert_log_at("request slow", Some("WARN".into()), None, 10)
+            .unwrap();
+        let info_new = db
+            .upsert_log_at("request done", Some("INFO".into()), None, 200)
+            .unwrap();
+        assert_eq!(
+            db.query_by_severity("request"),
+            [error, warn, info_new, info_old, none]
+        );
+        let opts = QueryOptions {
+            sort: SortOrder::Severity,
+            limit: Some(1),
+            ..QueryOptions::default()
+        };
+        assert_eq!(db.query_opts("done OR failed", &opts), [error]);
+    }
+
+    #[test]
+    fn boosts_flip_the_ranking_of_or_branches() {
+        let mut db = LogDB::new();
+        let alpha = db.upsert_simple("alpha event").unwrap();
+        let beta = db.upsert_simple("beta event").unwrap();
+        let order =
+            |q: &str| -> Vec<DocId> { db.query_ranked(q).into_iter().map(|(id, _)| id).collect() };
+        assert_eq!(order("alpha^3 OR beta"), [alpha, beta]);
+        assert_eq!(order("alpha OR beta^3"), [beta, alpha]);
+
+        let scores = db.query_ranked("alpha^2 OR beta");
+        assert!(scores[0].1 > scores[1].1);
+        // Unranked queries ignore boosts.
+        let mut plain = db.query("alpha^3 OR beta");
+        plain.sort();
+        assert_eq!(plain, [alpha, beta]);
+    }
+
+    #[test]
+    fn oversized_queries_are_rejected() {
+        let mut db = LogDB::with_config(LogConfig {
+            max_query_clauses: 8,
+            max_query_depth: 4,
+            ..LogConfig::default()
+        });
+        for entry in sample_entries(100) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let or_of = |n: usize| (0..n).map(word).collect::<Vec<_>>().join(" OR ");
+        assert_eq!(db.try_query(&or_of(8)).map(|ids| ids.len()), Ok(24));
+        assert_eq!(
+            db.try_query(&or_of(9)),
+            Err(QueryError::TooComplex {
+                clauses: 9,
+                depth: 2
+            })
+        );
+        assert!(db.query(&or_of(9)).is_empty());
+
+        let nested = |depth: usize| {
+            (1..depth).fold(QueryNode::Term(word(1)), |node, _| {
+                QueryNode::Not(Box::new(node))
+            })
+        };
+        assert!(db.try_query_ast(&nested(4)).is_ok());
+        assert_eq!(
+            db.try_query_ast(&nested(5)),
+            Err(QueryError::TooComplex {
+                clauses: 1,
+                depth: 5
+            })
+        );
+    }
+
+    #[test]
+    fn exhausted_budgets_return_partial_results() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(300) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let q = "level:ERROR OR level:WARN OR level:INFO";
+        let (all, exhausted) = db.query_with_budget(q, 1_000);
+        assert!(!exhausted);
+        assert_eq!(all.len(), 300);
+        // The budget runs out on the second level, so the third is never evaluated.
+        let (partial, exhausted) = db.query_with_budget(q, 150);
+        assert!(exhausted);
+        assert_eq!(partial.len(), 200);
+        assert!(partial.iter().all(|id| all.contains(id)));
+    }
+
+    #[test]
+    fn synonym_rings_expand_bare_words_only() {
+        let mut db = LogDB::new();
+        let err = db.upsert_simple("err on disk").unwrap();
+        let error = db.upsert_simple("error on disk").unwrap();
+        let failure = db.upsert_simple("failure on net").unwrap();
+        db.upsert_simple("all good").unwrap();
+        db.add_synonyms(&["err", "error", "failure"]);
+        let sorted = |mut found: Vec<DocId>| {
+            found.sort();
+            found
+        };
+        for member in ["err", "error", "failure", "ERROR"] {
+            assert_eq!(sorted(db.query(member)), [err, error, failure], "{member}");
+        }
+        assert_eq!(sorted(db.query("error disk")), [err, error]);
+        // Phrases match their words exactly.
+        assert_eq!(db.query(r#""error on""#), [error]);
+
+        let exact = QueryOptions {
+            enable_synonyms: false,
+            ..QueryOptions::default()
+        };
+        assert_eq!(db.query_opts("error", &exact), [error]);
+        assert_eq!(db.query_opts("failure", &exact), [failure]);
+    }
+
+    /// Returns a word for `n` that collides with no other, spelled with the letters
+    /// from `k` on, which hash to two digits each.
+    fn distinct_word(n: usize) -> String {
+        format!("{n:04}")
+            .bytes()
+            .map(|d| (d - b'0' + b'k') as char)
+            .collect()
+    }
+
+    /// Indexes one word per document into a `LogDB` holding at most 20 postings, and
+    /// returns which of the first words survive once a 21st is indexed.
+    fn surviving_words(policy: EvictionPolicy, touch_first: bool) -> Vec<usize> {
+        let mut db = LogDB::with_config(LogConfig {
+            max_postings: 20,
+            eviction_policy: policy,
+            enable_ngrams: false,
+            ..LogConfig::default()
+        });
+        for i in 0..20 {
+            db.upsert_v2(&distinct_word(i), None, None).unwrap();
+        }
+        if touch_first {
+            assert_eq!(db.query(&distinct_word(0)).len(), 1);
+        }
+        db.upsert_v2(&distinct_word(20), None, None).unwrap();
+        (0..21)
+            .filter(|&i| !db.query(&distinct_word(i)).is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn eviction_drops_the_least_valuable_postings() {
+        let all: Vec<usize> = (0..21).collect();
+        assert_eq!(surviving_words(EvictionPolicy::Never, false), all);
+        // Going over the limit frees a tenth of it, the oldest three words.
+        assert_eq!(surviving_words(EvictionPolicy::Fifo, true), all[3..]);
+        assert_eq!(surviving_words(EvictionPolicy::Lru, false), all[3..]);
+        // A word queried since it was indexed is kept in place of the next oldest.
+        let mut kept = vec![0];
+        kept.extend(4..21);
+        assert_eq!(surviving_words(EvictionPolicy::Lru, true), kept);
+    }
+
+    #[test]
+    fn template_params_are_literal_values() {
+        let mut db = LogDB::new();
+        let auth_error = db
+            .upsert_log("login failed", Some("ERROR".into()), Some("auth".into()))
+            .unwrap();
+        db.upsert_log("login failed", Some("DEBUG".into()), Some("auth".into()))
+            .unwrap();
+        let literal = db
+            .upsert_log(
+                "x OR level:DEBUG",
+                Some("ERROR".into()),
+                Some("auth".into()),
+            )
+            .unwrap();
+        db.register_template("errors", "level:ERROR service:$svc $word")
+            .unwrap();
+        assert_eq!(
+            db.query_template("errors", &[("svc", "auth"), ("word", "failed")]),
+            Ok(vec![auth_error])
+        );
+        // The value is searched as a phrase, not read as query syntax, so it can't
+        // widen the query.
+        let injected = db
+            .query_template("errors", &[("svc", "auth"), ("word", "x OR level:DEBUG")])
+            .unwrap();
+        assert_eq!(injected, [literal]);
+
+        assert_eq!(
+            db.query_template("errors", &[("svc", "auth")]),
+            Err(QueryError::MissingParam("word".into()))
+        );
+        assert_eq!(
+            db.query_template("errors", &[("svc", "a"), ("word", "b"), ("lvl", "c")]),
+            Err(QueryError::UnknownParam("lvl".into()))
+        );
+        assert_eq!(
+            db.query_template("nope", &[]),
+            Err(QueryError::UnknownTemplate("nope".into()))
+        );
+    }
+
+    #[test]
+    fn query_options_apply_alone_and_together() {
+        let mut db = LogDB::new();
+        let lines = [
+            ("Disk full", "auth", 100),
+            ("disk full", "auth", 200),
+            ("disk full", "db", 300),
+            ("Disk slow", "db", 400),
+        ];
+        let ids: Vec<DocId> = lines
+            .iter()
+            .map(|(content, service, ts)| {
+                db.upsert_log_at(content, None, Some(service.to_string()), *ts)
+                    .unwrap()
+            })
+            .collect();
+        let run = |opts: QueryOptions| {
+            let mut found = db.query_opts("disk", &opts);
+            if opts.limit.is_none() {
+                found.sort();
+            }
+            found
+        };
+        assert_eq!(run(QueryOptions::default()), ids);
+        let limit = QueryOptions {
+            limit: Some(2),
+            sort: SortOrder::Oldest,
+            ..QueryOptions::default()
+        };
+        assert_eq!(run(limit), ids[..2]);
+        let offset = QueryOptions {
+            offset: 3,
+            sort: SortOrder::Oldest,
+            limit: Some(5),
+            ..QueryOptions::default()
+        };
+        assert_eq!(run(offset), ids[3..]);
+        let case = QueryOptions {
+            case_insensitive: Some(false),
+            ..QueryOptions::default()
+        };
+        assert_eq!(db.query_opts("Disk", &case), [ids[0], ids[3]]);
+        let time = QueryOptions {
+            time_range: Some((200, 300)),
+            ..QueryOptions::default()
+        };
+        assert_eq!(run(time), ids[1..3]);
+        let namespace = QueryOptions {
+            namespace: Some("db".into()),
+            ..QueryOptions::default()
+        };
+        assert_eq!(run(namespace), ids[2..]);
+
+        let combined = QueryOptions {
+            limit: Some(1),
+            sort: SortOrder::Newest,
+            case_insensitive: Some(false),
+            time_range: Some((0, 350)),
+            namespace: Some("auth".into()),
+            ..QueryOptions::default()
+        };
+        assert_eq!(db.query_opts("Disk", &combined), [ids[0]]);
+        assert_eq!(db.search("Disk", &combined).total, 1);
+    }
+
+    #[test]
+    fn phrase_intersection_checks_each_phrase_in_order() {
+        let mut db = LogDB::new();
+        let both = db
+            .upsert_simple("connection refused then timeout exceeded twice")
+            .unwrap();
+        db.upsert_simple("refused connection and exceeded timeout")
+            .unwrap();
+        db.upsert_simple("connection was refused, timeout not exceeded")
+            .unwrap();
+        let one = db.upsert_simple("connection refused only").unwrap();
+        let long = db
+            .upsert_simple("the quick brown fox jumps over the lazy dog")
+            .unwrap();
+
+        let phrases = ["connection refused", "timeout exceeded"];
+        assert_eq!(db.query_intersection_of_phrases(&phrases), [both]);
+        let mut refused = db.query_intersection_of_phrases(&["connection refused"]);
+        refused.sort();
+        assert_eq!(refused, [both, one]);
+        // Longer than the indexed N-grams, so only the positions decide.
+        assert_eq!(
+            db.query_intersection_of_phrases(&["brown fox jumps over the lazy", ""]),
+            [long]
+        );
+        assert!(db
+            .query_intersection_of_phrases(&["fox brown jumps over the lazy"])
+            .is_empty());
+    }
+
+    #[test]
+    fn heavy_queries_show_up_in_the_slow_query_log() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(2_000) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let heavy = (0..40).map(word).collect::<Vec<_>>().join(" OR ");
+        db.query("level:ERROR");
+        assert_eq!(db.query(&heavy).len(), 2_000);
+        let slow = db.slow_queries(0);
+        assert_eq!(slow.len(), 2);
+        let record = slow.last().unwrap();
+        assert_eq!(record.query, heavy);
+        assert_eq!((record.clauses, record.results), (40, 2_000));
+        assert!(record.took > Duration::ZERO);
+        assert!(db.slow_queries(60_000_000).is_empty());
+        assert_eq!(db.query_timing_summary().count, 2);
+
+        let quiet = LogDB::with_config(LogConfig {
+            query_log_capacity: 0,
+            ..LogConfig::default()
+        });
+        quiet.query(&heavy);
+        assert!(quiet.slow_queries(0).is_empty());
+    }
+
+    #[test]
+    fn health_report_is_clean_for_a_healthy_corpus() {
+        let mut db = LogDB::new();
+        for i in 0..200 {
+            db.upsert_v2(
+                &format!("{} {}", distinct_word(i), distinct_word(i % 10 + 1_000)),
+                Some("INFO".into()),
+                Some("api".into()),
+            )
+            .unwrap();
+        }
+        let report = db.health_report();
+        assert_eq!(report.findings, []);
+        assert_eq!(report.to_string(), "no problems found\n");
+    }
+
+    #[test]
+    fn health_report_flags_each_problem() {
+        // Every document shares one word and has no level.
+        let mut db = LogDB::new();
+        for i in 0..120 {
+            db.upsert_v2(&format!("boilerplate {}", distinct_word(i)), None, None)
+                .unwrap();
+        }
+        let report = db.health_report();
+        let codes: Vec<&str> = report.findings.iter().map(|f| f.code).collect();
+        assert_eq!(codes, ["common_token", "missing_level"]);
+        assert!(report.findings[0].message.starts_with("boilerplate (100%)"));
+        assert!(!report.has_warnings());
+
+        let mut db = LogDB::with_config(LogConfig {
+            index_whitespace: true,
+            ..LogConfig::default()
+        });
+        // A table that never grows puts every posting in one bucket.
+        db.postings = Arc::new(BugguHashSet::new(1));
+        for i in 0..15 {
+            db.upsert_v2(&distinct_word(i), Some("INFO".into()), None)
+                .unwrap();
+        }
+        assert!(!db.health_report().has("postings_near_limit"));
+        // Just under the limit, then over it.
+        db.max_postings = db.postings.len() + 1;
+        let report = db.health_report();
+        assert!(report.has("long_bucket_chain") && report.has("whitespace_tokens"));
+        assert!(report.has("postings_near_limit") && !report.has("postings_over_limit"));
+        for i in 15..20 {
+            db.upsert_v2(&distinct_word(i), Some("INFO".into()), None)
+                .unwrap();
+        }
+        let report = db.health_report();
+        assert!(report.has("postings_over_limit") && !report.has("postings_near_limit"));
+        // Warnings come first, in the text report too.
+        assert_eq!(report.findings[0].severity, Severity::Warning);
+        assert!(report.to_string().starts_with("warning "));
+        assert!(!report.has("missing_level") && !report.has("common_token"));
+    }
+
+    #[test]
+    fn restored_databases_continue_the_id_counter() {
+        let path = temp_path("next-id.snap");
+        let mut db = LogDB::new();
+        for entry in sample_entries(10) {
+            db.upsert_entry(entry).unwrap();
+        }
+        // The highest ID is deleted, so only the saved counter knows it was used.
+        db.delete(&DocId(10));
+        db.snapshot_to(&path).unwrap();
+
+        let mut restored = LogDB::new();
+        restored.restore_from(&[&path]).unwrap();
+        assert_eq!(restored.len(), 9);
+        assert_eq!(restored.next_doc_id(), DocId(11));
+        assert_eq!(restored.upsert_simple("after restore"), Ok(DocId(11)));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn facets_count_matches_per_value() {
+        let mut db = LogDB::new();
+        for (content, service) in [
+            ("timeout status:504", "api"),
+            ("timeout status:504 status:504", "api"),
+            ("timeout status:500", "auth"),
+            ("timeout", "auth"),
+            ("timeout STATUS:503", "auth"),
+            ("all fine status:200", "api"),
+        ] {
+            db.upsert_log(content, Some("WARN".into()), Some(service.into()))
+                .unwrap();
+        }
+        let counts = |field: &str| db.facet("timeout", field);
+        assert_eq!(counts("service"), [("auth".into(), 3), ("api".into(), 2)]);
+        assert_eq!(counts("level"), [("WARN".into(), 5)]);
+        // Each value once per document; field names ignore case.
+        assert_eq!(
+            counts("status"),
+            [("504".into(), 2), ("500".into(), 1), ("503".into(), 1)]
+        );
+        assert!(counts("region").is_empty());
+        assert!(db.facet("nothing", "service").is_empty());
+    }
+
+    #[test]
+    fn parse_only_renders_the_stress_test_queries() {
+        // The queries of the `test.js` stress test, then a few that nest.
+        let queries = [
+            "authentication",
+            "level:ERROR",
+            "service:payment-service",
+            "level:INFO service:auth-service",
+            "failed",
+            "user john",
+            "level:WARN",
+            "service:db-service",
+            "contains:timeout",
+            "payment",
+            "level:ERROR service:monitoring",
+            "database",
+            "server",
+            "level:INFO contains:completed",
+            "level:ERROR service:payment-service",
+            "level:WARN contains:server",
+            "user authentication",
+            "service:db-service level:WARN",
+            "level:DEBUG",
+            "ERROR",
+            "payment OR server",
+            r#"NOT level:(DEBUG|TRACE) "disk full" timeout^2 timestamp:>=100"#,
+            "fuzzy:smith~2 -has:service",
+        ];
+        let db = LogDB::new();
+        let rendered: String = queries
+            .iter()
+            .map(|q| format!("{q}\n{:#}", db.parse_only(q).unwrap()))
+            .collect();
+        assert_eq!(rendered, include_str!("../tests/fixtures/query_trees.txt"));
+        assert!(matches!(db.parse_only(" "), Err(QueryError::Empty)));
+    }
+
+    #[test]
+    fn boolean_operators_are_not_searched_as_words() {
+        let mut db = LogDB::new();
+        let matching = db
+            .upsert_log(
+                "database connection lost",
+                Some("ERROR".into()),
+                Some("db".into()),
+            )
+            .unwrap();
+        let and_word = db
+            .upsert_log("AND gate failed", Some("ERROR".into()), Some("hw".into()))
+            .unwrap();
+        let info = db
+            .upsert_log(
+                "database vacuum done",
+                Some("INFO".into()),
+                Some("db".into()),
+            )
+            .unwrap();
+
+        let q = "level:ERROR AND contains:database";
+        assert_eq!(
+            format!("{:?}", db.parse_only(q).unwrap()),
+            r#"And([FieldTerm("level", "ERROR"), Contains("database")])"#
+        );
+        assert_eq!(db.query(q), vec![matching]);
+        // A document holding the word AND is not what makes it match.
+        assert_eq!(db.query("gate AND failed"), vec![and_word]);
+        let mut either = db.query("level:ERROR OR vacuum");
+        either.sort_unstable();
+        assert_eq!(either, vec![matching, and_word, info]);
+        assert_eq!(db.query("database NOT level:INFO"), vec![matching]);
+        // Quoted, an operator is a word again.
+        assert_eq!(db.query("\"AND\""), vec![and_word]);
+    }
+
+    #[test]
+    fn advanced_query_forms_reach_the_executor() {
+        let mut db = LogDB::new();
+        let timeout = db
+            .upsert_log("upstream timeout", Some("ERROR".into()), Some("api".into()))
+            .unwrap();
+        let typo = db
+            .upsert_log("upstream timout", Some("WARN".into()), None)
+            .unwrap();
+        let other = db
+            .upsert_log("cache warmed", Some("INFO".into()), Some("api".into()))
+            .unwrap();
+
+        for (q, node) in [
+            ("fuzzy:timeout~1", "Fuzzy"),
+            ("_exists_:service", "Exists"),
+            ("level:ERROR OR level:WARN", "Or"),
+            ("NOT level:INFO", "Not"),
+            ("contains:time", "Contains"),
+        ] {
+            let parsed = format!("{:?}", db.parse_only(q).unwrap());
+            assert!(parsed.starts_with(node), "{q} parsed to {parsed}");
+        }
+        let sorted = |mut ids: Vec<DocId>| {
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(sorted(db.query("fuzzy:timeout~1")), [timeout, typo]);
+        assert_eq!(db.query("fuzzy:timeout~0"), [timeout]);
+        assert_eq!(sorted(db.query("_exists_:service")), [timeout, other]);
+        assert_eq!(
+            sorted(db.query("level:ERROR OR level:WARN")),
+            [timeout, typo]
+        );
+        assert_eq!(sorted(db.query("upstream NOT level:WARN")), [timeout]);
+        assert_eq!(sorted(db.query("fuzzy:timeout~1 service:api")), [timeout]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_entries_are_searchable_by_field_and_message() {
+        let mut db = LogDB::new();
+        // Words with characters other than letters are told apart by their first
+        // six bytes only, so the nested field has a short name.
+        let failed = db
+            .upsert_json(
+                r#"{"level":"ERROR","service":"auth","msg":"login failed","timestamp":1700,
+                    "u":{"id":42,"admin":false},"tag":["db","slow"],"note":null,"id":9}"#,
+            )
+            .unwrap();
+        let ok = db
+            .upsert_json(r#"{"message":"login ok","service":"auth","u":{"id":7}}"#)
+            .unwrap();
+
+        let entry = db.get_entry(&failed).unwrap();
+        assert_eq!(entry.timestamp(), 1700);
+        assert_eq!(db.query("failed"), [failed]);
+        assert_eq!(db.query("level:ERROR"), [failed]);
+        assert_eq!(db.query("u.id:42"), [failed]);
+        assert_eq!(db.query("u.id:7 login"), [ok]);
+        assert_eq!(db.query("tag:slow"), [failed]);
+        assert_eq!(db.query("u.admin:false"), [failed]);
+        assert!(db.query("id:9").is_empty());
+        let mut auth = db.query("service:auth");
+        auth.sort_unstable();
+        assert_eq!(auth, [failed, ok]);
+
+        assert!(matches!(
+            db.upsert_json("not json"),
+            Err(IngestError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            db.upsert_json("[1, 2]"),
+            Err(IngestError::NotAnObject)
+        ));
+        assert_eq!(db.len(), 2);
+    }
+
+    #[test]
+    fn oversized_content_is_truncated_at_a_char_boundary() {
+        let mut db = LogDB::with_config(LogConfig {
+            max_content_bytes: 24,
+            ..LogConfig::default()
+        });
+        let entry = |content: &str| LogEntry::new(content.to_string(), TokenMode::Mixed);
+        // The cut would fall inside the three bytes of the euro sign, at byte 9.
+        let long = "abcdefgh€ trailing words";
+        let outcome = db.upsert_entry_outcome(entry(long)).unwrap();
+        assert!(outcome.truncated);
+        let stored = db.get_entry(&outcome.id).unwrap();
+        assert_eq!(stored.content(), "abcdefgh truncated:true");
+        assert_eq!(stored.truncated_from(), Some(long.len()));
+
+        // Content of exactly the limit is kept whole.
+        let exact = "exactly twenty-four byte";
+        assert_eq!(exact.len(), 24);
+        let kept = db.upsert_entry_outcome(entry(exact)).unwrap();
+        assert!(!kept.truncated);
+        assert_eq!(db.get_entry(&kept.id).unwrap().truncated_from(), None);
+
+        assert_eq!(db.query("abcdefgh"), [outcome.id]);
+        assert!(db.query("trailing").is_empty());
+        assert_eq!(db.query("truncated:true"), [outcome.id]);
+        assert_eq!(db.metrics().truncations(), 1);
+        let stats = db.bulk_load([entry(long), entry("short")].into_iter());
+        assert_eq!((stats.docs, stats.truncated), (2, 1));
+        assert_eq!(db.query("truncated:true").len(), 2);
+    }
+
+    #[test]
+    fn most_common_terms_rank_the_dominant_word_first() {
+        let mut db = LogDB::new();
+        for i in 0..10 {
+            let status = if i < 6 { "error" } else { "fine" };
+            db.upsert_simple(&format!("the {status} {} the", distinct_word(i)))
+                .unwrap();
+        }
+        let top = |n| db.most_common_terms(n);
+        assert_eq!(
+            top(3),
+            [
+                ("the".to_string(), 10),
+                ("error".to_string(), 6),
+                ("fine".to_string(), 4)
+            ]
+        );
+        // A word is counted once per document however often it occurs, and ties
+        // are ordered alphabetically.
+        let all = top(100);
+        assert_eq!(all.len(), 13);
+        assert!(all[3..].iter().all(|(_, count)| *count == 1));
+        assert!(all[3..].windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(top(0).is_empty());
+    }
+
+    #[test]
+    fn match_spans_land_on_char_boundaries_of_unicode_content() {
+        let mut db = LogDB::new();
+        let content = "Grüße aus Zürich: ERROR in zürich überlastet";
+        let id = db.upsert_log(content, Some("ERROR".into()), None).unwrap();
+        db.upsert_simple("nothing to see").unwrap();
+
+        let spans = |q: &str| -> Vec<&str> {
+            let matches = db.query_matches(q);
+            assert_eq!(matches.len(), 1, "{q}");
+            assert_eq!(matches[0].id, id);
+            let spans = &matches[0].spans;
+            assert!(spans.windows(2).all(|pair| pair[0].1 <= pair[1].0), "{q}");
+            spans
+                .iter()
+                .map(|&(start, end)| &content[start as usize..end as usize])
+                .collect()
+        };
+        assert_eq!(spans("error"), ["ERROR"]);
+        assert_eq!(spans("überlastet"), ["überlastet"]);
+        assert_eq!(spans("Grüße error"), ["Grüße", "ERROR"]);
+        assert_eq!(spans("\"in zürich\""), ["in zürich"]);
+        assert_eq!(spans("überlastet -nothing"), ["überlastet"]);
+        assert!(spans("level:ERROR").is_empty());
+    }
+
+    #[test]
+    fn hand_built_asts_run_like_the_queries_they_spell() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(90) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let ast = QueryNode::And(vec![
+            QueryNode::FieldTerm("level", "ERROR".to_string()),
+            QueryNode::Or(vec![
+                QueryNode::FieldTerm("service", "auth".to_string()),
+                QueryNode::And(vec![
+                    QueryNode::Term(word(102)),
+                    QueryNode::Not(Box::new(QueryNode::FieldTerm("service", "db".to_string()))),
+                ]),
+            ]),
+        ]);
+        let mut got = db.query_ast(&ast);
+        got.sort_unstable();
+        let expected: Vec<DocId> = (0..90u64)
+            .filter(|i| {
+                let (level, service) = (i % 3, (i / 3) % 3);
+                level == 2 && (service == 0 || (i % 7 == 2 && service != 2))
+            })
+            .map(|i| DocId(i + 1))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn top_terms_rank_the_words_of_the_matches() {
+        let mut db = LogDB::new();
+        // One error repeats its timeout, so the word occurs in fewer documents than
+        // `disk` but more often.
+        for i in 0..4 {
+            let slow = [" timeout timeout timeout", " timeout", " timeout", ""][i];
+            let content = format!("the disk {}{slow}", distinct_word(i));
+            db.upsert_log(&content, Some("ERROR".into()), None).unwrap();
+        }
+        for i in 4..7 {
+            let content = format!("the login of {}", distinct_word(i));
+            db.upsert_log(&content, Some("INFO".into()), None).unwrap();
+        }
+        let top = |q, n, exclude| -> Vec<(String, usize)> { db.top_terms(q, n, exclude) };
+        let owned = |terms: &[(&str, usize)]| -> Vec<(String, usize)> {
+            terms.iter().map(|&(w, c)| (w.to_string(), c)).collect()
+        };
+
+        assert_eq!(
+            top("level:ERROR", 2, true),
+            owned(&[("timeout", 5), ("disk", 4)])
+        );
+        assert_eq!(
+            top("level:ERROR", 3, false),
+            owned(&[("timeout", 5), ("disk", 4), ("the", 4)])
+        );
+        assert_eq!(top("level:INFO", 1, true), owned(&[("login", 3)]));
+        assert_eq!(
+            top("level:INFO", 3, false),
+            owned(&[("login", 3), ("of", 3), ("the", 3)])
+        );
+        assert!(top("level:WARN", 5, false).is_empty());
+        assert!(top("level:ERROR", 0, false).is_empty());
+
+        let stats = db.term_stats();
+        assert!(stats.heaviest.windows(2).all(|pair| pair[0].2 >= pair[1].2));
+        assert!(stats
+            .heaviest
+            .iter()
+            .any(|(_, word, docs)| word.as_deref() == Some("the") && *docs == 7));
+        assert!(stats.postings_entries >= stats.distinct_terms);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ndjson_exports_read_back_as_the_same_documents() {
+        let mut db = LogDB::new();
+        db.upsert_log_at(
+            "quote \" backslash \\ tab\tend",
+            Some("ERROR".into()),
+            Some("auth".into()),
+            1_700,
+        )
+        .unwrap();
+        db.upsert_log_at("grüße, zürich", None, None, 1_701)
+            .unwrap();
+        db.upsert_log_at("skipped", Some("INFO".into()), None, 1_702)
+            .unwrap();
+
+        let mut ndjson = Vec::new();
+        assert_eq!(
+            db.export("-skipped", ExportFormat::Ndjson, &mut ndjson)
+                .unwrap(),
+            2
+        );
+        let mut restored = LogDB::new();
+        for line in std::str::from_utf8(&ndjson).unwrap().lines() {
+            restored.upsert_json(line).unwrap();
+        }
+        let rows = |db: &LogDB| {
+            let mut rows: Vec<_> = db
+                .query("*")
+                .into_iter()
+                .map(|id| {
+                    let entry = db.get_entry(&id).unwrap();
+                    (
+                        entry.timestamp(),
+                        entry.level().map(str::to_string),
+                        entry.service().map(str::to_string),
+                        entry.content().to_string(),
+                    )
+                })
+                .collect();
+            rows.sort();
+            rows
+        };
+        let mut expected = rows(&db);
+        expected.retain(|row| row.3 != "skipped");
+        assert_eq!(rows(&restored), expected);
+        assert_eq!(restored.query("level:ERROR service:auth").len(), 1);
+
+        let mut csv = Vec::new();
+        assert_eq!(db.export("zürich", ExportFormat::Csv, &mut csv).unwrap(), 1);
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            format!(
+                "{}\r\n2,1701,,,\"grüße, zürich\"\r\n",
+                crate::export::CSV_HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn equivalent_indexes_give_equal_snapshots() {
+        let entries = sample_entries(400);
+        let mut incremental = LogDB::new();
+        for entry in entries.iter().cloned() {
+            incremental.upsert_entry(entry).unwrap();
+        }
+        // Bulk loaded into tables of another size, so the buckets are laid out
+        // differently.
+        let mut bulk = LogDB::with_config(LogConfig {
+            initial_capacity: 4_096,
+            ..LogConfig::default()
+        });
+        bulk.bulk_load(entries.into_iter());
+
+        let snapshot = incremental.debug_snapshot();
+        assert_eq!(snapshot, bulk.debug_snapshot());
+        assert_eq!(snapshot.docs.len(), 400);
+        assert_eq!(snapshot.postings[&word(3)].len(), 10);
+        assert!(snapshot
+            .postings
+            .values()
+            .chain(snapshot.unnamed_postings.values())
+            .all(|ids| ids.windows(2).all(|pair| pair[0] < pair[1])));
+
+        let id = DocId(1);
+        bulk.delete(&id);
+        let changed = bulk.debug_snapshot();
+        assert_ne!(snapshot, changed);
+        assert!(!changed.docs.contains_key(&id));
+        assert!(!changed.postings[&word(0)].contains(&id));
+    }
+
+    #[test]
+    fn repeated_fields_match_and_count_every_value() {
+        let mut db = LogDB::new();
+        let both = db
+            .upsert_fields(
+                "query took long",
+                &[("tag", "db"), ("tag", "slow"), ("host", "a1")],
+            )
+            .unwrap();
+        let db_only = db.upsert_fields("query ok", &[("tag", "db")]).unwrap();
+        db.upsert_fields("other", &[]).unwrap();
+
+        assert_eq!(
+            db.fields(&both),
+            [("tag", "db"), ("tag", "slow"), ("host", "a1")]
+        );
+        assert_eq!(db.fields(&db_only), [("tag", "db")]);
+        let mut tagged_db = db.query("tag:db");
+        tagged_db.sort_unstable();
+        assert_eq!(tagged_db, [both, db_only]);
+        assert_eq!(db.query("tag:slow"), [both]);
+        assert_eq!(db.query("tag:db tag:slow host:a1"), [both]);
+        assert_eq!(db.query("has:tag").len(), 2);
+        // The document is counted under each of its tags.
+        assert_eq!(
+            db.facet("*", "tag"),
+            [("db".to_string(), 2), ("slow".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn unknown_fields_follow_the_configured_mode() {
+        let corpus = |mode| {
+            let mut db = LogDB::with_config(LogConfig {
+                unknown_field_mode: mode,
+                ..LogConfig::default()
+            });
+            let extracted = db
+                .upsert_log("request status:500 done", Some("ERROR".into()), None)
+                .unwrap();
+            let text = db
+                .upsert_log("status page returned 500", Some("INFO".into()), None)
+                .unwrap();
+            (db, extracted, text)
+        };
+
+        let (db, extracted, _) = corpus(UnknownFieldMode::AsExtractedField);
+        assert_eq!(db.try_query("status:500"), Ok(vec![extracted]));
+        assert_eq!(db.try_query("status:500 level:INFO"), Ok(vec![]));
+
+        let (db, _, text) = corpus(UnknownFieldMode::AsText);
+        assert_eq!(
+            format!("{:?}", db.parse_only("status:500").unwrap()),
+            r#"And([Term("status"), Term("500")])"#
+        );
+        assert_eq!(db.try_query("status:500"), Ok(vec![text]));
+
+        let (db, extracted, _) = corpus(UnknownFieldMode::Error);
+        assert_eq!(
+            db.try_query("status:500"),
+            Err(QueryError::UnknownField("status".to_string()))
+        );
+        assert!(db.query("status:500").is_empty());
+        // Known fields are unaffected.
+        assert_eq!(db.try_query("level:ERROR"), Ok(vec![extracted]));
+        assert_eq!(db.try_query("contains:request"), Ok(vec![extracted]));
+    }
+
+    #[test]
+    fn reserved_postings_take_a_batch_without_growing() {
+        // Each entry brings a distinct word along with its level, service and the
+        // words labelling them.
+        let batch = |db: &mut LogDB| {
+            for i in 0..5_000 {
+                db.upsert_log(&distinct_word(i), Some("INFO".into()), Some("api".into()))
+                    .unwrap();
+            }
+        };
+        let mut reserved = LogDB::new();
+        reserved.reserve_postings(5_100);
+        let buckets = reserved.postings.bucket_count();
+        batch(&mut reserved);
+        assert_eq!(reserved.postings.bucket_count(), buckets);
+        assert!(reserved.postings.len() > 5_000);
+
+        let mut unreserved = LogDB::new();
+        let initial = unreserved.postings.bucket_count();
+        batch(&mut unreserved);
+        assert!(unreserved.postings.bucket_count() > initial);
+        assert_eq!(reserved.debug_snapshot(), unreserved.debug_snapshot());
+        // Reserving less than is already held leaves the table alone.
+        reserved.reserve_postings(10);
+        assert_eq!(reserved.postings.bucket_count(), buckets);
+    }
+
+    #[test]
+    fn sounds_like_matches_words_sharing_a_soundex_code() {
+        let mut db = LogDB::with_config(LogConfig {
+            enable_phonetic: true,
+            ..LogConfig::default()
+        });
+        let robert = db.upsert_simple("login by Robert").unwrap();
+        let smyth = db.upsert_simple("login by smyth").unwrap();
+        db.upsert_simple("login by alice").unwrap();
+
+        assert_eq!(db.query("sounds:Rupert"), [robert]);
+        assert_eq!(db.query("sounds:smith"), [smyth]);
+        assert!(db.query("sounds:zebra").is_empty());
+        assert_eq!(db.query("login sounds:rupert"), [robert]);
+
+        for (word, code) in [("Robert", "R163"), ("Rupert", "R163"), ("Tymczak", "T522")] {
+            assert_eq!(soundex(word).as_deref(), Some(code), "{word}");
+        }
+        assert_eq!(soundex("42"), None);
+
+        // Without the flag no codes are indexed, so nothing sounds like anything.
+        let mut plain = LogDB::new();
+        plain.upsert_simple("login by Robert").unwrap();
+        assert!(plain.query("sounds:Rupert").is_empty());
+    }
+
+    #[test]
+    fn deleted_documents_leave_the_level_and_service_indexes() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(30) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let errors = db.query("level:ERROR");
+        assert_eq!(errors.len(), 10);
+        for id in &errors[..4] {
+            assert!(db.delete(id));
+        }
+        // A second delete finds nothing left to remove.
+        assert!(!db.delete(&errors[0]));
+
+        let mut left = db.query("level:ERROR");
+        left.sort_unstable();
+        let mut expected = errors[4..].to_vec();
+        expected.sort_unstable();
+        assert_eq!(left, expected);
+        let auth = db.query("service:auth");
+        assert!(auth.iter().all(|id| !errors[..4].contains(id)));
+        let mut facets = db.facet("*", "level");
+        facets.sort();
+        assert_eq!(
+            facets,
+            [
+                ("ERROR".to_string(), 6),
+                ("INFO".to_string(), 10),
+                ("WARN".to_string(), 10)
+            ]
+        );
+        let services: usize = db.facet("*", "service").iter().map(|(_, n)| n).sum();
+        assert_eq!(services, 26);
+
+        // Rebuilding the indexes from the documents, even twice, gives the same
+        // filters with no ID listed twice.
+        db.rebuild_indexes();
+        db.rebuild_indexes();
+        let mut rebuilt = db.query("level:ERROR");
+        rebuilt.sort_unstable();
+        assert_eq!(rebuilt, expected);
+        assert_eq!(db.query("service:auth").len(), auth.len());
+    }
+
+    #[test]
+    fn shared_snapshots_keep_their_state_through_later_writes() {
+        let mut db = LogDB::new();
+        for entry in sample_entries(200) {
+            db.upsert_entry(entry).unwrap();
+        }
+        let snapshot = db.snapshot();
+        // Until a write, the snapshot shares the tables rather than copying them.
+        assert!(Arc::ptr_eq(&snapshot.docs, &db.docs));
+        assert!(Arc::ptr_eq(&snapshot.postings, &db.postings));
+        let before = snapshot.debug_snapshot();
+
+        let errors = db.query("level:ERROR");
+        db.delete(&errors[0]);
+        let added = db
+            .upsert_log(&word(3), Some("ERROR".into()), Some("auth".into()))
+            .unwrap();
+        assert!(!Arc::ptr_eq(&snapshot.docs, &db.docs));
+        assert!(!Arc::ptr_eq(&snapshot.postings, &db.postings));
+
+        assert_eq!(snapshot.debug_snapshot(), before);
+        assert_eq!(snapshot.len(), 200);
+        assert_eq!(db.len(), 200);
+        assert_eq!(snapshot.query("level:ERROR").len(), errors.len());
+        assert!(snapshot.query(&word(3)).iter().all(|id| *id != added));
+        assert!(db.query(&word(3)).contains(&added));
+        assert!(snapshot.get_entry(&errors[0]).is_some());
+        assert!(db.get_entry(&errors[0]).is_none());
+        // The snapshot outlives the `LogDB` it was taken from.
+        drop(db);
+        assert_eq!(snapshot.debug_snapshot(), before);
+    }
+
+    #[test]
+    fn severity_ranges_match_levels_by_priority() {
+        let mut db = LogDB::new();
+        let ids: Vec<(&str, DocId)> = ["DEBUG", "INFO", "WARN", "ERROR", "FATAL", "NOTICE"]
+            .into_iter()
+            .map(|level| {
+                let id = db.upsert_log("event", Some(level.into()), None).unwrap();
+                (level, id)
+            })
+            .collect();
+        db.upsert_simple("event without a level").unwrap();
+        let levels = |q: &str| -> Vec<&str> {
+            let found = db.try_query(q).unwrap();
+            ids.iter()
+                .filter(|(_, id)| found.contains(id))
+                .map(|&(level, _)| level)
+                .collect()
+        };
+
+        assert_eq!(levels("severity:>=WARN"), ["WARN", "ERROR", "FATAL"]);
+        assert_eq!(levels("severity:>WARN"), ["ERROR", "FATAL"]);
+        assert_eq!(levels("severity:<=DEBUG"), ["DEBUG"]);
+        // An unconfigured level ranks as INFO.
+        assert_eq!(levels("severity:INFO"), ["INFO", "NOTICE"]);
+        assert_eq!(levels("severity:>=warn event"), ["WARN", "ERROR", "FATAL"]);
+        assert_eq!(levels("severity:>=4"), ["ERROR", "FATAL"]);
+        for bad in ["severity:>=LOUD", "severity:>=", "severity:"] {
+            assert!(
+                matches!(db.try_query(bad), Err(QueryError::InvalidSeverity(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn colored_lines_index_like_their_plain_text() {
+        let colored = "\x1b[1;31mERROR\x1b[0m disk \x1b[4mfull\x1b[24m\r";
+        let plain = "ERROR disk full";
+        let mut db = LogDB::with_config(LogConfig {
+            keep_raw_content: true,
+            ..LogConfig::default()
+        });
+        let id = db.upsert_simple(colored).unwrap();
+        let mut reference = LogDB::new();
+        let plain_id = reference.upsert_simple(plain).unwrap();
+
+        assert_eq!(
+            db.get_entry(&id).unwrap().tokens,
+            reference.get_entry(&plain_id).unwrap().tokens
+        );
+        assert_eq!(db.query_content("disk"), [plain]);
+        assert_eq!(db.query("full"), [id]);
+        assert_eq!(db.get_doc_raw(&id), Some(colored));
+        // Without keeping it, the raw content is the cleaned content.
+        let cleaned = reference.upsert_simple(colored).unwrap();
+        assert_eq!(reference.get_doc_raw(&cleaned), Some(plain));
+
+        // With stripping off, the escapes are indexed as they are.
+        let mut raw = LogDB::with_config(LogConfig {
+            strip_ansi: false,
+            ..LogConfig::default()
+        });
+        let id = raw.upsert_simple(colored).unwrap();
+        assert_eq!(raw.get_content(&id).as_deref(), Some(colored));
+        assert!(raw.query("full").is_empty());
     }
 }
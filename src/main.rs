@@ -0,0 +1,259 @@
+//! # micro-search
+//!
+//! A command-line front end for `LogDB`, so that
+//! `tail -f app.log | micro-search --stdin --format logfmt --query "level:ERROR"`
+//! indexes every line of a log as it arrives and echoes the lines matching a query.
+//!
+//! Only matching lines are written to standard output, so the output can be piped on
+//! like that of `grep`. The periodic statistics asked for with `--stats-every` and
+//! the summary printed at the end of the input go to standard error.
+//!
+//! With the `http` feature, `--serve ADDR` also answers the HTTP API of
+//! `buggu::http` on `ADDR`, over the same index, and keeps serving after the end of
+//! the input until the process is stopped.
+//!
+//! Build with `cargo build --no-default-features --features cli`, or
+//! `--features http` for `--serve`.
+
+use buggu::logdb::IngestError;
+use buggu::{DocId, LogDB};
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+/// The help printed by `--help` and after a usage error.
+const USAGE: &str = "\
+Usage: micro-search [--stdin] [--format simple|logfmt|json] [--query QUERY] [--stats-every N]
+                    [--serve ADDR]
+
+Options:
+  --stdin           Index each line of standard input as a document
+  --format FORMAT   How to read each line: simple (the default), logfmt or json
+  --query QUERY     Echo the lines matching QUERY to standard output
+  --stats-every N   Print statistics to standard error after every N lines
+  --serve ADDR      Serve the HTTP API on ADDR, such as 127.0.0.1:7700 (needs the
+                    http feature)
+  --help            Print this help";
+
+/// How each input line is turned into a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Format {
+    /// The whole line is the content, as with `LogDB::upsert_simple`.
+    #[default]
+    Simple,
+    /// The line is a logfmt record, as read by `LogDB::upsert_logfmt`.
+    Logfmt,
+    /// The line is a JSON object, as read by `LogDB::upsert_json`.
+    Json,
+}
+
+/// The parsed command line.
+#[derive(Debug, Default)]
+struct Args {
+    /// Whether to index standard input.
+    stdin: bool,
+    /// How to read each line.
+    format: Format,
+    /// The query whose matching lines are echoed.
+    query: Option<String>,
+    /// How many lines to read between statistics reports, if any.
+    stats_every: Option<u64>,
+    /// The address to serve the HTTP API on, if any.
+    serve: Option<String>,
+    /// Whether `--help` was given.
+    help: bool,
+}
+
+/// Parses the command-line arguments, without the program name.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "--stdin" => parsed.stdin = true,
+            "--format" => {
+                parsed.format = match value("--format")?.as_str() {
+                    "simple" => Format::Simple,
+                    "logfmt" => Format::Logfmt,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {other:?}")),
+                }
+            }
+            "--query" => parsed.query = Some(value("--query")?),
+            "--stats-every" => {
+                let n = value("--stats-every")?;
+                match n.parse() {
+                    Ok(n) if n > 0 => parsed.stats_every = Some(n),
+                    _ => return Err(format!("--stats-every needs a positive number, not {n:?}")),
+                }
+            }
+            "--serve" if cfg!(feature = "http") => parsed.serve = Some(value("--serve")?),
+            "--serve" => return Err("--serve needs the http feature".to_string()),
+            "-h" | "--help" => parsed.help = true,
+            other => return Err(format!("unknown argument {other:?}")),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Counts of what happened to the input lines, for the statistics and the summary.
+#[derive(Debug, Default)]
+struct Counts {
+    /// The non-empty lines read.
+    lines: u64,
+    /// The lines indexed.
+    indexed: u64,
+    /// The lines that could not be indexed, such as invalid JSON or lines rejected
+    /// by the ingest hook.
+    failed: u64,
+    /// The lines that matched the query.
+    matched: u64,
+}
+
+/// Indexes each line of `input` into `db` and echoes the ones matching `query`.
+///
+/// Each line is also indexed alone in a scratch `LogDB`, where the query is run and
+/// the line deleted again, so checking a line costs the same however many lines came
+/// before it. `db` is locked only while a line is indexed, so an HTTP server sharing
+/// it keeps answering.
+fn ingest_lines(
+    db: &Mutex<LogDB>,
+    input: impl BufRead,
+    format: Format,
+    query: Option<&str>,
+    stats_every: Option<u64>,
+) -> io::Result<Counts> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut probe = query.map(|_| LogDB::new());
+    let mut counts = Counts::default();
+    for line in input.split(b'\n') {
+        let line = String::from_utf8_lossy(&line?)
+            .trim_end_matches('\r')
+            .to_string();
+        if line.is_empty() {
+            continue;
+        }
+        counts.lines += 1;
+        let mut db = db
+            .lock()
+            .map_err(|_| io::Error::other("LogDB mutex is poisoned"))?;
+        match upsert(&mut db, format, &line) {
+            Ok(_) => counts.indexed += 1,
+            Err(_) => counts.failed += 1,
+        }
+        if let (Some(probe), Some(query)) = (probe.as_mut(), query) {
+            if let Ok(id) = upsert(probe, format, &line) {
+                if !probe.query(query).is_empty() {
+                    counts.matched += 1;
+                    writeln!(out, "{line}")?;
+                }
+                probe.delete(&id);
+            }
+        }
+        if stats_every.is_some_and(|n| counts.lines % n == 0) {
+            eprintln!("{}", stats(&db, &counts));
+        }
+    }
+    out.flush()?;
+    Ok(counts)
+}
+
+/// Indexes one line in the given format.
+fn upsert(db: &mut LogDB, format: Format, line: &str) -> Result<DocId, IngestError> {
+    match format {
+        Format::Simple => db.upsert_simple(line),
+        Format::Logfmt => db.upsert_logfmt(line),
+        Format::Json => db.upsert_json(line),
+    }
+}
+
+/// Formats the statistics line printed by `--stats-every`.
+fn stats(db: &LogDB, counts: &Counts) -> String {
+    format!(
+        "stats: {} lines, {} indexed, {} failed, {} matched, {} docs, ~{} bytes",
+        counts.lines,
+        counts.indexed,
+        counts.failed,
+        counts.matched,
+        db.len(),
+        db.approx_bytes(),
+    )
+}
+
+/// Starts serving the HTTP API on `addr` on a background thread.
+#[cfg(feature = "http")]
+fn serve(addr: &str, db: &Arc<Mutex<LogDB>>) -> io::Result<std::thread::JoinHandle<()>> {
+    let server = buggu::http::HttpServer::bind(addr, Arc::clone(db))?;
+    // Printed once the socket is bound, so a caller that asked for port 0 can read
+    // the port picked.
+    eprintln!("micro-search: listening on http://{}", server.local_addr()?);
+    Ok(std::thread::spawn(move || server.run()))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("micro-search: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.help {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    if !args.stdin && args.serve.is_none() {
+        eprintln!("micro-search: nothing to do\n\n{USAGE}");
+        return ExitCode::from(2);
+    }
+    if let Some(query) = &args.query {
+        if let Err(e) = LogDB::new().parse_only(query) {
+            eprintln!("micro-search: invalid query {query:?}: {e}");
+            return ExitCode::from(2);
+        }
+    }
+
+    let db = Arc::new(Mutex::new(LogDB::new()));
+    #[cfg(feature = "http")]
+    let server = match args.serve.as_deref().map(|addr| serve(addr, &db)) {
+        None => None,
+        Some(Ok(server)) => Some(server),
+        Some(Err(e)) => {
+            eprintln!(
+                "micro-search: cannot serve on {}: {e}",
+                args.serve.unwrap_or_default()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.stdin {
+        match ingest_lines(
+            &db,
+            io::stdin().lock(),
+            args.format,
+            args.query.as_deref(),
+            args.stats_every,
+        ) {
+            Ok(counts) => eprintln!(
+                "micro-search: read {} lines: {} indexed, {} failed, {} matched",
+                counts.lines, counts.indexed, counts.failed, counts.matched,
+            ),
+            // The reader of the output went away, as with `| head`.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("micro-search: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(server) = server {
+        // The server runs until the process is stopped.
+        let _ = server.join();
+    }
+    ExitCode::SUCCESS
+}
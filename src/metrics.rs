@@ -0,0 +1,448 @@
+//! # Metrics
+//!
+//! This module defines the counters, gauges and query latency histogram maintained by
+//! `LogDB`, and renders them in the Prometheus text exposition format. All values are
+//! plain integers in `Cell`s, so recording them on the hot path costs a few additions
+//! and `query` can keep taking `&self`. It also holds the slow-query log, a ring of the
+//! most recent queries with their latencies.
+
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+use std::time::Duration;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::Instant;
+
+/// The upper bounds of the query latency histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 6] = [0.000_01, 0.000_1, 0.001, 0.01, 0.1, 1.0];
+
+/// The number of power-of-two latency buckets behind `Metrics::timing_summary`.
+/// The last one also holds every query slower than about four seconds.
+const LOG2_BUCKETS: usize = 32;
+
+/// Measures how long a query takes.
+///
+/// `Instant::now` panics on `wasm32-unknown-unknown`, so there every query is
+/// recorded with a zero duration and lands in the first latency bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryTimer {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    start: Instant,
+}
+
+impl QueryTimer {
+    /// Starts timing.
+    #[inline]
+    pub fn start() -> Self {
+        Self {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            start: Instant::now(),
+        }
+    }
+
+    /// Returns the time elapsed since `start`.
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        {
+            self.start.elapsed()
+        }
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Operation counters and a query latency histogram for a `LogDB`.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// The number of documents inserted.
+    inserts: Cell<u64>,
+    /// The number of documents deleted.
+    deletes: Cell<u64>,
+    /// The number of documents whose content was truncated.
+    truncations: Cell<u64>,
+    /// The number of entries rejected by the ingest hook.
+    rejections: Cell<u64>,
+    /// The number of queries executed.
+    queries: Cell<u64>,
+    /// The number of queries that failed to parse or were over the complexity limits.
+    query_errors: Cell<u64>,
+    /// The number of queries per latency bucket, not cumulative. Queries slower than
+    /// the last bound are only counted in `queries`.
+    latency_buckets: [Cell<u64>; LATENCY_BUCKETS.len()],
+    /// The total time spent executing queries, in nanoseconds.
+    latency_sum_ns: Cell<u64>,
+    /// The number of queries per power-of-two latency bucket: bucket `i` counts the
+    /// queries that took fewer than `2^i` nanoseconds but not fewer than `2^(i-1)`.
+    latency_log2: [Cell<u64>; LOG2_BUCKETS],
+    /// The slowest query so far, in nanoseconds.
+    latency_max_ns: Cell<u64>,
+}
+
+/// Query latency statistics aggregated since a `LogDB` was created, returned by
+/// `LogDB::query_timing_summary`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryTimingSummary {
+    /// The number of queries executed.
+    pub count: u64,
+    /// The mean query time.
+    pub mean: Duration,
+    /// The 95th percentile query time, rounded up to a power of two nanoseconds and
+    /// capped at `max`.
+    pub p95: Duration,
+    /// The slowest query time.
+    pub max: Duration,
+}
+
+impl Metrics {
+    /// Records an inserted document.
+    #[inline]
+    pub fn record_insert(&self) {
+        self.inserts.set(self.inserts.get() + 1);
+    }
+
+    /// Records a document whose content was truncated.
+    #[inline]
+    pub fn record_truncation(&self) {
+        self.truncations.set(self.truncations.get() + 1);
+    }
+
+    /// Records an entry rejected by the ingest hook.
+    #[inline]
+    pub fn record_rejection(&self) {
+        self.rejections.set(self.rejections.get() + 1);
+    }
+
+    /// Records a deleted document.
+    #[inline]
+    pub fn record_delete(&self) {
+        self.deletes.set(self.deletes.get() + 1);
+    }
+
+    /// Records an executed query and how long it took.
+    #[inline]
+    pub fn record_query(&self, elapsed: Duration) {
+        self.queries.set(self.queries.get() + 1);
+        let nanos = elapsed.as_nanos() as u64;
+        self.latency_sum_ns
+            .set(self.latency_sum_ns.get().saturating_add(nanos));
+        self.latency_max_ns
+            .set(self.latency_max_ns.get().max(nanos));
+        let log2 = ((u64::BITS - nanos.leading_zeros()) as usize).min(LOG2_BUCKETS - 1);
+        self.latency_log2[log2].set(self.latency_log2[log2].get() + 1);
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| secs <= le) {
+            self.latency_buckets[i].set(self.latency_buckets[i].get() + 1);
+        }
+    }
+
+    /// Records a query that failed to parse or was over the complexity limits.
+    #[inline]
+    pub fn record_query_error(&self) {
+        self.query_errors.set(self.query_errors.get() + 1);
+    }
+
+    /// Returns the number of documents inserted.
+    pub fn inserts(&self) -> u64 {
+        self.inserts.get()
+    }
+
+    /// Returns the number of documents deleted.
+    pub fn deletes(&self) -> u64 {
+        self.deletes.get()
+    }
+
+    /// Returns the number of documents whose content was truncated.
+    pub fn truncations(&self) -> u64 {
+        self.truncations.get()
+    }
+
+    /// Returns the number of entries rejected by the ingest hook.
+    pub fn rejections(&self) -> u64 {
+        self.rejections.get()
+    }
+
+    /// Returns the number of queries executed.
+    pub fn queries(&self) -> u64 {
+        self.queries.get()
+    }
+
+    /// Returns the number of queries that failed to parse or were over the complexity
+    /// limits.
+    pub fn query_errors(&self) -> u64 {
+        self.query_errors.get()
+    }
+
+    /// Returns the count, mean, 95th percentile and maximum of the query times
+    /// recorded so far.
+    pub fn timing_summary(&self) -> QueryTimingSummary {
+        let count = self.queries();
+        if count == 0 {
+            return QueryTimingSummary::default();
+        }
+        let max = self.latency_max_ns.get();
+        // The smallest bucket holding the fastest 95% of queries, rounding up.
+        let rank = count - count / 20;
+        let mut seen = 0;
+        let mut p95 = max;
+        for (i, bucket) in self.latency_log2.iter().enumerate() {
+            seen += bucket.get();
+            if seen >= rank {
+                p95 = p95.min((1u64 << i).saturating_sub(1));
+                break;
+            }
+        }
+        QueryTimingSummary {
+            count,
+            mean: Duration::from_nanos(self.latency_sum_ns.get() / count),
+            p95: Duration::from_nanos(p95),
+            max: Duration::from_nanos(max),
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    ///
+    /// # Arguments
+    /// * `docs` - The current number of documents, reported as a gauge.
+    /// * `approx_bytes` - The estimated memory footprint, reported as a gauge.
+    /// * `term_collisions` - The number of words whose token collides with another
+    ///   word's, reported as a gauge.
+    pub fn render(&self, docs: usize, approx_bytes: usize, term_collisions: usize) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("buggu_inserts_total", "Documents inserted.", self.inserts()),
+            ("buggu_deletes_total", "Documents deleted.", self.deletes()),
+            (
+                "buggu_truncated_docs_total",
+                "Documents whose content was truncated.",
+                self.truncations(),
+            ),
+            (
+                "buggu_rejected_entries_total",
+                "Entries rejected by the ingest hook.",
+                self.rejections(),
+            ),
+            ("buggu_queries_total", "Queries executed.", self.queries()),
+            (
+                "buggu_query_errors_total",
+                "Queries that failed to parse or were over the complexity limits.",
+                self.query_errors(),
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {value}");
+        }
+
+        let name = "buggu_query_duration_seconds";
+        let _ = writeln!(out, "# HELP {name} Query execution time.");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (le, count) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += count.get();
+            let _ = writeln!(out, "{name}_bucket{{le=\"{le}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.queries());
+        let sum = self.latency_sum_ns.get() as f64 / 1e9;
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {}", self.queries());
+
+        let gauges = [
+            ("buggu_documents", "Documents currently stored.", docs),
+            (
+                "buggu_memory_bytes",
+                "Estimated memory used by the index.",
+                approx_bytes,
+            ),
+            (
+                "buggu_term_collisions",
+                "Indexed words whose token collides with another word's.",
+                term_collisions,
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {value}");
+        }
+        out
+    }
+}
+
+/// A query kept by the slow-query log, returned by `LogDB::slow_queries`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SlowQueryRecord {
+    /// The query string, as given.
+    pub query: String,
+    /// The number of clauses, such as terms and field filters, the query parses
+    /// into, or 0 if it does not parse.
+    pub clauses: usize,
+    /// The number of documents the query matched.
+    pub results: usize,
+    /// How long the query took.
+    pub took: Duration,
+}
+
+/// A query recorded in a `QueryLog` slot.
+#[derive(Debug, Clone, Default)]
+struct LoggedQuery {
+    query: String,
+    results: usize,
+    took: Duration,
+}
+
+/// A fixed-capacity ring of the most recent queries, with how many documents each
+/// matched and how long it took, behind `LogDB::slow_queries`.
+///
+/// Once full, each query overwrites the oldest slot, reusing its string buffer, so
+/// recording allocates only while the ring fills up or a query outgrows its slot.
+/// A capacity of 0 disables the log.
+#[derive(Debug, Clone, Default)]
+pub struct QueryLog {
+    /// The recorded queries, in insertion order until the ring wraps around.
+    slots: RefCell<Vec<LoggedQuery>>,
+    /// The slot the next query is written to once the ring is full.
+    next: Cell<usize>,
+    /// The most queries kept.
+    capacity: usize,
+}
+
+impl QueryLog {
+    /// Creates a log keeping the last `capacity` queries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: RefCell::new(Vec::new()),
+            next: Cell::new(0),
+            capacity,
+        }
+    }
+
+    /// Records a query, overwriting the oldest one once the log is full.
+    #[inline]
+    pub fn record(&self, query: &str, results: usize, took: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut slots = self.slots.borrow_mut();
+        if slots.len() < self.capacity {
+            slots.push(LoggedQuery {
+                query: query.to_owned(),
+                results,
+                took,
+            });
+            return;
+        }
+        let next = self.next.get();
+        let slot = &mut slots[next];
+        slot.query.clear();
+        slot.query.push_str(query);
+        slot.results = results;
+        slot.took = took;
+        self.next.set((next + 1) % self.capacity);
+    }
+
+    /// Returns the recorded queries that took longer than `threshold`, oldest first,
+    /// counting the clauses of each with `clauses`.
+    pub fn slower_than(
+        &self,
+        threshold: Duration,
+        clauses: impl Fn(&str) -> usize,
+    ) -> Vec<SlowQueryRecord> {
+        let slots = self.slots.borrow();
+        let (newer, older) = slots.split_at(self.next.get());
+        older
+            .iter()
+            .chain(newer)
+            .filter(|q| q.took > threshold)
+            .map(|q| SlowQueryRecord {
+                query: q.query.clone(),
+                clauses: clauses(&q.query),
+                results: q.results,
+                took: q.took,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logdb::LogDB;
+
+    /// Parses the samples of a Prometheus text exposition into `(name, value)` pairs,
+    /// checking that every sample follows `# HELP` and `# TYPE` lines for its metric.
+    fn samples(text: &str) -> Vec<(String, f64)> {
+        let mut typed = Vec::new();
+        let mut samples = Vec::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(matches!(kind, "counter" | "gauge" | "histogram"), "{line}");
+                typed.push(name.to_string());
+            } else if !line.starts_with("# HELP ") {
+                let (name, value) = line.rsplit_once(' ').unwrap();
+                assert!(
+                    typed.iter().any(|t| name.starts_with(t.as_str())),
+                    "{name} has no TYPE line"
+                );
+                samples.push((name.to_string(), value.parse().unwrap()));
+            }
+        }
+        samples
+    }
+
+    fn sample(text: &str, name: &str) -> f64 {
+        samples(text)
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .unwrap_or_else(|| panic!("no sample {name}"))
+            .1
+    }
+
+    #[test]
+    fn counters_move_with_operations() {
+        let mut db = LogDB::new();
+        let before = db.metrics_text();
+        assert_eq!(sample(&before, "buggu_inserts_total"), 0.0);
+        assert_eq!(sample(&before, "buggu_documents"), 0.0);
+
+        let id = db.upsert_simple("disk full").unwrap();
+        db.upsert_simple("disk ok").unwrap();
+        db.query("disk");
+        db.query("\"unterminated");
+        let _ = db.try_query("");
+        db.delete(&id);
+
+        let after = db.metrics_text();
+        assert_eq!(sample(&after, "buggu_inserts_total"), 2.0);
+        assert_eq!(sample(&after, "buggu_deletes_total"), 1.0);
+        assert_eq!(sample(&after, "buggu_queries_total"), 2.0);
+        assert_eq!(sample(&after, "buggu_query_errors_total"), 2.0);
+        assert_eq!(sample(&after, "buggu_documents"), 1.0);
+        assert!(sample(&after, "buggu_memory_bytes") > 0.0);
+        assert_eq!(sample(&after, "buggu_query_duration_seconds_count"), 2.0);
+        assert_eq!(
+            sample(&after, "buggu_query_duration_seconds_bucket{le=\"+Inf\"}"),
+            2.0
+        );
+    }
+
+    #[test]
+    fn latency_buckets_are_cumulative() {
+        let metrics = Metrics::default();
+        for micros in [5, 50, 50, 5_000, 2_000_000] {
+            metrics.record_query(Duration::from_micros(micros));
+        }
+        let text = metrics.render(0, 0, 0);
+        let buckets: Vec<f64> = samples(&text)
+            .into_iter()
+            .filter(|(name, _)| name.starts_with("buggu_query_duration_seconds_bucket"))
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(buckets, [1.0, 3.0, 3.0, 4.0, 4.0, 4.0, 5.0]);
+        let sum = sample(&text, "buggu_query_duration_seconds_sum");
+        assert!((sum - 2.005_105).abs() < 1e-9, "{sum}");
+    }
+}
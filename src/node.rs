@@ -0,0 +1,1329 @@
+//! # Node.js Bindings
+//!
+//! This module exposes the `MicroSearch` struct and its associated methods to
+//! JavaScript through N-API, allowing Node.js applications to leverage the
+//! high-performance search and indexing capabilities of the underlying `LogDB`. It is
+//! compiled only with the `node` feature.
+
+use crate::config::LogConfig;
+use crate::export::ExportFormat;
+use crate::ingest;
+use crate::logdb::{IngestError, LogDB, MetaEntry, QueryOptions, Snapshot, SortOrder};
+use crate::query::{parse_iso8601, QueryError, QueryNode};
+use crate::query_spec::QuerySpec;
+use crate::types::DocId;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Parses a document ID received from JavaScript, rejecting malformed input.
+fn parse_doc_id(id: &str) -> Result<DocId> {
+    id.parse::<DocId>()
+        .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))
+}
+
+/// Converts a timestamp in seconds received from JavaScript into epoch seconds,
+/// dropping any fraction of a second and rejecting NaN, infinite and negative values.
+fn parse_timestamp_secs(secs: f64) -> Result<u64> {
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(Error::new(
+            Status::InvalidArg,
+            format!("invalid timestamp {secs}: expected non-negative seconds"),
+        ));
+    }
+    Ok(secs as u64)
+}
+
+/// Converts the result of an upsert into the document ID as a string, or an
+/// `InvalidArg` error saying why the entry was not inserted.
+fn inserted(result: std::result::Result<DocId, IngestError>) -> Result<String> {
+    result
+        .map(|doc_id| doc_id.to_string())
+        .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))
+}
+
+/// Converts a query error into a JavaScript error. A query over the complexity limits
+/// gets the `EQUERY_COMPLEX` code, and any other query error `InvalidArg`.
+fn query_error(e: QueryError) -> Error<String> {
+    let code = match e {
+        QueryError::TooComplex { .. } => "EQUERY_COMPLEX",
+        _ => Status::InvalidArg.as_ref(),
+    };
+    Error::new(code.to_string(), e.to_string())
+}
+
+/// A node of a parsed query, returned by `parseQuery()`.
+#[napi(object)]
+pub struct QueryAst {
+    /// The node type: `"and"`, `"or"`, `"not"`, `"boost"`, `"term"`, `"phrase"`,
+    /// `"field"`, `"contains"`, `"fuzzy"`, `"range"`, `"has"`, `"missing"` or
+    /// `"match_all"`.
+    #[napi(js_name = "type")]
+    pub kind: String,
+    /// The field of a `field`, `range`, `has` or `missing` node.
+    pub field: Option<String>,
+    /// The word, phrase or value of a `term`, `phrase`, `field`, `contains` or `fuzzy`
+    /// node.
+    pub value: Option<String>,
+    /// The most edits a word matched by a `fuzzy` node may be from its value.
+    pub distance: Option<u32>,
+    /// The lower bound of a `range` node, inclusive.
+    pub from: Option<f64>,
+    /// The upper bound of a `range` node, inclusive, or absent if it has none.
+    pub to: Option<f64>,
+    /// The factor of a `boost` node.
+    pub boost: Option<f64>,
+    /// The children of an `and`, `or`, `not` or `boost` node.
+    pub children: Option<Vec<QueryAst>>,
+}
+
+impl From<&QueryNode> for QueryAst {
+    fn from(node: &QueryNode) -> Self {
+        let mut ast = QueryAst {
+            kind: node.kind().to_string(),
+            field: None,
+            value: None,
+            from: None,
+            to: None,
+            distance: None,
+            boost: None,
+            children: None,
+        };
+        match node {
+            QueryNode::Term(v)
+            | QueryNode::Phrase(v)
+            | QueryNode::Contains(v)
+            | QueryNode::Phonetic(v) => ast.value = Some(v.clone()),
+            QueryNode::FieldTerm(field, v) => {
+                ast.field = Some(field.to_string());
+                ast.value = Some(v.clone());
+            }
+            QueryNode::NumericRange(field, lo, hi) => {
+                ast.field = Some(field.to_string());
+                ast.from = Some(*lo as f64);
+                ast.to = (*hi != u64::MAX).then_some(*hi as f64);
+            }
+            QueryNode::Fuzzy(v, distance) => {
+                ast.value = Some(v.clone());
+                ast.distance = Some(*distance as u32);
+            }
+            QueryNode::Exists(field, _) => ast.field = Some(field.clone()),
+            QueryNode::Boost(boost, _) => ast.boost = Some(*boost as f64),
+            _ => {}
+        }
+        if matches!(
+            node,
+            QueryNode::And(_) | QueryNode::Or(_) | QueryNode::Not(_) | QueryNode::Boost(..)
+        ) {
+            ast.children = Some(node.children().iter().map(QueryAst::from).collect());
+        }
+        ast
+    }
+}
+
+/// A stored document returned by `getDocument()`.
+#[napi(object)]
+pub struct Document {
+    /// The document ID.
+    pub id: String,
+    /// The original content of the document.
+    pub content: String,
+    /// The log level, if one was specified.
+    pub level: Option<String>,
+    /// The service name, if one was specified.
+    pub service: Option<String>,
+    /// Every service the document is tagged with, `service` first.
+    pub services: Vec<String>,
+    /// The timestamp of the document, in seconds since the Unix epoch.
+    pub timestamp: i64,
+    /// The metadata fields of the document, each name with all of its values.
+    pub fields: HashMap<String, Vec<String>>,
+}
+
+/// Bucket distribution statistics for the postings table, returned by `bucketStats()`.
+#[napi(object)]
+pub struct BucketStats {
+    /// The number of buckets holding no tokens.
+    pub empty: u32,
+    /// The number of buckets storing their tokens inline.
+    pub inline: u32,
+    /// The number of buckets that spilled into a heap-allocated overflow vector.
+    pub overflow: u32,
+    /// The average number of tokens per bucket.
+    pub load_factor: f64,
+    /// The number of tokens in the most populated bucket.
+    pub max_chain_len: u32,
+}
+
+/// The options of `search()`. Every field is optional.
+#[napi(object)]
+pub struct SearchOptions {
+    /// The most hits to return, after skipping `offset`. All of them by default.
+    pub limit: Option<u32>,
+    /// The number of hits to skip, for paging. 0 by default.
+    pub offset: Option<u32>,
+    /// The order of the hits: `"index"` (the default), `"newest"`, `"oldest"`,
+    /// `"severity"` or `"relevance"`.
+    pub sort: Option<String>,
+    /// Set to `false` to also require words and phrases to match with their case.
+    pub case_insensitive: Option<bool>,
+    /// The earliest timestamp to match, in seconds since the Unix epoch.
+    pub time_from: Option<i64>,
+    /// The latest timestamp to match, in seconds since the Unix epoch.
+    pub time_to: Option<i64>,
+    /// Restricts hits to documents of this service.
+    pub namespace: Option<String>,
+    /// Set to `false` to match words without their synonyms.
+    pub enable_synonyms: Option<bool>,
+}
+
+/// The hits of a search, returned by `search()`.
+#[napi(object)]
+pub struct SearchResult {
+    /// The matching document IDs, after `offset` and `limit`.
+    pub hits: Vec<String>,
+    /// The number of matching documents, before `offset` and `limit`.
+    pub total: u32,
+    /// How long the search took, in milliseconds.
+    pub took_ms: f64,
+}
+
+/// Converts the options of `search()` into `QueryOptions`, rejecting an unknown sort
+/// order.
+fn query_options(options: SearchOptions) -> Result<QueryOptions> {
+    let sort = match options.sort.as_deref() {
+        None | Some("index") => SortOrder::Index,
+        Some("newest") => SortOrder::Newest,
+        Some("oldest") => SortOrder::Oldest,
+        Some("severity") => SortOrder::Severity,
+        Some("relevance") => SortOrder::Relevance,
+        Some(other) => {
+            return Err(Error::new(
+                Status::InvalidArg,
+                format!("unknown sort order {other:?}"),
+            ))
+        }
+    };
+    let time_range = match (options.time_from, options.time_to) {
+        (None, None) => None,
+        (from, to) => Some((
+            from.map_or(0, |t| t.max(0) as u64),
+            to.map_or(u64::MAX, |t| t.max(0) as u64),
+        )),
+    };
+    Ok(QueryOptions {
+        limit: options.limit.map(|l| l as usize),
+        offset: options.offset.unwrap_or(0) as usize,
+        sort,
+        case_insensitive: options.case_insensitive,
+        time_range,
+        namespace: options.namespace,
+        enable_synonyms: options.enable_synonyms.unwrap_or(true),
+        ..QueryOptions::default()
+    })
+}
+
+/// A query kept by the slow-query log, returned by `slowQueries()`.
+#[napi(object)]
+pub struct SlowQuery {
+    /// The query string, as given.
+    pub query: String,
+    /// The number of clauses the query parses into, or 0 if it does not parse.
+    pub clauses: u32,
+    /// The number of documents the query matched.
+    pub results: u32,
+    /// How long the query took, in microseconds.
+    pub took_micros: f64,
+}
+
+/// Query latency statistics since the index was created, returned by
+/// `queryTimingSummary()`.
+#[napi(object)]
+pub struct QueryTimingSummary {
+    /// The number of queries executed.
+    pub count: i64,
+    /// The mean query time, in microseconds.
+    pub mean_micros: f64,
+    /// The 95th percentile query time, in microseconds, rounded up to a power of two
+    /// nanoseconds.
+    pub p95_micros: f64,
+    /// The slowest query time, in microseconds.
+    pub max_micros: f64,
+}
+
+/// A problem found by `healthReport()`.
+#[napi(object)]
+pub struct HealthFinding {
+    /// `"warning"` or `"info"`.
+    pub severity: String,
+    /// A stable code naming the kind of problem, such as `"long_bucket_chain"`.
+    pub code: String,
+    /// A description of the problem and what to do about it.
+    pub message: String,
+}
+
+/// The result of `healthReport()`.
+#[napi(object)]
+pub struct HealthReport {
+    /// The problems found, most severe first.
+    pub findings: Vec<HealthFinding>,
+    /// The findings rendered one per line, for logging.
+    pub text: String,
+}
+
+/// A document matched by a ranked query, returned by `queryRanked()`.
+#[napi(object)]
+pub struct RankedHit {
+    /// The document ID.
+    pub id: String,
+    /// The relevance score of the document; higher is more relevant.
+    pub score: f64,
+}
+
+/// A word and the number of times it occurs, returned by `topTerms()`.
+#[napi(object)]
+pub struct TermCount {
+    /// The word.
+    pub term: String,
+    /// The number of times it occurs.
+    pub count: u32,
+}
+
+/// A document matched by `queryMatches()`, with where the query matched it.
+#[napi(object)]
+pub struct DocMatch {
+    /// The document ID.
+    pub id: String,
+    /// The `[start, end]` byte ranges of the content that the query matched, sorted
+    /// and non-overlapping, `end` exclusive.
+    pub spans: Vec<Vec<u32>>,
+}
+
+/// Settings for a new `MicroSearch` index, passed to its constructor.
+#[napi(object)]
+pub struct IndexOptions {
+    /// Whether to index the Soundex code of every word, for `sounds:` queries. Off by
+    /// default.
+    pub enable_phonetic: Option<bool>,
+    /// Whether to remove ANSI escape sequences and control characters other than
+    /// newlines and tabs from content before it is stored. On by default.
+    pub strip_ansi: Option<bool>,
+    /// Whether to also keep content as it was given, before it was cleaned, for
+    /// `getDocRaw()`. Off by default.
+    pub keep_raw_content: Option<bool>,
+}
+
+/// Checks `setIngestRules()` applies to every entry before it is indexed.
+#[napi(object)]
+pub struct IngestRules {
+    /// Whether to reject entries without a level.
+    pub require_level: Option<bool>,
+    /// Whether to remove ANSI escape sequences, such as color codes, from the content.
+    pub strip_ansi: Option<bool>,
+    /// The longest content to accept, in bytes, after any stripping.
+    pub max_bytes: Option<u32>,
+}
+
+/// A cursor paging through the hits of a query, returned by `openCursor()`.
+///
+/// The cursor pins a snapshot of the index taken when it was opened, so documents
+/// ingested while paging neither show up in later pages nor shift them. Other writes
+/// still show through: a matching document deleted while paging shifts the later
+/// pages by a hit, which is then skipped, and postings evicted under `max_postings`
+/// take their hits with them. It does not hold the index itself; pass it to
+/// `nextPage()` of the `MicroSearch` that opened it.
+#[napi]
+pub struct QueryCursor {
+    /// The view of the index the pages are read from.
+    snapshot: Snapshot,
+    /// The query being paged through.
+    query: String,
+    /// The options of the query, with `offset` at the start of the next page.
+    opts: QueryOptions,
+    /// The number of hits per page.
+    page_size: usize,
+}
+
+#[napi]
+impl QueryCursor {
+    /// Returns the number of hits already returned by `nextPage()`.
+    #[napi]
+    pub fn position(&self) -> u32 {
+        self.opts.offset as u32
+    }
+}
+
+/// The background task behind `exportToFile()`, writing a copy of the matching
+/// documents to a file on the libuv thread pool.
+pub struct ExportTask {
+    /// The matching documents, copied out of the index on the JavaScript thread.
+    rows: Vec<(DocId, MetaEntry)>,
+    /// The file to write.
+    path: String,
+    /// The format to write the rows in.
+    format: ExportFormat,
+}
+
+impl Task for ExportTask {
+    type Output = usize;
+    type JsValue = u32;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let io_error = |e: std::io::Error| {
+            Error::new(
+                Status::GenericFailure,
+                format!("cannot export to {}: {e}", self.path),
+            )
+        };
+        let mut out = BufWriter::new(File::create(&self.path).map_err(io_error)?);
+        self.format.write_header(&mut out).map_err(io_error)?;
+        for (id, entry) in &self.rows {
+            self.format
+                .write_row(&mut out, *id, entry)
+                .map_err(io_error)?;
+        }
+        out.flush().map_err(io_error)?;
+        Ok(self.rows.len())
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        Ok(output as u32)
+    }
+}
+
+/// A high-performance, in-memory search engine exposed as a Node.js addon.
+///
+/// The `MicroSearch` struct wraps the `LogDB`, providing a simplified interface for
+/// creating, updating, and querying documents. This struct is designed to be
+/// instantiated and used from JavaScript code.
+#[napi]
+pub struct MicroSearch {
+    /// The underlying `LogDB` instance that handles the actual search and indexing logic.
+    inner: LogDB,
+}
+
+#[napi]
+impl MicroSearch {
+    /// Creates a new instance of `MicroSearch`.
+    ///
+    /// This constructor initializes a new `LogDB` with default settings, changed by
+    /// any `options` given, and wraps it in a `MicroSearch` struct, making it available
+    /// for use in a Node.js environment.
+    ///
+    /// # Returns
+    /// A `Result` containing the new `MicroSearch` instance or an error if initialization fails.
+    #[napi(constructor)]
+    pub fn new(options: Option<IndexOptions>) -> Result<Self> {
+        let mut config = LogConfig::default();
+        if let Some(options) = options {
+            config.enable_phonetic = options.enable_phonetic.unwrap_or(false);
+            config.strip_ansi = options.strip_ansi.unwrap_or(true);
+            config.keep_raw_content = options.keep_raw_content.unwrap_or(false);
+        }
+        Ok(Self {
+            inner: LogDB::with_config(config),
+        })
+    }
+
+    /// Sets checks applied to every entry before it is indexed, replacing any set
+    /// before, or removes them when `rules` is omitted.
+    ///
+    /// An entry failing a check is not inserted, and the `upsert` method given it
+    /// throws an `InvalidArg` error saying which check it failed. Rejected entries
+    /// are counted in `buggu_rejected_entries_total` in `metricsText()`.
+    ///
+    /// # Arguments
+    /// * `rules` - The checks: `requireLevel` rejects entries without a level,
+    ///   `stripAnsi` removes color codes and other ANSI escape sequences from the
+    ///   content before it is indexed, and `maxBytes` rejects content longer than
+    ///   that many bytes.
+    #[napi]
+    pub fn set_ingest_rules(&mut self, rules: Option<IngestRules>) {
+        let Some(rules) = rules else {
+            self.inner.clear_ingest_hook();
+            return;
+        };
+        let rules = ingest::IngestRules {
+            require_level: rules.require_level.unwrap_or(false),
+            strip_ansi: rules.strip_ansi.unwrap_or(false),
+            max_bytes: rules.max_bytes.map(|max| max as usize),
+        };
+        self.inner.set_ingest_hook(rules.into_hook());
+    }
+
+    /// Inserts or updates a simple document with the given content.
+    ///
+    /// This method provides a straightforward way to add content to the search index
+    /// without specifying additional metadata like log level or service.
+    ///
+    /// # Arguments
+    /// * `content` - The string content of the document to be indexed.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string, or an error if the operation fails.
+    #[napi]
+    pub fn upsert_simple(&mut self, content: String) -> Result<String> {
+        let doc_id = self.inner.upsert_simple(&content);
+        inserted(doc_id)
+    }
+
+    /// Inserts or updates a log entry with additional metadata.
+    ///
+    /// This method allows for the indexing of structured log data, including log level
+    /// and service name, which can be used for more advanced filtering and querying.
+    ///
+    /// # Arguments
+    /// * `content` - The main content of the log entry.
+    /// * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
+    /// * `service` - An optional string specifying the service name.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string, or an error if the operation fails.
+    #[napi]
+    pub fn upsert_log(
+        &mut self,
+        content: String,
+        level: Option<String>,
+        service: Option<String>,
+    ) -> Result<String> {
+        let doc_id = self.inner.upsert_log(&content, level, service);
+        inserted(doc_id)
+    }
+
+    /// Inserts a log entry with an explicit timestamp instead of the current time, for
+    /// backfilling historical logs.
+    ///
+    /// # Arguments
+    /// * `content` - The main content of the log entry.
+    /// * `timestamp_secs` - The event time, in seconds since the Unix epoch. Any
+    ///   fraction of a second is dropped.
+    /// * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
+    /// * `service` - An optional string specifying the service name.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string, or an `InvalidArg` error if
+    /// `timestamp_secs` is NaN, infinite or negative.
+    #[napi]
+    pub fn upsert_log_at(
+        &mut self,
+        content: String,
+        timestamp_secs: f64,
+        level: Option<String>,
+        service: Option<String>,
+    ) -> Result<String> {
+        let timestamp = parse_timestamp_secs(timestamp_secs)?;
+        let doc_id = self
+            .inner
+            .upsert_log_at(&content, level, service, timestamp);
+        inserted(doc_id)
+    }
+
+    /// Inserts a log entry like `upsertLogAt`, with the event time given as an
+    /// ISO-8601 UTC date or time, such as `2024-03-01` or `2024-03-01T12:30:00Z`.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string, or an `InvalidArg` error if
+    /// `timestamp` is not of this form.
+    #[napi]
+    pub fn upsert_log_at_iso(
+        &mut self,
+        content: String,
+        timestamp: String,
+        level: Option<String>,
+        service: Option<String>,
+    ) -> Result<String> {
+        let timestamp = parse_iso8601(&timestamp).ok_or_else(|| {
+            Error::new(
+                Status::InvalidArg,
+                format!("invalid timestamp {timestamp:?}: expected an ISO-8601 UTC time"),
+            )
+        })?;
+        let doc_id = self
+            .inner
+            .upsert_log_at(&content, level, service, timestamp);
+        inserted(doc_id)
+    }
+
+    /// Inserts a log entry given as a JSON object. The `level`, `service` and
+    /// `message` (or `msg` or `content`) keys become the entry's level, service and
+    /// content, a numeric `timestamp` key its timestamp in seconds, and every other
+    /// scalar field except `id` is indexed as a `key:value` word, nested keys joined
+    /// by dots and each element of an array as its own value.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string, or an `InvalidArg` error if
+    /// `json` is not a JSON object or the ingest rules reject the entry.
+    #[napi]
+    pub fn upsert_json(&mut self, json: String) -> Result<String> {
+        inserted(self.inner.upsert_json(&json))
+    }
+
+    /// Inserts a log entry with metadata fields, each name given with all of its
+    /// values, such as `{ tag: ["db", "slow"] }`. Each value is indexed as a
+    /// `name:value` word, so the entry matches both `tag:db` and `tag:slow`.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string.
+    #[napi]
+    pub fn upsert_fields(
+        &mut self,
+        content: String,
+        fields: HashMap<String, Vec<String>>,
+    ) -> Result<String> {
+        let mut pairs: Vec<(&str, &str)> = fields
+            .iter()
+            .flat_map(|(name, values)| values.iter().map(move |v| (name.as_str(), v.as_str())))
+            .collect();
+        pairs.sort_by_key(|&(name, _)| name);
+        let doc_id = self.inner.upsert_fields(&content, &pairs);
+        inserted(doc_id)
+    }
+
+    /// Inserts a log entry tagged with several services, so that a `service:` query
+    /// for any of them finds it.
+    ///
+    /// # Arguments
+    /// * `content` - The main content of the log entry.
+    /// * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
+    /// * `services` - The service names, such as every service a request passed through.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string.
+    #[napi]
+    pub fn upsert_log_services(
+        &mut self,
+        content: String,
+        level: Option<String>,
+        services: Vec<String>,
+    ) -> Result<String> {
+        let doc_id = self.inner.upsert_log_services(&content, level, services);
+        inserted(doc_id)
+    }
+
+    /// Inserts a log entry like `upsertLog`, but indexes only its content as words.
+    ///
+    /// The level and service are searchable only through `level:` and `service:`
+    /// queries, so bare words never match a document through its metadata.
+    ///
+    /// # Arguments
+    /// * `content` - The main content of the log entry.
+    /// * `level` - An optional string specifying the log level (e.g., "INFO", "ERROR").
+    /// * `service` - An optional string specifying the service name.
+    ///
+    /// # Returns
+    /// A `Result` containing the document ID as a string, or an error if the operation fails.
+    #[napi]
+    pub fn upsert_v2(
+        &mut self,
+        content: String,
+        level: Option<String>,
+        service: Option<String>,
+    ) -> Result<String> {
+        let doc_id = self.inner.upsert_v2(&content, level, service);
+        inserted(doc_id)
+    }
+
+    /// Executes a search query and returns a list of matching document IDs.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    ///
+    /// # Returns
+    /// A `Result` containing a vector of document IDs as strings. An `EQUERY_COMPLEX`
+    /// error is returned if the query is over the configured complexity limits.
+    #[napi]
+    pub fn query(&self, query: String) -> Result<Vec<String>, String> {
+        let results = match self.inner.try_query(&query) {
+            Ok(results) => results,
+            Err(e @ QueryError::TooComplex { .. }) => return Err(query_error(e)),
+            // As with `LogDB::query`, a query that cannot be parsed matches nothing.
+            Err(_) => Vec::new(),
+        };
+        Ok(results.into_iter().map(|id| id.to_string()).collect())
+    }
+
+    /// Executes a search query with options to restrict, order and page through the
+    /// hits.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    /// * `options` - The optional settings described on `SearchOptions`.
+    ///
+    /// # Returns
+    /// A `Result` containing the page of hits, the total number of matches and the
+    /// time taken. An `InvalidArg` error is returned for an unknown sort order.
+    #[napi]
+    pub fn search(&self, query: String, options: Option<SearchOptions>) -> Result<SearchResult> {
+        let opts = match options {
+            Some(options) => query_options(options)?,
+            None => QueryOptions::default(),
+        };
+        let result = self.inner.search(&query, &opts);
+        Ok(SearchResult {
+            hits: result.hits.into_iter().map(|id| id.to_string()).collect(),
+            total: result.total as u32,
+            took_ms: result.took.as_secs_f64() * 1000.0,
+        })
+    }
+
+    /// Opens a cursor paging through the hits of a query, `pageSize` at a time, as
+    /// they were when the cursor was opened.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    /// * `page_size` - The number of hits per page.
+    /// * `options` - The optional settings described on `SearchOptions`. `offset` is
+    ///   where the first page starts and `limit` is ignored.
+    ///
+    /// # Returns
+    /// A `Result` containing the cursor, or an `InvalidArg` error for an unknown sort
+    /// order or a page size of 0.
+    #[napi]
+    pub fn open_cursor(
+        &self,
+        query: String,
+        page_size: u32,
+        options: Option<SearchOptions>,
+    ) -> Result<QueryCursor> {
+        if page_size == 0 {
+            return Err(Error::new(
+                Status::InvalidArg,
+                "page size must be at least 1".to_string(),
+            ));
+        }
+        let opts = match options {
+            Some(options) => query_options(options)?,
+            None => QueryOptions::default(),
+        };
+        Ok(QueryCursor {
+            snapshot: self.inner.freeze(),
+            query,
+            opts,
+            page_size: page_size as usize,
+        })
+    }
+
+    /// Returns the next page of a cursor opened by `openCursor()` and moves the cursor
+    /// past it.
+    ///
+    /// # Returns
+    /// A `Result` containing the page of hits, which is empty once every hit has been
+    /// returned, with the total number of matches the cursor sees and the time taken.
+    #[napi]
+    pub fn next_page(&self, cursor: &mut QueryCursor) -> Result<SearchResult> {
+        let opts = QueryOptions {
+            limit: Some(cursor.page_size),
+            ..cursor.opts.clone()
+        };
+        let result = cursor.snapshot.search(&self.inner, &cursor.query, &opts);
+        cursor.opts.offset += result.hits.len();
+        Ok(SearchResult {
+            hits: result.hits.into_iter().map(|id| id.to_string()).collect(),
+            total: result.total as u32,
+            took_ms: result.took.as_secs_f64() * 1000.0,
+        })
+    }
+
+    /// Executes a search query and returns the full content of matching documents.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    ///
+    /// # Returns
+    /// A `Result` containing a vector of document content strings, or an error if the query fails.
+    #[napi]
+    pub fn query_content(&self, query: String) -> Result<Vec<String>> {
+        Ok(self.inner.query_content(&query))
+    }
+
+    /// Executes a structured query and returns a list of matching document IDs.
+    ///
+    /// The query is a plain object such as
+    /// `{ and: [{ field: { level: "ERROR" } }, { term: "timeout" }] }`, converted
+    /// directly from JavaScript without going through a JSON string.
+    ///
+    /// # Arguments
+    /// * `query` - The structured query.
+    ///
+    /// # Returns
+    /// A `Result` containing a vector of document IDs as strings. An `InvalidArg` error
+    /// naming the offending node is returned if the query is malformed, and an
+    /// `EQUERY_COMPLEX` error if it is over the configured complexity limits.
+    #[napi]
+    pub fn query_object(&self, query: serde_json::Value) -> Result<Vec<String>, String> {
+        let spec = QuerySpec::from_value(&query).map_err(query_error)?;
+        let results = self.inner.try_query_spec(&spec).map_err(query_error)?;
+        Ok(results.into_iter().map(|id| id.to_string()).collect())
+    }
+
+    /// Registers a query template with `$name` placeholders, such as
+    /// `level:ERROR service:$svc contains:$word`, replacing any template with the
+    /// same name.
+    ///
+    /// # Arguments
+    /// * `name` - The name to run the template by with `queryTemplate`.
+    /// * `template` - The query string, parsed once here.
+    ///
+    /// # Returns
+    /// An `InvalidArg` error if the template cannot be parsed, or an `EQUERY_COMPLEX`
+    /// error if it is over the configured complexity limits.
+    #[napi]
+    pub fn register_template(&mut self, name: String, template: String) -> Result<(), String> {
+        self.inner
+            .register_template(&name, &template)
+            .map_err(query_error)
+    }
+
+    /// Executes a registered query template with its placeholders filled in. Values
+    /// are matched literally and never parsed as query syntax.
+    ///
+    /// # Arguments
+    /// * `name` - The name the template was registered under.
+    /// * `params` - The value of each placeholder, keyed by its name without the `$`.
+    ///
+    /// # Returns
+    /// A `Result` containing the matching document IDs as strings. An `InvalidArg`
+    /// error is returned for an unknown template, a placeholder with no value, or a
+    /// value with no placeholder.
+    #[napi]
+    pub fn query_template(
+        &self,
+        name: String,
+        params: HashMap<String, String>,
+    ) -> Result<Vec<String>, String> {
+        let params: Vec<(&str, &str)> = params
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let results = self
+            .inner
+            .query_template(&name, &params)
+            .map_err(query_error)?;
+        Ok(results.into_iter().map(|id| id.to_string()).collect())
+    }
+
+    /// Parses a query without running it, to see how the engine reads it.
+    ///
+    /// # Returns
+    /// A `Result` containing the root `QueryAst` node, such as
+    /// `{ type: "and", children: [...] }`. An `InvalidArg` error is returned for an
+    /// empty or malformed query, and an `EQUERY_COMPLEX` error for one over the
+    /// complexity limits.
+    #[napi]
+    pub fn parse_query(&self, query: String) -> Result<QueryAst, String> {
+        let ast = self.inner.parse_only(&query).map_err(query_error)?;
+        Ok(QueryAst::from(&ast))
+    }
+
+    /// Executes a search query restricted to the last `secs` seconds.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    /// * `secs` - The length of the time window, ending now.
+    ///
+    /// # Returns
+    /// A `Result` containing the matching document IDs as strings.
+    #[napi]
+    pub fn query_last(&self, query: String, secs: u32) -> Result<Vec<String>> {
+        let results = self.inner.query_last(&query, secs as u64);
+        Ok(results.into_iter().map(|id| id.to_string()).collect())
+    }
+
+    /// Executes a search query and returns the most recent matching document IDs.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    /// * `limit` - The maximum number of document IDs to return.
+    ///
+    /// # Returns
+    /// A `Result` containing up to `limit` document IDs as strings, newest first.
+    #[napi]
+    pub fn query_recent(&self, query: String, limit: u32) -> Result<Vec<String>> {
+        let results = self.inner.query_recent(&query, limit as usize);
+        Ok(results.into_iter().map(|id| id.to_string()).collect())
+    }
+
+    /// Executes a search query and returns the matching documents ranked by relevance.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string. A trailing `^N` on a word boosts it.
+    ///
+    /// # Returns
+    /// A `Result` containing the matching documents with their scores, highest first.
+    #[napi]
+    pub fn query_ranked(&self, query: String) -> Result<Vec<RankedHit>> {
+        let results = self.inner.query_ranked(&query);
+        Ok(results
+            .into_iter()
+            .map(|(id, score)| RankedHit {
+                id: id.to_string(),
+                score: score as f64,
+            })
+            .collect())
+    }
+
+    /// Executes a search query and returns each matching document with the byte ranges
+    /// of its content that the query's words and phrases matched.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    ///
+    /// # Returns
+    /// A `Result` containing the matching documents with their sorted,
+    /// non-overlapping ranges.
+    #[napi]
+    pub fn query_matches(&self, query: String) -> Result<Vec<DocMatch>> {
+        Ok(self
+            .inner
+            .query_matches(&query)
+            .into_iter()
+            .map(|m| DocMatch {
+                id: m.id.to_string(),
+                spans: m.spans.into_iter().map(|(s, e)| vec![s, e]).collect(),
+            })
+            .collect())
+    }
+
+    /// Writes the documents matching a query to a file, as NDJSON or CSV.
+    ///
+    /// The matching documents are copied out of the index before the call returns,
+    /// and the file is written on the libuv thread pool, so later changes to the
+    /// index do not show up in the export.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    /// * `path` - The file to write, replaced if it exists.
+    /// * `format` - `"ndjson"` or `"csv"`.
+    ///
+    /// # Returns
+    /// A promise of the number of documents written, rejected if the file cannot be
+    /// written. An `InvalidArg` error is thrown for an unknown format.
+    #[napi(ts_return_type = "Promise<number>")]
+    pub fn export_to_file(
+        &self,
+        query: String,
+        path: String,
+        format: String,
+    ) -> Result<AsyncTask<ExportTask>> {
+        let format = format
+            .parse::<ExportFormat>()
+            .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+        let mut rows = Vec::new();
+        self.inner.query_for_each(&query, |id| {
+            rows.extend(self.inner.get_entry(&id).map(|entry| (id, entry.clone())));
+            true
+        });
+        Ok(AsyncTask::new(ExportTask { rows, path, format }))
+    }
+
+    /// Executes a search query and returns the matching document IDs, most severe first.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    ///
+    /// # Returns
+    /// A `Result` containing the matching document IDs as strings, ordered by level
+    /// priority and then newest first.
+    #[napi]
+    pub fn query_by_severity(&self, query: String) -> Result<Vec<String>> {
+        let results = self.inner.query_by_severity(&query);
+        Ok(results.into_iter().map(|id| id.to_string()).collect())
+    }
+
+    /// Adds a synonym ring, so that a query for any of its words also matches
+    /// documents containing the others, including documents already indexed.
+    ///
+    /// # Arguments
+    /// * `words` - The words to treat as synonyms, e.g. `["db", "database", "postgres"]`.
+    #[napi]
+    pub fn add_synonyms(&mut self, words: Vec<String>) -> Result<()> {
+        let ring: Vec<&str> = words.iter().map(String::as_str).collect();
+        self.inner.add_synonyms(&ring);
+        Ok(())
+    }
+
+    /// Suggests indexed words that start with a prefix, for type-ahead search.
+    ///
+    /// # Arguments
+    /// * `prefix` - The prefix to complete. Matching is case-insensitive.
+    /// * `limit` - The maximum number of suggestions to return.
+    ///
+    /// # Returns
+    /// A `Result` containing up to `limit` lowercase words, most frequent first.
+    #[napi]
+    pub fn suggest(&self, prefix: String, limit: u32) -> Result<Vec<String>> {
+        Ok(self.inner.suggest(&prefix, limit as usize))
+    }
+
+    /// Returns the words occurring most often in the documents matching a query.
+    ///
+    /// # Arguments
+    /// * `query` - The search query string.
+    /// * `n` - The maximum number of words to return.
+    /// * `exclude_stopwords` - Whether to leave out common English words such as
+    ///   `the`. Defaults to `false`.
+    ///
+    /// # Returns
+    /// A `Result` containing up to `n` words with their occurrence counts, most
+    /// frequent first.
+    #[napi]
+    pub fn top_terms(
+        &self,
+        query: String,
+        n: u32,
+        exclude_stopwords: Option<bool>,
+    ) -> Result<Vec<TermCount>> {
+        let terms = self
+            .inner
+            .top_terms(&query, n as usize, exclude_stopwords.unwrap_or(false));
+        Ok(terms
+            .into_iter()
+            .map(|(term, count)| TermCount {
+                term,
+                count: count as u32,
+            })
+            .collect())
+    }
+
+    /// Retrieves the content of a document as it was given, before escape sequences
+    /// and control characters were removed from it, when the index was created with
+    /// `keepRawContent`; otherwise the stored content.
+    ///
+    /// # Arguments
+    /// * `id` - The document ID, as returned by the upsert methods.
+    ///
+    /// # Returns
+    /// A `Result` containing the content, or `null` if no document has this ID. An
+    /// `InvalidArg` error is returned if `id` is not a valid document ID.
+    #[napi]
+    pub fn get_doc_raw(&self, id: String) -> Result<Option<String>> {
+        let doc_id = parse_doc_id(&id)?;
+        Ok(self.inner.get_doc_raw(&doc_id).map(str::to_string))
+    }
+
+    /// Retrieves a stored document by its ID.
+    ///
+    /// # Arguments
+    /// * `id` - The document ID, as returned by the upsert methods.
+    ///
+    /// # Returns
+    /// A `Result` containing the document, or `null` if no document has this ID. An
+    /// `InvalidArg` error is returned if `id` is not a valid document ID.
+    #[napi]
+    pub fn get_document(&self, id: String) -> Result<Option<Document>> {
+        let doc_id = parse_doc_id(&id)?;
+        Ok(self.inner.get_entry(&doc_id).map(|entry| {
+            let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+            for (name, value) in self.inner.fields(&doc_id) {
+                fields
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(value.to_string());
+            }
+            Document {
+                id,
+                content: entry.content().to_string(),
+                level: entry.level().map(str::to_string),
+                service: entry.service().map(str::to_string),
+                services: entry.services().map(str::to_string).collect(),
+                timestamp: entry.timestamp() as i64,
+                fields,
+            }
+        }))
+    }
+
+    /// Deletes a document from the index.
+    ///
+    /// # Arguments
+    /// * `id` - The document ID, as returned by the upsert methods.
+    ///
+    /// # Returns
+    /// A `Result` containing `true` if the document was deleted, or `false` if no
+    /// document has this ID. An `InvalidArg` error is returned if `id` is not a valid
+    /// document ID.
+    #[napi]
+    pub fn delete(&mut self, id: String) -> Result<bool> {
+        let doc_id = parse_doc_id(&id)?;
+        Ok(self.inner.delete(&doc_id))
+    }
+
+    /// Returns bucket distribution statistics for the postings table.
+    ///
+    /// The table doubles its buckets once it averages two tokens per bucket, so a
+    /// healthy table has a `loadFactor` of at most 2.0, a `maxChainLen` of at most 8
+    /// (beyond which `healthReport()` reports `long_bucket_chain`) and an `overflow`
+    /// count that is a small fraction of `inline`. Values beyond these ranges
+    /// indicate that lookups are walking overflow vectors and the table would
+    /// benefit from more buckets.
+    ///
+    /// ```javascript
+    /// const stats = db.bucketStats();
+    /// if (stats.loadFactor > 2.0 || stats.maxChainLen > 8) {
+    ///   console.warn(`postings table is crowded: ${JSON.stringify(stats)}`);
+    /// }
+    /// ```
+    ///
+    /// # Returns
+    /// A `Result` containing the `BucketStats` object.
+    #[napi]
+    pub fn bucket_stats(&self) -> Result<BucketStats> {
+        let (empty, inline, overflow) = self.inner.postings_bucket_stats();
+        Ok(BucketStats {
+            empty: empty as u32,
+            inline: inline as u32,
+            overflow: overflow as u32,
+            load_factor: self.inner.postings_load_factor(),
+            max_chain_len: self.inner.postings_max_chain_len() as u32,
+        })
+    }
+
+    /// Returns the index metrics in the Prometheus text exposition format.
+    ///
+    /// The output covers insert, delete, query and query error counters, a query
+    /// latency histogram, and gauges for the document count and estimated memory use.
+    /// Serve it from a `/metrics` route to have Prometheus scrape it.
+    ///
+    /// # Returns
+    /// A `Result` containing the metrics text.
+    #[napi]
+    pub fn metrics_text(&self) -> Result<String> {
+        Ok(self.inner.metrics_text())
+    }
+
+    /// Inspects the index for problems that slow queries down or waste memory, such
+    /// as words in most documents, overfull hash table buckets, documents without a
+    /// level and postings past the postings limit.
+    ///
+    /// # Returns
+    /// A `Result` containing the `HealthReport` object.
+    #[napi]
+    pub fn health_report(&self) -> Result<HealthReport> {
+        let report = self.inner.health_report();
+        let text = report.to_string();
+        Ok(HealthReport {
+            findings: report
+                .findings
+                .into_iter()
+                .map(|finding| HealthFinding {
+                    severity: finding.severity.to_string(),
+                    code: finding.code.to_string(),
+                    message: finding.message,
+                })
+                .collect(),
+            text,
+        })
+    }
+
+    /// Returns the recent queries that took longer than `threshold_micros`
+    /// microseconds, oldest first, to find the query shapes behind latency spikes.
+    ///
+    /// The last 256 queries are kept. Queries run through `queryTemplate()` are not.
+    ///
+    /// # Returns
+    /// A `Result` containing the slow queries.
+    #[napi]
+    pub fn slow_queries(&self, threshold_micros: u32) -> Result<Vec<SlowQuery>> {
+        Ok(self
+            .inner
+            .slow_queries(threshold_micros as u64)
+            .into_iter()
+            .map(|record| SlowQuery {
+                query: record.query,
+                clauses: record.clauses as u32,
+                results: record.results as u32,
+                took_micros: record.took.as_secs_f64() * 1e6,
+            })
+            .collect())
+    }
+
+    /// Returns the count, mean, 95th percentile and maximum of the query times since
+    /// the index was created.
+    ///
+    /// # Returns
+    /// A `Result` containing the `QueryTimingSummary` object.
+    #[napi]
+    pub fn query_timing_summary(&self) -> Result<QueryTimingSummary> {
+        let summary = self.inner.query_timing_summary();
+        Ok(QueryTimingSummary {
+            count: summary.count as i64,
+            mean_micros: summary.mean.as_secs_f64() * 1e6,
+            p95_micros: summary.p95.as_secs_f64() * 1e6,
+            max_micros: summary.max.as_secs_f64() * 1e6,
+        })
+    }
+
+    /// Drops every parsed query cached for reuse. Queries are never stale in the
+    /// cache, so this only frees its memory.
+    #[napi]
+    pub fn clear_query_cache(&self) -> Result<()> {
+        self.inner.clear_query_cache();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ingest_rules_reject_with_invalid_arg() {
+        let mut db = MicroSearch::new(None).unwrap();
+        db.set_ingest_rules(Some(IngestRules {
+            require_level: Some(true),
+            strip_ansi: Some(true),
+            max_bytes: None,
+        }));
+
+        let err = db
+            .upsert_log("no level".to_string(), None, None)
+            .unwrap_err();
+        assert_eq!(err.status, Status::InvalidArg);
+        assert_eq!(err.reason, "entry rejected: entry has no level");
+
+        let id = db
+            .upsert_log(
+                "\x1b[31mboom\x1b[0m".to_string(),
+                Some("ERROR".to_string()),
+                None,
+            )
+            .unwrap();
+        assert_eq!(db.inner.query("boom"), vec![parse_doc_id(&id).unwrap()]);
+
+        db.set_ingest_rules(None);
+        assert!(db.upsert_log("no level".to_string(), None, None).is_ok());
+    }
+
+    #[test]
+    fn cursor_pages_ignore_later_inserts() {
+        let mut db = MicroSearch::new(None).unwrap();
+        for i in 0..25 {
+            db.upsert_simple(format!("request number {i}")).unwrap();
+        }
+        let mut cursor = db.open_cursor("request".to_string(), 10, None).unwrap();
+        let first = db.next_page(&mut cursor).unwrap();
+        for i in 0..1000 {
+            db.upsert_simple(format!("request late {i}")).unwrap();
+        }
+        let second = db.next_page(&mut cursor).unwrap();
+        let third = db.next_page(&mut cursor).unwrap();
+
+        assert_eq!((first.total, second.total, third.total), (25, 25, 25));
+        assert_eq!(
+            (first.hits.len(), second.hits.len(), third.hits.len()),
+            (10, 10, 5)
+        );
+        let mut seen: Vec<_> = [first.hits, second.hits, third.hits].concat();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 25);
+        assert!(db.next_page(&mut cursor).unwrap().hits.is_empty());
+        assert_eq!(cursor.position(), 25);
+
+        assert!(matches!(
+            db.open_cursor("request".to_string(), 0, None),
+            Err(e) if e.status == Status::InvalidArg
+        ));
+    }
+
+    #[test]
+    fn malformed_doc_ids_are_invalid_args() {
+        let mut db = MicroSearch::new(None).unwrap();
+        let id = db.upsert_simple("hello".to_string()).unwrap();
+        for bad in ["", "abc", "1x", "-1"] {
+            assert!(matches!(
+                db.get_document(bad.to_string()),
+                Err(e) if e.status == Status::InvalidArg
+            ));
+            assert_eq!(
+                db.delete(bad.to_string()).unwrap_err().status,
+                Status::InvalidArg
+            );
+        }
+        assert!(!db.delete("999".to_string()).unwrap());
+        assert!(db.delete(id).unwrap());
+    }
+
+    #[test]
+    fn event_times_are_kept_from_seconds_and_iso_dates() {
+        let mut db = MicroSearch::new(None).unwrap();
+        let timestamp = |db: &MicroSearch, id: &str| {
+            db.inner
+                .get_entry(&parse_doc_id(id).unwrap())
+                .unwrap()
+                .timestamp()
+        };
+
+        let id = db
+            .upsert_log_at("late".to_string(), 1_709_251_200.9, None, None)
+            .unwrap();
+        assert_eq!(timestamp(&db, &id), 1_709_251_200);
+        let day = db
+            .upsert_log_at_iso("day".to_string(), "2024-03-01".to_string(), None, None)
+            .unwrap();
+        assert_eq!(timestamp(&db, &day), 1_709_251_200);
+        let time = db
+            .upsert_log_at_iso(
+                "time".to_string(),
+                "2024-03-01T12:30:00Z".to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(timestamp(&db, &time), 1_709_251_200 + 45_000);
+        let mut before_noon = db.inner.query("timestamp:<=2024-03-01T12:00:00Z");
+        before_noon.sort_unstable();
+        assert_eq!(
+            before_noon,
+            [parse_doc_id(&id).unwrap(), parse_doc_id(&day).unwrap()]
+        );
+
+        for bad in [f64::NAN, f64::INFINITY, -1.0] {
+            let err = db
+                .upsert_log_at("bad".to_string(), bad, None, None)
+                .unwrap_err();
+            assert_eq!(err.status, Status::InvalidArg, "{bad}");
+        }
+        for bad in ["", "yesterday", "2024-13-01", "2024-03-01 12:30"] {
+            let err = db
+                .upsert_log_at_iso("bad".to_string(), bad.to_string(), None, None)
+                .unwrap_err();
+            assert_eq!(err.status, Status::InvalidArg, "{bad:?}");
+        }
+        assert_eq!(db.inner.len(), 3);
+    }
+
+    #[test]
+    fn bucket_stats_describe_the_postings_table() {
+        let mut db = MicroSearch::new(None).unwrap();
+        let fresh = db.bucket_stats().unwrap();
+        assert_eq!(
+            (fresh.inline, fresh.overflow, fresh.max_chain_len),
+            (0, 0, 0)
+        );
+        assert_eq!(fresh.load_factor, 0.0);
+        assert_eq!(fresh.empty as usize, db.inner.postings_bucket_count());
+
+        for i in 0..500 {
+            // Digits spelled as the letters from k, so that no two words share a hash.
+            let word: String = format!("{i:03}")
+                .bytes()
+                .map(|d| (d - b'0' + b'k') as char)
+                .collect();
+            db.upsert_simple(format!("request {word} served")).unwrap();
+        }
+        let stats = db.bucket_stats().unwrap();
+        let buckets = db.inner.postings_bucket_count();
+        assert_eq!(
+            (stats.empty + stats.inline + stats.overflow) as usize,
+            buckets
+        );
+        assert!(stats.inline > 0 && stats.max_chain_len >= 1);
+        let tokens = stats.load_factor * buckets as f64;
+        assert!(tokens >= 500.0, "{tokens} tokens");
+        // The table grows as it fills, so it stays in the healthy range.
+        assert!(stats.load_factor <= 2.0, "{}", stats.load_factor);
+        assert!(stats.max_chain_len <= 8, "{}", stats.max_chain_len);
+    }
+}
@@ -1,147 +1,1089 @@
-//! # Query Parsing and Execution
+//! # Query Parsing
 //!
-//! This module is responsible for parsing user-provided query strings into a structured
-//! Abstract Syntax Tree (AST) and providing the building blocks for query execution.
-//! It defines the `QueryNode` enum, which represents the different types of query
-//! operations, such as term searches, phrase searches, and logical combinations.
+//! This module defines the `QueryNode` AST every `LogDB` query method executes, the
+//! `QueryError` returned for a query that cannot be run, and `parse_query`, which
+//! reads the query string syntax into the AST. `QueryNode`'s `Display` implementation
+//! writes it back out in the same syntax.
 
-use crate::config::LogConfig;
+use crate::config::{LogConfig, UnknownFieldMode};
+use crate::ufhg::lightning_hash_str;
+use crate::utils::buggu_hash_set::BugguHashSet;
+use std::cell::Cell;
 
-/// Represents a node in the Abstract Syntax Tree (AST) of a parsed query.
+/// Defines the Abstract Syntax Tree (AST) for a parsed query.
 ///
-/// Each variant of this enum corresponds to a specific type of search operation,
-/// allowing for a structured representation of complex user queries. This AST is
-/// then used by the query execution engine to retrieve matching documents.
+/// This enum represents the structure of a search query, allowing for complex
+/// logical combinations of search terms, phrases, and field-specific filters. It is
+/// the tree every query method executes; `LogDB::parse_only` returns it without
+/// running it.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum QueryNode {
-    /// A basic search for a single term.
+    /// A single search term.
     Term(String),
-    /// A search for an exact sequence of terms.
+    /// An exact phrase search.
     Phrase(String),
-    /// A search for a term within a specific field, such as `level:ERROR`.
+    /// A search for a term within a specific field (e.g., `level:ERROR`).
     FieldTerm(&'static str, String),
-    /// A search for a numeric value within a given range, such as `timestamp:>=12345`.
+    /// A search for a numeric range within a field (e.g., `timestamp:>=12345`). The
+    /// `severity` field ranges over the priorities `LogConfig::log_level_priority`
+    /// gives document levels (e.g., `severity:>=WARN`).
     NumericRange(&'static str, u64, u64),
-    /// A search for content that contains a specific substring.
+    /// A search for a substring within the content of a log entry.
     Contains(String),
-    /// A search using N-grams, which allows for partial word matching.
-    NGram(Vec<String>),
-    /// A search that allows for approximate matching of a term.
+    /// A search for words within an edit distance of a word (e.g., `fuzzy:smith~1`).
     Fuzzy(String, u8),
-    /// A search using regular expressions for advanced pattern matching.
-    Regex(String),
-    /// A logical AND, requiring all sub-queries to match.
+    /// A search for words that sound like a word, having the same Soundex code
+    /// (e.g., `sounds:smith`).
+    Phonetic(String),
+    /// A logical AND operation, requiring all child nodes to match.
     And(Vec<QueryNode>),
-    /// A logical OR, requiring at least one sub-query to match.
+    /// A logical OR operation, requiring at least one child node to match.
     Or(Vec<QueryNode>),
-    /// A logical NOT, excluding documents that match the sub-query.
+    /// A logical NOT operation, excluding documents that match the child node.
     Not(Box<QueryNode>),
+    /// A search for documents that have (`true`) or lack (`false`) a value for a field
+    /// (e.g., `has:service` or `missing:level`).
+    Exists(String, bool),
+    /// Matches every document (`*` or `match_all:`).
+    MatchAll,
+    /// Weights the child's contribution to a ranked query's score (e.g., `error^2`).
+    /// Unranked queries match the child alone.
+    Boost(f32, Box<QueryNode>),
 }
 
-/// Parses a raw query string into a `QueryNode` AST.
+impl QueryNode {
+    /// Returns the lowercase name of the node's type, such as `and` or `term`, as
+    /// shown in the tree rendering.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QueryNode::Term(_) => "term",
+            QueryNode::Phrase(_) => "phrase",
+            QueryNode::FieldTerm(..) => "field",
+            QueryNode::NumericRange(..) => "range",
+            QueryNode::Contains(_) => "contains",
+            QueryNode::Fuzzy(..) => "fuzzy",
+            QueryNode::Phonetic(_) => "phonetic",
+            QueryNode::And(_) => "and",
+            QueryNode::Or(_) => "or",
+            QueryNode::Not(_) => "not",
+            QueryNode::Exists(_, true) => "has",
+            QueryNode::Exists(_, false) => "missing",
+            QueryNode::MatchAll => "match_all",
+            QueryNode::Boost(..) => "boost",
+        }
+    }
+
+    /// Returns the node's children: those of an `And` or `Or`, or the single child of
+    /// a `Not` or `Boost`.
+    pub fn children(&self) -> &[QueryNode] {
+        match self {
+            QueryNode::And(children) | QueryNode::Or(children) => children,
+            QueryNode::Not(child) | QueryNode::Boost(_, child) => std::slice::from_ref(child),
+            _ => &[],
+        }
+    }
+
+    /// Writes the node as an indented tree, a line per node with its type and values
+    /// and its children indented beneath it.
+    fn write_tree(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        write!(f, "{:indent$}{}", "", self.kind(), indent = depth * 2)?;
+        match self {
+            QueryNode::Term(v)
+            | QueryNode::Phrase(v)
+            | QueryNode::Contains(v)
+            | QueryNode::Phonetic(v) => write!(f, " {v:?}")?,
+            QueryNode::FieldTerm(field, v) => write!(f, " {field} = {v:?}")?,
+            QueryNode::Fuzzy(v, distance) => write!(f, " {v:?} ~{distance}")?,
+            QueryNode::NumericRange(field, lo, hi) => write!(f, " {field} {lo}..={hi}")?,
+            QueryNode::Exists(field, _) => write!(f, " {field}")?,
+            QueryNode::Boost(boost, _) => write!(f, " {boost}")?,
+            _ => {}
+        }
+        writeln!(f)?;
+        for child in self.children() {
+            child.write_tree(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for QueryNode {
+    /// Renders the node as a canonical query string, which `parse_query` reads back
+    /// into an equivalent tree. Words and values are quoted where the syntax needs it,
+    /// and an `Or` of values of one field is written as `level:(ERROR|WARN)`. An `Or` of
+    /// anything else, and a negated or boosted group of several words, have no form in
+    /// the string syntax and are written as `(a OR b)`, `NOT (a b)` and `(a b)^2`.
+    ///
+    /// The alternate form, `{:#}`, renders an indented tree instead, a line per node
+    /// giving its type and values, such as `field level = "ERROR"`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            return self.write_tree(f, 0);
+        }
+        match self {
+            QueryNode::Term(w) => write_word(f, w),
+            QueryNode::Phrase(p) => write_quoted(f, p),
+            QueryNode::FieldTerm(field, v) => {
+                write!(f, "{field}:")?;
+                write_value(f, v)
+            }
+            QueryNode::Contains(v) => {
+                f.write_str("contains:")?;
+                write_value(f, v)
+            }
+            QueryNode::Fuzzy(v, distance) => {
+                f.write_str("fuzzy:")?;
+                write_value(f, v)?;
+                write!(f, "~{distance}")
+            }
+            QueryNode::Phonetic(v) => {
+                f.write_str("sounds:")?;
+                write_value(f, v)
+            }
+            QueryNode::NumericRange(field, lo, hi) => match (*lo, *hi) {
+                (lo, hi) if lo > hi => write!(f, "{field}:<0"),
+                (lo, hi) if lo == hi => write!(f, "{field}:{lo}"),
+                (lo, u64::MAX) => write!(f, "{field}:>={lo}"),
+                (0, hi) => write!(f, "{field}:<={hi}"),
+                (lo, hi) => write!(f, "{field}:>={lo} {field}:<={hi}"),
+            },
+            QueryNode::And(children) => write_joined(f, children, " "),
+            QueryNode::Or(children) => match field_alternatives(children) {
+                Some((field, values)) => {
+                    write!(f, "{field}:(")?;
+                    for (i, v) in values.into_iter().enumerate() {
+                        if i > 0 {
+                            f.write_str("|")?;
+                        }
+                        write_value(f, v)?;
+                    }
+                    f.write_str(")")
+                }
+                // OR binds tighter than the AND of adjacent words, so alternatives
+                // that are single words need no parentheses to read back.
+                None if children.iter().all(is_or_operand) => write_joined(f, children, " OR "),
+                None => {
+                    f.write_str("(")?;
+                    write_joined(f, children, " OR ")?;
+                    f.write_str(")")
+                }
+            },
+            QueryNode::Not(child) if is_single_word(child) => write!(f, "-{child}"),
+            QueryNode::Not(child) => write!(f, "NOT ({child})"),
+            QueryNode::Exists(field, present) => {
+                f.write_str(if *present { "has:" } else { "missing:" })?;
+                write_value(f, field)
+            }
+            QueryNode::MatchAll => f.write_str("*"),
+            QueryNode::Boost(boost, child) if is_single_word(child) => write!(f, "{child}^{boost}"),
+            QueryNode::Boost(boost, child) => write!(f, "({child})^{boost}"),
+        }
+    }
+}
+
+/// Writes a search word, quoting it if it would otherwise be read as a field, an
+/// operator, a negation, a boost or `*`.
+fn write_word(f: &mut std::fmt::Formatter<'_>, word: &str) -> std::fmt::Result {
+    let quote = word.is_empty()
+        || word == "*"
+        || (word.len() > 1 && word.starts_with('-'))
+        || matches!(word, "AND" | "OR" | "NOT")
+        || word.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\\' | ':' | '^'));
+    if quote {
+        write_quoted(f, word)
+    } else {
+        f.write_str(word)
+    }
+}
+
+/// Writes a field value, quoting it if it would otherwise be split into alternatives,
+/// read as a boost or end the word.
+fn write_value(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    let quote = value.is_empty()
+        || value.contains(|c: char| {
+            c.is_whitespace() || matches!(c, '"' | '\\' | '(' | ')' | ',' | '|' | '^')
+        });
+    if quote {
+        write_quoted(f, value)
+    } else {
+        f.write_str(value)
+    }
+}
+
+/// Writes text in double quotes, escaping the quotes and backslashes in it.
+fn write_quoted(f: &mut std::fmt::Formatter<'_>, text: &str) -> std::fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        if matches!(c, '"' | '\\') {
+            f.write_str("\\")?;
+        }
+        write!(f, "{c}")?;
+    }
+    f.write_str("\"")
+}
+
+/// Writes each node, separated by `sep`.
+fn write_joined(
+    f: &mut std::fmt::Formatter<'_>,
+    nodes: &[QueryNode],
+    sep: &str,
+) -> std::fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            f.write_str(sep)?;
+        }
+        write!(f, "{node}")?;
+    }
+    Ok(())
+}
+
+/// Returns the field and values of an `Or` whose children are all values of the same
+/// `level`, `service` or `contains` field, which renders as `field:(a|b)`.
+fn field_alternatives(children: &[QueryNode]) -> Option<(&str, Vec<&str>)> {
+    let field = match children.first()? {
+        QueryNode::FieldTerm(field, _) => *field,
+        QueryNode::Contains(_) => "contains",
+        _ => return None,
+    };
+    let values = children
+        .iter()
+        .map(|child| match child {
+            QueryNode::FieldTerm(f, v) if *f == field => Some(v.as_str()),
+            QueryNode::Contains(v) if field == "contains" => Some(v.as_str()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some((field, values))
+}
+
+/// Checks whether a node renders as a single query word, possibly negated, which
+/// the parser reads as one side of an `OR`.
+fn is_or_operand(node: &QueryNode) -> bool {
+    match node {
+        QueryNode::Not(child) => is_single_word(child),
+        _ => is_single_word(node),
+    }
+}
+
+/// Checks whether a node renders as a single query word, which a leading `-` or a
+/// trailing `^N` applies to as a whole.
+fn is_single_word(node: &QueryNode) -> bool {
+    match node {
+        QueryNode::NumericRange(_, lo, hi) => lo >= hi || *lo == 0 || *hi == u64::MAX,
+        QueryNode::And(children) => matches!(children.as_slice(), [only] if is_single_word(only)),
+        QueryNode::Or(children) => field_alternatives(children).is_some(),
+        QueryNode::Not(_) => false,
+        QueryNode::Boost(_, child) => is_single_word(child),
+        _ => true,
+    }
+}
+
+/// The error returned when a query cannot be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// The query string is empty or only whitespace. Use `*` to match every document.
+    Empty,
+    /// A `timestamp:` value is not a valid time expression.
+    InvalidTimestamp(String),
+    /// A `severity:` value is neither a configured log level nor a priority.
+    InvalidSeverity(String),
+    /// The query has more clauses or nests deeper than `LogConfig::max_query_clauses`
+    /// and `LogConfig::max_query_depth` allow.
+    TooComplex {
+        /// The number of clauses in the query.
+        clauses: usize,
+        /// How deeply the query nests.
+        depth: usize,
+    },
+    /// A double quote is never closed. `offset` is its byte offset in the query.
+    UnterminatedQuote {
+        /// The byte offset of the opening quote.
+        offset: usize,
+    },
+    /// The input is not valid JSON.
+    InvalidJson(String),
+    /// A node is malformed. `path` locates it, e.g. `and[1].fied`, and is empty for
+    /// the root node.
+    InvalidNode {
+        /// The path to the malformed node.
+        path: String,
+        /// What is wrong with it.
+        message: String,
+    },
+    /// No query template is registered under this name.
+    UnknownTemplate(String),
+    /// A template placeholder was given no value. Holds its name, without the `$`.
+    MissingParam(String),
+    /// A value was given for a placeholder the template doesn't have.
+    UnknownParam(String),
+    /// A query word names a field the query syntax doesn't know, and
+    /// `LogConfig::unknown_field_mode` is `UnknownFieldMode::Error`. Holds the field.
+    UnknownField(String),
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::Empty => f.write_str("empty query"),
+            QueryError::InvalidTimestamp(val) => write!(f, "invalid timestamp expression {val:?}"),
+            QueryError::InvalidSeverity(val) => write!(f, "invalid severity expression {val:?}"),
+            QueryError::TooComplex { clauses, depth } => write!(
+                f,
+                "query too complex: {clauses} clauses nested {depth} deep"
+            ),
+            QueryError::UnterminatedQuote { offset } => {
+                write!(f, "unterminated quote at byte {offset}")
+            }
+            QueryError::InvalidJson(msg) => write!(f, "invalid JSON query: {msg}"),
+            QueryError::InvalidNode { path, message } if path.is_empty() => f.write_str(message),
+            QueryError::InvalidNode { path, message } => write!(f, "{path}: {message}"),
+            QueryError::UnknownTemplate(name) => write!(f, "unknown query template {name:?}"),
+            QueryError::MissingParam(name) => write!(f, "missing value for ${name}"),
+            QueryError::UnknownParam(name) => write!(f, "template has no placeholder ${name}"),
+            QueryError::UnknownField(name) => write!(f, "unknown query field {name:?}"),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Returns the word to look up if `q` would parse to a lone `Term` or `Contains`
+/// node, or `None` if it needs the full parser.
+pub(crate) fn single_term(q: &str) -> Option<&str> {
+    let q = q.trim();
+    if q.is_empty() || matches!(q, "*" | "AND" | "OR" | "NOT") || q.contains(char::is_whitespace) {
+        return None;
+    }
+    // A negated word must be parsed; a lone `-` is a plain term.
+    if q.len() > 1 && q.starts_with('-') {
+        return None;
+    }
+    // Quotes, escapes and boosts must be decoded by the parser.
+    if q.contains(['"', '\\', '^']) {
+        return None;
+    }
+    if let Some(val) = q.strip_prefix("contains:") {
+        if val.starts_with('(') || val.contains(',') {
+            return None;
+        }
+        return Some(val);
+    }
+    if q.contains(':') {
+        return None;
+    }
+    Some(q)
+}
+
+/// Splits a query string into words at whitespace outside double quotes, so a quoted
+/// value such as `key:"a b"` is a single word. Words are returned as written, quotes
+/// and escapes included, for `unescape` to decode.
 ///
-/// This function tokenizes the input string and constructs a query tree based on
-/// special characters and keywords. It supports field-based searches (e.g., `field:value`),
-/// quoted phrases, and logical operators (AND, OR, NOT).
+/// # Returns
+/// The words, or `QueryError::UnterminatedQuote` with the byte offset of a quote that
+/// is never closed.
+fn query_words(q: &str) -> Result<Vec<&str>, QueryError> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    for (i, c, escaped) in escaped_chars(q) {
+        if c == '"' && !escaped {
+            quote = match quote {
+                Some(_) => None,
+                None => Some(i),
+            };
+        }
+        if c.is_whitespace() && quote.is_none() {
+            if let Some(start) = start.take() {
+                words.push(&q[start..i]);
+            }
+        } else {
+            start.get_or_insert(i);
+        }
+    }
+    if let Some(offset) = quote {
+        return Err(QueryError::UnterminatedQuote { offset });
+    }
+    if let Some(start) = start {
+        words.push(&q[start..]);
+    }
+    Ok(words)
+}
+
+/// Iterates over the characters of a query word with their byte offsets, reading the
+/// escapes `\"`, `\\` and `\:` as the character they escape, flagged as escaped. Any
+/// other backslash is an ordinary character, so a Windows path such as
+/// `C:\logs\app.log` needs no escaping.
+fn escaped_chars(raw: &str) -> impl Iterator<Item = (usize, char, bool)> + '_ {
+    let mut chars = raw.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (i, c) = chars.next()?;
+        if c == '\\' {
+            if let Some(&(_, escaped @ ('"' | '\\' | ':'))) = chars.peek() {
+                chars.next();
+                return Some((i, escaped, true));
+            }
+        }
+        Some((i, c, false))
+    })
+}
+
+/// Splits a query word at its first colon that is neither escaped nor quoted,
+/// returning the decoded field and the value as written.
+fn split_field(tok: &str) -> Option<(String, &str)> {
+    let mut quoted = false;
+    let (idx, ..) = escaped_chars(tok).find(|&(_, c, escaped)| {
+        if c == '"' && !escaped {
+            quoted = !quoted;
+        }
+        c == ':' && !escaped && !quoted
+    })?;
+    Some((unescape(&tok[..idx]), &tok[idx + 1..]))
+}
+
+/// Decodes a query word or value, dropping the double quotes around its quoted parts
+/// and replacing each escape with the character it escapes.
+fn unescape(raw: &str) -> String {
+    escaped_chars(raw)
+        .filter(|&(_, c, escaped)| escaped || c != '"')
+        .map(|(_, c, _)| c)
+        .collect()
+}
+
+/// Splits a trailing boost such as `^2` or `^0.5` off a query word.
 ///
-/// # Arguments
-/// * `q` - The raw query string to parse.
-/// * `config` - The `LogConfig` to use for parsing, which may contain information
-///              about available fields and other settings.
+/// # Returns
+/// The word without its boost, and the boost, or `None` if the word does not end in
+/// `^` and a finite, non-negative number.
+fn split_boost(tok: &str) -> Option<(&str, f32)> {
+    let (inner, boost) = tok.rsplit_once('^')?;
+    let boost = boost.parse::<f32>().ok()?;
+    (!inner.is_empty() && boost.is_finite() && boost >= 0.0).then_some((inner, boost))
+}
+
+/// Builds the node searching for a decoded literal: a phrase if it spans several
+/// words, or a term otherwise.
+pub(crate) fn literal(text: String) -> QueryNode {
+    if text.contains(char::is_whitespace) {
+        QueryNode::Phrase(text)
+    } else {
+        QueryNode::Term(text)
+    }
+}
+
+/// Splits a field value written as `(a|b|c)` or `a,b,c` into its alternatives. A
+/// delimiter inside double quotes is part of the value, so `("pay|ments"|auth)` has
+/// two alternatives.
 ///
 /// # Returns
-/// A `QueryNode` representing the root of the parsed query AST.
-pub fn parse_query(q: &str, config: &LogConfig) -> QueryNode {
-    let mut nodes = Vec::new();
-    let mut it = q.split_whitespace().peekable();
+/// The decoded alternatives, or `None` if the value is a single unparenthesized one.
+fn split_alternatives(val: &str) -> Option<Vec<String>> {
+    let (inner, delim) = match val.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+        Some(inner) => (inner, '|'),
+        None => (val, ','),
+    };
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for (_, c, escaped) in escaped_chars(inner) {
+        match c {
+            _ if escaped => current.push(c),
+            '"' => quoted = !quoted,
+            c if c == delim && !quoted => values.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    values.push(current);
+    values.retain(|v| !v.is_empty());
+    (delim == '|' || values.len() > 1).then_some(values)
+}
+
+/// Builds the node matching one alternative of a multi-valued field such as
+/// `level:(ERROR|WARN)`. Fields without a dedicated index are matched by
+/// `unknown_field_node`, as in `parse_query`.
+fn alternative_node(
+    field: &str,
+    value: String,
+    cx: ParseContext<'_>,
+) -> Result<QueryNode, QueryError> {
+    Ok(match field {
+        "level" => QueryNode::FieldTerm("level", value),
+        "service" => QueryNode::FieldTerm("service", value),
+        "contains" => QueryNode::Contains(value),
+        "fuzzy" => fuzzy_node(value),
+        "sounds" => QueryNode::Phonetic(value),
+        _ => return unknown_field_node(field, value, cx.unknown_fields),
+    })
+}
+
+/// Builds the node matching a `field:value` word whose field the query syntax doesn't
+/// know, as `mode` says.
+fn unknown_field_node(
+    field: &str,
+    value: String,
+    mode: UnknownFieldMode,
+) -> Result<QueryNode, QueryError> {
+    match mode {
+        UnknownFieldMode::Error => Err(QueryError::UnknownField(field.to_string())),
+        UnknownFieldMode::AsExtractedField => Ok(literal(format!("{field}:{value}"))),
+        UnknownFieldMode::AsText if value.is_empty() => Ok(literal(field.to_string())),
+        UnknownFieldMode::AsText => Ok(QueryNode::And(vec![
+            literal(field.to_string()),
+            literal(value),
+        ])),
+    }
+}
+
+/// The settings `parse_query` passes down to the words it parses.
+#[derive(Clone, Copy)]
+struct ParseContext<'a> {
+    /// The clock `now` is read from in `timestamp:` values.
+    clock: fn() -> u64,
+    /// How words with unknown fields are matched.
+    unknown_fields: UnknownFieldMode,
+    /// The priority of each configured log level, by level hash, for `severity:`
+    /// values.
+    log_levels: &'a BugguHashSet<u64, u8>,
+    /// Set once a `timestamp:` value is parsed relative to `now`.
+    relative: &'a Cell<bool>,
+}
+
+/// Builds the node of a `fuzzy:` value, a word with an optional `~N` suffix giving
+/// the most edits a matching word may be from it. Without a suffix, one edit is
+/// allowed.
+fn fuzzy_node(value: String) -> QueryNode {
+    match value.rsplit_once('~') {
+        Some((word, distance)) => match distance.parse::<u8>() {
+            Ok(distance) => QueryNode::Fuzzy(word.to_string(), distance),
+            Err(_) => QueryNode::Fuzzy(value, 1),
+        },
+        None => QueryNode::Fuzzy(value, 1),
+    }
+}
+
+/// Parses a query string into a `QueryNode` AST.
+///
+/// Only the known fields (`level`, `service`, `contains`, `fuzzy`, `sounds`, `has`,
+/// `missing`, `_exists_` and `timestamp`) are treated specially; any other
+/// `key:value` word, such as `status:500` or `http://example.com`, is matched as
+/// `LogConfig::unknown_field_mode` says, by default as a literal term. A colon
+/// escaped as `\:` never starts a field, so `level\:ERROR` searches for the literal
+/// word `level:ERROR`. A quoted word is a literal term, and several quoted words form
+/// a phrase; inside quotes, colons and whitespace are literal and `\"` and `\\` escape
+/// a quote and a backslash. A bare `*` or `match_all:` matches every document, and
+/// `"*"` searches for a literal asterisk. A field given several values, as
+/// `level:(ERROR|WARN)` or `level:ERROR,WARN`, expands to an `Or` of one node per
+/// value. A leading `-` negates a word, so `error -service:payment` excludes that
+/// service, while `us-east-1` and a lone `-` are plain terms. `has:service` (or
+/// `_exists_:service`) matches documents with a value for a field and
+/// `missing:service` those without one. A trailing `^N` boosts a word for
+/// `LogDB::query_ranked` and is ignored otherwise. `fuzzy:smith` matches words one
+/// edit from `smith`, such as `smyth`, and `fuzzy:smith~2` those up to two edits away.
+/// `sounds:smith` matches words with the same Soundex code, given
+/// `LogConfig::enable_phonetic`.
+/// A `timestamp:` value is parsed by `parse_timestamp_range`, which reads `now` from
+/// `clock`.
+///
+/// Words are joined by AND, which may also be written as `AND`. `a OR b` matches
+/// either word and binds tighter than AND, so `level:ERROR db OR cache` requires an
+/// error mentioning either; `a OR b OR c` is a single `Or`. `NOT word` negates the word
+/// like `-word`. Only these uppercase spellings are operators; lowercase `and`, `or`
+/// and `not` and quoted `"AND"` are searched as words, and an operator with nothing to
+/// apply to is ignored.
+///
+/// # Returns
+/// `QueryError::UnterminatedQuote` if a double quote is never closed,
+/// `QueryError::InvalidTimestamp` if a `timestamp:` value is not a valid time
+/// expression, or `QueryError::UnknownField` for an unknown field when
+/// `unknown_field_mode` is `UnknownFieldMode::Error`.
+pub(crate) fn parse_query(
+    q: &str,
+    config: &LogConfig,
+    clock: fn() -> u64,
+) -> Result<QueryNode, QueryError> {
+    parse_query_relative(q, config, clock).map(|(ast, _)| ast)
+}
+
+/// Parses a query like `parse_query`, also returning whether a `timestamp:` value
+/// was resolved relative to `now`, in which case parsing it again later can give a
+/// different AST.
+pub(crate) fn parse_query_relative(
+    q: &str,
+    config: &LogConfig,
+    clock: fn() -> u64,
+) -> Result<(QueryNode, bool), QueryError> {
+    let relative = Cell::new(false);
+    let cx = ParseContext {
+        clock,
+        unknown_fields: config.unknown_field_mode,
+        log_levels: &config.log_levels,
+        relative: &relative,
+    };
+    let mut nodes = Vec::<QueryNode>::new();
+    let mut it = query_words(q)?.into_iter();
 
     while let Some(tok) = it.next() {
-        if tok.contains(':') {
-            let mut sp = tok.splitn(2, ':');
-            let field = sp.next().unwrap();
-            let mut val = sp.next().unwrap().to_string();
-
-            // Handle quoted values that may contain spaces.
-            if val.starts_with('"') && !val.ends_with('"') {
-                for nxt in it.by_ref() {
-                    val.push(' ');
-                    val.push_str(nxt);
-                    if nxt.ends_with('"') {
-                        break;
+        match tok {
+            "AND" => {}
+            "OR" => {
+                let Some(right) = parse_operand(it.next(), &mut it, cx)? else {
+                    continue;
+                };
+                match nodes.pop() {
+                    Some(QueryNode::Or(mut alts)) => {
+                        alts.push(right);
+                        nodes.push(QueryNode::Or(alts));
                     }
+                    Some(left) => nodes.push(QueryNode::Or(vec![left, right])),
+                    None => nodes.push(right),
                 }
-                val = val.trim_matches('"').to_string();
-            } else {
-                val = val.trim_matches('"').to_string();
             }
+            _ => nodes.extend(parse_operand(Some(tok), &mut it, cx)?),
+        }
+    }
 
-            match field {
-                "level" => nodes.push(QueryNode::FieldTerm("level", val)),
-                "service" => nodes.push(QueryNode::FieldTerm("service", val)),
-                "contains" => nodes.push(QueryNode::Contains(val)),
-                "phrase" => nodes.push(QueryNode::Phrase(val)),
-                "fuzzy" => {
-                    if let Some((word, dist)) = val.split_once('~') {
-                        let distance = dist.parse::<u8>().unwrap_or(1);
-                        nodes.push(QueryNode::Fuzzy(word.to_string(), distance));
-                    } else {
-                        nodes.push(QueryNode::Fuzzy(val, 1));
-                    }
-                }
-                "regex" => nodes.push(QueryNode::Regex(val)),
-                "timestamp" => {
-                    if let Some(lo) = val.strip_prefix(">=") {
-                        let lo = lo.parse::<u64>().unwrap_or(0);
-                        nodes.push(QueryNode::NumericRange("timestamp", lo, u64::MAX));
-                    } else if let Some(hi) = val.strip_prefix("<=") {
-                        let hi = hi.parse::<u64>().unwrap_or(u64::MAX);
-                        nodes.push(QueryNode::NumericRange("timestamp", 0, hi));
-                    } else {
-                        let ts = val.parse::<u64>().unwrap_or(0);
-                        nodes.push(QueryNode::NumericRange("timestamp", ts, ts));
-                    }
+    let ast = if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        QueryNode::And(nodes)
+    };
+    Ok((ast, relative.get()))
+}
+
+/// Parses an operand of `parse_query`: a word, negated by a leading `-` or preceded by
+/// any number of `NOT`s.
+///
+/// # Returns
+/// The operand's node, or `None` when `tok` is missing or is a `NOT` with nothing
+/// after it.
+fn parse_operand<'a, I: Iterator<Item = &'a str>>(
+    tok: Option<&str>,
+    it: &mut I,
+    cx: ParseContext<'_>,
+) -> Result<Option<QueryNode>, QueryError> {
+    let Some(tok) = tok else {
+        return Ok(None);
+    };
+    if tok == "NOT" {
+        let operand = parse_operand(it.next(), it, cx)?;
+        return Ok(operand.map(|node| QueryNode::Not(Box::new(node))));
+    }
+    let node = match tok.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() => QueryNode::Not(Box::new(parse_word(rest, it, cx)?)),
+        _ => parse_word(tok, it, cx)?,
+    };
+    Ok(Some(node))
+}
+
+/// Parses a single query word, consuming the following words from `it` when a
+/// parenthesized value spans several of them.
+///
+/// # Returns
+/// The word's node, `QueryError::InvalidTimestamp` for a `timestamp:` word whose
+/// value is not a valid time expression, `QueryError::InvalidSeverity` for a
+/// `severity:` word whose value is not a level or priority, or
+/// `QueryError::UnknownField` for a field rejected by `cx`.
+fn parse_word<'a, I: Iterator<Item = &'a str>>(
+    tok: &str,
+    it: &mut I,
+    cx: ParseContext<'_>,
+) -> Result<QueryNode, QueryError> {
+    if let Some((inner, boost)) = split_boost(tok) {
+        let node = parse_word(inner, it, cx)?;
+        return Ok(QueryNode::Boost(boost, Box::new(node)));
+    }
+    if tok.starts_with('"') {
+        Ok(literal(unescape(tok)))
+    } else if let Some((field, val)) = split_field(tok) {
+        let mut val = val.to_string();
+        if val.starts_with('(') && !val.ends_with(')') {
+            for nxt in it.by_ref() {
+                val.push(' ');
+                val.push_str(nxt);
+                if nxt.ends_with(')') {
+                    break;
                 }
-                _ => nodes.push(QueryNode::FieldTerm("unknown", val)),
             }
-        } else if tok.starts_with('"') {
-            let phrase = tok.trim_matches('"').to_string();
-            nodes.push(QueryNode::Phrase(phrase));
-        } else {
-            // Handle logical operators.
-            match tok.to_uppercase().as_str() {
-                "AND" => continue, // AND is the default operator.
-                "OR" => {
-                    // Combine the last node with the next node in an OR expression.
-                    if let Some(last) = nodes.pop() {
-                        if let Some(next_tok) = it.next() {
-                            let next_node = if next_tok.contains(':') {
-                                // Simplified parsing for the next term in an OR clause.
-                                QueryNode::Term(next_tok.to_string())
-                            } else {
-                                QueryNode::Term(next_tok.to_string())
-                            };
-                            nodes.push(QueryNode::Or(vec![last, next_node]));
-                        }
-                    }
-                }
-                "NOT" => {
-                    // Create a NOT node for the next term.
-                    if let Some(next_tok) = it.next() {
-                        let next_node = QueryNode::Term(next_tok.to_string());
-                        nodes.push(QueryNode::Not(Box::new(next_node)));
-                    }
+        }
+
+        let alternatives = match field.as_str() {
+            "has" | "missing" | "_exists_" | "match_all" | "timestamp" | "severity" => None,
+            _ => split_alternatives(&val),
+        };
+        if let Some(values) = alternatives {
+            let mut alts = values
+                .into_iter()
+                .map(|v| alternative_node(&field, v, cx))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(if alts.len() == 1 {
+                alts.pop().unwrap()
+            } else {
+                QueryNode::Or(alts)
+            });
+        }
+        let val = unescape(&val);
+
+        match field.as_str() {
+            "level" => Ok(QueryNode::FieldTerm("level", val)),
+            "service" => Ok(QueryNode::FieldTerm("service", val)),
+            "contains" => Ok(QueryNode::Contains(val)),
+            "fuzzy" => Ok(fuzzy_node(val)),
+            "sounds" => Ok(QueryNode::Phonetic(val)),
+            "has" | "_exists_" => Ok(QueryNode::Exists(val, true)),
+            "missing" => Ok(QueryNode::Exists(val, false)),
+            "match_all" if val.is_empty() => Ok(QueryNode::MatchAll),
+            "timestamp" => {
+                if split_comparator(&val).1.starts_with("now") {
+                    cx.relative.set(true);
                 }
-                _ => nodes.push(QueryNode::Term(tok.to_string())),
+                let (lo, hi) = parse_timestamp_range(&val, cx.clock)?;
+                Ok(QueryNode::NumericRange("timestamp", lo, hi))
+            }
+            "severity" => {
+                let (lo, hi) = parse_severity_range(&val, cx.log_levels)?;
+                Ok(QueryNode::NumericRange("severity", lo, hi))
             }
+            _ => unknown_field_node(&field, val, cx.unknown_fields),
         }
+    } else if tok == "*" {
+        Ok(QueryNode::MatchAll)
+    } else {
+        Ok(literal(unescape(tok)))
     }
+}
 
-    // Combine multiple nodes with a default AND operator.
-    if nodes.len() == 1 {
-        nodes.pop().unwrap()
-    } else if nodes.is_empty() {
-        QueryNode::Term("".to_string()) // Return an empty term if the query is empty.
-    } else {
-        QueryNode::And(nodes)
+/// Parses the value of a `timestamp:` query word into an inclusive range of epoch
+/// seconds.
+///
+/// The value is an optional comparator (`>=`, `<=`, `>` or `<`, or none for an exact
+/// match) followed by a time: epoch seconds, an ISO-8601 UTC date or time such as
+/// `2024-03-01` or `2024-03-01T00:00:00Z`, or `now` with an optional offset such as
+/// `now-15m`, in `s`, `m`, `h`, `d` or `w`. `now` is read from `clock`.
+///
+/// # Returns
+/// `QueryError::InvalidTimestamp` if the value is not of this form.
+fn parse_timestamp_range(val: &str, clock: fn() -> u64) -> Result<(u64, u64), QueryError> {
+    let invalid = || QueryError::InvalidTimestamp(val.to_string());
+    let (cmp, time) = split_comparator(val);
+    let t = parse_time(time, clock).ok_or_else(invalid)?;
+    comparator_range(cmp, t).ok_or_else(invalid)
+}
+
+/// Parses the value of a `severity:` query word into an inclusive range of level
+/// priorities.
+///
+/// The value is an optional comparator, as for `timestamp:`, followed by a log level
+/// configured in `log_levels`, such as `WARN`, matched regardless of case, or a
+/// priority such as `3`.
+///
+/// # Returns
+/// `QueryError::InvalidSeverity` if the value is not of this form.
+fn parse_severity_range(
+    val: &str,
+    log_levels: &BugguHashSet<u64, u8>,
+) -> Result<(u64, u64), QueryError> {
+    let invalid = || QueryError::InvalidSeverity(val.to_string());
+    let (cmp, level) = split_comparator(val);
+    let priority = match digits(level) {
+        Some(priority) => priority,
+        None if level.is_empty() => return Err(invalid()),
+        None => log_levels
+            .get(&lightning_hash_str(level))
+            .map(|&priority| u64::from(priority))
+            .ok_or_else(invalid)?,
+    };
+    comparator_range(cmp, priority).ok_or_else(invalid)
+}
+
+/// Splits the comparator, `>=`, `<=`, `>` or `<`, off the front of a range value,
+/// returning an empty comparator for an exact match.
+fn split_comparator(val: &str) -> (&str, &str) {
+    [">=", "<=", ">", "<"]
+        .into_iter()
+        .find_map(|cmp| val.strip_prefix(cmp).map(|rest| (cmp, rest)))
+        .unwrap_or(("", val))
+}
+
+/// Returns the inclusive range of values a comparator matches against `n`, or `None`
+/// for `>` the largest value.
+fn comparator_range(cmp: &str, n: u64) -> Option<(u64, u64)> {
+    Some(match cmp {
+        ">=" => (n, u64::MAX),
+        "<=" => (0, n),
+        ">" => (n.checked_add(1)?, u64::MAX),
+        // Nothing is below zero, so `<0` is an empty range.
+        "<" => n.checked_sub(1).map_or((1, 0), |hi| (0, hi)),
+        _ => (n, n),
+    })
+}
+
+/// Parses a time as described on `parse_timestamp_range`.
+fn parse_time(time: &str, clock: fn() -> u64) -> Option<u64> {
+    if let Some(offset) = time.strip_prefix("now") {
+        let now = clock();
+        if offset.is_empty() {
+            return Some(now);
+        }
+        if let Some(ago) = offset.strip_prefix('-') {
+            return parse_duration(ago).map(|secs| now.saturating_sub(secs));
+        }
+        return parse_duration(offset.strip_prefix('+')?).map(|secs| now.saturating_add(secs));
+    }
+    if let Some(secs) = digits(time) {
+        return Some(secs);
+    }
+    parse_iso8601(time)
+}
+
+/// Parses a duration such as `15m` into seconds.
+fn parse_duration(duration: &str) -> Option<u64> {
+    let unit = match duration.bytes().last()? {
+        b's' => 1,
+        b'm' => 60,
+        b'h' => 60 * 60,
+        b'd' => 24 * 60 * 60,
+        b'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    digits(&duration[..duration.len() - 1])?.checked_mul(unit)
+}
+
+/// Parses an ISO-8601 UTC date, `YYYY-MM-DD`, or time, `YYYY-MM-DDTHH:MM:SSZ`, into
+/// epoch seconds.
+pub(crate) fn parse_iso8601(time: &str) -> Option<u64> {
+    let (date, clock_time) = match time.split_once('T') {
+        Some((date, clock_time)) => (date, Some(clock_time.strip_suffix('Z')?)),
+        None => (time, None),
+    };
+    let fixed = |s: &str, len: usize| digits(s).filter(|_| s.len() == len);
+
+    let mut parts = date.split('-');
+    let year = fixed(parts.next()?, 4)?;
+    let month = fixed(parts.next()?, 2)?;
+    let day = fixed(parts.next()?, 2)?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_len = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if parts.next().is_some() || year < 1970 || !(1..=12).contains(&month) {
+        return None;
+    }
+    if !(1..=month_len).contains(&day) {
+        return None;
+    }
+
+    let mut secs_of_day = 0;
+    if let Some(clock_time) = clock_time {
+        let mut parts = clock_time.split(':');
+        let hour = fixed(parts.next()?, 2).filter(|&h| h < 24)?;
+        let minute = fixed(parts.next()?, 2).filter(|&m| m < 60)?;
+        let second = fixed(parts.next()?, 2).filter(|&s| s < 60)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        secs_of_day = hour * 3600 + minute * 60 + second;
+    }
+
+    // Days since the epoch, counting years from March so the leap day comes last.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    Some(days * 86_400 + secs_of_day)
+}
+
+/// Parses a non-empty string of ASCII digits.
+fn digits(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relative(q: &str) -> bool {
+        parse_query_relative(q, &LogConfig::default(), || 1_000_000)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn only_timestamps_from_now_are_relative() {
+        assert!(relative("timestamp:>=now-15m"));
+        assert!(relative("error timestamp:now"));
+        assert!(!relative("timestamp:>=1700000000"));
+        assert!(!relative("timestamp:2024-03-01"));
+        assert!(!relative("unknown snow known"));
+        assert!(!relative("contains:now level:ERROR"));
+    }
+
+    #[test]
+    fn relative_timestamp_reads_the_clock() {
+        let (ast, _) =
+            parse_query_relative("timestamp:>=now-1m", &LogConfig::default(), || 1_000).unwrap();
+        assert!(matches!(
+            ast,
+            QueryNode::NumericRange("timestamp", 940, u64::MAX)
+        ));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn single_term_agrees_with_the_parser() {
+        let parse = |q: &str| parse_query(q, &LogConfig::default(), || 0).unwrap();
+        for q in [
+            "ERROR",
+            "  timeout ",
+            "-",
+            "user42",
+            "contains:fail",
+            "a-b",
+            "x.y/z",
+        ] {
+            let word = single_term(q).unwrap_or_else(|| panic!("{q:?} needs the parser"));
+            match parse(q) {
+                QueryNode::Term(w) | QueryNode::Contains(w) => assert_eq!(w, word, "{q:?}"),
+                other => panic!("{q:?} parsed to {other}"),
+            }
+        }
+        for q in [
+            "",
+            "*",
+            "AND",
+            "two words",
+            "-negated",
+            "\"quoted\"",
+            "esc\\:aped",
+            "boost^2",
+            "level:ERROR",
+            "contains:(a|b)",
+            "contains:a,b",
+        ] {
+            assert_eq!(single_term(q), None, "{q:?}");
+        }
+    }
+
+    #[test]
+    fn colons_outside_known_fields_stay_in_the_term() {
+        let parse = |q: &str| parse_query(q, &LogConfig::default(), || 0).unwrap();
+        assert!(
+            matches!(parse("http://example.com"), QueryNode::Term(t) if t == "http://example.com")
+        );
+        assert!(matches!(parse("12:30:05"), QueryNode::Term(t) if t == "12:30:05"));
+        assert!(matches!(parse("level:ERROR"), QueryNode::FieldTerm("level", v) if v == "ERROR"));
+        match parse("level:ERROR http://example.com") {
+            QueryNode::And(children) => assert!(matches!(
+                children.as_slice(),
+                [QueryNode::FieldTerm("level", _), QueryNode::Term(t)] if t == "http://example.com"
+            )),
+            other => panic!("parsed to {other}"),
+        }
+    }
+
+    #[test]
+    fn multi_value_fields_expand_to_or() {
+        let parse = |q: &str| format!("{:?}", parse_query(q, &LogConfig::default(), || 0).unwrap());
+        assert_eq!(
+            parse("level:(ERROR|WARN)"),
+            r#"Or([FieldTerm("level", "ERROR"), FieldTerm("level", "WARN")])"#
+        );
+        assert_eq!(
+            parse("service:(auth|api|db)"),
+            r#"Or([FieldTerm("service", "auth"), FieldTerm("service", "api"), FieldTerm("service", "db")])"#
+        );
+        assert_eq!(
+            parse(r#"service:(auth|"a|b")"#),
+            r#"Or([FieldTerm("service", "auth"), FieldTerm("service", "a|b")])"#
+        );
+        assert_eq!(
+            parse("NOT level:(DEBUG|TRACE) disk"),
+            r#"And([Not(Or([FieldTerm("level", "DEBUG"), FieldTerm("level", "TRACE")])), Term("disk")])"#
+        );
+        assert_eq!(
+            parse("-level:(DEBUG|TRACE) disk"),
+            parse("NOT level:(DEBUG|TRACE) disk")
+        );
+        assert_eq!(parse("level:(ERROR)"), r#"FieldTerm("level", "ERROR")"#);
+    }
+
+    #[test]
+    fn escaped_values_parse_to_single_words() {
+        let parse = |q: &str| format!("{:?}", parse_query(q, &LogConfig::default(), || 0).unwrap());
+        assert_eq!(
+            parse("url:https://example.com:8443/x"),
+            r#"Term("url:https://example.com:8443/x")"#
+        );
+        // Windows paths, with escaped backslashes, bare or quoted.
+        assert_eq!(
+            parse(r"C:\\Windows\\System32"),
+            r#"Term("C:\\Windows\\System32")"#
+        );
+        assert_eq!(parse(r"path:C\:\\Windows"), r#"Term("path:C:\\Windows")"#);
+        assert_eq!(
+            parse(r#""C:\\Program Files\\app""#),
+            r#"Phrase("C:\\Program Files\\app")"#
+        );
+        // JSON snippets, with escaped quotes.
+        assert_eq!(
+            parse(r#"body:"{\"code\":500}""#),
+            r#"Term("body:{\"code\":500}")"#
+        );
+        assert_eq!(
+            parse(r#"contains:"say \"hi\"""#),
+            r#"Contains("say \"hi\"")"#
+        );
+        assert_eq!(
+            parse(r"service:my\:svc"),
+            r#"FieldTerm("service", "my:svc")"#
+        );
+    }
+
+    #[test]
+    fn unterminated_quotes_report_their_offset() {
+        let parse = |q: &str| parse_query(q, &LogConfig::default(), || 0);
+        assert_eq!(
+            parse(r#"level:ERROR "disk full"#).unwrap_err(),
+            QueryError::UnterminatedQuote { offset: 12 }
+        );
+        assert_eq!(
+            parse(r#"a "b"#).unwrap_err(),
+            QueryError::UnterminatedQuote { offset: 2 }
+        );
+        assert!(parse(r#"a \"b"#).is_ok());
+    }
+
+    #[test]
+    fn display_reparses_to_the_same_tree() {
+        let parse = |q: &str| parse_query(q, &LogConfig::default(), || 0).unwrap();
+        for q in [
+            "timeout",
+            "level:ERROR service:auth",
+            r#""connection refused" -retry"#,
+            "payment OR server",
+            "level:(ERROR|WARN) contains:disk",
+            r#"service:"a|b" "AND" "-x""#,
+            r"path:C\:\\Windows url:https://example.com:8443/x",
+            "NOT level:(DEBUG|TRACE)",
+            "fuzzy:smith~2 sounds:rupert",
+            "has:service missing:level",
+            "timestamp:>=100 timestamp:<=200 severity:>=WARN",
+            "alpha^2 OR beta",
+            "* -level:INFO",
+        ] {
+            let ast = parse(q);
+            let rendered = ast.to_string();
+            assert_eq!(
+                format!("{:?}", parse(&rendered)),
+                format!("{ast:?}"),
+                "{q:?} rendered as {rendered:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! # Query Cache
+//!
+//! This module defines `QueryCache`, the bounded cache of parsed queries kept by
+//! `LogDB`. Dashboards re-run the same query strings over and over, and parsing is
+//! pure, so each string is parsed once and its AST reused until it falls out of the
+//! cache. Like the metrics, the cache lives behind `RefCell`s so `query` can keep
+//! taking `&self`.
+
+use crate::query::QueryNode;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// A parsed query and when it was last used.
+#[derive(Debug, Clone)]
+struct CachedQuery {
+    ast: QueryNode,
+    last_used: u64,
+}
+
+/// A least-recently-used cache of parsed queries, keyed by query string.
+///
+/// Once full, inserting a query evicts the one used longest ago. A capacity of 0
+/// disables the cache.
+#[derive(Debug, Clone, Default)]
+pub struct QueryCache {
+    /// The cached queries, by query string.
+    entries: RefCell<HashMap<String, CachedQuery>>,
+    /// Counts lookups and inserts, to order the entries by use.
+    clock: Cell<u64>,
+    /// The most queries kept.
+    capacity: usize,
+}
+
+impl QueryCache {
+    /// Creates a cache keeping up to `capacity` parsed queries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: RefCell::new(HashMap::new()),
+            clock: Cell::new(0),
+            capacity,
+        }
+    }
+
+    /// Returns a copy of the cached AST of a query, marking it as recently used.
+    #[inline]
+    pub fn get(&self, query: &str) -> Option<QueryNode> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut entries = self.entries.borrow_mut();
+        let entry = entries.get_mut(query)?;
+        entry.last_used = self.tick();
+        Some(entry.ast.clone())
+    }
+
+    /// Caches the AST of a query, evicting the least recently used query if the
+    /// cache is full.
+    pub fn insert(&self, query: &str, ast: &QueryNode) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.borrow_mut();
+        if entries.len() >= self.capacity && !entries.contains_key(query) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(query, _)| query.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let last_used = self.tick();
+        entries.insert(
+            query.to_owned(),
+            CachedQuery {
+                ast: ast.clone(),
+                last_used,
+            },
+        );
+    }
+
+    /// Drops every cached query.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    /// Returns the number of cached queries.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// Returns `true` if no query is cached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Advances the use clock and returns its new value.
+    fn tick(&self) -> u64 {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+        now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::literal;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = QueryCache::new(2);
+        cache.insert("a", &literal("a".to_string()));
+        cache.insert("b", &literal("b".to_string()));
+        assert!(cache.get("a").is_some());
+        cache.insert("c", &literal("c".to_string()));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn zero_capacity_disables_cache() {
+        let cache = QueryCache::new(0);
+        cache.insert("a", &literal("a".to_string()));
+        assert!(cache.is_empty());
+        assert!(cache.get("a").is_none());
+    }
+}
@@ -0,0 +1,321 @@
+//! # Query DSL
+//!
+//! This module defines `QuerySpec`, a structured form of the query language that can be
+//! built as a JSON value instead of a query string, so clients never have to get the
+//! quoting and escaping of the string syntax right. Each node is an object with a single
+//! key naming its type:
+//!
+//! | Node | Example | String equivalent |
+//! |------|---------|-------------------|
+//! | `term` | `{"term": "timeout"}` | `timeout` |
+//! | `phrase` | `{"phrase": "connection failed"}` | `"connection failed"` |
+//! | `field` | `{"field": {"level": "ERROR"}}` | `level:ERROR` |
+//! | `contains` | `{"contains": "database"}` | `contains:database` |
+//! | `fuzzy` | `{"fuzzy": "smith"}` or `{"fuzzy": {"smith": 2}}` | `fuzzy:smith` or `fuzzy:smith~2` |
+//! | `sounds` | `{"sounds": "smith"}` | `sounds:smith` |
+//! | `exists` | `{"exists": "service"}` | `has:service` |
+//! | `missing` | `{"missing": "service"}` | `missing:service` |
+//! | `range` | `{"range": {"timestamp": {"gte": 1700000000}}}` or `{"range": {"severity": {"gte": 3}}}` | `timestamp:>=1700000000` or `severity:>=3` |
+//! | `and` / `or` | `{"and": [{"term": "a"}, {"term": "b"}]}` | `a b` |
+//! | `not` | `{"not": {"term": "retry"}}` | `NOT retry` |
+//! | `match_all` | `{"match_all": {}}` | `*` |
+//!
+//! A malformed node is reported with the path to it, e.g. `and[1].fied: unknown node`.
+
+pub use crate::query::QueryError;
+use crate::query::QueryNode;
+use serde::de::{Deserialize, Deserializer, Error as _};
+use serde_json::{Map, Value};
+
+/// A query in the structured JSON form, ready to be executed by `LogDB::query_spec`.
+#[derive(Debug, Clone)]
+pub struct QuerySpec(QueryNode);
+
+impl QuerySpec {
+    /// Parses a structured query from a JSON string.
+    pub fn from_json(json: &str) -> Result<Self, QueryError> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| QueryError::InvalidJson(e.to_string()))?;
+        Self::from_value(&value)
+    }
+
+    /// Parses a structured query from an already decoded JSON value.
+    pub fn from_value(value: &Value) -> Result<Self, QueryError> {
+        parse_node(value, "").map(QuerySpec)
+    }
+
+    /// Returns the query as an AST.
+    pub fn node(&self) -> &QueryNode {
+        &self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for QuerySpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        QuerySpec::from_value(&value).map_err(D::Error::custom)
+    }
+}
+
+/// Builds an `InvalidNode` error.
+fn invalid(path: &str, message: impl Into<String>) -> QueryError {
+    QueryError::InvalidNode {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+/// Appends a key to a node path.
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Returns the single key and value of a node object.
+fn single_entry<'a>(value: &'a Value, path: &str) -> Result<(&'a str, &'a Value), QueryError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| invalid(path, "expected an object"))?;
+    let mut entries = object.iter();
+    match (entries.next(), entries.next()) {
+        (Some((key, value)), None) => Ok((key, value)),
+        _ => Err(invalid(path, "expected an object with exactly one key")),
+    }
+}
+
+/// Returns a node's string argument.
+fn string_arg(value: &Value, path: &str) -> Result<String, QueryError> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| invalid(path, "expected a string"))
+}
+
+/// Parses one node and its children.
+fn parse_node(value: &Value, path: &str) -> Result<QueryNode, QueryError> {
+    let (kind, arg) = single_entry(value, path)?;
+    let path = join(path, kind);
+    match kind {
+        "term" => Ok(QueryNode::Term(string_arg(arg, &path)?)),
+        "phrase" => {
+            // A one-word phrase is a plain term, as with a quoted word in the string
+            // syntax.
+            let phrase = string_arg(arg, &path)?;
+            if phrase.contains(char::is_whitespace) {
+                Ok(QueryNode::Phrase(phrase))
+            } else {
+                Ok(QueryNode::Term(phrase))
+            }
+        }
+        "contains" => Ok(QueryNode::Contains(string_arg(arg, &path)?)),
+        "exists" => Ok(QueryNode::Exists(string_arg(arg, &path)?, true)),
+        "missing" => Ok(QueryNode::Exists(string_arg(arg, &path)?, false)),
+        "fuzzy" => parse_fuzzy(arg, &path),
+        "sounds" => Ok(QueryNode::Phonetic(string_arg(arg, &path)?)),
+        "field" => parse_field(arg, &path),
+        "range" => parse_range(arg, &path),
+        "and" | "or" => {
+            let children = arg
+                .as_array()
+                .ok_or_else(|| invalid(&path, "expected an array"))?
+                .iter()
+                .enumerate()
+                .map(|(i, child)| parse_node(child, &format!("{path}[{i}]")))
+                .collect::<Result<Vec<_>, _>>()?;
+            if kind == "and" {
+                Ok(QueryNode::And(children))
+            } else {
+                Ok(QueryNode::Or(children))
+            }
+        }
+        "not" => Ok(QueryNode::Not(Box::new(parse_node(arg, &path)?))),
+        "match_all" if arg.as_object().is_some_and(Map::is_empty) => Ok(QueryNode::MatchAll),
+        "match_all" => Err(invalid(&path, "expected an empty object")),
+        _ => Err(invalid(&path, "unknown node")),
+    }
+}
+
+/// Parses the argument of a `field` node. Fields other than `level` and `service` are
+/// searched as literal `key:value` words, as in the string syntax.
+fn parse_field(arg: &Value, path: &str) -> Result<QueryNode, QueryError> {
+    let (field, value) = single_entry(arg, path)?;
+    let value_path = join(path, field);
+    let value = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => return Err(invalid(&value_path, "expected a string, number or boolean")),
+    };
+    Ok(match field {
+        "level" => QueryNode::FieldTerm("level", value),
+        "service" => QueryNode::FieldTerm("service", value),
+        _ => QueryNode::Term(format!("{field}:{value}")),
+    })
+}
+
+/// Parses the argument of a `fuzzy` node: a word, allowed one edit, or an object
+/// giving the word and the most edits allowed, e.g. `{"smith": 2}`.
+fn parse_fuzzy(arg: &Value, path: &str) -> Result<QueryNode, QueryError> {
+    if let Some(word) = arg.as_str() {
+        return Ok(QueryNode::Fuzzy(word.to_string(), 1));
+    }
+    if !arg.is_object() {
+        return Err(invalid(path, "expected a string or an object"));
+    }
+    let (word, distance) = single_entry(arg, path)?;
+    let distance = distance
+        .as_u64()
+        .and_then(|d| u8::try_from(d).ok())
+        .ok_or_else(|| invalid(&join(path, word), "expected an edit distance up to 255"))?;
+    Ok(QueryNode::Fuzzy(word.to_string(), distance))
+}
+
+/// Parses the argument of a `range` node, e.g. `{"timestamp": {"gte": 1, "lte": 2}}`
+/// or `{"severity": {"gte": 3}}`.
+fn parse_range(arg: &Value, path: &str) -> Result<QueryNode, QueryError> {
+    let (field, bounds) = single_entry(arg, path)?;
+    let bounds_path = join(path, field);
+    let field = match field {
+        "timestamp" => "timestamp",
+        "severity" => "severity",
+        _ => return Err(invalid(&bounds_path, "unknown range field")),
+    };
+    let bounds: &Map<String, Value> = bounds
+        .as_object()
+        .ok_or_else(|| invalid(&bounds_path, "expected an object"))?;
+    let mut lo = 0;
+    let mut hi = u64::MAX;
+    for (bound, value) in bounds {
+        let bound_path = join(&bounds_path, bound);
+        let value = value
+            .as_u64()
+            .ok_or_else(|| invalid(&bound_path, "expected a non-negative integer"))?;
+        match bound.as_str() {
+            "gte" => lo = value,
+            "lte" => hi = value,
+            _ => return Err(invalid(&bound_path, "unknown bound")),
+        }
+    }
+    Ok(QueryNode::NumericRange(field, lo, hi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logdb::LogDB;
+
+    /// The log lines of the `test.js` stress test.
+    const LOGS: [(&str, &str, &str); 19] = [
+        (
+            "Failed login attempt for user john",
+            "ERROR",
+            "auth-service",
+        ),
+        ("Database connection established", "INFO", "db-service"),
+        ("Payment processing started", "INFO", "payment-service"),
+        ("Credit card validation failed", "ERROR", "payment-service"),
+        ("API rate limit exceeded", "WARN", "api-gateway"),
+        ("Server startup complete", "INFO", "web-server"),
+        ("Memory usage high", "WARN", "monitoring"),
+        (
+            "Backup process completed successfully",
+            "INFO",
+            "backup-service",
+        ),
+        ("SSL certificate expiring soon", "WARN", "security"),
+        ("User session timeout", "INFO", "session-manager"),
+        ("Database query took 5.2 seconds", "WARN", "db-service"),
+        ("Cache miss for user profile", "DEBUG", "cache-service"),
+        ("Email notification sent", "INFO", "notification-service"),
+        ("Disk space low on server", "ERROR", "monitoring"),
+        ("User john logged out", "INFO", "auth-service"),
+        ("Payment transaction completed", "INFO", "payment-service"),
+        ("API response time degraded", "WARN", "api-gateway"),
+        ("Configuration file reloaded", "INFO", "config-manager"),
+        ("Health check failed", "ERROR", "health-service"),
+    ];
+
+    #[test]
+    fn specs_match_their_string_queries() {
+        let mut db = LogDB::new();
+        for (content, level, service) in LOGS {
+            db.upsert_log(content, Some(level.into()), Some(service.into()))
+                .unwrap();
+        }
+        let level = |l: &str| format!(r#"{{"field": {{"level": "{l}"}}}}"#);
+        let service = |s: &str| format!(r#"{{"field": {{"service": "{s}"}}}}"#);
+        let term = |t: &str| format!(r#"{{"term": "{t}"}}"#);
+        let contains = |t: &str| format!(r#"{{"contains": "{t}"}}"#);
+        let and = |a: String, b: String| format!(r#"{{"and": [{a}, {b}]}}"#);
+        let cases = [
+            ("authentication", term("authentication")),
+            ("level:ERROR", level("ERROR")),
+            ("service:payment-service", service("payment-service")),
+            (
+                "level:INFO service:auth-service",
+                and(level("INFO"), service("auth-service")),
+            ),
+            ("failed", term("failed")),
+            ("user john", and(term("user"), term("john"))),
+            ("service:db-service", service("db-service")),
+            ("contains:timeout", contains("timeout")),
+            (
+                "level:ERROR service:monitoring",
+                and(level("ERROR"), service("monitoring")),
+            ),
+            (
+                "level:WARN contains:server",
+                and(level("WARN"), contains("server")),
+            ),
+            (
+                "level:INFO contains:completed",
+                and(level("INFO"), contains("completed")),
+            ),
+            ("level:DEBUG", level("DEBUG")),
+            (
+                "payment OR server",
+                format!(r#"{{"or": [{}, {}]}}"#, term("payment"), term("server")),
+            ),
+            ("NOT level:INFO", format!(r#"{{"not": {}}}"#, level("INFO"))),
+            ("*", r#"{"match_all": {}}"#.to_string()),
+        ];
+        let mut matched = 0;
+        for (q, spec) in &cases {
+            let mut expected = db.query(q);
+            let mut got = db
+                .query_json(spec)
+                .unwrap_or_else(|e| panic!("{spec}: {e}"));
+            expected.sort();
+            got.sort();
+            assert_eq!(got, expected, "{q} vs {spec}");
+            matched += usize::from(!got.is_empty());
+        }
+        // Only `authentication` and `level:WARN contains:server` match nothing, as in
+        // the stress test.
+        assert_eq!(matched, cases.len() - 2);
+    }
+
+    #[test]
+    fn malformed_nodes_are_reported_with_their_path() {
+        let error = |json: &str| QuerySpec::from_json(json).unwrap_err().to_string();
+        assert_eq!(
+            error(r#"{"and": [{"term": "a"}, {"fied": "b"}]}"#),
+            "and[1].fied: unknown node"
+        );
+        assert_eq!(
+            error(r#"{"or": [{"not": {"term": 3}}]}"#),
+            "or[0].not.term: expected a string"
+        );
+        assert_eq!(
+            error(r#"{"range": {"timestamp": {"gt": 1}}}"#),
+            "range.timestamp.gt: unknown bound"
+        );
+        assert!(matches!(
+            QuerySpec::from_json("{"),
+            Err(QueryError::InvalidJson(_))
+        ));
+    }
+}
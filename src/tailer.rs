@@ -0,0 +1,390 @@
+//! # File Tailing
+//!
+//! This module provides `LogTailer`, which follows a growing log file and ingests each
+//! line appended to it into a `LogDB`, the way `tail -F` follows a file. It detects
+//! rotation (the path now names a different file) and truncation (the file shrank),
+//! and can persist its byte offset to a sidecar file so a restarted process carries on
+//! where it stopped instead of ingesting the same lines again.
+//!
+//! A tailer can be driven by hand with `poll`, or run on a background thread with
+//! `spawn`, which returns a `TailHandle` for stopping it.
+
+use crate::logdb::LogDB;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Follows a log file and ingests each appended line as a document.
+///
+/// Lines are ingested with `LogDB::upsert_log`, tagged with the service set by
+/// `service`, if any. A trailing line without a newline is left for the next poll, so
+/// a line that is still being written is never split into two documents.
+#[derive(Debug)]
+pub struct LogTailer {
+    /// The file being followed.
+    path: PathBuf,
+    /// Where the read position is persisted between runs, if anywhere.
+    offset_path: Option<PathBuf>,
+    /// The service name given to every ingested line.
+    service: Option<String>,
+    /// How long the background thread sleeps between polls.
+    poll_interval: Duration,
+    /// The open file and its identity, once the path has been opened.
+    file: Option<(File, u64)>,
+    /// The byte offset just past the last ingested line.
+    offset: u64,
+}
+
+impl LogTailer {
+    /// Creates a tailer for the file at `path`.
+    ///
+    /// Unless a saved offset is found by `offset_file`, tailing starts at the current
+    /// end of the file, so only lines appended from now on are ingested.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            offset_path: None,
+            service: None,
+            poll_interval: Duration::from_millis(250),
+            file: None,
+            offset: 0,
+        }
+    }
+
+    /// Persists the read position to `path` after every poll that ingests lines, and
+    /// resumes from the position saved there, if any.
+    pub fn offset_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.offset_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Tags every ingested line with a service name.
+    pub fn service(mut self, service: impl Into<String>) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    /// Sets how long the background thread started by `spawn` waits between polls.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Returns the byte offset just past the last ingested line.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Ingests every complete line appended since the last poll.
+    ///
+    /// # Returns
+    /// The number of lines ingested.
+    pub fn poll(&mut self, db: &mut LogDB) -> io::Result<usize> {
+        let lines = self.read_lines()?;
+        for line in &lines {
+            // A line the ingest hook rejects is counted in `Metrics::rejections`.
+            let _ = db.upsert_log(line, None, self.service.clone());
+        }
+        self.save_offset(!lines.is_empty())?;
+        Ok(lines.len())
+    }
+
+    /// Starts polling on a background thread, ingesting into a shared database.
+    ///
+    /// The database is locked only while the lines read by a poll are ingested. The
+    /// thread stops at the first I/O error, which `TailHandle::stop` returns.
+    pub fn spawn(mut self, db: Arc<Mutex<LogDB>>) -> TailHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = Arc::clone(&stop);
+        let thread = thread::spawn(move || -> io::Result<()> {
+            while !stop_flag.load(Ordering::Relaxed) {
+                let lines = self.read_lines()?;
+                if !lines.is_empty() {
+                    let mut db = db
+                        .lock()
+                        .map_err(|_| io::Error::other("LogDB mutex is poisoned"))?;
+                    for line in &lines {
+                        let _ = db.upsert_log(line, None, self.service.clone());
+                    }
+                }
+                self.save_offset(!lines.is_empty())?;
+                thread::park_timeout(self.poll_interval);
+            }
+            Ok(())
+        });
+        TailHandle {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Reads the complete lines appended since the last call, reopening the file if it
+    /// was rotated and rewinding if it was truncated.
+    fn read_lines(&mut self) -> io::Result<Vec<String>> {
+        let mut lines = Vec::new();
+        let current_id = match fs::metadata(&self.path) {
+            Ok(meta) => Some(file_id(&meta)),
+            // The file may be briefly missing while it is being rotated.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+
+        if self.file.is_none() {
+            let Some(id) = current_id else {
+                return Ok(lines);
+            };
+            self.open(id)?;
+        } else if current_id.is_some_and(|id| Some(id) != self.file.as_ref().map(|f| f.1)) {
+            // Rotated: finish the old file, then continue with the new one from its
+            // start.
+            self.read_complete_lines(&mut lines)?;
+            self.file = Some((File::open(&self.path)?, current_id.unwrap_or_default()));
+            self.offset = 0;
+        }
+
+        let len = self
+            .file
+            .as_ref()
+            .map_or(Ok(0), |(f, _)| f.metadata().map(|m| m.len()))?;
+        if len < self.offset {
+            // Truncated in place.
+            self.offset = 0;
+        }
+        self.read_complete_lines(&mut lines)?;
+        Ok(lines)
+    }
+
+    /// Opens the file, starting from the saved offset if it was saved for this file,
+    /// or from the end otherwise.
+    fn open(&mut self, id: u64) -> io::Result<()> {
+        let file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        self.offset = match self.load_offset()? {
+            Some((saved_id, offset)) if saved_id == id && offset <= len => offset,
+            // The file was rotated while no one was watching it, so all of it is new.
+            Some(_) => 0,
+            None => len,
+        };
+        self.file = Some((file, id));
+        Ok(())
+    }
+
+    /// Appends the complete lines between the offset and the end of the open file,
+    /// advancing the offset past the last newline.
+    fn read_complete_lines(&mut self, lines: &mut Vec<String>) -> io::Result<()> {
+        let Some((file, _)) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf)?;
+        let Some(end) = buf.iter().rposition(|&b| b == b'\n') else {
+            return Ok(());
+        };
+        for line in buf[..end].split(|&b| b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim_end_matches('\r');
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+        }
+        self.offset += end as u64 + 1;
+        Ok(())
+    }
+
+    /// Reads the file identity and offset saved by `save_offset`.
+    fn load_offset(&self) -> io::Result<Option<(u64, u64)>> {
+        let Some(path) = &self.offset_path else {
+            return Ok(None);
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut parts = text.split_whitespace().map(str::parse::<u64>);
+        match (parts.next(), parts.next()) {
+            (Some(Ok(id)), Some(Ok(offset))) => Ok(Some((id, offset))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed tail offset file",
+            )),
+        }
+    }
+
+    /// Writes the file identity and offset to the offset file, if one is set and
+    /// `changed` is true.
+    fn save_offset(&self, changed: bool) -> io::Result<()> {
+        match (&self.offset_path, &self.file) {
+            (Some(path), Some((_, id))) if changed => {
+                fs::write(path, format!("{id} {}\n", self.offset))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns a value identifying the file behind some metadata, so a rotated file can be
+/// told apart from the original. On Unix this is the inode; elsewhere it is the
+/// creation time, or 0 if that is unavailable.
+fn file_id(meta: &fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        meta.ino()
+    }
+    #[cfg(not(unix))]
+    {
+        meta.created()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos() as u64)
+    }
+}
+
+/// A handle to a tailer running on a background thread, returned by `LogTailer::spawn`.
+///
+/// Dropping the handle stops the thread without waiting for it.
+#[derive(Debug)]
+pub struct TailHandle {
+    /// Tells the thread to exit after its current poll.
+    stop: Arc<AtomicBool>,
+    /// The polling thread.
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl TailHandle {
+    /// Stops the tailer and waits for its thread to exit.
+    ///
+    /// # Returns
+    /// The I/O error that stopped the thread early, if any.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        thread.thread().unpark();
+        thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("tail thread panicked")))
+    }
+}
+
+impl Drop for TailHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("buggu-{}-{name}", std::process::id()))
+    }
+
+    fn append(path: &Path, text: &str) {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(text.as_bytes())
+            .unwrap();
+    }
+
+    /// Checks that each word matches exactly one document.
+    fn assert_each_once(db: &LogDB, words: &[&str]) {
+        for word in words {
+            assert_eq!(db.query(word).len(), 1, "{word}");
+        }
+    }
+
+    #[test]
+    fn lines_before_and_after_rotation_are_ingested_once() {
+        let (log, rotated) = (temp_path("rotate.log"), temp_path("rotate.log.1"));
+        fs::write(&log, "old0 ignored\n").unwrap();
+        let mut db = LogDB::new();
+        let mut tailer = LogTailer::new(&log).service("app");
+        assert_eq!(tailer.poll(&mut db).unwrap(), 0);
+
+        append(&log, "a1 first\na2 second\na3 par");
+        assert_eq!(tailer.poll(&mut db).unwrap(), 2);
+        // The rest of the partial line arrives, then the file is rotated with a line
+        // still unread in it.
+        append(&log, "tial\na4 last before rotation\n");
+        fs::rename(&log, &rotated).unwrap();
+        fs::write(&log, "b1 after rotation\n").unwrap();
+        assert_eq!(tailer.poll(&mut db).unwrap(), 3);
+        append(&log, "b2 more\n");
+        assert_eq!(tailer.poll(&mut db).unwrap(), 1);
+        assert_eq!(tailer.poll(&mut db).unwrap(), 0);
+
+        assert_eq!(db.len(), 6);
+        assert_each_once(&db, &["a1", "a2", "a3", "a4", "b1", "b2", "partial"]);
+        assert!(db.query("old0").is_empty());
+        assert!(db.query("par").is_empty());
+        assert_eq!(db.query("service:app").len(), 6);
+
+        // Truncated in place: reading starts over.
+        fs::write(&log, "c1 after truncation\n").unwrap();
+        assert_eq!(tailer.poll(&mut db).unwrap(), 1);
+        assert_each_once(&db, &["c1"]);
+        let _ = (fs::remove_file(&log), fs::remove_file(&rotated));
+    }
+
+    #[test]
+    fn a_restarted_tailer_resumes_from_the_saved_offset() {
+        let (log, offset) = (temp_path("resume.log"), temp_path("resume.offset"));
+        fs::write(&log, "").unwrap();
+        let _ = fs::remove_file(&offset);
+        let mut db = LogDB::new();
+        let mut tailer = LogTailer::new(&log).offset_file(&offset);
+        tailer.poll(&mut db).unwrap();
+        append(&log, "r1 seen\n");
+        assert_eq!(tailer.poll(&mut db).unwrap(), 1);
+        drop(tailer);
+
+        append(&log, "r2 while stopped\n");
+        let mut tailer = LogTailer::new(&log).offset_file(&offset);
+        assert_eq!(tailer.poll(&mut db).unwrap(), 1);
+        assert_eq!(tailer.offset(), fs::metadata(&log).unwrap().len());
+        assert_each_once(&db, &["r1", "r2"]);
+        let _ = (fs::remove_file(&log), fs::remove_file(&offset));
+    }
+
+    #[test]
+    fn spawned_tailer_ingests_until_stopped() {
+        let log = temp_path("spawn.log");
+        fs::write(&log, "").unwrap();
+        let db = Arc::new(Mutex::new(LogDB::new()));
+        let handle = LogTailer::new(&log)
+            .poll_interval(Duration::from_millis(5))
+            .spawn(Arc::clone(&db));
+        // Give the thread time to open the file at its current end.
+        thread::sleep(Duration::from_millis(50));
+        append(&log, "s1 from the test\ns2 also\n");
+        for _ in 0..200 {
+            if db.lock().unwrap().len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        handle.stop().unwrap();
+        append(&log, "s3 after stop\n");
+        thread::sleep(Duration::from_millis(20));
+        let db = db.lock().unwrap();
+        assert_each_once(&db, &["s1", "s2"]);
+        assert!(db.query("s3").is_empty());
+        let _ = fs::remove_file(&log);
+    }
+}
@@ -0,0 +1,221 @@
+//! # Term Dictionary
+//!
+//! This module defines `TermDict`, the map from the token of each word indexed by
+//! `LogDB` back to the word itself. Tokens are hashes, so features that need the text
+//! of a token, such as dictionary scans, read it from here. The first spelling seen
+//! for a token is kept, and any other word that hashes to the same token is recorded
+//! as a collision: such words are indistinguishable to queries.
+
+use crate::types::Tok;
+use crate::ufhg::lightning_hash_str;
+use crate::utils::buggu_hash_set::BugguHashSet;
+
+/// A word in the dictionary.
+#[derive(Debug, Clone, Default)]
+struct Term {
+    /// The first spelling of the word seen.
+    word: String,
+    /// Other words seen with the same token, each spelled as first seen.
+    colliders: Vec<String>,
+    /// When the word was last interned, by the dictionary's own clock.
+    last_used: u64,
+}
+
+/// A map from tokens to the words they were hashed from.
+///
+/// Words are compared case-insensitively, as they are hashed, so `Error` and `error`
+/// are the same term rather than a collision. With a capacity set, the least recently
+/// interned terms are evicted once the dictionary outgrows it.
+#[derive(Debug, Clone)]
+pub struct TermDict {
+    /// The terms, by token.
+    terms: BugguHashSet<Tok, Term>,
+    /// The number of distinct words that hash to an already taken token.
+    collisions: usize,
+    /// The most terms kept, if bounded.
+    capacity: Option<usize>,
+    /// Counts calls to `intern`, to order the terms by use.
+    clock: u64,
+}
+
+impl Default for TermDict {
+    /// Creates an unbounded, empty dictionary.
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl TermDict {
+    /// Creates an empty dictionary keeping at most `capacity` terms, or any number
+    /// with `None`.
+    pub fn new(capacity: Option<usize>) -> Self {
+        Self {
+            terms: BugguHashSet::growable(256),
+            collisions: 0,
+            capacity,
+            clock: 0,
+        }
+    }
+
+    /// Adds a word to the dictionary and returns its token.
+    ///
+    /// A word whose token is already taken by a different word is recorded as a
+    /// collision, once per distinct word; the first word seen keeps the token.
+    pub fn intern(&mut self, word: &str) -> Tok {
+        let tok = lightning_hash_str(word);
+        self.clock += 1;
+        let term = self.terms.entry(tok).or_default();
+        term.last_used = self.clock;
+        if term.word.is_empty() {
+            term.word = word.to_owned();
+        } else if !term.word.eq_ignore_ascii_case(word)
+            && !term.colliders.iter().any(|c| c.eq_ignore_ascii_case(word))
+        {
+            term.colliders.push(word.to_owned());
+            self.collisions += 1;
+        }
+        if self.capacity.is_some_and(|cap| self.terms.len() > cap) {
+            self.evict();
+        }
+        tok
+    }
+
+    /// Returns the word a token was interned from, as first seen.
+    pub fn resolve(&self, tok: Tok) -> Option<&str> {
+        self.terms.get(&tok).map(|term| term.word.as_str())
+    }
+
+    /// Returns the other words seen with the same token as `tok`'s word.
+    pub fn colliders(&self, tok: Tok) -> &[String] {
+        self.terms
+            .get(&tok)
+            .map_or(&[], |term| term.colliders.as_slice())
+    }
+
+    /// Iterates over every token in the dictionary with its word, in no particular
+    /// order.
+    pub fn iter_terms(&self) -> impl Iterator<Item = (Tok, &str)> + '_ {
+        self.terms
+            .iter()
+            .map(|(tok, term)| (*tok, term.word.as_str()))
+    }
+
+    /// Removes a token and its word.
+    pub fn remove(&mut self, tok: Tok) {
+        if let Some(term) = self.terms.remove(&tok) {
+            self.collisions -= term.colliders.len();
+        }
+    }
+
+    /// Returns the number of distinct words that hashed to a token already taken by
+    /// another word.
+    pub fn collisions(&self) -> usize {
+        self.collisions
+    }
+
+    /// Returns the number of terms in the dictionary.
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Returns `true` if the dictionary holds no terms.
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Returns the memory held by the dictionary, in bytes.
+    pub fn approx_bytes(&self) -> usize {
+        self.terms.table_bytes()
+            + self
+                .terms
+                .iter()
+                .map(|(_, term)| {
+                    term.word.capacity()
+                        + term.colliders.capacity() * std::mem::size_of::<String>()
+                        + term.colliders.iter().map(String::capacity).sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+
+    /// Evicts the least recently interned terms down to the capacity, and at least an
+    /// eighth of the terms, so that eviction runs once per many inserts rather than
+    /// on each one.
+    fn evict(&mut self) {
+        let cap = self.capacity.unwrap_or(usize::MAX);
+        let len = self.terms.len();
+        let count = len.saturating_sub(cap).max(len / 8).max(1);
+        let mut ages: Vec<u64> = self.terms.iter().map(|(_, term)| term.last_used).collect();
+        // Every interned term has a distinct age, so exactly `count` are at or below
+        // the cutoff.
+        let (_, &mut newest_evicted, _) = ages.select_nth_unstable(count - 1);
+        let mut evicted_collisions = 0;
+        self.terms.retain(|_, term| {
+            let keep = term.last_used > newest_evicted;
+            if !keep {
+                evicted_collisions += term.colliders.len();
+            }
+            keep
+        });
+        self.collisions -= evicted_collisions;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_returns_the_first_spelling_interned() {
+        let mut dict = TermDict::default();
+        let words = ["timeout", "status:500", "user_42", "überlastet"];
+        let toks: Vec<Tok> = words.iter().map(|w| dict.intern(w)).collect();
+        for (word, &tok) in words.iter().zip(&toks) {
+            assert_eq!(tok, lightning_hash_str(word));
+            assert_eq!(dict.resolve(tok), Some(*word));
+        }
+        // Another case of a word is the same term, spelled as first seen.
+        assert_eq!(dict.intern("TimeOut"), toks[0]);
+        assert_eq!(dict.resolve(toks[0]), Some("timeout"));
+        assert_eq!((dict.len(), dict.collisions()), (4, 0));
+        let mut terms: Vec<&str> = dict.iter_terms().map(|(_, word)| word).collect();
+        terms.sort_unstable();
+        assert_eq!(terms, ["status:500", "timeout", "user_42", "überlastet"]);
+        assert_eq!(dict.resolve(lightning_hash_str("absent")), None);
+    }
+
+    #[test]
+    fn words_sharing_a_token_are_counted_as_collisions() {
+        let mut dict = TermDict::default();
+        // Letter positions are concatenated in decimal: w a a b and w a l both
+        // spell 23 1 1 2. Words with other characters hash only their first bytes.
+        let tok = dict.intern("waab");
+        assert_eq!(dict.intern("wal"), tok);
+        assert_eq!(dict.intern("WAL"), tok);
+        let node = dict.intern("node12a");
+        assert_eq!(dict.intern("node12b"), node);
+        assert_eq!(dict.collisions(), 2);
+        assert_eq!(dict.resolve(tok), Some("waab"));
+        assert_eq!(dict.colliders(tok), ["wal"]);
+        assert_eq!(dict.colliders(node), ["node12b"]);
+
+        dict.remove(tok);
+        assert_eq!((dict.resolve(tok), dict.collisions()), (None, 1));
+        assert!(dict.colliders(tok).is_empty());
+    }
+
+    #[test]
+    fn a_bounded_dictionary_evicts_the_least_recently_interned() {
+        let mut dict = TermDict::new(Some(16));
+        let words: Vec<String> = (0..16).map(|i| format!("w{i}x")).collect();
+        for word in &words {
+            dict.intern(word);
+        }
+        // Touching the first word makes the second the oldest.
+        dict.intern(&words[0]);
+        dict.intern("fresh");
+        assert!(dict.len() <= 16);
+        assert_eq!(dict.resolve(lightning_hash_str(&words[1])), None);
+        assert_eq!(dict.resolve(lightning_hash_str(&words[0])), Some("w0x"));
+        assert_eq!(dict.resolve(lightning_hash_str("fresh")), Some("fresh"));
+    }
+}
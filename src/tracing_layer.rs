@@ -0,0 +1,168 @@
+//! # Tracing Integration
+//!
+//! This module provides `MicroSearchLayer`, a `tracing_subscriber::Layer` that indexes
+//! each `tracing` event into a shared `LogDB`. It lets a Rust service search its own
+//! logs, for example from a local debugging endpoint.
+//!
+//! Each event becomes one document:
+//! - the level (`ERROR`, `WARN`, ...) is stored as the document's level,
+//! - the target (usually the module path) is stored as the document's service,
+//! - the message and the remaining fields form the content, with each field written
+//!   as a `key:value` word so it can be queried directly (e.g. `user:alice`).
+
+use crate::logdb::LogDB;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// A `tracing_subscriber::Layer` that indexes events into a shared `LogDB`.
+///
+/// The layer never blocks the application: when the database is locked by another
+/// thread (a query, or another event being indexed), the event is dropped and counted
+/// instead. The count is available through `dropped_events`.
+#[derive(Debug, Clone)]
+pub struct MicroSearchLayer {
+    /// The database that events are indexed into.
+    db: Arc<Mutex<LogDB>>,
+    /// The number of events dropped because the database was busy.
+    dropped: Arc<AtomicU64>,
+}
+
+impl MicroSearchLayer {
+    /// Creates a layer that indexes events into the given database.
+    pub fn new(db: Arc<Mutex<LogDB>>) -> Self {
+        Self {
+            db,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Returns a handle to the count of dropped events.
+    ///
+    /// The handle stays valid after the layer is moved into a subscriber.
+    pub fn dropped_events(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.dropped)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for MicroSearchLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+
+        // A poisoned lock is treated like a busy one: indexing must never take the
+        // application down with it.
+        let Ok(mut db) = self.db.try_lock() else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let metadata = event.metadata();
+        // An event the ingest hook rejects is counted in `Metrics::rejections`.
+        let _ = db.upsert_log(
+            &visitor.into_content(),
+            Some(metadata.level().as_str().to_string()),
+            Some(metadata.target().to_string()),
+        );
+    }
+}
+
+/// Collects an event's message and fields into document content.
+#[derive(Default)]
+struct EventVisitor {
+    /// The event's `message` field.
+    message: String,
+    /// The remaining fields, as space-separated `key:value` words.
+    fields: String,
+}
+
+impl EventVisitor {
+    /// Returns the message followed by the fields.
+    fn into_content(self) -> String {
+        match (self.message.is_empty(), self.fields.is_empty()) {
+            (_, true) => self.message,
+            (true, false) => self.fields,
+            (false, false) => format!("{} {}", self.message, self.fields),
+        }
+    }
+
+    /// Appends a `key:value` word for a field.
+    fn push_field(&mut self, field: &Field, value: fmt::Arguments<'_>) {
+        if !self.fields.is_empty() {
+            self.fields.push(' ');
+        }
+        let _ = write!(self.fields, "{}:{}", field.name(), value);
+    }
+}
+
+impl Visit for EventVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.push_field(field, format_args!("{value}"));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            self.push_field(field, format_args!("{value:?}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Runs `f` with a subscriber that indexes its events into a fresh database.
+    fn with_layer(f: impl FnOnce(&Arc<Mutex<LogDB>>)) -> (Arc<Mutex<LogDB>>, u64) {
+        let db = Arc::new(Mutex::new(LogDB::new()));
+        let layer = MicroSearchLayer::new(Arc::clone(&db));
+        let dropped = layer.dropped_events();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || f(&db));
+        let dropped = dropped.load(Ordering::Relaxed);
+        (db, dropped)
+    }
+
+    #[test]
+    fn events_are_indexed_with_their_fields() {
+        let (db, dropped) = with_layer(|_| {
+            tracing::error!(target: "auth", user = "alice", attempts = 3, "login failed");
+            tracing::info!(target: "billing", "invoice sent");
+        });
+        assert_eq!(dropped, 0);
+        let db = db.lock().unwrap();
+        let hits = db.query("user:alice");
+        assert_eq!(hits.len(), 1);
+        let entry = db.get_entry(&hits[0]).unwrap();
+        assert_eq!(entry.content(), "login failed user:alice attempts:3");
+        assert_eq!(entry.level(), Some("ERROR"));
+        assert_eq!(entry.service(), Some("auth"));
+        assert_eq!(db.query("attempts:3 level:ERROR service:auth"), hits);
+        assert_eq!(db.query("invoice level:INFO service:billing").len(), 1);
+        assert!(db.query("login level:INFO").is_empty());
+    }
+
+    #[test]
+    fn events_are_dropped_while_the_db_is_busy() {
+        let (db, dropped) = with_layer(|db| {
+            let guard = db.lock().unwrap();
+            tracing::warn!("lost while locked");
+            tracing::warn!("also lost");
+            drop(guard);
+            tracing::warn!("kept");
+        });
+        assert_eq!(dropped, 2);
+        let db = db.lock().unwrap();
+        assert_eq!(db.len(), 1);
+        assert!(db.query("lost").is_empty());
+        assert_eq!(db.query("kept").len(), 1);
+    }
+}
@@ -4,7 +4,10 @@
 //! LogDB system. These types provide a consistent and efficient representation for
 //! key entities such as tokens, document IDs, and log entries.
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::utils::buggu_hash_set::BugguHashable;
+use crate::utils::buggu_ultra_fast_hash::buggu_hash_u64_minimal;
+use std::fmt;
+use std::str::FromStr;
 
 /// Represents a token, which is a fundamental unit of information in the search index.
 ///
@@ -15,20 +18,94 @@ pub type Tok = u64;
 /// A unique identifier for a document, which corresponds to a single log entry.
 ///
 /// Each document in the database is assigned a unique `DocId` to allow for precise
-/// retrieval and management.
-pub type DocId = u64;
+/// retrieval and management. Ids cross the N-API boundary as decimal strings, so
+/// `FromStr` only accepts plain ASCII digits; anything else is rejected rather than
+/// silently matching no document.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct DocId(pub u64);
+
+/// The previous raw representation of a document identifier.
+#[deprecated(note = "`DocId` is now a newtype; use `DocId::get` for the raw value")]
+pub type RawDocId = u64;
+
+impl DocId {
+    /// Returns the raw numeric value of the identifier.
+    #[inline]
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for DocId {
+    #[inline]
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<DocId> for u64 {
+    #[inline]
+    fn from(id: DocId) -> Self {
+        id.0
+    }
+}
+
+impl fmt::Display for DocId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The error returned when a string is not a valid `DocId`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDocIdError {
+    /// The rejected input.
+    pub input: String,
+}
+
+impl fmt::Display for ParseDocIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid document id {:?}", self.input)
+    }
+}
+
+impl std::error::Error for ParseDocIdError {}
+
+impl FromStr for DocId {
+    type Err = ParseDocIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDocIdError {
+            input: s.to_string(),
+        };
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(err());
+        }
+        s.parse::<u64>().map(DocId).map_err(|_| err())
+    }
+}
+
+impl BugguHashable for DocId {
+    /// Hashes a `DocId` exactly like its raw `u64` value.
+    fn buggu_hash(&self) -> u64 {
+        buggu_hash_u64_minimal(self.0)
+    }
+}
 
 /// Defines the tokenization mode for a log entry.
 ///
 /// This enum allows for different strategies when processing log content, enabling
 /// a balance between structured data extraction and full-text indexing.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum TokenMode {
     /// Only parses structured `field:value` pairs, ignoring unstructured text.
     Structured,
     /// Performs full-text indexing, including N-grams and pattern extraction.
     FullText,
     /// Combines both structured parsing and full-text indexing.
+    #[default]
     Mixed,
 }
 
@@ -48,8 +125,19 @@ pub struct LogEntry {
     pub level: Option<String>,
     /// The name of the service that generated the log, if available.
     pub service: Option<String>,
+    /// The other services the log is tagged with, besides `service`, such as the
+    /// services a request passed through. Empty for most entries.
+    pub extra_services: Vec<String>,
+    /// The metadata fields of the log entry, as `(name, value)` pairs. A name may
+    /// appear several times, once per value. Each pair is appended to the content as
+    /// a `name:value` word, so `tag:db` finds an entry with a `tag` field of `db`.
+    pub fields: Vec<(String, String)>,
     /// The tokenization mode to be used for this log entry.
     pub mode: TokenMode,
+    /// Whether only the content is tokenized. When `false`, the level and service are
+    /// also indexed as words, along with the literal words "level", "service" and
+    /// "content" that label them.
+    pub content_only: bool,
 }
 
 impl LogEntry {
@@ -59,12 +147,15 @@ impl LogEntry {
     /// The timestamp is set to the current time.
     pub fn new(content: String, mode: TokenMode) -> Self {
         Self {
-            id: 0, // The ID will be assigned by LogDB during insertion.
+            id: DocId(0), // The ID will be assigned by LogDB during insertion.
             content,
             timestamp: now_secs(),
             level: None,
             service: None,
+            extra_services: Vec::new(),
+            fields: Vec::new(),
             mode,
+            content_only: false,
         }
     }
 
@@ -79,12 +170,15 @@ impl LogEntry {
         mode: TokenMode,
     ) -> Self {
         Self {
-            id: 0, // The ID will be assigned by LogDB.
+            id: DocId(0), // The ID will be assigned by LogDB.
             content,
             timestamp: now_secs(),
             level,
             service,
+            extra_services: Vec::new(),
+            fields: Vec::new(),
             mode,
+            content_only: false,
         }
     }
 }
@@ -92,10 +186,74 @@ impl LogEntry {
 /// Returns the current time in seconds since the Unix epoch.
 ///
 /// This is a convenience function for creating timestamps for log entries.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 #[inline]
 pub fn now_secs() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs()
-}
\ No newline at end of file
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+///
+/// `SystemTime::now` panics on `wasm32-unknown-unknown`, so the time is read from the
+/// JavaScript `Date` when the `wasm` feature is enabled, and is 0 otherwise. Use
+/// `LogDB::set_clock` to supply a clock for other hosts.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+#[inline]
+pub fn now_secs() -> u64 {
+    #[cfg(feature = "wasm")]
+    {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+    #[cfg(not(feature = "wasm"))]
+    {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doc_id_parses_plain_digits() {
+        assert_eq!("0".parse(), Ok(DocId(0)));
+        assert_eq!("42".parse(), Ok(DocId(42)));
+        assert_eq!(u64::MAX.to_string().parse(), Ok(DocId(u64::MAX)));
+        assert_eq!(DocId::from(7).to_string(), "7");
+        assert_eq!(u64::from(DocId(7)), 7);
+    }
+
+    #[test]
+    fn doc_id_rejects_non_numeric_input() {
+        for input in [
+            "",
+            "abc",
+            "12a",
+            "-1",
+            "+1",
+            " 1",
+            "1 ",
+            "1.0",
+            "18446744073709551616",
+        ] {
+            let err = input.parse::<DocId>().unwrap_err();
+            assert_eq!(err.input, input);
+            assert_eq!(err.to_string(), format!("invalid document id {input:?}"));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn doc_id_round_trips_through_json() {
+        let json = serde_json::to_string(&DocId(42)).unwrap();
+        assert_eq!(json, "42");
+        assert_eq!(serde_json::from_str::<DocId>(&json).unwrap(), DocId(42));
+        let ids: Vec<DocId> = serde_json::from_str("[1, 2, 3]").unwrap();
+        assert_eq!(ids, [DocId(1), DocId(2), DocId(3)]);
+        assert!(serde_json::from_str::<DocId>("\"42\"").is_err());
+    }
+}
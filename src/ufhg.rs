@@ -37,10 +37,11 @@ pub fn lightning_hash_str(s: &str) -> u64 {
                 break;
             }
         };
+        // Long words overflow; wrapping keeps debug and release builds agreeing.
         result = if pos < 10 {
-            result * 10 + pos as u64
+            result.wrapping_mul(10).wrapping_add(pos as u64)
         } else {
-            result * 100 + pos as u64
+            result.wrapping_mul(100).wrapping_add(pos as u64)
         };
     }
     if has_special {
@@ -49,6 +50,28 @@ pub fn lightning_hash_str(s: &str) -> u64 {
     result
 }
 
+/// Hashes many words at once, appending their hashes to `out` in order.
+///
+/// Each hash is the one `lightning_hash_str` gives. With the `simd` feature on an
+/// x86-64 CPU supporting AVX2, words are hashed four at a time in vector lanes;
+/// otherwise, and for words with characters other than ASCII letters, they are
+/// hashed one by one.
+pub fn lightning_hash_many(words: &[&str], out: &mut Vec<u64>) {
+    out.reserve(words.len());
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if std::is_x86_feature_detected!("avx2") {
+        let mut chunks = words.chunks_exact(4);
+        for chunk in &mut chunks {
+            let chunk = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            // SAFETY: AVX2 support was checked just above.
+            out.extend(unsafe { avx2::lightning_hash_x4(chunk) });
+        }
+        out.extend(chunks.remainder().iter().map(|w| lightning_hash_str(w)));
+        return;
+    }
+    out.extend(words.iter().map(|w| lightning_hash_str(w)));
+}
+
 /// The central component for tokenization and hashing.
 ///
 /// The `UFHGHeadquarters` is responsible for converting raw strings into sequences
@@ -59,16 +82,52 @@ pub fn lightning_hash_str(s: &str) -> u64 {
 pub struct UFHGHeadquarters {
     /// A reusable vector for storing word hashes during tokenization.
     word_hashes: Vec<u64>,
+    /// Whether `tokenize_zero_copy` emits a token for each run of whitespace.
+    index_whitespace: bool,
+    /// The minimum length, in characters, of the words and fields emitted.
+    min_token_len: usize,
+}
+
+impl Default for UFHGHeadquarters {
+    /// Creates a new `UFHGHeadquarters` that emits whitespace tokens.
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UFHGHeadquarters {
     /// Creates a new `UFHGHeadquarters` with an initial capacity.
     pub fn new() -> Self {
+        Self::with_whitespace_tokens(true)
+    }
+
+    /// Creates a new `UFHGHeadquarters` that emits whitespace tokens only when
+    /// `index_whitespace` is set.
+    ///
+    /// Skipping them leaves phrase matching unaffected, since sequence hashes are
+    /// built from words alone.
+    pub fn with_whitespace_tokens(index_whitespace: bool) -> Self {
         Self {
             word_hashes: Vec::with_capacity(64),
+            index_whitespace,
+            min_token_len: 0,
         }
     }
 
+    /// Drops words and fields shorter than `min_token_len` characters from
+    /// `tokenize_zero_copy` and `field_hashes`.
+    pub fn with_min_token_len(mut self, min_token_len: usize) -> Self {
+        self.min_token_len = min_token_len;
+        self
+    }
+
+    /// Returns `true` if a word is long enough to be emitted as a token.
+    #[inline]
+    pub fn keeps_word(&self, word: &str) -> bool {
+        word.len() >= self.min_token_len
+            && (self.min_token_len <= 1 || word.chars().count() >= self.min_token_len)
+    }
+
     /// A highly optimized string hashing function.
     ///
     /// This is an instance method version of the `lightning_hash_str` function,
@@ -91,9 +150,9 @@ impl UFHGHeadquarters {
                 }
             };
             result = if pos < 10 {
-                result * 10 + pos as u64
+                result.wrapping_mul(10).wrapping_add(pos as u64)
             } else {
-                result * 100 + pos as u64
+                result.wrapping_mul(100).wrapping_add(pos as u64)
             };
         }
         if has_special {
@@ -127,19 +186,54 @@ impl UFHGHeadquarters {
                 }
                 let word_slice = unsafe { std::str::from_utf8_unchecked(&bytes[start..i]) };
                 if !word_slice.is_empty() {
-                    seq_hash = (lightning_hash_str(word_slice))
-                        .wrapping_mul(31)
-                        .wrapping_add(seq_hash);
+                    seq_hash = combine_seq_hash(seq_hash, lightning_hash_str(word_slice));
                 }
+            } else {
+                i += 1;
             }
         }
         seq_hash
     }
 
+    /// Computes the sequence hashes of every word N-gram in a message.
+    ///
+    /// N-grams from 2 up to `max_n` words are generated, each hashed with the same
+    /// recurrence as `string_to_u64_to_seq_hash`, so indexing these hashes lets phrase
+    /// queries of up to `max_n` words find the document.
+    pub fn ngram_hashes(&self, message: &str, max_n: usize) -> Vec<u64> {
+        let word_hashes: Vec<u64> = message
+            .split(is_separator)
+            .filter(|w| !w.is_empty())
+            .map(lightning_hash_str)
+            .collect();
+        let mut hashes = Vec::new();
+        for n in 2..=max_n.min(word_hashes.len()) {
+            for window in word_hashes.windows(n) {
+                hashes.push(window.iter().fold(0, |seq, &h| combine_seq_hash(seq, h)));
+            }
+        }
+        hashes
+    }
+
+    /// Extracts the hashes of structured `key:value` words in a message.
+    ///
+    /// A word is treated as a field token when it contains a colon with a non-empty
+    /// key before it and a non-empty value after it. The whole word is hashed, so a
+    /// query for `status:500` matches a document containing `status:500`.
+    pub fn field_hashes(&self, message: &str) -> Vec<u64> {
+        message
+            .split(is_separator)
+            .filter(|w| matches!(w.split_once(':'), Some((k, v)) if !k.is_empty() && !v.is_empty()))
+            .filter(|w| self.keeps_word(w))
+            .map(lightning_hash_str)
+            .collect()
+    }
+
     /// Tokenizes a message using a zero-copy approach.
     ///
-    /// This method processes a string message, breaking it into words and whitespace,
-    /// and converting each component into a hash. It avoids unnecessary memory
+    /// This method processes a string message, breaking it into words and, when
+    /// whitespace tokens are enabled, runs of whitespace, and converting each
+    /// component into a hash. It avoids unnecessary memory
     /// allocations by writing the hashes directly into a pre-allocated buffer.
     ///
     /// # Returns
@@ -151,6 +245,10 @@ impl UFHGHeadquarters {
         if message.is_empty() {
             return (vec![], vec![]);
         }
+        // With the `simd` feature, words are collected and hashed in bulk once the
+        // message is split, each into the slot it was given in `word_hashes`.
+        #[cfg(feature = "simd")]
+        let (mut slots, mut words) = (Vec::new(), Vec::new());
         let bytes = message.as_bytes();
         let mut i = 0;
         while i < bytes.len() {
@@ -167,8 +265,10 @@ impl UFHGHeadquarters {
                         break;
                     }
                 }
-                let hash = process_whitespace_len(whitespace_count);
-                self.word_hashes.push(hash);
+                if self.index_whitespace {
+                    let hash = process_whitespace_len(whitespace_count);
+                    self.word_hashes.push(hash);
+                }
             } else {
                 let start = i;
                 while i < bytes.len()
@@ -180,12 +280,29 @@ impl UFHGHeadquarters {
                     i += 1;
                 }
                 let word_slice = unsafe { std::str::from_utf8_unchecked(&bytes[start..i]) };
-                if !word_slice.is_empty() {
-                    let hash = self.lightning_hash_str(word_slice);
-                    self.word_hashes.push(hash);
+                if !word_slice.is_empty() && self.keeps_word(word_slice) {
+                    #[cfg(feature = "simd")]
+                    {
+                        slots.push(self.word_hashes.len());
+                        words.push(word_slice);
+                        self.word_hashes.push(0);
+                    }
+                    #[cfg(not(feature = "simd"))]
+                    {
+                        let hash = self.lightning_hash_str(word_slice);
+                        self.word_hashes.push(hash);
+                    }
                 }
             }
         }
+        #[cfg(feature = "simd")]
+        {
+            let mut hashes = Vec::new();
+            lightning_hash_many(&words, &mut hashes);
+            for (slot, hash) in slots.into_iter().zip(hashes) {
+                self.word_hashes[slot] = hash;
+            }
+        }
         // Take ownership of the computed hashes, leaving an empty Vec in its place.
         // This avoids cloning twice – we only clone once to create the second copy.
         let hashes = std::mem::take(&mut self.word_hashes);
@@ -194,6 +311,21 @@ impl UFHGHeadquarters {
     }
 }
 
+/// Returns `true` for the whitespace characters that separate words.
+#[inline(always)]
+fn is_separator(c: char) -> bool {
+    c == ' ' || c == '\t' || c == '\n' || c == '\r'
+}
+
+/// Folds the hash of the next word into a running sequence hash.
+///
+/// The running hash is the one multiplied, so each word is weighted by its position
+/// and `b a` hashes differently from `a b`.
+#[inline(always)]
+fn combine_seq_hash(seq_hash: u64, word_hash: u64) -> u64 {
+    seq_hash.wrapping_mul(31).wrapping_add(word_hash)
+}
+
 /// Processes the length of a whitespace sequence to generate a hash.
 ///
 /// This function takes the length of a sequence of whitespace characters and
@@ -208,3 +340,114 @@ fn process_whitespace_len(len: u64) -> u64 {
     x = x * 1000 + len;
     buggu_hash_u64_minimal(x)
 }
+
+/// The AVX2 kernel behind `lightning_hash_many`.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use super::lightning_hash_str_64;
+    use std::arch::x86_64::*;
+
+    /// Hashes four words as `lightning_hash_str` does, one per 64-bit lane.
+    ///
+    /// Each step folds the next letter of every word still being read into its lane,
+    /// multiplying by 10 or 100 with shifts and adds since AVX2 has no 64-bit
+    /// multiply. A word with a byte that is not an ASCII letter drops out of the lanes
+    /// and is hashed with `lightning_hash_str_64`, as the scalar hash does.
+    ///
+    /// # Safety
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn lightning_hash_x4(words: [&str; 4]) -> [u64; 4] {
+        let words = words.map(str::as_bytes);
+        let longest = words.iter().map(|w| w.len()).max().unwrap_or(0);
+        let mut special = [false; 4];
+        let mut acc = _mm256_setzero_si256();
+        for j in 0..longest {
+            let mut pos = [0i64; 4];
+            let mut active = [0i64; 4];
+            let mut wide = [0i64; 4];
+            for lane in 0..4 {
+                let Some(&byte) = words[lane].get(j) else {
+                    continue;
+                };
+                if special[lane] {
+                    continue;
+                }
+                if !byte.is_ascii_alphabetic() {
+                    special[lane] = true;
+                    continue;
+                }
+                let p = (byte.to_ascii_lowercase() - b'a' + 1) as i64;
+                pos[lane] = p;
+                active[lane] = -1;
+                wide[lane] = if p < 10 { 0 } else { -1 };
+            }
+            let pos = _mm256_loadu_si256(pos.as_ptr().cast());
+            let active = _mm256_loadu_si256(active.as_ptr().cast());
+            let wide = _mm256_loadu_si256(wide.as_ptr().cast());
+            // 10x = 8x + 2x, and 100x = 64x + 32x + 4x.
+            let times10 = _mm256_add_epi64(_mm256_slli_epi64(acc, 3), _mm256_slli_epi64(acc, 1));
+            let times100 = _mm256_add_epi64(
+                _mm256_add_epi64(_mm256_slli_epi64(acc, 6), _mm256_slli_epi64(acc, 5)),
+                _mm256_slli_epi64(acc, 2),
+            );
+            let next = _mm256_add_epi64(_mm256_blendv_epi8(times10, times100, wide), pos);
+            acc = _mm256_blendv_epi8(acc, next, active);
+        }
+        let mut out = [0u64; 4];
+        _mm256_storeu_si256(out.as_mut_ptr().cast(), acc);
+        for lane in 0..4 {
+            if special[lane] {
+                // SAFETY: the bytes came from a `&str`.
+                out[lane] = lightning_hash_str_64(std::str::from_utf8_unchecked(words[lane]));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phrase_hashes_depend_on_word_order() {
+        let ufhg = UFHGHeadquarters::new();
+        let seq = |s: &str| ufhg.string_to_u64_to_seq_hash(s);
+        assert_ne!(seq("refused connection"), seq("connection refused"));
+        assert_ne!(seq("a b c"), seq("c b a"));
+        assert_eq!(seq("connection   refused"), seq("connection refused"));
+        // The n-grams of a message hash like the phrases they spell.
+        let ngrams = ufhg.ngram_hashes("connection refused by peer", 3);
+        assert!(ngrams.contains(&seq("connection refused")));
+        assert!(ngrams.contains(&seq("refused by peer")));
+        assert!(!ngrams.contains(&seq("refused connection")));
+    }
+
+    #[test]
+    fn bulk_hashes_match_word_at_a_time_hashes() {
+        // Long and mixed-case words, words with digits and punctuation, and lengths
+        // leaving every remainder after the chunks of four.
+        let words = [
+            "timeout",
+            "Refused",
+            "CONNECTION",
+            "a",
+            "status:500",
+            "user_42",
+            "abcdefghijklmnopqrstuvwxyz",
+            "überlastet",
+            "retry",
+            "http://example.com",
+            "z",
+            "latency",
+            "",
+        ];
+        for len in 0..=words.len() {
+            let mut bulk = Vec::new();
+            lightning_hash_many(&words[..len], &mut bulk);
+            let one_by_one: Vec<u64> = words[..len].iter().map(|w| lightning_hash_str(w)).collect();
+            assert_eq!(bulk, one_by_one, "first {len} words");
+        }
+    }
+}
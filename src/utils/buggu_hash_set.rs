@@ -9,6 +9,8 @@
 
 use crate::utils::buggu_random_generator::BugguRng;
 use crate::utils::buggu_ultra_fast_hash::{buggu_hash_u64_minimal, lightning_hash_str};
+use std::collections::HashMap;
+use std::hash::Hash;
 
 /// The number of entries that can be stored directly within a bucket before
 /// it transitions to an overflow structure. This is a key parameter for tuning
@@ -19,6 +21,14 @@ const INLINE_BUCKET_SIZE: usize = 4;
 /// it allocates an overflow vector with this capacity.
 const OVERFLOW_BUCKET_SIZE: usize = 8;
 
+/// How many times its length an overflow bucket's capacity may reach through removals
+/// before the vector is shrunk to twice its length.
+const OVERFLOW_SHRINK_FACTOR: usize = 4;
+
+/// The average number of entries per bucket a growable set may reach before its table
+/// doubles.
+const GROW_LOAD_FACTOR: usize = 2;
+
 // =============================================================================
 // HASHABLE TRAIT
 // =============================================================================
@@ -320,6 +330,143 @@ where
     }
 }
 
+/// An iterator over the entries of a `BugguHashSet`.
+#[derive(Debug, Clone)]
+pub struct BugguIter<'a, K, V> {
+    storage: std::slice::Iter<'a, BugguBucket<K, V>>,
+    current: std::slice::Iter<'a, (K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for BugguIter<'a, K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default,
+    V: Clone + Default,
+{
+    type Item = (&'a K, &'a V);
+
+    #[inline(always)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        loop {
+            if let Some((k, v)) = self.current.next() {
+                self.remaining -= 1;
+                return Some((k, v));
+            }
+            self.current = match self.storage.next()? {
+                BugguBucket::Empty => [].iter(),
+                BugguBucket::Inline { entries, len } => entries[..*len as usize].iter(),
+                BugguBucket::Overflow { entries } => entries.iter(),
+            };
+        }
+    }
+}
+
+/// The entries of the bucket a `BugguIntoIter` is currently moving out of.
+#[derive(Debug, Clone)]
+enum BucketDrain<K, V> {
+    Inline(std::iter::Take<std::array::IntoIter<(K, V), INLINE_BUCKET_SIZE>>),
+    Overflow(std::vec::IntoIter<(K, V)>),
+}
+
+/// A consuming iterator over the entries of a `BugguHashSet`.
+///
+/// Entries are moved out bucket by bucket, without allocating.
+#[derive(Debug, Clone)]
+pub struct BugguIntoIter<K, V> {
+    storage: std::vec::IntoIter<BugguBucket<K, V>>,
+    current: Option<BucketDrain<K, V>>,
+    remaining: usize,
+}
+
+impl<K, V> Iterator for BugguIntoIter<K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default,
+    V: Clone + Default,
+{
+    type Item = (K, V);
+
+    #[inline(always)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        loop {
+            let entry = match self.current.as_mut() {
+                Some(BucketDrain::Inline(entries)) => entries.next(),
+                Some(BucketDrain::Overflow(entries)) => entries.next(),
+                None => None,
+            };
+            if entry.is_some() {
+                self.remaining -= 1;
+                return entry;
+            }
+            self.current = match self.storage.next()? {
+                BugguBucket::Empty => None,
+                BugguBucket::Inline { entries, len } => {
+                    Some(BucketDrain::Inline(entries.into_iter().take(len as usize)))
+                }
+                BugguBucket::Overflow { entries } => {
+                    Some(BucketDrain::Overflow(entries.into_iter()))
+                }
+            };
+        }
+    }
+}
+
+impl<K, V> IntoIterator for BugguHashSet<K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default,
+    V: Clone + Default,
+{
+    type Item = (K, V);
+    type IntoIter = BugguIntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        BugguIntoIter {
+            storage: self.storage.into_iter(),
+            current: None,
+            remaining: self.count,
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BugguHashSet<K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default,
+    V: Clone + Default,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = BugguIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut BugguHashSet<K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default,
+    V: Clone + Default,
+{
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = BugguIterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
 /// Represents an entry in the `BugguHashSet`, which can be either occupied or vacant.
 pub enum BugguEntry<'a, K, V>
 where
@@ -379,6 +526,12 @@ where
         }
     }
 
+    /// Inserts `V::default()` if the entry is vacant.
+    #[inline(always)]
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+
     /// Returns the key of the entry.
     #[inline(always)]
     pub fn key(&self) -> &K {
@@ -538,7 +691,7 @@ where
 // =============================================================================
 
 /// A high-performance, cache-friendly hash set.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct BugguHashSet<K, V = ()>
 where
     K: BugguHashable + Eq + PartialEq + Clone + Default,
@@ -546,6 +699,19 @@ where
 {
     pub storage: Vec<BugguBucket<K, V>>,
     count: usize,
+    /// Whether the table doubles as entries are inserted; see `growable`.
+    grows: bool,
+}
+
+impl<K, V> Default for BugguHashSet<K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default,
+    V: Clone + Default,
+{
+    /// Creates an empty `BugguHashSet` with a single bucket.
+    fn default() -> Self {
+        Self::new(1)
+    }
 }
 
 impl<K, V> BugguHashSet<K, V>
@@ -554,10 +720,52 @@ where
     V: Clone + Default,
 {
     /// Creates a new `BugguHashSet` with a specified table size.
+    ///
+    /// At least one bucket is always allocated, so a table size of zero behaves like
+    /// a table size of one.
     pub fn new(table_size: usize) -> Self {
         BugguHashSet {
-            storage: vec![BugguBucket::Empty; table_size],
+            storage: vec![BugguBucket::Empty; table_size.max(1)],
             count: 0,
+            grows: false,
+        }
+    }
+
+    /// Creates a new `BugguHashSet` that starts with `table_size` buckets and doubles
+    /// its table whenever it averages more than two entries per bucket, so it can
+    /// start small without long bucket chains later.
+    ///
+    /// Each doubling moves every entry, but the cost is amortized over the inserts
+    /// that filled the table. The table never shrinks on its own; `retain_compact`
+    /// shrinks it after large removals.
+    pub fn growable(table_size: usize) -> Self {
+        BugguHashSet {
+            grows: true,
+            ..Self::new(table_size)
+        }
+    }
+
+    /// Creates a growable `BugguHashSet` with enough buckets to hold `entries` entries
+    /// without doubling.
+    pub fn growable_for(entries: usize) -> Self {
+        Self::growable(entries.div_ceil(GROW_LOAD_FACTOR))
+    }
+
+    /// Grows the table of a growable set so it can take `additional` more entries
+    /// without doubling along the way. Fixed-size sets are left unchanged.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = (self.count + additional).div_ceil(GROW_LOAD_FACTOR);
+        if self.grows && needed > self.storage.len() {
+            self.rebuild(needed);
+        }
+    }
+
+    /// Doubles the table of a growable set once the next insert could take it past
+    /// `GROW_LOAD_FACTOR`.
+    #[inline(always)]
+    fn grow_if_loaded(&mut self) {
+        if self.grows && self.count >= self.storage.len() * GROW_LOAD_FACTOR {
+            self.rebuild(self.storage.len() * 2);
         }
     }
 
@@ -574,7 +782,13 @@ where
     }
 
     /// Retains only the elements specified by the predicate.
-    pub fn retain<F>(&mut self, mut f: F)
+    ///
+    /// The table is modified in place and keeps its size; use `retain_compact` to
+    /// shrink it after large removals.
+    ///
+    /// # Returns
+    /// The number of removed entries.
+    pub fn retain<F>(&mut self, mut f: F) -> usize
     where
         F: FnMut(&K, &mut V) -> bool,
     {
@@ -619,24 +833,59 @@ where
                     let new_len = entries.len();
                     total_removed += original_len - new_len;
 
-                    if new_len == 0 {
-                        *bucket = BugguBucket::Empty;
-                    } else if new_len <= INLINE_BUCKET_SIZE {
-                        let mut inline_entries =
-                            core::array::from_fn(|_| (K::default(), V::default()));
-                        for (i, entry) in entries.drain(..).enumerate() {
-                            inline_entries[i] = entry;
-                        }
-                        *bucket = BugguBucket::Inline {
-                            entries: inline_entries,
-                            len: new_len as u8,
-                        };
-                    }
+                    Self::shrink_overflow(bucket);
                 }
             }
         }
 
         self.count -= total_removed;
+        total_removed
+    }
+
+    /// Retains only the elements specified by the predicate, then rebuilds the table
+    /// of a growable set at twice the remaining entry count if its load factor fell
+    /// below `min_load_factor`.
+    ///
+    /// Small removals leave the load factor above the threshold and stay in place. A
+    /// fixed-size set keeps its table, since it could not grow back if it were shrunk.
+    ///
+    /// # Returns
+    /// The number of removed entries.
+    pub fn retain_compact<F>(&mut self, f: F, min_load_factor: f64) -> usize
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let removed = self.retain(f);
+        if self.grows && removed > 0 && self.load_factor() < min_load_factor {
+            self.rebuild(self.count * 2);
+        }
+        removed
+    }
+
+    /// Moves every entry into a new table of `table_size` buckets.
+    ///
+    /// A growable set stays growable.
+    pub fn rebuild(&mut self, table_size: usize) {
+        let fresh = BugguHashSet {
+            grows: self.grows,
+            ..Self::new(table_size)
+        };
+        let old = std::mem::replace(self, fresh);
+        for bucket in old.storage {
+            match bucket {
+                BugguBucket::Empty => {}
+                BugguBucket::Inline { entries, len } => {
+                    for (k, v) in entries.into_iter().take(len as usize) {
+                        self.insert(k, v);
+                    }
+                }
+                BugguBucket::Overflow { entries } => {
+                    for (k, v) in entries {
+                        self.insert(k, v);
+                    }
+                }
+            }
+        }
     }
 
     /// Computes the rank (bucket index) for a given key.
@@ -648,27 +897,13 @@ where
     }
 
     /// Performs a fast intersection with a slice of keys.
+    ///
+    /// The keys present in the set are returned in the order of `keys`.
     pub fn fast_intersect_slice(&self, keys: &[K]) -> Vec<K> {
-        let mut result = Vec::new();
-
-        for key in keys {
-            let rank_idx = self.get_rank_for_key(key);
-            let bucket = unsafe { self.storage.get_unchecked(rank_idx) };
-
-            let found = match bucket {
-                BugguBucket::Empty => false,
-                BugguBucket::Inline { entries, len } => {
-                    let current_len = *len as usize;
-                    (0..current_len).any(|i| unsafe { &entries.get_unchecked(i).0 == key })
-                }
-                BugguBucket::Overflow { entries } => entries.iter().any(|(k, _)| k == key),
-            };
-
-            if found {
-                result.push(key.clone());
-            }
-        }
-        result
+        keys.iter()
+            .zip(self.get_many(keys))
+            .filter_map(|(key, value)| value.map(|_| key.clone()))
+            .collect()
     }
 
     /// Creates an index from the hash set based on a field extractor function.
@@ -677,7 +912,19 @@ where
         F: Fn(&V) -> Option<V2>,
         V2: BugguHashable + Eq + PartialEq + Clone + Default,
     {
-        let mut index = BugguHashSet::new(128);
+        self.create_index_into(field_extractor)
+    }
+
+    /// Creates an index like `create_index_for`, collecting the keys of each field
+    /// value into any collection that can be extended with them, such as a set that
+    /// drops duplicates.
+    pub fn create_index_into<F, V2, C>(&self, field_extractor: F) -> BugguHashSet<V2, C>
+    where
+        F: Fn(&V) -> Option<V2>,
+        V2: BugguHashable + Eq + PartialEq + Clone + Default,
+        C: Extend<K> + Clone + Default,
+    {
+        let mut index = BugguHashSet::<V2, C>::new(128);
 
         for bucket in &self.storage {
             match bucket {
@@ -686,20 +933,14 @@ where
                     for i in 0..*len as usize {
                         let (k, v) = unsafe { entries.get_unchecked(i) };
                         if let Some(field_value) = field_extractor(v) {
-                            index
-                                .entry(field_value)
-                                .or_insert_with(Vec::new)
-                                .push(k.clone());
+                            index.entry(field_value).or_default().extend([k.clone()]);
                         }
                     }
                 }
                 BugguBucket::Overflow { entries } => {
                     for (k, v) in entries {
                         if let Some(field_value) = field_extractor(v) {
-                            index
-                                .entry(field_value)
-                                .or_insert_with(Vec::new)
-                                .push(k.clone());
+                            index.entry(field_value).or_default().extend([k.clone()]);
                         }
                     }
                 }
@@ -786,6 +1027,7 @@ where
 
     /// Inserts a key-value pair into the hash set.
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.grow_if_loaded();
         let rank_idx = self.get_rank_for_key(&key);
         let bucket = unsafe { self.storage.get_unchecked_mut(rank_idx) };
 
@@ -839,43 +1081,125 @@ where
         }
     }
 
+    /// Inserts a key-value pair like `insert`, also saying whether the key is new.
+    ///
+    /// # Returns
+    /// `(true, None)` if the key was absent, or `(false, Some(old))` with the value it
+    /// replaced.
+    pub fn insert_or_update(&mut self, key: K, value: V) -> (bool, Option<V>) {
+        let old = self.insert(key, value);
+        (old.is_none(), old)
+    }
+
+    /// Returns a mutable reference to the value for `key`, inserting `default` first
+    /// if the key is absent. See `get_mut_or_insert_with`.
+    #[inline(always)]
+    pub fn get_mut_or_insert(&mut self, key: K, default: V) -> &mut V {
+        self.get_mut_or_insert_with(key, || default)
+    }
+
+    /// Returns a mutable reference to the value for `key`, inserting the value
+    /// `default` returns first if the key is absent.
+    ///
+    /// Unlike `entry(key).or_insert_with(default)`, the bucket is scanned once and no
+    /// entry is built, which suits hot update loops such as posting updates, and
+    /// `default` is only called on a miss.
+    #[inline(always)]
+    pub fn get_mut_or_insert_with(&mut self, key: K, default: impl FnOnce() -> V) -> &mut V {
+        self.grow_if_loaded();
+        let rank_idx = self.get_rank_for_key(&key);
+        let bucket = unsafe { self.storage.get_unchecked_mut(rank_idx) };
+
+        let hit = match &*bucket {
+            BugguBucket::Empty => None,
+            BugguBucket::Inline { entries, len } => {
+                (0..*len as usize).find(|&i| unsafe { entries.get_unchecked(i).0 == key })
+            }
+            BugguBucket::Overflow { entries } => entries.iter().position(|(k, _)| *k == key),
+        };
+        if let Some(i) = hit {
+            return match bucket {
+                BugguBucket::Inline { entries, .. } => unsafe {
+                    &mut entries.get_unchecked_mut(i).1
+                },
+                BugguBucket::Overflow { entries } => unsafe { &mut entries.get_unchecked_mut(i).1 },
+                BugguBucket::Empty => unreachable!(),
+            };
+        }
+
+        // The new pair always goes last in its bucket, so it is found there below.
+        let value = default();
+        self.count += 1;
+        match bucket {
+            BugguBucket::Empty => {
+                let mut entries = core::array::from_fn(|_| (K::default(), V::default()));
+                entries[0] = (key, value);
+                *bucket = BugguBucket::Inline { entries, len: 1 };
+            }
+            BugguBucket::Inline { entries, len } if (*len as usize) < INLINE_BUCKET_SIZE => {
+                entries[*len as usize] = (key, value);
+                *len += 1;
+            }
+            BugguBucket::Inline { entries, .. } => {
+                let mut overflow_vec = Vec::with_capacity(OVERFLOW_BUCKET_SIZE);
+                for item in entries.iter_mut() {
+                    overflow_vec.push(std::mem::take(item));
+                }
+                overflow_vec.push((key, value));
+                *bucket = BugguBucket::Overflow {
+                    entries: overflow_vec,
+                };
+            }
+            BugguBucket::Overflow { entries } => {
+                if entries.len() == entries.capacity() {
+                    entries.reserve(entries.capacity());
+                }
+                entries.push((key, value));
+            }
+        }
+        match bucket {
+            BugguBucket::Inline { entries, len } => unsafe {
+                &mut entries.get_unchecked_mut(*len as usize - 1).1
+            },
+            BugguBucket::Overflow { entries } => &mut entries.last_mut().unwrap().1,
+            BugguBucket::Empty => unreachable!(),
+        }
+    }
+
     /// Gets an entry for the given key, allowing for insertion or modification.
-    pub fn entry(&mut self, key: K) -> BugguEntry<K, V> {
+    pub fn entry(&mut self, key: K) -> BugguEntry<'_, K, V> {
+        self.grow_if_loaded();
         let bucket_idx = self.get_rank_for_key(&key);
 
-        let entry_info: Option<usize> = {
-            let bucket = unsafe { self.storage.get_unchecked(bucket_idx) };
-            match bucket {
-                BugguBucket::Empty => None,
-                BugguBucket::Inline { entries, len } => {
-                    let current_len = *len as usize;
-                    for i in 0..current_len {
-                        if unsafe { &entries.get_unchecked(i).0 } == &key {
-                            return BugguEntry::Occupied(BugguOccupiedEntry {
-                                key,
-                                hashset: self,
-                                bucket_idx,
-                                entry_idx: i,
-                            });
-                        }
+        let bucket = unsafe { self.storage.get_unchecked(bucket_idx) };
+        match bucket {
+            BugguBucket::Empty => {}
+            BugguBucket::Inline { entries, len } => {
+                let current_len = *len as usize;
+                for i in 0..current_len {
+                    if unsafe { &entries.get_unchecked(i).0 } == &key {
+                        return BugguEntry::Occupied(BugguOccupiedEntry {
+                            key,
+                            hashset: self,
+                            bucket_idx,
+                            entry_idx: i,
+                        });
                     }
-                    None
                 }
-                BugguBucket::Overflow { entries } => {
-                    for (i, (k, _)) in entries.iter().enumerate() {
-                        if k == &key {
-                            return BugguEntry::Occupied(BugguOccupiedEntry {
-                                key,
-                                hashset: self,
-                                bucket_idx,
-                                entry_idx: i,
-                            });
-                        }
+            }
+            BugguBucket::Overflow { entries } => {
+                for (i, (k, _)) in entries.iter().enumerate() {
+                    if k == &key {
+                        return BugguEntry::Occupied(BugguOccupiedEntry {
+                            key,
+                            hashset: self,
+                            bucket_idx,
+                            entry_idx: i,
+                        });
                     }
-                    None
                 }
             }
-        };
+        }
 
         BugguEntry::Vacant(BugguVacantEntry {
             key,
@@ -887,7 +1211,25 @@ where
     /// Retrieves a reference to the value associated with the given key.
     #[inline(always)]
     pub fn get(&self, key: &K) -> Option<&V> {
-        let rank_idx = self.get_rank_for_key(key);
+        self.get_in_bucket(self.get_rank_for_key(key), key)
+    }
+
+    /// Retrieves references to the values associated with each of the given keys.
+    ///
+    /// All keys are hashed in a first pass and probed in a second, so the bucket
+    /// reads are independent of each other and their cache misses can overlap. The
+    /// results are returned in the order of `keys`.
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<&V>> {
+        let ranks: Vec<usize> = keys.iter().map(|key| self.get_rank_for_key(key)).collect();
+        keys.iter()
+            .zip(ranks)
+            .map(|(key, rank_idx)| self.get_in_bucket(rank_idx, key))
+            .collect()
+    }
+
+    /// Looks up a key in the bucket at `rank_idx`, which must be the key's rank.
+    #[inline(always)]
+    fn get_in_bucket(&self, rank_idx: usize, key: &K) -> Option<&V> {
         let bucket = unsafe { self.storage.get_unchecked(rank_idx) };
 
         match bucket {
@@ -927,18 +1269,11 @@ where
                     let entry = unsafe { entries.get_unchecked_mut(i) };
                     if entry.0 == *key {
                         let old_value = std::mem::take(&mut entry.1);
-                        unsafe {
-                            let ptr = entries.as_mut_ptr();
-                            for j in i..(current_len - 1) {
-                                let src_ptr = ptr.add(j + 1);
-                                let dst_ptr = ptr.add(j);
-                                std::ptr::copy_nonoverlapping(src_ptr, dst_ptr, 1);
-                            }
-                        }
-                        unsafe {
-                            let last = entries.get_unchecked_mut(current_len - 1);
-                            *last = (K::default(), V::default());
-                        }
+                        // Rotating, rather than copying each entry down, leaves every
+                        // entry owned once, so the removed key is dropped exactly once
+                        // when the freed slot is reset.
+                        entries[i..current_len].rotate_left(1);
+                        entries[current_len - 1] = (K::default(), V::default());
                         *len -= 1;
                         if *len == 0 {
                             *bucket = BugguBucket::Empty;
@@ -953,22 +1288,7 @@ where
                 for i in 0..entries.len() {
                     if unsafe { &entries.get_unchecked(i).0 } == key {
                         let (_, old_value) = entries.swap_remove(i);
-                        if entries.len() <= INLINE_BUCKET_SIZE {
-                            let entries_len = entries.len();
-                            let mut inline_entries =
-                                core::array::from_fn(|_| (K::default(), V::default()));
-                            for (i, entry) in entries.drain(..).enumerate() {
-                                inline_entries[i] = entry;
-                            }
-                            *bucket = if entries_len == 0 {
-                                BugguBucket::Empty
-                            } else {
-                                BugguBucket::Inline {
-                                    entries: inline_entries,
-                                    len: entries_len as u8,
-                                }
-                            };
-                        }
+                        Self::shrink_overflow(bucket);
                         self.count -= 1;
                         return Some(old_value);
                     }
@@ -978,6 +1298,88 @@ where
         }
     }
 
+    /// Removes every key in `keys` from the hash set.
+    ///
+    /// The keys are grouped by bucket, so each bucket is visited once and an overflow
+    /// bucket is compacted once after all of its removals rather than after each one.
+    /// Keys that are absent, or repeated, are skipped.
+    ///
+    /// # Returns
+    /// The number of entries actually removed.
+    pub fn remove_batch(&mut self, keys: &[K]) -> usize {
+        let mut ranked: Vec<(usize, &K)> = keys
+            .iter()
+            .map(|key| (self.get_rank_for_key(key), key))
+            .collect();
+        ranked.sort_unstable_by_key(|&(rank_idx, _)| rank_idx);
+
+        let mut removed = 0;
+        for group in ranked.chunk_by(|a, b| a.0 == b.0) {
+            let is_removed = |key: &K| group.iter().any(|&(_, k)| k == key);
+            let bucket = unsafe { self.storage.get_unchecked_mut(group[0].0) };
+            match bucket {
+                BugguBucket::Empty => {}
+                BugguBucket::Inline { entries, len } => {
+                    let current_len = *len as usize;
+                    let mut kept = 0;
+                    for i in 0..current_len {
+                        if is_removed(&entries[i].0) {
+                            entries[i] = (K::default(), V::default());
+                        } else {
+                            entries.swap(kept, i);
+                            kept += 1;
+                        }
+                    }
+                    removed += current_len - kept;
+                    *len = kept as u8;
+                    if kept == 0 {
+                        *bucket = BugguBucket::Empty;
+                    }
+                }
+                BugguBucket::Overflow { entries } => {
+                    let before = entries.len();
+                    entries.retain(|(key, _)| !is_removed(key));
+                    removed += before - entries.len();
+                    Self::shrink_overflow(bucket);
+                }
+            }
+        }
+        self.count -= removed;
+        removed
+    }
+
+    /// Moves the entries of an overflow bucket back inline once they fit, or empties
+    /// the bucket if none are left.
+    ///
+    /// A bucket that stays an overflow has its vector shrunk to twice its length once
+    /// the capacity reaches `OVERFLOW_SHRINK_FACTOR` times the length, so a bucket
+    /// that briefly held many entries gives their memory back. Shrinking only that
+    /// far behind keeps alternating inserts and removals from reallocating each time.
+    fn shrink_overflow(bucket: &mut BugguBucket<K, V>) {
+        let BugguBucket::Overflow { entries } = bucket else {
+            return;
+        };
+        if entries.len() > INLINE_BUCKET_SIZE {
+            if entries.capacity() >= entries.len() * OVERFLOW_SHRINK_FACTOR {
+                entries.shrink_to((entries.len() * 2).max(OVERFLOW_BUCKET_SIZE));
+            }
+            return;
+        }
+        let entries_len = entries.len();
+        let mut inline_entries = core::array::from_fn(|_| (K::default(), V::default()));
+        for (i, entry) in entries.drain(..).enumerate() {
+            inline_entries[i] = entry;
+        }
+        *bucket = if entries_len == 0 {
+            BugguBucket::Empty
+        } else {
+            BugguBucket::Inline {
+                entries: inline_entries,
+                len: entries_len as u8,
+            }
+        };
+    }
+
     /// Updates the value associated with a key.
     #[inline(always)]
     pub fn update(&mut self, key: &K, value: V) -> Option<V> {
@@ -1063,7 +1465,7 @@ where
     }
 
     /// Returns an iterator over the keys of the hash set.
-    pub fn iter_keys(&self) -> BugguKeyIterator<K, V> {
+    pub fn iter_keys(&self) -> BugguKeyIterator<'_, K, V> {
         BugguKeyIterator {
             storage: &self.storage,
             bucket_idx: 0,
@@ -1072,8 +1474,17 @@ where
         }
     }
 
+    /// Returns an iterator over the entries of the hash set.
+    pub fn iter(&self) -> BugguIter<'_, K, V> {
+        BugguIter {
+            storage: self.storage.iter(),
+            current: [].iter(),
+            remaining: self.count,
+        }
+    }
+
     /// Returns a mutable iterator over the entries of the hash set.
-    pub fn iter_mut(&mut self) -> BugguIterMut<K, V> {
+    pub fn iter_mut(&mut self) -> BugguIterMut<'_, K, V> {
         BugguIterMut::new(&mut self.storage, self.count)
     }
 
@@ -1098,4 +1509,440 @@ where
         }
         (empty, inline, overflow)
     }
+
+    /// Returns the number of buckets allocated in the table.
+    #[inline]
+    pub fn bucket_count(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Returns the memory held by the bucket table and its overflow vectors, in bytes.
+    ///
+    /// Heap memory owned by the keys and values themselves is not counted.
+    pub fn table_bytes(&self) -> usize {
+        let overflow: usize = self
+            .storage
+            .iter()
+            .map(|bucket| match bucket {
+                BugguBucket::Overflow { entries } => entries.capacity(),
+                _ => 0,
+            })
+            .sum();
+        self.storage.capacity() * std::mem::size_of::<BugguBucket<K, V>>()
+            + overflow * std::mem::size_of::<(K, V)>()
+    }
+
+    /// Returns the average number of entries per bucket.
+    pub fn load_factor(&self) -> f64 {
+        if self.storage.is_empty() {
+            return 0.0;
+        }
+        self.count as f64 / self.storage.len() as f64
+    }
+
+    /// Returns the number of entries held by the most populated bucket.
+    pub fn max_chain_len(&self) -> usize {
+        self.storage
+            .iter()
+            .map(|bucket| match bucket {
+                BugguBucket::Empty => 0,
+                BugguBucket::Inline { len, .. } => *len as usize,
+                BugguBucket::Overflow { entries } => entries.len(),
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl<K, V> BugguHashSet<K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default + Hash,
+    V: Clone + Default,
+{
+    /// Copies the entries into a `std::collections::HashMap`.
+    pub fn to_std_hashmap(&self) -> HashMap<K, V> {
+        self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    /// Builds a `BugguHashSet` from a `std::collections::HashMap`, with one bucket
+    /// per entry.
+    pub fn from_std_hashmap<S>(map: HashMap<K, V, S>) -> Self {
+        let mut set = Self::new(map.len());
+        for (key, value) in map {
+            set.insert(key, value);
+        }
+        set
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K, V> BugguHashSet<K, V>
+where
+    K: BugguHashable + Eq + PartialEq + Clone + Default + Send + Sync,
+    V: Clone + Default + Sync,
+{
+    /// Computes the intersection of two hash sets like `intersect_with`, probing the
+    /// larger set from several threads.
+    ///
+    /// The keys of the smaller set are split across the rayon thread pool and the
+    /// matches gathered back in order, so the result is built exactly as
+    /// `intersect_with` builds it and iterates in the same order.
+    pub fn par_intersect_with(&self, other: &BugguHashSet<K, V>) -> BugguHashSet<K, ()> {
+        use rayon::prelude::*;
+
+        let (smaller, larger) = if self.len() < other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        let matches: Vec<K> = smaller
+            .keys()
+            .into_par_iter()
+            .filter(|k| larger.get(k).is_some())
+            .collect();
+        let mut result = BugguHashSet::new(smaller.len());
+        for k in matches {
+            result.insert(k, ());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Counts a fixed pseudo-random key sequence with `get_mut_or_insert_with` and
+    /// with a `HashMap`, and checks they agree.
+    fn check_counts(mut set: BugguHashSet<u64, u32>) {
+        let mut expected: HashMap<u64, u32> = HashMap::new();
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for _ in 0..20_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let key = state % 1_500;
+            *set.get_mut_or_insert_with(key, || 0) += 1;
+            *expected.entry(key).or_default() += 1;
+        }
+        assert_eq!(set.len(), expected.len());
+        for (key, count) in &expected {
+            assert_eq!(set.get(key), Some(count), "key {key}");
+        }
+    }
+
+    #[test]
+    fn get_mut_or_insert_matches_hash_map() {
+        // A fixed table of four buckets spills every bucket into overflow.
+        check_counts(BugguHashSet::new(4));
+        // A growable table rebuilds several times along the way.
+        check_counts(BugguHashSet::growable(1));
+    }
+
+    #[test]
+    fn get_mut_or_insert_calls_default_only_on_miss() {
+        let mut set: BugguHashSet<u64, Vec<u64>> = BugguHashSet::new(2);
+        let mut calls = 0;
+        for key in [7, 9, 7, 7, 11, 9] {
+            set.get_mut_or_insert_with(key, || {
+                calls += 1;
+                Vec::new()
+            })
+            .push(key);
+        }
+        assert_eq!(calls, 3);
+        assert_eq!(set.get(&7), Some(&vec![7, 7, 7]));
+        assert_eq!(set.get(&9), Some(&vec![9, 9]));
+
+        *set.get_mut_or_insert(11, vec![0]) = vec![1];
+        assert_eq!(set.get(&11), Some(&vec![1]));
+        assert_eq!(set.get_mut_or_insert(13, vec![13]), &vec![13]);
+        assert_eq!(set.len(), 4);
+    }
+
+    #[test]
+    fn remove_from_inline_bucket_keeps_other_values() {
+        // One bucket, so every key shares the inline entries.
+        let mut set: BugguHashSet<u64, String> = BugguHashSet::new(1);
+        for key in 0..INLINE_BUCKET_SIZE as u64 {
+            set.insert(key, format!("value {key}"));
+        }
+        assert_eq!(set.remove(&0), Some("value 0".to_string()));
+        assert_eq!(set.remove(&0), None);
+        for key in 1..INLINE_BUCKET_SIZE as u64 {
+            assert_eq!(set.get(&key), Some(&format!("value {key}")));
+        }
+        set.insert(0, "again".to_string());
+        for key in 0..INLINE_BUCKET_SIZE as u64 {
+            assert!(set.remove(&key).is_some());
+        }
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn zero_sized_tables_hold_entries() {
+        for mut set in [
+            BugguHashSet::<u64, u64>::new(0),
+            BugguHashSet::growable(0),
+            BugguHashSet::default(),
+        ] {
+            assert_eq!(set.storage.len(), 1);
+            assert_eq!(set.get(&3), None);
+            assert_eq!(set.remove(&3), None);
+            for key in 0..100 {
+                set.insert(key, key * 2);
+            }
+            assert_eq!(set.len(), 100);
+            assert_eq!(set.get(&42), Some(&84));
+            assert_eq!(set.remove(&42), Some(84));
+            assert_eq!(set.get(&42), None);
+            assert_eq!(set.fast_intersect_slice(&[1, 42, 500, 99]), [1, 99]);
+        }
+    }
+
+    #[test]
+    fn get_many_matches_get() {
+        for mut set in [
+            BugguHashSet::<u64, String>::new(3),
+            BugguHashSet::growable(1),
+        ] {
+            for key in (0..600).step_by(3) {
+                set.insert(key, format!("v{key}"));
+            }
+            set.remove(&300);
+            let keys: Vec<u64> = (0..700).rev().chain([0, 0, 3]).collect();
+            let many = set.get_many(&keys);
+            assert_eq!(many.len(), keys.len());
+            for (key, value) in keys.iter().zip(&many) {
+                assert_eq!(*value, set.get(key), "key {key}");
+            }
+            assert_eq!(many.iter().filter(|v| v.is_some()).count(), 199 + 3);
+            assert!(set.get_many(&[]).is_empty());
+        }
+    }
+
+    #[test]
+    fn retain_returns_the_number_removed() {
+        let mut set: BugguHashSet<u64, String> = BugguHashSet::new(8);
+        for key in 0..500 {
+            set.insert(key, key.to_string());
+        }
+        let mut rejected = 0;
+        let removed = set.retain(|key, value| {
+            value.push('!');
+            let keep = key % 3 != 0;
+            rejected += usize::from(!keep);
+            keep
+        });
+        assert_eq!(removed, rejected);
+        assert_eq!(removed, 167);
+        assert_eq!(set.len(), 500 - 167);
+        assert_eq!(set.get(&3), None);
+        assert_eq!(set.get(&4), Some(&"4!".to_string()));
+        assert_eq!(set.retain(|_, _| true), 0);
+    }
+
+    #[test]
+    fn retain_compact_shrinks_sparse_tables() {
+        let mut set: BugguHashSet<u64, u64> = BugguHashSet::growable(256);
+        for key in 0..1_000 {
+            set.insert(key, key);
+        }
+        let grown = set.storage.len();
+        // A small removal leaves the table alone.
+        assert_eq!(set.retain_compact(|key, _| *key != 7, 0.5), 1);
+        assert_eq!(set.storage.len(), grown);
+
+        assert_eq!(set.retain_compact(|key, _| *key < 40, 0.5), 960);
+        assert_eq!(set.len(), 39);
+        assert!(set.storage.len() < grown);
+        for key in 0..1_000 {
+            assert_eq!(set.get(&key).is_some(), key < 40 && key != 7, "key {key}");
+        }
+        // Shrunk below its starting size, the table still grows back as needed.
+        for key in 1_000..2_000 {
+            set.insert(key, key);
+        }
+        assert!(set.storage.len() >= 1_039 / GROW_LOAD_FACTOR);
+    }
+
+    #[test]
+    fn retain_compact_keeps_the_table_of_fixed_size_sets() {
+        let mut set: BugguHashSet<u64, u64> = BugguHashSet::new(256);
+        for key in 0..1_000 {
+            set.insert(key, key);
+        }
+        assert_eq!(set.retain_compact(|key, _| *key < 40, 0.5), 960);
+        assert_eq!(set.len(), 40);
+        assert_eq!(set.storage.len(), 256);
+        for key in 0..40 {
+            assert_eq!(set.get(&key), Some(&key));
+        }
+    }
+
+    #[test]
+    fn into_iter_yields_every_entry_by_value_and_by_reference() {
+        let mut set: BugguHashSet<u64, String> = BugguHashSet::new(4);
+        for key in 0..50 {
+            set.insert(key, format!("v{key}"));
+        }
+        let mut borrowed: Vec<(u64, String)> = Vec::new();
+        for (key, value) in &set {
+            borrowed.push((*key, value.clone()));
+        }
+        for (_, value) in &mut set {
+            value.push('!');
+        }
+        let mut owned: Vec<(u64, String)> = set.into_iter().collect();
+        borrowed.sort();
+        owned.sort();
+        assert_eq!(borrowed.len(), 50);
+        assert_eq!(borrowed[7], (7, "v7".to_string()));
+        assert_eq!(owned.len(), 50);
+        assert_eq!(owned[7], (7, "v7!".to_string()));
+    }
+
+    #[test]
+    fn into_iter_moves_values_out_without_cloning() {
+        let shared = std::rc::Rc::new(0u64);
+        // Two buckets, so some spill into overflow.
+        let mut set: BugguHashSet<u64, std::rc::Rc<u64>> = BugguHashSet::new(2);
+        for key in 0..40 {
+            set.insert(key, std::rc::Rc::clone(&shared));
+        }
+        assert_eq!(std::rc::Rc::strong_count(&shared), 41);
+
+        let mut iter = set.into_iter();
+        assert_eq!(iter.size_hint(), (40, Some(40)));
+        let taken: Vec<_> = iter.by_ref().take(15).collect();
+        assert_eq!(iter.size_hint(), (25, Some(25)));
+        assert_eq!(std::rc::Rc::strong_count(&shared), 41);
+        // Dropping a half-used iterator drops the entries it still holds.
+        drop(iter);
+        assert_eq!(std::rc::Rc::strong_count(&shared), 16);
+        drop(taken);
+        assert_eq!(std::rc::Rc::strong_count(&shared), 1);
+
+        let empty: BugguHashSet<u64, u64> = BugguHashSet::new(0);
+        assert_eq!(empty.into_iter().next(), None);
+    }
+
+    #[test]
+    fn or_default_groups_values_into_vecs() {
+        let words = [
+            "error", "disk", "full", "retry", "auth", "token", "db", "ok",
+        ];
+        let mut by_len: BugguHashSet<u64, Vec<&str>> = BugguHashSet::new(2);
+        let mut expected: HashMap<u64, Vec<&str>> = HashMap::new();
+        for word in words {
+            let len = word.len() as u64;
+            by_len.entry(len).or_default().push(word);
+            expected.entry(len).or_default().push(word);
+        }
+        assert_eq!(by_len.len(), expected.len());
+        for (len, group) in &expected {
+            assert_eq!(by_len.get(len), Some(group), "length {len}");
+        }
+        assert_eq!(by_len.get(&5), Some(&vec!["error", "retry", "token"]));
+        // An occupied entry hands back the existing vector.
+        assert!(by_len.entry(2).or_default().contains(&"ok"));
+        assert_eq!(by_len.get(&2).map(Vec::len), Some(2));
+    }
+
+    #[test]
+    fn round_trips_through_a_std_hash_map() {
+        let mut set: BugguHashSet<u64, u32> = BugguHashSet::new(8);
+        for key in 0..500u64 {
+            set.insert(key * 7919, key as u32);
+        }
+        let map = set.to_std_hashmap();
+        assert_eq!(map.len(), 500);
+        assert_eq!(map.get(&(42 * 7919)), Some(&42));
+
+        let back = BugguHashSet::from_std_hashmap(map.clone());
+        assert_eq!(back.len(), 500);
+        for (key, value) in set.iter() {
+            assert_eq!(back.get(key), Some(value), "key {key}");
+        }
+        assert_eq!(back.to_std_hashmap(), map);
+
+        let empty = BugguHashSet::<u64, u32>::from_std_hashmap(HashMap::new());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn remove_batch_removes_exactly_the_listed_keys() {
+        // Few buckets, so most of them spill into overflow.
+        let mut set: BugguHashSet<u64, String> = BugguHashSet::new(16);
+        for key in 0..1_000u64 {
+            set.insert(key, key.to_string());
+        }
+        let mut evens: Vec<u64> = (0..1_000).step_by(2).collect();
+        // An absent key and a repeated one are skipped.
+        evens.extend([5_000, 0]);
+        assert_eq!(set.remove_batch(&evens), 500);
+        assert_eq!(set.len(), 500);
+        for key in 0..1_000u64 {
+            if key % 2 == 0 {
+                assert_eq!(set.get(&key), None, "{key} should be gone");
+            } else {
+                assert_eq!(set.get(&key), Some(&key.to_string()));
+            }
+        }
+        assert_eq!(set.remove_batch(&evens), 0);
+        assert_eq!(set.remove_batch(&[]), 0);
+    }
+
+    #[test]
+    fn removing_most_of_an_overflow_bucket_gives_its_memory_back() {
+        // A fixed table of one bucket keeps every entry in the same overflow vector.
+        let mut set: BugguHashSet<u64, u64> = BugguHashSet::new(1);
+        for key in 0..1_000 {
+            set.insert(key, key * 2);
+        }
+        let full = set.table_bytes();
+        assert!(full >= 1_000 * std::mem::size_of::<(u64, u64)>());
+
+        for key in 20..1_000 {
+            assert_eq!(set.remove(&key), Some(key * 2));
+        }
+        let shrunk = set.table_bytes();
+        assert!(shrunk * 10 < full, "{shrunk} bytes of {full} kept");
+        assert_eq!(set.len(), 20);
+        assert!((0..20).all(|key| set.get(&key) == Some(&(key * 2))));
+
+        // Refilling and retaining a few shrinks the bucket the same way.
+        for key in 20..1_000 {
+            set.insert(key, key * 2);
+        }
+        assert_eq!(set.retain(|key, _| *key < 20), 980);
+        assert!(set.table_bytes() * 10 < full);
+        // Once the entries fit inline, no overflow vector is left.
+        for key in 2..20 {
+            set.remove(&key);
+        }
+        assert_eq!(
+            set.table_bytes(),
+            std::mem::size_of::<BugguBucket<u64, u64>>()
+        );
+    }
+
+    #[test]
+    fn insert_or_update_reports_whether_the_key_was_new() {
+        for mut set in [BugguHashSet::new(1), BugguHashSet::growable(1)] {
+            // A default value stored for a key is not mistaken for an absent key.
+            assert_eq!(set.insert_or_update(7u64, 0u32), (true, None));
+            assert_eq!(set.insert_or_update(7, 5), (false, Some(0)));
+            assert_eq!(set.insert_or_update(7, 9), (false, Some(5)));
+            assert_eq!(set.get(&7), Some(&9));
+            // Past the inline slots of the single bucket too.
+            for key in 100..120 {
+                assert_eq!(set.insert_or_update(key, 1), (true, None));
+            }
+            assert_eq!(set.insert_or_update(119, 2), (false, Some(1)));
+            assert_eq!(set.len(), 21);
+        }
+    }
 }
@@ -23,8 +23,8 @@ use crate::utils::buggu_ultra_fast_hash::buggu_hash_u64_branchless;
 /// core generation logic.
 ///
 /// # Example
-/// ```rust
-/// use your_crate::BugguRng;
+/// ```rust,no_run
+/// use buggu::utils::buggu_random_generator::BugguRng;
 /// let mut rng = BugguRng::new(42);
 /// let value = rng.range(1, 100);
 /// ```
@@ -32,6 +32,11 @@ pub const FAST_K1: u64 = 0x517cc1b727220a95;
 /// that the pointer `bytes.as_ptr()` is valid and that reading chunks of memory will
 /// not go out of bounds. This is safe for string slices, which are guaranteed to be
 /// valid UTF-8 and have a known length.
+///
+/// Each arm reads at most as many bytes as the length it matches, and
+/// `read_unaligned` has no alignment requirement, so this also holds on `wasm32`.
+/// WebAssembly is little-endian like the native targets, so both builds produce the
+/// same hashes.
 #[inline(always)]
 pub fn lightning_hash_str(s: &str) -> u64 {
     let bytes = s.as_bytes();
@@ -131,3 +136,41 @@ pub fn lightning_hash_str_64(s: &str) -> u64 {
     };
     buggu_hash_u64_minimal(data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hashes `s` the way `lightning_hash_str` does, with safe little-endian reads.
+    fn reference_hash(s: &str) -> u64 {
+        let bytes = s.as_bytes();
+        let read = match bytes.len() {
+            0 => return FAST_K1,
+            1 => 1,
+            2..=3 => 2,
+            4..=5 => 4,
+            _ => 6,
+        };
+        let mut data = [0u8; 8];
+        data[..read].copy_from_slice(&bytes[..read]);
+        buggu_hash_u64_minimal(u64::from_le_bytes(data))
+    }
+
+    #[test]
+    fn unaligned_reads_match_safe_little_endian_reads() {
+        let text = "level:ERROR service=auth msg=\"token refresh failed\" ünïcödé";
+        // Every start offset, so the reads land on every alignment, and every length
+        // up to past the six bytes the hash reads.
+        for start in 0..text.len() {
+            for end in start..text.len().min(start + 9) {
+                let Some(s) = text.get(start..end) else {
+                    continue;
+                };
+                assert_eq!(lightning_hash_str(s), reference_hash(s), "{s:?}");
+                assert_eq!(lightning_hash_str_64(s), lightning_hash_str(s), "{s:?}");
+                let shifted = format!(" {s}");
+                assert_eq!(lightning_hash_str(s), lightning_hash_str(&shifted[1..]));
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! # WebAssembly Bindings
+//!
+//! This module exposes a `MicroSearch` class to JavaScript through `wasm-bindgen`,
+//! mirroring the Node.js addon so the same index can run in a browser, a Cloudflare
+//! Worker or any other WebAssembly host. It is compiled only with the `wasm` feature.
+
+use crate::logdb::{IngestError, LogDB};
+use crate::types::DocId;
+use wasm_bindgen::prelude::*;
+
+/// Parses a document ID received from JavaScript, rejecting malformed input.
+fn parse_doc_id(id: &str) -> Result<DocId, JsError> {
+    id.parse::<DocId>()
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Converts the result of an upsert into the document ID as a string, or an error
+/// saying why the entry was not inserted.
+fn inserted(result: Result<DocId, IngestError>) -> Result<String, JsError> {
+    result
+        .map(|doc_id| doc_id.to_string())
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Bucket distribution statistics for the postings table, returned by `bucketStats()`.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct BucketStats {
+    /// The number of buckets holding no tokens.
+    pub empty: u32,
+    /// The number of buckets storing their tokens inline.
+    pub inline: u32,
+    /// The number of buckets that spilled into a heap-allocated overflow vector.
+    pub overflow: u32,
+    /// The average number of tokens per bucket.
+    #[wasm_bindgen(js_name = loadFactor)]
+    pub load_factor: f64,
+    /// The number of tokens in the most populated bucket.
+    #[wasm_bindgen(js_name = maxChainLen)]
+    pub max_chain_len: u32,
+}
+
+/// A document matched by a ranked query, returned by `queryRanked()`.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct RankedHit {
+    /// The document ID.
+    pub id: String,
+    /// The relevance score of the document; higher is more relevant.
+    pub score: f64,
+}
+
+/// A high-performance, in-memory search engine exposed to WebAssembly hosts.
+///
+/// The `MicroSearch` struct wraps the `LogDB` with the same methods as the Node.js
+/// addon, so JavaScript code can switch between the two builds unchanged.
+#[wasm_bindgen]
+pub struct MicroSearch {
+    /// The underlying `LogDB` instance that handles the actual search and indexing logic.
+    inner: LogDB,
+}
+
+#[wasm_bindgen]
+impl MicroSearch {
+    /// Creates a new instance of `MicroSearch`.
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            inner: LogDB::new(),
+        }
+    }
+
+    /// Inserts a simple document with the given content.
+    ///
+    /// # Returns
+    /// The document ID as a string, or an error if the ingest hook rejected the entry.
+    #[wasm_bindgen(js_name = upsertSimple)]
+    pub fn upsert_simple(&mut self, content: &str) -> Result<String, JsError> {
+        inserted(self.inner.upsert_simple(content))
+    }
+
+    /// Inserts a log entry with an optional level and service.
+    ///
+    /// # Returns
+    /// The document ID as a string, or an error if the ingest hook rejected the entry.
+    #[wasm_bindgen(js_name = upsertLog)]
+    pub fn upsert_log(
+        &mut self,
+        content: &str,
+        level: Option<String>,
+        service: Option<String>,
+    ) -> Result<String, JsError> {
+        inserted(self.inner.upsert_log(content, level, service))
+    }
+
+    /// Inserts a log entry tagged with several services, so that a `service:` query
+    /// for any of them finds it.
+    ///
+    /// # Returns
+    /// The document ID as a string, or an error if the ingest hook rejected the entry.
+    #[wasm_bindgen(js_name = upsertLogServices)]
+    pub fn upsert_log_services(
+        &mut self,
+        content: &str,
+        level: Option<String>,
+        services: Vec<String>,
+    ) -> Result<String, JsError> {
+        inserted(self.inner.upsert_log_services(content, level, services))
+    }
+
+    /// Inserts a log entry like `upsertLog`, but indexes only its content as words.
+    ///
+    /// # Returns
+    /// The document ID as a string, or an error if the ingest hook rejected the entry.
+    #[wasm_bindgen(js_name = upsertV2)]
+    pub fn upsert_v2(
+        &mut self,
+        content: &str,
+        level: Option<String>,
+        service: Option<String>,
+    ) -> Result<String, JsError> {
+        inserted(self.inner.upsert_v2(content, level, service))
+    }
+
+    /// Adds a synonym ring, so that a query for any of its words also matches
+    /// documents containing the others.
+    #[wasm_bindgen(js_name = addSynonyms)]
+    pub fn add_synonyms(&mut self, words: Vec<String>) {
+        let ring: Vec<&str> = words.iter().map(String::as_str).collect();
+        self.inner.add_synonyms(&ring);
+    }
+
+    /// Executes a search query and returns the matching document IDs.
+    pub fn query(&self, query: &str) -> Vec<String> {
+        self.inner
+            .query(query)
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    /// Executes a search query restricted to the last `secs` seconds.
+    #[wasm_bindgen(js_name = queryLast)]
+    pub fn query_last(&self, query: &str, secs: u32) -> Vec<String> {
+        self.inner
+            .query_last(query, secs as u64)
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    /// Executes a search query and returns up to `limit` matching document IDs,
+    /// newest first.
+    #[wasm_bindgen(js_name = queryRecent)]
+    pub fn query_recent(&self, query: &str, limit: u32) -> Vec<String> {
+        self.inner
+            .query_recent(query, limit as usize)
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    /// Executes a search query and returns the matching documents ranked by relevance,
+    /// highest first.
+    #[wasm_bindgen(js_name = queryRanked)]
+    pub fn query_ranked(&self, query: &str) -> Vec<RankedHit> {
+        self.inner
+            .query_ranked(query)
+            .into_iter()
+            .map(|(id, score)| RankedHit {
+                id: id.to_string(),
+                score: score as f64,
+            })
+            .collect()
+    }
+
+    /// Executes a search query and returns the matching document IDs, most severe
+    /// first.
+    #[wasm_bindgen(js_name = queryBySeverity)]
+    pub fn query_by_severity(&self, query: &str) -> Vec<String> {
+        self.inner
+            .query_by_severity(query)
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    /// Executes a search query and returns the content of the matching documents.
+    #[wasm_bindgen(js_name = queryContent)]
+    pub fn query_content(&self, query: &str) -> Vec<String> {
+        self.inner.query_content(query)
+    }
+
+    /// Deletes a document from the index.
+    ///
+    /// # Returns
+    /// `true` if the document was deleted, or `false` if no document has this ID. An
+    /// error is thrown if `id` is not a valid document ID.
+    pub fn delete(&mut self, id: &str) -> Result<bool, JsError> {
+        let doc_id = parse_doc_id(id)?;
+        Ok(self.inner.delete(&doc_id))
+    }
+
+    /// Returns bucket distribution statistics for the postings table.
+    #[wasm_bindgen(js_name = bucketStats)]
+    pub fn bucket_stats(&self) -> BucketStats {
+        let (empty, inline, overflow) = self.inner.postings_bucket_stats();
+        BucketStats {
+            empty: empty as u32,
+            inline: inline as u32,
+            overflow: overflow as u32,
+            load_factor: self.inner.postings_load_factor(),
+            max_chain_len: self.inner.postings_max_chain_len() as u32,
+        }
+    }
+
+    /// Returns the index metrics in the Prometheus text exposition format.
+    ///
+    /// Query latencies are not measured on `wasm32-unknown-unknown`, so every query
+    /// is reported in the lowest latency bucket there.
+    #[wasm_bindgen(js_name = metricsText)]
+    pub fn metrics_text(&self) -> String {
+        self.inner.metrics_text()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_cross_the_boundary_as_strings() {
+        let mut search = MicroSearch::new();
+        let first = search
+            .upsert_log(
+                "disk full",
+                Some("ERROR".to_string()),
+                Some("db".to_string()),
+            )
+            .unwrap();
+        let second = search
+            .upsert_log_services(
+                "disk almost full",
+                Some("WARN".to_string()),
+                vec!["db".to_string(), "backup".to_string()],
+            )
+            .unwrap();
+        search.upsert_simple("all good").unwrap();
+        assert_eq!(first.parse::<DocId>().unwrap().to_string(), first);
+
+        let mut disk = search.query("disk");
+        disk.sort();
+        assert_eq!(disk, [first.clone(), second.clone()]);
+        assert_eq!(search.query("service:backup"), [second.as_str()]);
+        assert_eq!(
+            search.query_by_severity("full"),
+            [first.clone(), second.clone()]
+        );
+        assert_eq!(search.query_recent("disk", 1), [second.as_str()]);
+        assert_eq!(search.query_content("level:ERROR"), ["disk full"]);
+
+        assert!(search.delete(&first).unwrap());
+        assert!(!search.delete(&first).unwrap());
+        assert_eq!(search.query("disk"), [second]);
+    }
+
+    #[test]
+    fn synonyms_and_ranking_match_the_library() {
+        let mut search = MicroSearch::new();
+        let db = search.upsert_simple("db timeout").unwrap();
+        let database = search.upsert_simple("database timeout").unwrap();
+        search.add_synonyms(vec!["db".to_string(), "database".to_string()]);
+        let mut both = search.query("db");
+        both.sort();
+        assert_eq!(both, [db, database]);
+
+        let ranked = search.query_ranked("database timeout");
+        assert_eq!(ranked.len(), 2);
+        assert!(ranked[0].score >= ranked[1].score && ranked[1].score > 0.0);
+    }
+
+    #[test]
+    fn bucket_stats_count_every_bucket() {
+        let mut search = MicroSearch::new();
+        for i in 0..200 {
+            search.upsert_simple(&format!("event{}", i % 50)).unwrap();
+        }
+        let stats = search.bucket_stats();
+        assert!(stats.inline + stats.overflow > 0);
+        assert!(stats.load_factor > 0.0 && stats.load_factor <= 2.0);
+        assert!(stats.max_chain_len >= 1);
+        assert!(search.metrics_text().contains("buggu_inserts_total 200\n"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Runs the `micro-search` binary over a fixture log, as in
+//! `tail -f app.log | micro-search --stdin --query ...`.
+//!
+//! Run with `cargo test --no-default-features --features cli --test cli`.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
+
+const FIXTURE: &str = include_str!("fixtures/app.log");
+
+/// Runs the binary with `args`, piping `input` to its standard input.
+fn run(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_micro-search"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn lines(bytes: &[u8]) -> Vec<&str> {
+    std::str::from_utf8(bytes).unwrap().lines().collect()
+}
+
+#[test]
+fn echoes_lines_matching_the_query() {
+    let output = run(
+        &["--stdin", "--format", "logfmt", "--query", "level:ERROR"],
+        FIXTURE,
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        lines(&output.stdout),
+        [
+            r#"level=ERROR service=auth msg="token refresh failed" user=bo"#,
+            r#"level=ERROR service=billing msg="charge declined" user=ana"#,
+            r#"level=ERROR service=search msg="query timed out" slow"#,
+        ]
+    );
+    assert_eq!(
+        lines(&output.stderr),
+        ["micro-search: read 6 lines: 6 indexed, 0 failed, 3 matched"]
+    );
+}
+
+#[test]
+fn echoes_each_match_before_the_input_ends() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_micro-search"))
+        .args(["--stdin", "--query", "boom"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut echoed = String::new();
+    for line in ["quiet line", "first boom"] {
+        writeln!(stdin, "{line}").unwrap();
+    }
+    stdin.flush().unwrap();
+    stdout.read_line(&mut echoed).unwrap();
+    assert_eq!(echoed, "first boom\n");
+
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn queries_see_fields_and_words() {
+    let output = run(
+        &[
+            "--stdin",
+            "--format",
+            "logfmt",
+            "--query",
+            "user:ana -service:auth",
+        ],
+        FIXTURE,
+    );
+    assert_eq!(
+        lines(&output.stdout),
+        [r#"level=ERROR service=billing msg="charge declined" user=ana"#]
+    );
+
+    let output = run(&["--stdin", "--query", "timed"], FIXTURE);
+    assert_eq!(
+        lines(&output.stdout),
+        [r#"level=ERROR service=search msg="query timed out" slow"#]
+    );
+}
+
+#[test]
+fn reads_json_lines() {
+    let input = "{\"level\":\"ERROR\",\"msg\":\"disk full\"}\nnot json\n{\"msg\":\"ok\"}\n";
+    let output = run(&["--stdin", "--format", "json", "--query", "disk"], input);
+    assert!(output.status.success());
+    assert_eq!(
+        lines(&output.stdout),
+        [r#"{"level":"ERROR","msg":"disk full"}"#]
+    );
+    assert_eq!(
+        lines(&output.stderr),
+        ["micro-search: read 3 lines: 2 indexed, 1 failed, 1 matched"]
+    );
+}
+
+#[test]
+fn prints_stats_every_n_lines() {
+    let output = run(&["--stdin", "--stats-every", "2"], FIXTURE);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = lines(&output.stderr);
+    assert_eq!(stderr.len(), 4);
+    assert!(stderr[0].starts_with("stats: 2 lines, 2 indexed, 0 failed, 0 matched, 2 docs, ~"));
+    assert!(stderr[2].starts_with("stats: 6 lines, 6 indexed,"));
+    assert_eq!(
+        stderr[3],
+        "micro-search: read 6 lines: 6 indexed, 0 failed, 0 matched"
+    );
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    for args in [
+        &[][..],
+        &["--stdin", "--format", "xml"],
+        &["--stdin", "--stats-every", "0"],
+        &["--stdin", "--query"],
+        &["--stdin", "--query", "\"unterminated"],
+        &["--verbose"],
+    ] {
+        let output = run(args, "");
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(output.stdout.is_empty(), "{args:?}");
+    }
+    let output = run(&["--help"], "");
+    assert!(output.status.success());
+    assert!(lines(&output.stdout)[0].starts_with("Usage: micro-search"));
+}
@@ -0,0 +1,184 @@
+//! Runs `micro-search --serve` on an ephemeral port and drives its HTTP API.
+//!
+//! Run with `cargo test --no-default-features --features http --test http`.
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, ChildStderr, Command, Stdio};
+
+/// A running server, killed when dropped.
+struct Server {
+    child: Child,
+    addr: String,
+    /// The rest of the server's standard error, kept open so it can still write the
+    /// summary at the end of its input.
+    _stderr: BufReader<ChildStderr>,
+}
+
+impl Server {
+    /// Starts the binary with `args` and waits until it is listening.
+    fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_micro-search"))
+            .args(["--serve", "127.0.0.1:0"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let mut line = String::new();
+        stderr.read_line(&mut line).unwrap();
+        let addr = line
+            .trim()
+            .strip_prefix("micro-search: listening on http://")
+            .unwrap_or_else(|| panic!("unexpected output {line:?}"))
+            .to_string();
+        Self {
+            child,
+            addr,
+            _stderr: stderr,
+        }
+    }
+
+    /// Sends a request and returns the status code and the parsed JSON body.
+    fn request(&self, method: &str, target: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        write!(
+            stream,
+            "{method} {target} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{body}",
+            self.addr,
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Content-Type: application/json"), "{head}");
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn ingests_searches_and_deletes() {
+    let server = Server::start(&[]);
+    let ndjson = concat!(
+        r#"{"level":"ERROR","service":"auth","msg":"token refresh failed","timestamp":100}"#,
+        "\n",
+        r#"{"level":"INFO","service":"auth","msg":"user signed in","timestamp":101}"#,
+        "\n\n",
+        "not json\n",
+        r#"{"level":"ERROR","service":"billing","msg":"charge failed","timestamp":102}"#,
+        "\n",
+    );
+    let (status, body) = server.request("POST", "/ingest", ndjson);
+    assert_eq!(status, 200);
+    assert_eq!(body["ids"], json!([1, 2, 3]));
+    assert_eq!(body["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(body["errors"][0]["line"], 4);
+
+    let (status, body) = server.request("GET", "/search?q=failed+level%3AERROR", "");
+    assert_eq!(status, 200);
+    assert_eq!(body["total"], 2);
+    let mut hits = body["hits"].as_array().unwrap().clone();
+    hits.sort_by_key(|hit| hit["id"].as_u64());
+    assert_eq!(
+        hits,
+        [
+            json!({"id": 1, "timestamp": 100, "level": "ERROR", "service": "auth",
+                   "content": "token refresh failed"}),
+            json!({"id": 3, "timestamp": 102, "level": "ERROR", "service": "billing",
+                   "content": "charge failed"}),
+        ]
+    );
+
+    let (_, body) = server.request("GET", "/search?q=service:auth&limit=1", "");
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["hits"].as_array().unwrap().len(), 1);
+
+    let (status, body) = server.request("GET", "/stats", "");
+    assert_eq!(status, 200);
+    assert_eq!(body["docs"], 3);
+    assert_eq!(body["inserts"], 3);
+    assert!(body["approx_bytes"].as_u64().unwrap() > 0);
+
+    let mut stream = TcpStream::connect(&server.addr).unwrap();
+    write!(
+        stream,
+        "GET /metrics HTTP/1.1\r\nHost: {}\r\n\r\n",
+        server.addr
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, metrics) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+    assert!(
+        head.contains("Content-Type: text/plain; version=0.0.4"),
+        "{head}"
+    );
+    assert!(metrics.contains("# TYPE buggu_inserts_total counter\nbuggu_inserts_total 3\n"));
+
+    let (status, body) = server.request("DELETE", "/docs/1", "");
+    assert_eq!((status, body), (200, json!({"deleted": 1})));
+    let (status, _) = server.request("DELETE", "/docs/1", "");
+    assert_eq!(status, 404);
+    let (_, body) = server.request("GET", "/search?q=failed", "");
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["hits"][0]["id"], 3);
+    let (_, body) = server.request("GET", "/stats", "");
+    assert_eq!(
+        (body["docs"].clone(), body["deletes"].clone()),
+        (json!(2), json!(1))
+    );
+}
+
+#[test]
+fn rejects_bad_requests() {
+    let server = Server::start(&[]);
+    for (method, target, status) in [
+        ("GET", "/search", 400),
+        ("GET", "/search?q=%22unterminated", 400),
+        ("GET", "/search?q=x&limit=lots", 400),
+        ("DELETE", "/docs/abc", 400),
+        ("GET", "/ingest", 405),
+        ("GET", "/docs/1", 405),
+        ("GET", "/nowhere", 404),
+    ] {
+        let (got, body) = server.request(method, target, "");
+        assert_eq!(got, status, "{method} {target}");
+        assert!(body["error"].is_string(), "{method} {target}");
+    }
+}
+
+#[test]
+fn serves_lines_read_from_stdin() {
+    let mut server = Server::start(&["--stdin", "--format", "logfmt", "--query", "level:ERROR"]);
+    let mut stdin = server.child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(server.child.stdout.take().unwrap());
+    writeln!(stdin, r#"level=INFO msg="all good""#).unwrap();
+    writeln!(stdin, r#"level=ERROR msg="disk full""#).unwrap();
+    stdin.flush().unwrap();
+    // Once the second line is echoed, both have been indexed.
+    let mut echoed = String::new();
+    stdout.read_line(&mut echoed).unwrap();
+    assert_eq!(echoed, "level=ERROR msg=\"disk full\"\n");
+
+    let (_, body) = server.request("GET", "/search?q=disk", "");
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["hits"][0]["content"], "disk full");
+
+    // The server outlives the input.
+    drop(stdin);
+    let (_, body) = server.request("GET", "/stats", "");
+    assert_eq!(body["docs"], 2);
+}